@@ -1,9 +1,28 @@
-use std::sync::Arc;
-use tokio::sync::Mutex;
 use serde::{Deserialize, Serialize};
-use crate::simple_db::{SimpleDatabase, SimpleQueryResult, TableInfo, ColumnInfo, DetailedColumnInfo, ForeignKeyInfo, IndexInfo, ViewInfo, StoredProcedureInfo, MaterializedViewInfo, CreateIndexOptions};
-use crate::connection_pool::{ConnectionPool, PoolStatus};
+use crate::session_registry::{DatabaseSession, SessionInfo, SessionProfile, SessionRegistryState, DEFAULT_SESSION_ID};
+use crate::sql_builder::{qualified_name, RowChangeResult, RowIdentifier, RowVersionCheck};
+use crate::sql_import::{self, SqlImportOptions};
+use crate::export_format::{self, ExportFormatOptions};
+use crate::production_guard::{Access, GuardDecision, GuardOperation, GuardStatus, RowOperation};
+use crate::table_export::{run_table_export, ExportControl, ExportSource, TableExportOptions, TableExportRegistryState};
+use crate::table_import::{CsvOptions, ImportRowError, TableImportFormat, TableImportRequest};
+use crate::sql_script::{ScriptOptions, ScriptResult, StatementStatus};
+use crate::transaction_session::{transaction_control_keyword, TransactionInfo, TransactionOptions};
+use crate::simple_db::{DEFAULT_QUERY_TIMEOUT, RowEdit, SimpleDatabase, SimpleQueryResult, TableInfo, ColumnInfo, DetailedColumnInfo, ForeignKeyInfo, IndexInfo, ViewInfo, StoredProcedureInfo, MaterializedViewInfo, CreateIndexOptions};
+use crate::connection_pool::PoolStatus;
+use crate::connection_profile_store_commands::ConnectionProfileStoreState;
+use crate::credential_vault::VaultError;
+use crate::credential_vault_commands::CredentialVaultState;
+use crate::query_history::{NewHistoryEntry, QueryHistoryState};
+use crate::query_plan::{self, ExplainOptions, QueryPlan};
+use crate::query_stream::{run_query_stream, QueryStreamOptions, QueryStreamRegistryState};
+use crate::saved_query_store::SavedQueryStoreState;
+use crate::schema_diff::{diff_schemas, SchemaDiff, SchemaSnapshot};
+use crate::ssh_tunnel;
+use crate::value_codec::ColumnType;
 use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use tokio::io::AsyncRead;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ConnectionStatus {
@@ -16,18 +35,22 @@ pub struct ConnectionStatus {
 pub async fn connect_database(
     connection_string: String,
     _save_connection: Option<bool>,
-    simple_db: tauri::State<'_, Arc<Mutex<SimpleDatabase>>>,
-    connection_pool: tauri::State<'_, Arc<Mutex<ConnectionPool>>>,
+    session_id: Option<String>,
+    sessions: tauri::State<'_, SessionRegistryState>,
 ) -> Result<ConnectionStatus, String> {
     println!("🦀 [Command] connect_database called");
     
     // Connect both simple DB and connection pool
-    let mut db = simple_db.lock().await;
-    let mut pool = connection_pool.lock().await;
+    let session = sessions.lock().await.get_or_create(session_id.as_deref());
+    let mut db = session.simple_db.lock().await;
+    let mut pool = session.pool.lock().await;
     
     match db.connect(connection_string.clone()).await {
         Ok(_) => {
             println!("🦀 [Command] SimpleDB connection successful");
+            session.set_profile(None).await;
+            session.set_tunnel(None).await;
+            db.set_query_timeout(DEFAULT_QUERY_TIMEOUT);
             
             // Initialize connection pool
             match pool.initialize(connection_string).await {
@@ -55,35 +78,174 @@ pub async fn connect_database(
     }
 }
 
+/// Connect to a saved profile, reading its password from the credential vault.
+/// The secret never leaves the backend.
+#[tauri::command]
+pub async fn connect_profile(
+    profile_id: String,
+    session_id: Option<String>,
+    sessions: tauri::State<'_, SessionRegistryState>,
+    vault_state: tauri::State<'_, CredentialVaultState>,
+    store_state: tauri::State<'_, ConnectionProfileStoreState>,
+) -> Result<ConnectionStatus, String> {
+    println!("🦀 [Command] connect_profile called for profile: {}", profile_id);
+    
+    let profile = {
+        let store = store_state.lock().await;
+        store.get_profile(&profile_id).await.map_err(|e| e.to_string())?
+    };
+    
+    let (mut credentials, mut ssh_secret) = {
+        let vault = vault_state.lock().await;
+        let credentials = match vault.retrieve_credentials(&profile_id).await {
+            Ok(credentials) => credentials,
+            Err(VaultError::ProfileNotFound(_)) => {
+                return Err(format!("No credentials stored for profile '{}'", profile.name));
+            }
+            Err(e) => return Err(format!("Failed to retrieve credentials: {}", e)),
+        };
+        let ssh_secret = ssh_tunnel::ssh_secret_from_vault(&vault, &profile_id, &profile.config).await?;
+        (credentials, ssh_secret)
+    };
+    
+    let mut config = profile.config.clone();
+    if config.username.is_empty() {
+        config.username = credentials.username.clone();
+    }
+    
+    // Open the SSH port forward first so both the client and the pool connect through it
+    let tunnel = ssh_tunnel::open_for_config(&config, ssh_secret.as_deref()).await;
+    if let Some(secret) = ssh_secret.as_mut() {
+        zeroize::Zeroize::zeroize(secret);
+    }
+    let tunnel = match tunnel {
+        Ok(tunnel) => tunnel,
+        Err(e) => {
+            zeroize::Zeroize::zeroize(&mut credentials);
+            return Err(e);
+        }
+    };
+    
+    let pg_config = match &tunnel {
+        Some(tunnel) => config.to_tunneled_pg_config(&credentials.password, tunnel.local_addr()),
+        None => config.to_pg_config(&credentials.password),
+    };
+    zeroize::Zeroize::zeroize(&mut credentials);
+    let pg_config = pg_config?;
+    let tls = crate::tls_config::make_tls_connector(&config.ssl_config)?;
+    
+    let session = sessions.lock().await.get_or_create(session_id.as_deref());
+    let mut db = session.simple_db.lock().await;
+    let mut pool = session.pool.lock().await;
+    
+    db.set_query_timeout(config.query_timeout);
+    if let Err(e) = db.connect_with_config(pg_config.clone(), tls.clone()).await {
+        println!("🦀 [Command] Connection to profile {} failed: {}", profile_id, e);
+        return Err(format!("Connection failed: {}", e));
+    }
+    println!("🦀 [Command] SimpleDB connection successful");
+    session.set_tunnel(tunnel).await;
+    session.set_profile(Some(SessionProfile {
+        profile_id: profile.id.clone(),
+        profile_name: profile.name.clone(),
+        environment: profile.metadata.environment.clone(),
+    })).await;
+    
+    let message = match pool.initialize_with_config(pg_config, tls).await {
+        Ok(_) => {
+            println!("🦀 [Command] Connection pool initialized successfully");
+            format!("Connected to '{}' with connection pooling", profile.name)
+        }
+        Err(e) => {
+            println!("🦀 [Command] Connection pool initialization failed: {}", e);
+            format!("Connected to '{}' (pool initialization failed)", profile.name)
+        }
+    };
+    
+    {
+        let store = store_state.lock().await;
+        if let Err(e) = store.mark_profile_used(&profile_id).await {
+            println!("🦀 [Command] Failed to mark profile as used: {}", e);
+        }
+    }
+    
+    Ok(ConnectionStatus {
+        connected: true,
+        message,
+    })
+}
+
 #[tauri::command]
 pub async fn disconnect_database(
-    simple_db: tauri::State<'_, Arc<Mutex<SimpleDatabase>>>,
-    connection_pool: tauri::State<'_, Arc<Mutex<ConnectionPool>>>,
+    session_id: Option<String>,
+    sessions: tauri::State<'_, SessionRegistryState>,
 ) -> Result<(), String> {
     println!("🦀 [Command] disconnect_database called");
     
-    let mut db = simple_db.lock().await;
-    let mut pool = connection_pool.lock().await;
-    
-    let _ = db.disconnect().await;
-    let _ = pool.disconnect().await;
+    let session = sessions.lock().await.get(session_id.as_deref())?;
+    session.disconnect().await;
     println!("🦀 [Command] Disconnected successfully");
     Ok(())
 }
 
+/// List all open database sessions
+#[tauri::command]
+pub async fn list_sessions(
+    sessions: tauri::State<'_, SessionRegistryState>,
+) -> Result<Vec<SessionInfo>, String> {
+    let all_sessions = sessions.lock().await.sessions();
+    
+    let mut infos = Vec::with_capacity(all_sessions.len());
+    for session in all_sessions {
+        infos.push(session.info().await);
+    }
+    Ok(infos)
+}
+
+/// Disconnect a session and remove it from the registry
+#[tauri::command]
+pub async fn close_session(
+    session_id: String,
+    sessions: tauri::State<'_, SessionRegistryState>,
+) -> Result<(), String> {
+    println!("🦀 [Command] close_session called for session: {}", session_id);
+    
+    let session = {
+        let mut registry = sessions.lock().await;
+        if session_id == DEFAULT_SESSION_ID {
+            // The default session always exists, only drop its connections
+            registry.get(Some(&session_id)).ok()
+        } else {
+            registry.remove(&session_id)
+        }
+    };
+    
+    match session {
+        Some(session) => {
+            session.disconnect().await;
+            Ok(())
+        }
+        None => Err(format!("Session '{}' not found", session_id)),
+    }
+}
+
 #[tauri::command]
 pub async fn is_connected(
-    simple_db: tauri::State<'_, Arc<Mutex<SimpleDatabase>>>,
+    session_id: Option<String>,
+    sessions: tauri::State<'_, SessionRegistryState>,
 ) -> Result<bool, String> {
-    let db = simple_db.lock().await;
+    let session = sessions.lock().await.get(session_id.as_deref())?;
+    let db = session.simple_db.lock().await;
     Ok(db.is_connected())
 }
 
 #[tauri::command]
 pub async fn get_connection_status(
-    simple_db: tauri::State<'_, Arc<Mutex<SimpleDatabase>>>,
+    session_id: Option<String>,
+    sessions: tauri::State<'_, SessionRegistryState>,
 ) -> Result<ConnectionStatus, String> {
-    let db = simple_db.lock().await;
+    let session = sessions.lock().await.get(session_id.as_deref())?;
+    let db = session.simple_db.lock().await;
     let connected = db.is_connected();
     
     Ok(ConnectionStatus {
@@ -98,11 +260,13 @@ pub async fn get_connection_status(
 
 #[tauri::command]
 pub async fn get_pool_status(
-    connection_pool: tauri::State<'_, Arc<Mutex<ConnectionPool>>>,
+    session_id: Option<String>,
+    sessions: tauri::State<'_, SessionRegistryState>,
 ) -> Result<PoolStatus, String> {
     println!("🦀 [Command] get_pool_status called");
     
-    let pool = connection_pool.lock().await;
+    let session = sessions.lock().await.get(session_id.as_deref())?;
+    let pool = session.pool.lock().await;
     match pool.get_pool_status().await {
         Ok(status) => {
             println!("🦀 [Command] Pool status: {:?}", status);
@@ -117,14 +281,32 @@ pub async fn get_pool_status(
 
 // Query commands
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
 pub async fn execute_query(
     query: String,
-    simple_db: tauri::State<'_, Arc<Mutex<SimpleDatabase>>>,
+    execution_id: Option<String>,
+    timeout_ms: Option<u64>,
+    transaction_id: Option<String>,
+    confirmation_token: Option<String>,
+    session_id: Option<String>,
+    sessions: tauri::State<'_, SessionRegistryState>,
+    history: tauri::State<'_, QueryHistoryState>,
 ) -> Result<SimpleQueryResult, String> {
     println!("🦀 [Command] execute_query called");
     
-    let db = simple_db.lock().await;
-    match db.execute_query(&query).await {
+    let session = sessions.lock().await.get(session_id.as_deref())?;
+    let access = session
+        .authorize(&GuardOperation::Sql { sql: query.clone() }, confirmation_token.as_deref())
+        .await?;
+    let timeout = timeout_ms.map(Duration::from_millis);
+    let started = Instant::now();
+    
+    // The frontend passes its own execution id so it can cancel before the result arrives
+    let execution_id = execution_id.unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    let result = run_on_session(&session, &query, &[], &execution_id, timeout, transaction_id, access).await;
+    record_statement(&history, &session, &query, started, &result).await;
+    
+    match result {
         Ok(result) => {
             println!("🦀 [Command] Query executed successfully, {} rows", result.rows.len());
             Ok(result)
@@ -136,14 +318,356 @@ pub async fn execute_query(
     }
 }
 
+/// Run a saved query, binding `parameters` to its `:name` placeholders
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn execute_saved_query(
+    query_id: String,
+    parameters: Option<HashMap<String, serde_json::Value>>,
+    execution_id: Option<String>,
+    timeout_ms: Option<u64>,
+    transaction_id: Option<String>,
+    confirmation_token: Option<String>,
+    session_id: Option<String>,
+    sessions: tauri::State<'_, SessionRegistryState>,
+    saved_queries: tauri::State<'_, SavedQueryStoreState>,
+    history: tauri::State<'_, QueryHistoryState>,
+) -> Result<SimpleQueryResult, String> {
+    println!("🦀 [Command] execute_saved_query called for query: {}", query_id);
+    
+    let saved = saved_queries.lock().await.get(&query_id)?;
+    let (sql, params) = saved.bind(&parameters.unwrap_or_default())?;
+    
+    let session = sessions.lock().await.get(session_id.as_deref())?;
+    let access = session
+        .authorize(&GuardOperation::Sql { sql: sql.clone() }, confirmation_token.as_deref())
+        .await?;
+    let timeout = timeout_ms.map(Duration::from_millis);
+    let started = Instant::now();
+    
+    let execution_id = execution_id.unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    let result = run_on_session(&session, &sql, &params, &execution_id, timeout, transaction_id, access).await;
+    record_statement(&history, &session, &sql, started, &result).await;
+    
+    match result {
+        Ok(result) => {
+            println!("🦀 [Command] Saved query '{}' executed successfully, {} rows", saved.name, result.rows.len());
+            Ok(result)
+        }
+        Err(e) => {
+            println!("🦀 [Command] Saved query '{}' failed: {}", saved.name, e);
+            Err(format!("Query failed: {}", e))
+        }
+    }
+}
+
+/// `check_operation` for a saved query: the guard confirms the SQL with these parameters bound
+#[tauri::command]
+pub async fn check_saved_query(
+    query_id: String,
+    parameters: Option<HashMap<String, serde_json::Value>>,
+    session_id: Option<String>,
+    sessions: tauri::State<'_, SessionRegistryState>,
+    saved_queries: tauri::State<'_, SavedQueryStoreState>,
+) -> Result<GuardDecision, String> {
+    let saved = saved_queries.lock().await.get(&query_id)?;
+    let (sql, _) = saved.bind(&parameters.unwrap_or_default())?;
+    let session = sessions.lock().await.get(session_id.as_deref())?;
+    Ok(session.check_operation(&GuardOperation::Sql { sql }).await)
+}
+
+/// Show the plan for a single statement. With `options.analyze` the statement runs, inside a
+/// transaction that is rolled back afterwards.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn explain_query(
+    query: String,
+    options: Option<ExplainOptions>,
+    execution_id: Option<String>,
+    timeout_ms: Option<u64>,
+    confirmation_token: Option<String>,
+    session_id: Option<String>,
+    sessions: tauri::State<'_, SessionRegistryState>,
+    history: tauri::State<'_, QueryHistoryState>,
+) -> Result<QueryPlan, String> {
+    println!("🦀 [Command] explain_query called");
+    
+    let options = options.unwrap_or_default();
+    let session = sessions.lock().await.get(session_id.as_deref())?;
+    // EXPLAIN ANALYZE still takes the statement's locks and fires its triggers
+    let explain_sql = query_plan::explain_sql(&query, &options)?;
+    let access = session
+        .authorize(&GuardOperation::Sql { sql: explain_sql.clone() }, confirmation_token.as_deref())
+        .await?;
+    
+    let execution_id = execution_id.unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    let timeout = timeout_ms.map(Duration::from_millis);
+    let started = Instant::now();
+    let result = query_plan::run_explain(&session, &query, &options, &execution_id, timeout, access).await;
+    // Only EXPLAIN ANALYZE runs the statement
+    if options.analyze {
+        let error = result.as_ref().err().cloned();
+        let entry = history_entry(&session, &explain_sql, started.elapsed().as_millis() as u64, None, error).await;
+        record_history(&history, vec![entry]).await;
+    }
+    match result {
+        Ok(plan) => {
+            println!("🦀 [Command] Plan has {} hotspots", plan.hotspots.len());
+            Ok(plan)
+        }
+        Err(e) => {
+            println!("🦀 [Command] EXPLAIN failed: {}", e);
+            Err(e)
+        }
+    }
+}
+
+/// Run a multi-statement script on the session's shared connection.
+/// Cancelling `execution_id` stops the statement that is running; how the rest of the
+/// script continues depends on `options.on_error`.
+#[tauri::command]
+pub async fn execute_script(
+    script: String,
+    options: Option<ScriptOptions>,
+    execution_id: Option<String>,
+    confirmation_token: Option<String>,
+    session_id: Option<String>,
+    sessions: tauri::State<'_, SessionRegistryState>,
+    history: tauri::State<'_, QueryHistoryState>,
+) -> Result<ScriptResult, String> {
+    println!("🦀 [Command] execute_script called");
+    
+    let session = sessions.lock().await.get(session_id.as_deref())?;
+    let access = session
+        .authorize(&GuardOperation::Sql { sql: script.clone() }, confirmation_token.as_deref())
+        .await?;
+    let options = options.unwrap_or_default();
+    let execution_id = execution_id.unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    let started = Instant::now();
+    let result = {
+        let db = session.simple_db.lock().await;
+        session.register_query(&execution_id, db.canceller().await?).await?;
+        let result = db.execute_script(&script, &options, access).await;
+        session.finish_query(&execution_id).await;
+        result
+    };
+    
+    // Each statement that ran gets its own history entry
+    let mut entries = Vec::new();
+    match &result {
+        Ok(result) => {
+            for statement in result.statements.iter().filter(|s| s.status != StatementStatus::Skipped) {
+                let row_count = statement.result.as_ref().map(result_row_count);
+                let error = statement.error.clone();
+                entries.push(history_entry(&session, &statement.sql, statement.execution_time_ms, row_count, error).await);
+            }
+        }
+        Err(e) => {
+            let duration_ms = started.elapsed().as_millis() as u64;
+            entries.push(history_entry(&session, &script, duration_ms, None, Some(e.to_string())).await);
+        }
+    }
+    record_history(&history, entries).await;
+    
+    match result {
+        Ok(result) => {
+            println!(
+                "🦀 [Command] Script finished: {} succeeded, {} failed, {} skipped",
+                result.succeeded, result.failed, result.skipped
+            );
+            Ok(result)
+        }
+        Err(e) => {
+            println!("🦀 [Command] Script failed: {}", e);
+            Err(format!("Script failed: {}", e))
+        }
+    }
+}
+
+/// Run a statement on the session's shared connection, or inside `transaction_id`,
+/// registered under `execution_id` so `cancel_query` can stop it, read-only when `access` says so
+async fn run_on_session(
+    session: &DatabaseSession,
+    query: &str,
+    params: &[Option<String>],
+    execution_id: &str,
+    timeout: Option<Duration>,
+    transaction_id: Option<String>,
+    access: Access,
+) -> Result<SimpleQueryResult, String> {
+    match transaction_id {
+        // Only the tab that owns the transaction passes its id; everyone else stays outside it
+        Some(transaction_id) => {
+            let transaction = session.transaction(&transaction_id).await?;
+            session.register_query(execution_id, transaction.canceller().await?).await?;
+            let result = transaction.execute(query, params, timeout, access).await;
+            session.finish_query(execution_id).await;
+            result
+        }
+        None => {
+            // A transaction opened here would silently span every tab sharing the connection
+            if let Some(keyword) = transaction_control_keyword(query) {
+                return Err(format!("{} is not allowed on the shared connection; use begin_transaction to open a transaction", keyword));
+            }
+            let db = session.simple_db.lock().await;
+            session.register_query(execution_id, db.canceller().await?).await?;
+            let result = db.execute_query_with_access(query, params, timeout, access).await;
+            session.finish_query(execution_id).await;
+            result
+        }
+    }
+}
+
+async fn record_statement(
+    history: &QueryHistoryState,
+    session: &DatabaseSession,
+    sql: &str,
+    started: Instant,
+    result: &Result<SimpleQueryResult, String>,
+) {
+    let (duration_ms, row_count, error) = match result {
+        Ok(result) => (result.execution_time_ms, Some(result_row_count(result)), None),
+        Err(e) => (started.elapsed().as_millis() as u64, None, Some(e.to_string())),
+    };
+    let entry = history_entry(session, sql, duration_ms, row_count, error).await;
+    record_history(history, vec![entry]).await;
+}
+
+/// Record a row edit: a conflict is an edit that did not apply
+async fn record_row_edit(
+    history: &QueryHistoryState,
+    session: &DatabaseSession,
+    edit: &RowEdit,
+    started: Instant,
+    result: &Result<RowChangeResult, String>,
+) {
+    let (row_count, error) = match result {
+        Ok(result) if result.conflict.is_some() => (Some(0), Some("Row changed since it was read, edit not applied".to_string())),
+        Ok(result) => (Some(result.rows_affected), None),
+        Err(e) => (None, Some(e.clone())),
+    };
+    let entry = history_entry(session, &edit.statement.sql, started.elapsed().as_millis() as u64, row_count, error).await;
+    record_history(history, vec![entry]).await;
+}
+
+/// An import is one history entry, described by `sql`
+async fn record_import(history: &QueryHistoryState, session: &DatabaseSession, sql: &str, result: &ImportResult) {
+    let error = if result.success {
+        None
+    } else {
+        Some(result.errors.first().cloned().unwrap_or_else(|| "Import rolled back".to_string()))
+    };
+    let entry = history_entry(session, sql, result.execution_time_ms, Some(result.rows_imported as u64), error).await;
+    record_history(history, vec![entry]).await;
+}
+
+fn result_row_count(result: &SimpleQueryResult) -> u64 {
+    result.rows_affected.unwrap_or(result.row_count as u64)
+}
+
+async fn history_entry(
+    session: &DatabaseSession,
+    sql: &str,
+    duration_ms: u64,
+    row_count: Option<u64>,
+    error: Option<String>,
+) -> NewHistoryEntry {
+    NewHistoryEntry {
+        profile_id: session.profile().await.map(|profile| profile.profile_id),
+        session_id: session.id.clone(),
+        sql: sql.to_string(),
+        duration_ms,
+        row_count,
+        error,
+    }
+}
+
+/// History is best effort: failing to persist it never fails the query
+/// Only the in-memory update holds the store; the file is written on a blocking thread
+/// in the background
+async fn record_history(history: &QueryHistoryState, entries: Vec<NewHistoryEntry>) {
+    let pending = history.lock().await.record_all(entries);
+    match pending {
+        Ok(pending) => {
+            tokio::task::spawn_blocking(move || {
+                if let Err(e) = pending.write() {
+                    println!("🦀 [Command] Failed to save query history: {}", e);
+                }
+            });
+        }
+        Err(e) => println!("🦀 [Command] Failed to record query history: {}", e),
+    }
+}
+
+/// Cancel a statement started by `execute_query` in any session
+#[tauri::command]
+pub async fn cancel_query(
+    execution_id: String,
+    sessions: tauri::State<'_, SessionRegistryState>,
+) -> Result<bool, String> {
+    println!("🦀 [Command] cancel_query called for execution: {}", execution_id);
+    
+    let sessions = sessions.lock().await.sessions();
+    for session in sessions {
+        if session.cancel_query(&execution_id).await? {
+            println!("🦀 [Command] Cancel request sent for execution {} in session {}", execution_id, session.id);
+            return Ok(true);
+        }
+    }
+    
+    println!("🦀 [Command] Execution {} is not running", execution_id);
+    Ok(false)
+}
+
+/// Classify an operation and, on production, hand out the confirmation token it needs
+#[tauri::command]
+pub async fn check_operation(
+    operation: GuardOperation,
+    session_id: Option<String>,
+    sessions: tauri::State<'_, SessionRegistryState>,
+) -> Result<GuardDecision, String> {
+    let session = sessions.lock().await.get(session_id.as_deref())?;
+    Ok(session.check_operation(&operation).await)
+}
+
+/// Allow destructive operations on a production session for `duration_minutes`.
+/// `confirmation` must repeat the profile's name.
+#[tauri::command]
+pub async fn unlock_production_writes(
+    confirmation: String,
+    duration_minutes: Option<u64>,
+    session_id: Option<String>,
+    sessions: tauri::State<'_, SessionRegistryState>,
+) -> Result<GuardStatus, String> {
+    println!("🦀 [Command] unlock_production_writes called");
+    
+    let session = sessions.lock().await.get(session_id.as_deref())?;
+    let status = session.unlock_writes(&confirmation, duration_minutes).await?;
+    println!("🦀 [Command] Production writes unlocked until {:?}", status.unlocked_until);
+    Ok(status)
+}
+
+#[tauri::command]
+pub async fn lock_production_writes(
+    session_id: Option<String>,
+    sessions: tauri::State<'_, SessionRegistryState>,
+) -> Result<GuardStatus, String> {
+    println!("🦀 [Command] lock_production_writes called");
+    
+    let session = sessions.lock().await.get(session_id.as_deref())?;
+    Ok(session.lock_writes().await)
+}
+
 // Schema commands
 #[tauri::command]
 pub async fn get_tables(
-    simple_db: tauri::State<'_, Arc<Mutex<SimpleDatabase>>>,
+    session_id: Option<String>,
+    sessions: tauri::State<'_, SessionRegistryState>,
 ) -> Result<Vec<TableInfo>, String> {
     println!("🦀 [Command] get_tables called");
     
-    let db = simple_db.lock().await;
+    let session = sessions.lock().await.get(session_id.as_deref())?;
+    let db = session.simple_db.lock().await;
     match db.get_tables().await {
         Ok(tables) => {
             println!("🦀 [Command] Found {} tables", tables.len());
@@ -160,11 +684,13 @@ pub async fn get_tables(
 pub async fn get_table_columns(
     table_name: String,
     schema_name: Option<String>,
-    simple_db: tauri::State<'_, Arc<Mutex<SimpleDatabase>>>,
+    session_id: Option<String>,
+    sessions: tauri::State<'_, SessionRegistryState>,
 ) -> Result<Vec<ColumnInfo>, String> {
     println!("🦀 [Command] get_table_columns called for table: {}", table_name);
     
-    let db = simple_db.lock().await;
+    let session = sessions.lock().await.get(session_id.as_deref())?;
+    let db = session.simple_db.lock().await;
     match db.get_table_columns(&table_name, schema_name.as_deref()).await {
         Ok(columns) => {
             println!("🦀 [Command] Found {} columns", columns.len());
@@ -181,11 +707,13 @@ pub async fn get_table_columns(
 pub async fn build_safe_query(
     table_name: String,
     schema_name: Option<String>,
-    simple_db: tauri::State<'_, Arc<Mutex<SimpleDatabase>>>,
+    session_id: Option<String>,
+    sessions: tauri::State<'_, SessionRegistryState>,
 ) -> Result<String, String> {
     println!("🦀 [Command] build_safe_query called for table: {}", table_name);
     
-    let db = simple_db.lock().await;
+    let session = sessions.lock().await.get(session_id.as_deref())?;
+    let db = session.simple_db.lock().await;
     match db.build_safe_query(&table_name, schema_name.as_deref()).await {
         Ok(query) => {
             println!("🦀 [Command] Built safe query: {}", query);
@@ -202,11 +730,13 @@ pub async fn build_safe_query(
 pub async fn query_table(
     table_name: String,
     schema_name: Option<String>,
-    simple_db: tauri::State<'_, Arc<Mutex<SimpleDatabase>>>,
+    session_id: Option<String>,
+    sessions: tauri::State<'_, SessionRegistryState>,
 ) -> Result<SimpleQueryResult, String> {
     println!("🦀 [Command] query_table called for table: {}", table_name);
     
-    let db = simple_db.lock().await;
+    let session = sessions.lock().await.get(session_id.as_deref())?;
+    let db = session.simple_db.lock().await;
     match db.query_table(&table_name, schema_name.as_deref()).await {
         Ok(result) => {
             println!("🦀 [Command] Table query successful, {} rows", result.rows.len());
@@ -219,15 +749,40 @@ pub async fn query_table(
     }
 }
 
+#[tauri::command]
+pub async fn get_row_identifier(
+    table_name: String,
+    schema_name: Option<String>,
+    session_id: Option<String>,
+    sessions: tauri::State<'_, SessionRegistryState>,
+) -> Result<Option<RowIdentifier>, String> {
+    println!("🦀 [Command] get_row_identifier called for table: {}", table_name);
+    
+    let session = sessions.lock().await.get(session_id.as_deref())?;
+    let db = session.simple_db.lock().await;
+    match db.get_row_identifier(&table_name, schema_name.as_deref()).await {
+        Ok(identifier) => {
+            println!("🦀 [Command] Row identifier: {:?}", identifier.as_ref().map(|i| &i.columns));
+            Ok(identifier)
+        }
+        Err(e) => {
+            println!("🦀 [Command] Failed to get row identifier: {}", e);
+            Err(format!("Failed to get row identifier: {}", e))
+        }
+    }
+}
+
 #[tauri::command]
 pub async fn get_table_foreign_keys(
     table_name: String,
     schema_name: Option<String>,
-    simple_db: tauri::State<'_, Arc<Mutex<SimpleDatabase>>>,
+    session_id: Option<String>,
+    sessions: tauri::State<'_, SessionRegistryState>,
 ) -> Result<Vec<ForeignKeyInfo>, String> {
     println!("🦀 [Command] get_table_foreign_keys called for table: {}", table_name);
     
-    let db = simple_db.lock().await;
+    let session = sessions.lock().await.get(session_id.as_deref())?;
+    let db = session.simple_db.lock().await;
     match db.get_table_foreign_keys(&table_name, schema_name.as_deref()).await {
         Ok(foreign_keys) => {
             println!("🦀 [Command] Found {} foreign keys", foreign_keys.len());
@@ -244,11 +799,13 @@ pub async fn get_table_foreign_keys(
 pub async fn get_detailed_table_columns(
     table_name: String,
     schema_name: Option<String>,
-    simple_db: tauri::State<'_, Arc<Mutex<SimpleDatabase>>>,
+    session_id: Option<String>,
+    sessions: tauri::State<'_, SessionRegistryState>,
 ) -> Result<Vec<DetailedColumnInfo>, String> {
     println!("🦀 [Command] get_detailed_table_columns called for table: {}", table_name);
     
-    let db = simple_db.lock().await;
+    let session = sessions.lock().await.get(session_id.as_deref())?;
+    let db = session.simple_db.lock().await;
     match db.get_detailed_table_columns(&table_name, schema_name.as_deref()).await {
         Ok(columns) => {
             println!("🦀 [Command] Found {} detailed columns", columns.len());
@@ -264,11 +821,13 @@ pub async fn get_detailed_table_columns(
 #[tauri::command]
 pub async fn get_enum_values(
     enum_name: String,
-    simple_db: tauri::State<'_, Arc<Mutex<SimpleDatabase>>>,
+    session_id: Option<String>,
+    sessions: tauri::State<'_, SessionRegistryState>,
 ) -> Result<Vec<String>, String> {
     println!("🦀 [Command] get_enum_values called for enum: {}", enum_name);
     
-    let db = simple_db.lock().await;
+    let session = sessions.lock().await.get(session_id.as_deref())?;
+    let db = session.simple_db.lock().await;
     match db.get_enum_values(&enum_name).await {
         Ok(values) => {
             println!("🦀 [Command] Found {} enum values", values.len());
@@ -282,21 +841,47 @@ pub async fn get_enum_values(
 }
 
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
 pub async fn update_row(
     table_name: String,
     schema_name: Option<String>,
     primary_key_columns: Vec<String>,
     primary_key_values: Vec<serde_json::Value>,
     column_updates: HashMap<String, serde_json::Value>,
-    simple_db: tauri::State<'_, Arc<Mutex<SimpleDatabase>>>,
-) -> Result<u64, String> {
+    check: Option<RowVersionCheck>,
+    confirmation_token: Option<String>,
+    session_id: Option<String>,
+    sessions: tauri::State<'_, SessionRegistryState>,
+    history: tauri::State<'_, QueryHistoryState>,
+) -> Result<RowChangeResult, String> {
     println!("🦀 [Command] update_row called for table: {}", table_name);
     
-    let db = simple_db.lock().await;
-    match db.update_row(&table_name, schema_name.as_deref(), &primary_key_columns, &primary_key_values, &column_updates).await {
-        Ok(rows_affected) => {
-            println!("🦀 [Command] Updated {} rows", rows_affected);
-            Ok(rows_affected)
+    let check = check.unwrap_or_default();
+    let session = sessions.lock().await.get(session_id.as_deref())?;
+    let operation = GuardOperation::RowChange {
+        schema: schema_name.clone(),
+        table: table_name.clone(),
+        key: primary_key_values.clone(),
+        operation: RowOperation::Update,
+    };
+    session.authorize(&operation, confirmation_token.as_deref()).await?;
+    let db = session.simple_db.lock().await;
+    let edit = db
+        .build_update_row(&table_name, schema_name.as_deref(), &primary_key_columns, &primary_key_values, &column_updates, &check)
+        .await
+        .map_err(|e| format!("Failed to update row: {}", e))?;
+    let started = Instant::now();
+    let result = db.apply_row_edit(&edit).await;
+    drop(db);
+    record_row_edit(&history, &session, &edit, started, &result).await;
+    match result {
+        Ok(result) if result.conflict.is_some() => {
+            println!("🦀 [Command] Update conflicts with a concurrent change");
+            Ok(result)
+        }
+        Ok(result) => {
+            println!("🦀 [Command] Updated {} rows", result.rows_affected);
+            Ok(result)
         }
         Err(e) => {
             println!("🦀 [Command] Failed to update row: {}", e);
@@ -306,19 +891,39 @@ pub async fn update_row(
 }
 
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
 pub async fn insert_row(
     table_name: String,
     schema_name: Option<String>,
     column_values: HashMap<String, serde_json::Value>,
-    simple_db: tauri::State<'_, Arc<Mutex<SimpleDatabase>>>,
-) -> Result<u64, String> {
+    confirmation_token: Option<String>,
+    session_id: Option<String>,
+    sessions: tauri::State<'_, SessionRegistryState>,
+    history: tauri::State<'_, QueryHistoryState>,
+) -> Result<RowChangeResult, String> {
     println!("🦀 [Command] insert_row called for table: {}", table_name);
     
-    let db = simple_db.lock().await;
-    match db.insert_row(&table_name, schema_name.as_deref(), &column_values).await {
-        Ok(rows_affected) => {
-            println!("🦀 [Command] Inserted {} rows", rows_affected);
-            Ok(rows_affected)
+    let session = sessions.lock().await.get(session_id.as_deref())?;
+    let operation = GuardOperation::RowChange {
+        schema: schema_name.clone(),
+        table: table_name.clone(),
+        key: Vec::new(),
+        operation: RowOperation::Insert,
+    };
+    session.authorize(&operation, confirmation_token.as_deref()).await?;
+    let db = session.simple_db.lock().await;
+    let edit = db
+        .build_insert_row(&table_name, schema_name.as_deref(), &column_values)
+        .await
+        .map_err(|e| format!("Failed to insert row: {}", e))?;
+    let started = Instant::now();
+    let result = db.apply_row_edit(&edit).await;
+    drop(db);
+    record_row_edit(&history, &session, &edit, started, &result).await;
+    match result {
+        Ok(result) => {
+            println!("🦀 [Command] Inserted {} rows", result.rows_affected);
+            Ok(result)
         }
         Err(e) => {
             println!("🦀 [Command] Failed to insert row: {}", e);
@@ -328,20 +933,46 @@ pub async fn insert_row(
 }
 
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
 pub async fn delete_row(
     table_name: String,
     schema_name: Option<String>,
     primary_key_columns: Vec<String>,
     primary_key_values: Vec<serde_json::Value>,
-    simple_db: tauri::State<'_, Arc<Mutex<SimpleDatabase>>>,
-) -> Result<u64, String> {
+    check: Option<RowVersionCheck>,
+    confirmation_token: Option<String>,
+    session_id: Option<String>,
+    sessions: tauri::State<'_, SessionRegistryState>,
+    history: tauri::State<'_, QueryHistoryState>,
+) -> Result<RowChangeResult, String> {
     println!("🦀 [Command] delete_row called for table: {}", table_name);
     
-    let db = simple_db.lock().await;
-    match db.delete_row(&table_name, schema_name.as_deref(), &primary_key_columns, &primary_key_values).await {
-        Ok(rows_affected) => {
-            println!("🦀 [Command] Deleted {} rows", rows_affected);
-            Ok(rows_affected)
+    let check = check.unwrap_or_default();
+    let session = sessions.lock().await.get(session_id.as_deref())?;
+    let operation = GuardOperation::RowChange {
+        schema: schema_name.clone(),
+        table: table_name.clone(),
+        key: primary_key_values.clone(),
+        operation: RowOperation::Delete,
+    };
+    session.authorize(&operation, confirmation_token.as_deref()).await?;
+    let db = session.simple_db.lock().await;
+    let edit = db
+        .build_delete_row(&table_name, schema_name.as_deref(), &primary_key_columns, &primary_key_values, &check)
+        .await
+        .map_err(|e| format!("Failed to delete row: {}", e))?;
+    let started = Instant::now();
+    let result = db.apply_row_edit(&edit).await;
+    drop(db);
+    record_row_edit(&history, &session, &edit, started, &result).await;
+    match result {
+        Ok(result) if result.conflict.is_some() => {
+            println!("🦀 [Command] Delete conflicts with a concurrent change");
+            Ok(result)
+        }
+        Ok(result) => {
+            println!("🦀 [Command] Deleted {} rows", result.rows_affected);
+            Ok(result)
         }
         Err(e) => {
             println!("🦀 [Command] Failed to delete row: {}", e);
@@ -350,17 +981,21 @@ pub async fn delete_row(
     }
 }
 
+/// Open an interactive transaction on a connection of its own. Statements run in it
+/// by passing the returned `transaction_id` to `execute_query`.
 #[tauri::command]
 pub async fn begin_transaction(
-    simple_db: tauri::State<'_, Arc<Mutex<SimpleDatabase>>>,
-) -> Result<(), String> {
+    options: Option<TransactionOptions>,
+    session_id: Option<String>,
+    sessions: tauri::State<'_, SessionRegistryState>,
+) -> Result<TransactionInfo, String> {
     println!("🦀 [Command] begin_transaction called");
     
-    let db = simple_db.lock().await;
-    match db.begin_transaction().await {
-        Ok(_) => {
-            println!("🦀 [Command] Transaction started successfully");
-            Ok(())
+    let session = sessions.lock().await.get(session_id.as_deref())?;
+    match session.begin_transaction(options.unwrap_or_default()).await {
+        Ok(info) => {
+            println!("🦀 [Command] Transaction {:?} started", info.transaction_id);
+            Ok(info)
         }
         Err(e) => {
             println!("🦀 [Command] Failed to start transaction: {}", e);
@@ -371,51 +1006,131 @@ pub async fn begin_transaction(
 
 #[tauri::command]
 pub async fn commit_transaction(
-    simple_db: tauri::State<'_, Arc<Mutex<SimpleDatabase>>>,
-) -> Result<(), String> {
+    session_id: Option<String>,
+    sessions: tauri::State<'_, SessionRegistryState>,
+) -> Result<TransactionInfo, String> {
     println!("🦀 [Command] commit_transaction called");
     
-    let db = simple_db.lock().await;
-    match db.commit_transaction().await {
-        Ok(_) => {
+    let session = sessions.lock().await.get(session_id.as_deref())?;
+    match session.end_transaction(true).await {
+        Ok(info) => {
             println!("🦀 [Command] Transaction committed successfully");
-            Ok(())
+            Ok(info)
         }
         Err(e) => {
             println!("🦀 [Command] Failed to commit transaction: {}", e);
-            Err(format!("Failed to commit transaction: {}", e))
+            Err(e)
         }
     }
 }
 
 #[tauri::command]
 pub async fn rollback_transaction(
-    simple_db: tauri::State<'_, Arc<Mutex<SimpleDatabase>>>,
-) -> Result<(), String> {
+    session_id: Option<String>,
+    sessions: tauri::State<'_, SessionRegistryState>,
+) -> Result<TransactionInfo, String> {
     println!("🦀 [Command] rollback_transaction called");
     
-    let db = simple_db.lock().await;
-    match db.rollback_transaction().await {
-        Ok(_) => {
+    let session = sessions.lock().await.get(session_id.as_deref())?;
+    match session.end_transaction(false).await {
+        Ok(info) => {
             println!("🦀 [Command] Transaction rolled back successfully");
-            Ok(())
+            Ok(info)
         }
         Err(e) => {
             println!("🦀 [Command] Failed to rollback transaction: {}", e);
-            Err(format!("Failed to rollback transaction: {}", e))
+            Err(e)
         }
     }
 }
 
+#[tauri::command]
+pub async fn create_savepoint(
+    name: String,
+    session_id: Option<String>,
+    sessions: tauri::State<'_, SessionRegistryState>,
+) -> Result<TransactionInfo, String> {
+    println!("🦀 [Command] create_savepoint called: {}", name);
+    
+    let session = sessions.lock().await.get(session_id.as_deref())?;
+    session.current_transaction().await?.create_savepoint(&name).await
+}
+
+/// Undo everything after a savepoint; also recovers a failed transaction
+#[tauri::command]
+pub async fn rollback_to_savepoint(
+    name: String,
+    session_id: Option<String>,
+    sessions: tauri::State<'_, SessionRegistryState>,
+) -> Result<TransactionInfo, String> {
+    println!("🦀 [Command] rollback_to_savepoint called: {}", name);
+    
+    let session = sessions.lock().await.get(session_id.as_deref())?;
+    session.current_transaction().await?.rollback_to_savepoint(&name).await
+}
+
+#[tauri::command]
+pub async fn release_savepoint(
+    name: String,
+    session_id: Option<String>,
+    sessions: tauri::State<'_, SessionRegistryState>,
+) -> Result<TransactionInfo, String> {
+    println!("🦀 [Command] release_savepoint called: {}", name);
+    
+    let session = sessions.lock().await.get(session_id.as_deref())?;
+    session.current_transaction().await?.release_savepoint(&name).await
+}
+
+/// Whether the session has an open (or failed) transaction, so the UI can warn about
+/// uncommitted changes
+#[tauri::command]
+pub async fn get_transaction_state(
+    session_id: Option<String>,
+    sessions: tauri::State<'_, SessionRegistryState>,
+) -> Result<TransactionInfo, String> {
+    let session = sessions.lock().await.get(session_id.as_deref())?;
+    Ok(session.transaction_info().await)
+}
+
 #[tauri::command]
 pub async fn execute_transaction(
     operations: Vec<serde_json::Value>,
-    simple_db: tauri::State<'_, Arc<Mutex<SimpleDatabase>>>,
-) -> Result<Vec<u64>, String> {
+    confirmation_token: Option<String>,
+    session_id: Option<String>,
+    sessions: tauri::State<'_, SessionRegistryState>,
+    history: tauri::State<'_, QueryHistoryState>,
+) -> Result<Vec<RowChangeResult>, String> {
     println!("🦀 [Command] execute_transaction called with {} operations", operations.len());
     
-    let db = simple_db.lock().await;
-    match db.execute_transaction(operations).await {
+    let session = sessions.lock().await.get(session_id.as_deref())?;
+    let operation = GuardOperation::RowChanges { operations: operations.clone() };
+    session.authorize(&operation, confirmation_token.as_deref()).await?;
+    let db = session.simple_db.lock().await;
+    let edits = db
+        .build_transaction(&operations)
+        .await
+        .map_err(|e| format!("Failed to execute transaction: {}", e))?;
+    let started = Instant::now();
+    let result = db.execute_transaction(&edits).await;
+    drop(db);
+    
+    // The whole transaction is one history entry
+    let sql = edits.iter().map(|edit| format!("{};", edit.statement.sql)).collect::<Vec<_>>().join("\n");
+    let (row_count, error) = match &result {
+        Ok(results) if results.iter().any(|result| result.conflict.is_some()) => {
+            (Some(0), Some("Row changed since it was read, transaction rolled back".to_string()))
+        }
+        Ok(results) => (Some(results.iter().map(|result| result.rows_affected).sum()), None),
+        Err(e) => (None, Some(e.clone())),
+    };
+    let entry = history_entry(&session, &sql, started.elapsed().as_millis() as u64, row_count, error).await;
+    record_history(&history, vec![entry]).await;
+    
+    match result {
+        Ok(results) if results.iter().any(|result| result.conflict.is_some()) => {
+            println!("🦀 [Command] Transaction conflicts with a concurrent change, rolled back");
+            Ok(results)
+        }
         Ok(results) => {
             println!("🦀 [Command] Transaction executed successfully with {} results", results.len());
             Ok(results)
@@ -427,30 +1142,98 @@ pub async fn execute_transaction(
     }
 }
 
-// Streaming query for large datasets
+/// Start streaming a query's results through `query-stream-batch` events.
+/// The frontend acknowledges each batch with `ack_query_stream` to receive more.
+#[tauri::command]
+pub async fn start_query_stream(
+    query: String,
+    options: Option<QueryStreamOptions>,
+    session_id: Option<String>,
+    app: tauri::AppHandle,
+    sessions: tauri::State<'_, SessionRegistryState>,
+    streams: tauri::State<'_, QueryStreamRegistryState>,
+    history: tauri::State<'_, QueryHistoryState>,
+) -> Result<String, String> {
+    println!("🦀 [Command] start_query_stream called");
+    
+    let session = sessions.lock().await.get(session_id.as_deref())?;
+    // Streams are for reading; writes on production go through execute_query
+    let access = session.authorize(&GuardOperation::Sql { sql: query.clone() }, None).await?;
+    if !session.pool.lock().await.is_connected() {
+        return Err("Not connected to database".to_string());
+    }
+    
+    let options = options.unwrap_or_default();
+    let (stream_id, control) = streams.lock().await.register(&options);
+    let streams = streams.inner().clone();
+    let history = history.inner().clone();
+    let id = stream_id.clone();
+    tokio::spawn(async move {
+        let end = run_query_stream(session.clone(), streams, id, query.clone(), options, access, control, app).await;
+        let row_count = end.error.is_none().then_some(end.total_rows);
+        let entry = history_entry(&session, &query, end.execution_time_ms, row_count, end.error).await;
+        record_history(&history, vec![entry]).await;
+    });
+    
+    println!("🦀 [Command] Query stream {} started", stream_id);
+    Ok(stream_id)
+}
+
+/// Acknowledge a received batch so the stream fetches the next one
+#[tauri::command]
+pub async fn ack_query_stream(
+    stream_id: String,
+    streams: tauri::State<'_, QueryStreamRegistryState>,
+) -> Result<bool, String> {
+    streams.lock().await.ack(&stream_id)
+}
+
+/// Stop a query stream and release its connection
+#[tauri::command]
+pub async fn cancel_query_stream(
+    stream_id: String,
+    streams: tauri::State<'_, QueryStreamRegistryState>,
+) -> Result<bool, String> {
+    println!("🦀 [Command] cancel_query_stream called for stream: {}", stream_id);
+    Ok(streams.lock().await.cancel(&stream_id))
+}
+
+// Paged query kept for existing callers; large results should use start_query_stream
 #[tauri::command]
 pub async fn execute_streaming_query(
     query: String,
     page_size: Option<usize>,
     offset: Option<usize>,
-    simple_db: tauri::State<'_, Arc<Mutex<SimpleDatabase>>>,
+    session_id: Option<String>,
+    sessions: tauri::State<'_, SessionRegistryState>,
+    history: tauri::State<'_, QueryHistoryState>,
 ) -> Result<SimpleQueryResult, String> {
     println!("🦀 [Command] execute_streaming_query called with page_size: {:?}, offset: {:?}", page_size, offset);
     
     let page_size = page_size.unwrap_or(1000); // Default to 1000 rows per page
     let offset = offset.unwrap_or(0);
     
-    // Modify query to add LIMIT and OFFSET for pagination
-    let streaming_query = if query.trim().to_lowercase().contains("limit") {
-        // If query already has LIMIT, use as-is
-        query
-    } else {
-        // Add LIMIT and OFFSET
-        format!("{} LIMIT {} OFFSET {}", query.trim_end_matches(';'), page_size, offset)
-    };
+    // Wrap the query so its own LIMIT/ORDER BY (or a column named "limit") is left intact
+    let streaming_query = format!(
+        "SELECT * FROM ({}) AS paged_query LIMIT {} OFFSET {}",
+        query.trim().trim_end_matches(';'),
+        page_size,
+        offset
+    );
     
-    let db = simple_db.lock().await;
-    match db.execute_query(&streaming_query).await {
+    let session = sessions.lock().await.get(session_id.as_deref())?;
+    // Like start_query_stream, this is for reading only
+    let access = session.authorize(&GuardOperation::Sql { sql: query.clone() }, None).await?;
+    let started = Instant::now();
+    let result = {
+        let db = session.simple_db.lock().await;
+        db.execute_query_with_access(&streaming_query, &[], None, access).await
+    };
+    // Later pages of the same query would flood the history
+    if offset == 0 {
+        record_statement(&history, &session, &query, started, &result).await;
+    }
+    match result {
         Ok(mut result) => {
             // Add pagination metadata
             result.row_count = result.rows.len();
@@ -476,6 +1259,16 @@ pub struct ExportOptions {
     pub schema_name: Option<String>,
 }
 
+impl ExportOptions {
+    fn format_options(&self) -> ExportFormatOptions {
+        ExportFormatOptions {
+            include_headers: self.include_headers,
+            pretty_json: self.pretty_json,
+            ..Default::default()
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ExportResult {
     pub content: String,
@@ -485,6 +1278,49 @@ pub struct ExportResult {
     pub format: String,
 }
 
+/// Render a result that is already in memory in one of the `export_format` text formats
+fn render_export(
+    query_result: &SimpleQueryResult,
+    export_options: &ExportOptions,
+    default_name: &str,
+) -> Result<ExportResult, String> {
+    let format = export_format::find_format(&export_options.format)?;
+    if format.is_binary() {
+        return Err(format!(
+            "{} files cannot be returned as text; export straight to a file instead",
+            export_options.format
+        ));
+    }
+    
+    // Results sent back by the frontend may have lost their column types
+    let column_types = if query_result.column_types.len() == query_result.columns.len() {
+        query_result.column_types.clone()
+    } else {
+        query_result
+            .columns
+            .iter()
+            .map(|name| ColumnType {
+                name: name.clone(),
+                type_oid: 0,
+                type_name: "text".to_string(),
+            })
+            .collect()
+    };
+    let bytes = export_format::render(format, &column_types, &query_result.rows, &export_options.format_options())?;
+    let content = String::from_utf8(bytes).map_err(|e| format!("Export produced invalid UTF-8: {}", e))?;
+    
+    Ok(ExportResult {
+        size_bytes: content.len(),
+        content,
+        filename: export_options
+            .filename
+            .clone()
+            .unwrap_or_else(|| format!("{}.{}", default_name, format.extension())),
+        row_count: query_result.rows.len(),
+        format: export_options.format.clone(),
+    })
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ImportOptions {
     pub format: String,
@@ -492,15 +1328,75 @@ pub struct ImportOptions {
     pub schema_name: Option<String>,
     pub truncate_before_import: Option<bool>,
     pub create_table_if_not_exists: Option<bool>,
+    /// SQL imports: run the whole file in one transaction, rolled back on any error
+    pub single_transaction: Option<bool>,
+    /// Stop at the first error instead of running the rest of the file. Table imports
+    /// then roll back entirely; otherwise bad rows are skipped and reported.
+    pub stop_on_error: Option<bool>,
+    /// CSV imports: delimiter, quoting, header, NULL marker and encoding
+    pub csv_options: Option<CsvOptions>,
+    /// Table imports: source column to target column
+    pub column_mapping: Option<HashMap<String, String>>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+impl ImportOptions {
+    fn sql_import_options(&self) -> SqlImportOptions {
+        let truncate_table = match (&self.table_name, self.truncate_before_import) {
+            (Some(table), Some(true)) => Some(qualified_name(self.schema_name.as_deref().unwrap_or("public"), table)),
+            _ => None,
+        };
+        SqlImportOptions {
+            single_transaction: self.single_transaction.unwrap_or(false),
+            stop_on_error: self.stop_on_error.unwrap_or(false),
+            truncate_table,
+        }
+    }
+
+    fn table_import_request(&self) -> Result<TableImportRequest, String> {
+        Ok(TableImportRequest {
+            format: TableImportFormat::parse(&self.format)?,
+            schema: self.schema_name.clone().unwrap_or_else(|| "public".to_string()),
+            table: self.table_name.clone().ok_or("A target table is required")?,
+            csv: self.csv_options.clone().unwrap_or_default(),
+            column_mapping: self.column_mapping.clone(),
+            create_table: self.create_table_if_not_exists.unwrap_or(false),
+            truncate: self.truncate_before_import.unwrap_or(false),
+            stop_on_error: self.stop_on_error.unwrap_or(false),
+        })
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
 pub struct ImportResult {
     pub success: bool,
     pub rows_imported: usize,
     pub errors: Vec<String>,
     pub warnings: Vec<String>,
     pub execution_time_ms: u64,
+    #[serde(default)]
+    pub statements_executed: usize,
+    /// Nothing was applied because the import's transaction was rolled back
+    #[serde(default)]
+    pub rolled_back: bool,
+    /// Table imports: rows skipped because they could not be read or inserted
+    #[serde(default)]
+    pub rows_failed: usize,
+    /// Table imports: why rows failed, for the first of them
+    #[serde(default)]
+    pub row_errors: Vec<ImportRowError>,
+    #[serde(default)]
+    pub created_table: bool,
+}
+
+impl ImportResult {
+    fn failed(error: String, start_time: std::time::Instant) -> Self {
+        Self {
+            success: false,
+            errors: vec![error],
+            execution_time_ms: start_time.elapsed().as_millis() as u64,
+            ..Default::default()
+        }
+    }
 }
 
 #[tauri::command]
@@ -508,12 +1404,14 @@ pub async fn export_table_sql(
     table_name: String,
     schema_name: Option<String>,
     sql_type: String,
-    simple_db: tauri::State<'_, Arc<Mutex<SimpleDatabase>>>,
+    session_id: Option<String>,
+    sessions: tauri::State<'_, SessionRegistryState>,
 ) -> Result<ExportResult, String> {
     println!("🦀 [Command] export_table_sql called for table: {}", table_name);
     
     let start_time = std::time::Instant::now();
-    let db = simple_db.lock().await;
+    let session = sessions.lock().await.get(session_id.as_deref())?;
+    let db = session.simple_db.lock().await;
     
     match db.export_table_as_sql(&table_name, schema_name.as_deref(), &sql_type).await {
         Ok(content) => {
@@ -551,102 +1449,29 @@ pub async fn export_table_csv_json(
     table_name: String,
     schema_name: Option<String>,
     export_options: ExportOptions,
-    simple_db: tauri::State<'_, Arc<Mutex<SimpleDatabase>>>,
+    session_id: Option<String>,
+    sessions: tauri::State<'_, SessionRegistryState>,
+    history: tauri::State<'_, QueryHistoryState>,
 ) -> Result<ExportResult, String> {
     println!("🦀 [Command] export_table_csv_json called for table: {} in format: {}", table_name, export_options.format);
     
     let start_time = std::time::Instant::now();
-    let db = simple_db.lock().await;
+    let session = sessions.lock().await.get(session_id.as_deref())?;
     
     // First, query the entire table
     let schema = schema_name.as_deref().unwrap_or("public");
-    let query = format!("SELECT * FROM \"{}\".\"{}\"", schema, table_name);
+    let query = format!("SELECT * FROM {}", qualified_name(schema, &table_name));
     
-    match db.execute_query(&query).await {
+    let result = session.simple_db.lock().await.execute_query(&query).await;
+    record_statement(&history, &session, &query, start_time, &result).await;
+    match result {
         Ok(query_result) => {
-            let execution_time = start_time.elapsed().as_millis() as u64;
-            
-            // Convert to the requested format
-            let content = match export_options.format.as_str() {
-                "CSV" => {
-                    let mut csv_content = String::new();
-                    
-                    // Add headers if requested
-                    if export_options.include_headers {
-                        csv_content.push_str(&query_result.columns.join(","));
-                        csv_content.push('\n');
-                    }
-                    
-                    // Add data rows
-                    for row in &query_result.rows {
-                        let row_strings: Vec<String> = row.iter().map(|value| {
-                            match value {
-                                serde_json::Value::Null => String::new(),
-                                serde_json::Value::String(s) => {
-                                    // Escape quotes and wrap in quotes if contains comma or quote
-                                    if s.contains(',') || s.contains('"') || s.contains('\n') {
-                                        format!("\"{}\"", s.replace("\"", "\"\""))
-                                    } else {
-                                        s.clone()
-                                    }
-                                },
-                                _ => value.to_string().replace("\"", "")
-                            }
-                        }).collect();
-                        csv_content.push_str(&row_strings.join(","));
-                        csv_content.push('\n');
-                    }
-                    
-                    csv_content
-                },
-                "JSON" => {
-                    if export_options.pretty_json {
-                        // Pretty formatted JSON
-                        let mut json_array = Vec::new();
-                        for row in &query_result.rows {
-                            let mut json_object = serde_json::Map::new();
-                            for (i, column_name) in query_result.columns.iter().enumerate() {
-                                json_object.insert(column_name.clone(), row[i].clone());
-                            }
-                            json_array.push(serde_json::Value::Object(json_object));
-                        }
-                        serde_json::to_string_pretty(&json_array).unwrap_or_else(|_| "[]".to_string())
-                    } else {
-                        // Compact JSON
-                        let mut json_array = Vec::new();
-                        for row in &query_result.rows {
-                            let mut json_object = serde_json::Map::new();
-                            for (i, column_name) in query_result.columns.iter().enumerate() {
-                                json_object.insert(column_name.clone(), row[i].clone());
-                            }
-                            json_array.push(serde_json::Value::Object(json_object));
-                        }
-                        serde_json::to_string(&json_array).unwrap_or_else(|_| "[]".to_string())
-                    }
-                },
-                _ => return Err(format!("Unsupported export format: {}", export_options.format))
-            };
-            
-            let size_bytes = content.len();
-            let row_count = query_result.rows.len();
-            
-            let filename = export_options.filename.unwrap_or_else(|| {
-                match export_options.format.as_str() {
-                    "CSV" => format!("{}.csv", table_name),
-                    "JSON" => format!("{}.json", table_name),
-                    _ => format!("{}.txt", table_name),
-                }
-            });
-            
-            println!("🦀 [Command] Table export completed: {} bytes, {} rows, {}ms", size_bytes, row_count, execution_time);
-            
-            Ok(ExportResult {
-                content,
-                filename,
-                size_bytes,
-                row_count,
-                format: export_options.format,
-            })
+            let export = render_export(&query_result, &export_options, &table_name)?;
+            println!(
+                "🦀 [Command] Table export completed: {} bytes, {} rows, {}ms",
+                export.size_bytes, export.row_count, start_time.elapsed().as_millis()
+            );
+            Ok(export)
         }
         Err(e) => {
             println!("🦀 [Command] Table export failed: {}", e);
@@ -655,17 +1480,198 @@ pub async fn export_table_csv_json(
     }
 }
 
+/// Run a query and export its result in memory
+#[tauri::command]
+pub async fn export_query_results(
+    query: String,
+    export_options: ExportOptions,
+    session_id: Option<String>,
+    sessions: tauri::State<'_, SessionRegistryState>,
+    history: tauri::State<'_, QueryHistoryState>,
+) -> Result<ExportResult, String> {
+    println!("🦀 [Command] export_query_results called in format: {}", export_options.format);
+    
+    let session = sessions.lock().await.get(session_id.as_deref())?;
+    let access = session.authorize(&GuardOperation::Sql { sql: query.clone() }, None).await?;
+    let started = Instant::now();
+    let result = {
+        let db = session.simple_db.lock().await;
+        db.execute_query_with_access(&query, &[], None, access).await
+    };
+    record_statement(&history, &session, &query, started, &result).await;
+    match result {
+        Ok(query_result) => render_export(&query_result, &export_options, "query_export"),
+        Err(e) => {
+            println!("🦀 [Command] Query export failed: {}", e);
+            Err(format!("Query export failed: {}", e))
+        }
+    }
+}
+
+/// Export a result the frontend already has
+#[tauri::command]
+pub async fn export_existing_results(
+    query_result: SimpleQueryResult,
+    export_options: ExportOptions,
+) -> Result<ExportResult, String> {
+    println!("🦀 [Command] export_existing_results called in format: {}", export_options.format);
+    render_export(&query_result, &export_options, "query_export")
+}
+
+/// Export a table straight to `file_path`, streaming it from the server.
+/// Progress and the final status arrive as `table-export-progress` events.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn start_table_export(
+    table_name: String,
+    schema_name: Option<String>,
+    file_path: String,
+    options: TableExportOptions,
+    session_id: Option<String>,
+    app: tauri::AppHandle,
+    sessions: tauri::State<'_, SessionRegistryState>,
+    exports: tauri::State<'_, TableExportRegistryState>,
+    history: tauri::State<'_, QueryHistoryState>,
+) -> Result<String, String> {
+    println!("🦀 [Command] start_table_export called for table: {} to {}", table_name, file_path);
+    
+    let session = sessions.lock().await.get(session_id.as_deref())?;
+    if !session.pool.lock().await.is_connected() {
+        return Err("Not connected to database".to_string());
+    }
+    
+    let (export_id, control) = exports.lock().await.register();
+    let source = ExportSource::Table {
+        schema: schema_name.unwrap_or_else(|| "public".to_string()),
+        table: table_name,
+    };
+    let sql = source.select_sql();
+    // Reading the table is all it does
+    spawn_export(
+        session,
+        &exports,
+        &history,
+        export_id.clone(),
+        source,
+        sql,
+        file_path,
+        options,
+        Access::ReadOnly,
+        control,
+        app,
+    );
+    
+    println!("🦀 [Command] Table export {} started", export_id);
+    Ok(export_id)
+}
+
+/// Export a query's result straight to `file_path`, like `start_table_export`
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn start_query_export(
+    query: String,
+    file_path: String,
+    options: TableExportOptions,
+    session_id: Option<String>,
+    app: tauri::AppHandle,
+    sessions: tauri::State<'_, SessionRegistryState>,
+    exports: tauri::State<'_, TableExportRegistryState>,
+    history: tauri::State<'_, QueryHistoryState>,
+) -> Result<String, String> {
+    println!("🦀 [Command] start_query_export called to {}", file_path);
+    
+    let session = sessions.lock().await.get(session_id.as_deref())?;
+    let access = session.authorize(&GuardOperation::Sql { sql: query.clone() }, None).await?;
+    if !session.pool.lock().await.is_connected() {
+        return Err("Not connected to database".to_string());
+    }
+    
+    let (export_id, control) = exports.lock().await.register();
+    let source = ExportSource::Query(query.clone());
+    spawn_export(
+        session,
+        &exports,
+        &history,
+        export_id.clone(),
+        source,
+        query,
+        file_path,
+        options,
+        access,
+        control,
+        app,
+    );
+    
+    println!("🦀 [Command] Query export {} started", export_id);
+    Ok(export_id)
+}
+
+/// Run an export in the background and record `sql` in the history once it ends
+#[allow(clippy::too_many_arguments)]
+fn spawn_export(
+    session: std::sync::Arc<DatabaseSession>,
+    exports: &TableExportRegistryState,
+    history: &QueryHistoryState,
+    export_id: String,
+    source: ExportSource,
+    sql: String,
+    file_path: String,
+    options: TableExportOptions,
+    access: Access,
+    control: ExportControl,
+    app: tauri::AppHandle,
+) {
+    let exports = exports.clone();
+    let history = history.clone();
+    tokio::spawn(async move {
+        let end = run_table_export(
+            session.clone(),
+            exports,
+            export_id,
+            source,
+            file_path,
+            options,
+            access,
+            control,
+            app,
+        )
+        .await;
+        let row_count = end.error.is_none().then_some(end.rows_written);
+        let entry = history_entry(&session, &sql, end.execution_time_ms, row_count, end.error).await;
+        record_history(&history, vec![entry]).await;
+    });
+}
+
+/// Stop a table export and delete its partial file
+#[tauri::command]
+pub async fn cancel_table_export(
+    export_id: String,
+    exports: tauri::State<'_, TableExportRegistryState>,
+) -> Result<bool, String> {
+    println!("🦀 [Command] cancel_table_export called for export: {}", export_id);
+    let control = exports.lock().await.take(&export_id);
+    match control {
+        Some(control) => {
+            control.cancel().await;
+            Ok(true)
+        }
+        None => Ok(false),
+    }
+}
+
 #[tauri::command]
 pub async fn export_query_result_sql(
     query_result: SimpleQueryResult,
     table_name: String,
     schema_name: Option<String>,
-    simple_db: tauri::State<'_, Arc<Mutex<SimpleDatabase>>>,
+    session_id: Option<String>,
+    sessions: tauri::State<'_, SessionRegistryState>,
 ) -> Result<ExportResult, String> {
     println!("🦀 [Command] export_query_result_sql called");
     
     let start_time = std::time::Instant::now();
-    let db = simple_db.lock().await;
+    let session = sessions.lock().await.get(session_id.as_deref())?;
+    let db = session.simple_db.lock().await;
     
     match db.export_query_result_as_sql(&query_result, &table_name, schema_name.as_deref()).await {
         Ok(content) => {
@@ -692,114 +1698,179 @@ pub async fn export_query_result_sql(
     }
 }
 
-#[tauri::command]
-pub async fn import_sql_file(
-    sql_content: String,
-    import_options: ImportOptions,
-    simple_db: tauri::State<'_, Arc<Mutex<SimpleDatabase>>>,
-) -> Result<ImportResult, String> {
-    println!("🦀 [Command] import_sql_file called");
-    
+/// Run an SQL import on a pooled connection and summarize it. The history
+/// entry shows `description` as its SQL.
+#[allow(clippy::too_many_arguments)]
+async fn run_sql_import<R: AsyncRead + Unpin>(
+    session: &DatabaseSession,
+    history: &QueryHistoryState,
+    description: &str,
+    reader: R,
+    total_bytes: Option<u64>,
+    import_options: &ImportOptions,
+    import_id: Option<String>,
+    app: &tauri::AppHandle,
+) -> ImportResult {
+    let result = import_sql_on_session(session, reader, total_bytes, import_options, import_id, app).await;
+    record_import(history, session, description, &result).await;
+    result
+}
+
+async fn import_sql_on_session<R: AsyncRead + Unpin>(
+    session: &DatabaseSession,
+    reader: R,
+    total_bytes: Option<u64>,
+    import_options: &ImportOptions,
+    import_id: Option<String>,
+    app: &tauri::AppHandle,
+) -> ImportResult {
     let start_time = std::time::Instant::now();
-    let db = simple_db.lock().await;
-    
-    let truncate_before = import_options.truncate_before_import.unwrap_or(false);
+    let import_id = import_id.unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
     
-    match db.import_sql_data(
-        &sql_content,
-        import_options.table_name.as_deref(),
-        import_options.schema_name.as_deref(),
-        truncate_before
-    ).await {
-        Ok((rows_imported, errors)) => {
+    match sql_import::import_sql_on_pool(session, reader, total_bytes, &import_options.sql_import_options(), &import_id, app).await {
+        Ok(summary) => {
             let execution_time = start_time.elapsed().as_millis() as u64;
-            let success = errors.is_empty();
+            println!(
+                "🦀 [Command] SQL import completed: {} statements, {} rows imported, {} errors, {}ms",
+                summary.statements_executed, summary.rows_imported, summary.errors.len(), execution_time
+            );
             
-            println!("🦀 [Command] SQL import completed: {} rows imported, {} errors, {}ms", rows_imported, errors.len(), execution_time);
-            
-            Ok(ImportResult {
-                success,
-                rows_imported,
-                errors,
-                warnings: Vec::new(), // Could add warnings in the future
+            ImportResult {
+                success: summary.errors.is_empty(),
+                rows_imported: summary.rows_imported as usize,
+                errors: summary.errors,
+                warnings: summary.warnings,
                 execution_time_ms: execution_time,
-            })
+                statements_executed: summary.statements_executed,
+                rolled_back: summary.rolled_back,
+                ..Default::default()
+            }
         }
         Err(e) => {
-            let execution_time = start_time.elapsed().as_millis() as u64;
             println!("🦀 [Command] SQL import failed: {}", e);
-            
-            Ok(ImportResult {
-                success: false,
-                rows_imported: 0,
-                errors: vec![e],
-                warnings: Vec::new(),
-                execution_time_ms: execution_time,
-            })
+            ImportResult::failed(e, start_time)
         }
     }
 }
 
+/// Import SQL text. Progress is reported through `sql-import-progress` events tagged
+/// with `import_id`.
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn import_sql_file(
+    sql_content: String,
+    import_options: ImportOptions,
+    import_id: Option<String>,
+    confirmation_token: Option<String>,
+    session_id: Option<String>,
+    app: tauri::AppHandle,
+    sessions: tauri::State<'_, SessionRegistryState>,
+    history: tauri::State<'_, QueryHistoryState>,
+) -> Result<ImportResult, String> {
+    println!("🦀 [Command] import_sql_file called");
+    
+    let session = sessions.lock().await.get(session_id.as_deref())?;
+    session.authorize(&GuardOperation::Import, confirmation_token.as_deref()).await?;
+    let total_bytes = Some(sql_content.len() as u64);
+    Ok(run_sql_import(&session, &history, &sql_content, sql_content.as_bytes(), total_bytes, &import_options, import_id, &app).await)
+}
+
+/// Import an SQL file (e.g. a pg_dump plain-text dump), streaming it from disk
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
 pub async fn import_sql_from_file(
     file_path: String,
     import_options: ImportOptions,
-    simple_db: tauri::State<'_, Arc<Mutex<SimpleDatabase>>>,
+    import_id: Option<String>,
+    confirmation_token: Option<String>,
+    session_id: Option<String>,
+    app: tauri::AppHandle,
+    sessions: tauri::State<'_, SessionRegistryState>,
+    history: tauri::State<'_, QueryHistoryState>,
 ) -> Result<ImportResult, String> {
     println!("🦀 [Command] import_sql_from_file called with path: {}", file_path);
     
+    let session = sessions.lock().await.get(session_id.as_deref())?;
+    session.authorize(&GuardOperation::Import, confirmation_token.as_deref()).await?;
     let start_time = std::time::Instant::now();
+    let description = format!("-- SQL import from {}", file_path);
+    let file = match tokio::fs::File::open(&file_path).await {
+        Ok(file) => file,
+        Err(e) => {
+            println!("🦀 [Command] Failed to open file {}: {}", file_path, e);
+            let result = ImportResult::failed(format!("Failed to read file: {}", e), start_time);
+            record_import(&history, &session, &description, &result).await;
+            return Ok(result);
+        }
+    };
+    let total_bytes = file.metadata().await.ok().map(|metadata| metadata.len());
+    Ok(run_sql_import(&session, &history, &description, file, total_bytes, &import_options, import_id, &app).await)
+}
+
+/// Import a CSV, JSON or NDJSON file into a table, creating it when asked to
+#[tauri::command]
+pub async fn import_table_data(
+    file_path: String,
+    import_options: ImportOptions,
+    confirmation_token: Option<String>,
+    session_id: Option<String>,
+    sessions: tauri::State<'_, SessionRegistryState>,
+    history: tauri::State<'_, QueryHistoryState>,
+) -> Result<ImportResult, String> {
+    println!("🦀 [Command] import_table_data called with path: {}", file_path);
     
-    // Read file content
-    let sql_content = match std::fs::read_to_string(&file_path) {
-        Ok(content) => content,
+    let session = sessions.lock().await.get(session_id.as_deref())?;
+    session.authorize(&GuardOperation::Import, confirmation_token.as_deref()).await?;
+    let start_time = std::time::Instant::now();
+    let request = match import_options.table_import_request() {
+        Ok(request) => request,
+        Err(e) => return Ok(ImportResult::failed(e, start_time)),
+    };
+    let description = format!("-- Import of {} into {}", file_path, qualified_name(&request.schema, &request.table));
+    let result = import_table_file(&session, &file_path, &request, start_time).await;
+    record_import(&history, &session, &description, &result).await;
+    Ok(result)
+}
+
+async fn import_table_file(
+    session: &DatabaseSession,
+    file_path: &str,
+    request: &TableImportRequest,
+    start_time: std::time::Instant,
+) -> ImportResult {
+    let bytes = match tokio::fs::read(file_path).await {
+        Ok(bytes) => bytes,
         Err(e) => {
-            let execution_time = start_time.elapsed().as_millis() as u64;
             println!("🦀 [Command] Failed to read file {}: {}", file_path, e);
-            return Ok(ImportResult {
-                success: false,
-                rows_imported: 0,
-                errors: vec![format!("Failed to read file: {}", e)],
-                warnings: Vec::new(),
-                execution_time_ms: execution_time,
-            });
+            return ImportResult::failed(format!("Failed to read file: {}", e), start_time);
         }
     };
     
-    let db = simple_db.lock().await;
-    let truncate_before = import_options.truncate_before_import.unwrap_or(false);
-    
-    match db.import_sql_data(
-        &sql_content,
-        import_options.table_name.as_deref(),
-        import_options.schema_name.as_deref(),
-        truncate_before
-    ).await {
-        Ok((rows_imported, errors)) => {
+    let db = session.simple_db.lock().await;
+    match db.import_table_data(&bytes, request).await {
+        Ok(summary) => {
             let execution_time = start_time.elapsed().as_millis() as u64;
-            let success = errors.is_empty();
-            
-            println!("🦀 [Command] SQL file import completed: {} rows imported, {} errors, {}ms", rows_imported, errors.len(), execution_time);
+            println!(
+                "🦀 [Command] Table import completed: {} rows imported, {} rows failed, {}ms",
+                summary.rows_imported, summary.rows_failed, execution_time
+            );
             
-            Ok(ImportResult {
-                success,
-                rows_imported,
-                errors,
-                warnings: Vec::new(),
+            ImportResult {
+                success: !summary.rolled_back,
+                rows_imported: summary.rows_imported as usize,
+                errors: Vec::new(),
+                warnings: summary.warnings,
                 execution_time_ms: execution_time,
-            })
+                statements_executed: 0,
+                rolled_back: summary.rolled_back,
+                rows_failed: summary.rows_failed,
+                row_errors: summary.row_errors,
+                created_table: summary.created_table,
+            }
         }
         Err(e) => {
-            let execution_time = start_time.elapsed().as_millis() as u64;
-            println!("🦀 [Command] SQL file import failed: {}", e);
-            
-            Ok(ImportResult {
-                success: false,
-                rows_imported: 0,
-                errors: vec![e],
-                warnings: Vec::new(),
-                execution_time_ms: execution_time,
-            })
+            println!("🦀 [Command] Table import failed: {}", e);
+            ImportResult::failed(e, start_time)
         }
     }
 }
@@ -830,11 +1901,13 @@ pub async fn save_export_to_file(
 pub async fn get_table_indexes(
     table_name: String,
     schema_name: Option<String>,
-    simple_db: tauri::State<'_, Arc<Mutex<SimpleDatabase>>>,
+    session_id: Option<String>,
+    sessions: tauri::State<'_, SessionRegistryState>,
 ) -> Result<Vec<IndexInfo>, String> {
     println!("🦀 [Command] get_table_indexes called for table: {}", table_name);
     
-    let db = simple_db.lock().await;
+    let session = sessions.lock().await.get(session_id.as_deref())?;
+    let db = session.simple_db.lock().await;
     match db.get_table_indexes(&table_name, schema_name.as_deref()).await {
         Ok(indexes) => {
             println!("🦀 [Command] Found {} indexes", indexes.len());
@@ -849,11 +1922,13 @@ pub async fn get_table_indexes(
 
 #[tauri::command]
 pub async fn get_all_indexes(
-    simple_db: tauri::State<'_, Arc<Mutex<SimpleDatabase>>>,
+    session_id: Option<String>,
+    sessions: tauri::State<'_, SessionRegistryState>,
 ) -> Result<Vec<IndexInfo>, String> {
     println!("🦀 [Command] get_all_indexes called");
     
-    let db = simple_db.lock().await;
+    let session = sessions.lock().await.get(session_id.as_deref())?;
+    let db = session.simple_db.lock().await;
     match db.get_all_indexes().await {
         Ok(indexes) => {
             println!("🦀 [Command] Found {} total indexes", indexes.len());
@@ -869,12 +1944,20 @@ pub async fn get_all_indexes(
 #[tauri::command]
 pub async fn create_index(
     options: CreateIndexOptions,
-    simple_db: tauri::State<'_, Arc<Mutex<SimpleDatabase>>>,
+    confirmation_token: Option<String>,
+    session_id: Option<String>,
+    sessions: tauri::State<'_, SessionRegistryState>,
+    history: tauri::State<'_, QueryHistoryState>,
 ) -> Result<String, String> {
     println!("🦀 [Command] create_index called for index: {}", options.name);
     
-    let db = simple_db.lock().await;
-    match db.create_index(&options).await {
+    let session = sessions.lock().await.get(session_id.as_deref())?;
+    session.authorize(&GuardOperation::CreateIndex, confirmation_token.as_deref()).await?;
+    let started = Instant::now();
+    let result = session.simple_db.lock().await.create_index(&options).await;
+    let entry = history_entry(&session, &SimpleDatabase::create_index_sql(&options), started.elapsed().as_millis() as u64, None, result.as_ref().err().cloned()).await;
+    record_history(&history, vec![entry]).await;
+    match result {
         Ok(message) => {
             println!("🦀 [Command] Index created successfully: {}", message);
             Ok(message)
@@ -890,12 +1973,21 @@ pub async fn create_index(
 pub async fn drop_index(
     index_name: String,
     schema_name: Option<String>,
-    simple_db: tauri::State<'_, Arc<Mutex<SimpleDatabase>>>,
+    confirmation_token: Option<String>,
+    session_id: Option<String>,
+    sessions: tauri::State<'_, SessionRegistryState>,
+    history: tauri::State<'_, QueryHistoryState>,
 ) -> Result<String, String> {
     println!("🦀 [Command] drop_index called for index: {}", index_name);
     
-    let db = simple_db.lock().await;
-    match db.drop_index(&index_name, schema_name.as_deref()).await {
+    let session = sessions.lock().await.get(session_id.as_deref())?;
+    session.authorize(&GuardOperation::DropIndex, confirmation_token.as_deref()).await?;
+    let started = Instant::now();
+    let result = session.simple_db.lock().await.drop_index(&index_name, schema_name.as_deref()).await;
+    let sql = SimpleDatabase::drop_index_sql(&index_name, schema_name.as_deref());
+    let entry = history_entry(&session, &sql, started.elapsed().as_millis() as u64, None, result.as_ref().err().cloned()).await;
+    record_history(&history, vec![entry]).await;
+    match result {
         Ok(message) => {
             println!("🦀 [Command] Index dropped successfully: {}", message);
             Ok(message)
@@ -910,11 +2002,13 @@ pub async fn drop_index(
 // Views management commands
 #[tauri::command]
 pub async fn get_views(
-    simple_db: tauri::State<'_, Arc<Mutex<SimpleDatabase>>>,
+    session_id: Option<String>,
+    sessions: tauri::State<'_, SessionRegistryState>,
 ) -> Result<Vec<ViewInfo>, String> {
     println!("🦀 [Command] get_views called");
     
-    let db = simple_db.lock().await;
+    let session = sessions.lock().await.get(session_id.as_deref())?;
+    let db = session.simple_db.lock().await;
     match db.get_views().await {
         Ok(views) => {
             println!("🦀 [Command] Found {} views", views.len());
@@ -930,11 +2024,13 @@ pub async fn get_views(
 // Stored procedures management commands
 #[tauri::command]
 pub async fn get_stored_procedures(
-    simple_db: tauri::State<'_, Arc<Mutex<SimpleDatabase>>>,
+    session_id: Option<String>,
+    sessions: tauri::State<'_, SessionRegistryState>,
 ) -> Result<Vec<StoredProcedureInfo>, String> {
     println!("🦀 [Command] get_stored_procedures called");
     
-    let db = simple_db.lock().await;
+    let session = sessions.lock().await.get(session_id.as_deref())?;
+    let db = session.simple_db.lock().await;
     match db.get_stored_procedures().await {
         Ok(procedures) => {
             println!("🦀 [Command] Found {} stored procedures", procedures.len());
@@ -947,13 +2043,47 @@ pub async fn get_stored_procedures(
     }
 }
 
+/// Diff the schemas of two sessions and plan the migration that makes the target match the
+/// source. `schemas` limits the comparison; by default every user schema is compared.
+#[tauri::command]
+pub async fn compare_schemas(
+    source_session_id: String,
+    target_session_id: String,
+    schemas: Option<Vec<String>>,
+    sessions: tauri::State<'_, SessionRegistryState>,
+) -> Result<SchemaDiff, String> {
+    println!("🦀 [Command] compare_schemas called: {} -> {}", source_session_id, target_session_id);
+    
+    let (source, target) = {
+        let mut registry = sessions.lock().await;
+        (registry.get(Some(&source_session_id))?, registry.get(Some(&target_session_id))?)
+    };
+    // One session at a time, so comparing a session with itself cannot deadlock
+    let source = {
+        let db = source.simple_db.lock().await;
+        SchemaSnapshot::capture(&db, schemas.as_deref()).await
+            .map_err(|e| format!("Failed to read source schema: {}", e))?
+    };
+    let target = {
+        let db = target.simple_db.lock().await;
+        SchemaSnapshot::capture(&db, schemas.as_deref()).await
+            .map_err(|e| format!("Failed to read target schema: {}", e))?
+    };
+    
+    let diff = diff_schemas(&source, &target);
+    println!("🦀 [Command] Found {} schema changes, {} migration steps", diff.changes.len(), diff.migration.len());
+    Ok(diff)
+}
+
 #[tauri::command]
 pub async fn get_materialized_views(
-    simple_db: tauri::State<'_, Arc<Mutex<SimpleDatabase>>>,
+    session_id: Option<String>,
+    sessions: tauri::State<'_, SessionRegistryState>,
 ) -> Result<Vec<MaterializedViewInfo>, String> {
     println!("🦀 [Command] get_materialized_views called");
     
-    let db = simple_db.lock().await;
+    let session = sessions.lock().await.get(session_id.as_deref())?;
+    let db = session.simple_db.lock().await;
     match db.get_materialized_views().await {
         Ok(materialized_views) => {
             println!("🦀 [Command] Found {} materialized views", materialized_views.len());
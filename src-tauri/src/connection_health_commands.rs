@@ -4,6 +4,7 @@ use crate::connection_health_service::{
 };
 use crate::connection_profile::{AdvancedConnectionConfig, ConnectionProfile, HealthCheckResult};
 use crate::credential_vault::CredentialVault;
+use crate::ssh_tunnel::ssh_secret_from_vault;
 use std::sync::Arc;
 use tauri::State;
 use tokio::sync::Mutex;
@@ -35,12 +36,13 @@ pub async fn test_connection_profile(
         .retrieve_credentials(&profile.id)
         .await
         .map_err(|e| format!("Failed to retrieve credentials: {}", e))?;
+    let ssh_secret = ssh_secret_from_vault(&vault, &profile.id, &profile.config).await?;
 
     drop(vault); // Release the vault lock
 
     let service = health_service.lock().await;
     let result = service
-        .test_profile_connection(&profile, &credentials.password, options)
+        .test_profile_connection(&profile, &credentials.password, ssh_secret.as_deref(), options)
         .await;
     Ok(result)
 }
@@ -68,11 +70,12 @@ pub async fn test_connection_by_profile_id(
         .retrieve_credentials(&profile_id)
         .await
         .map_err(|e| format!("Failed to retrieve credentials: {}", e))?;
+    let ssh_secret = ssh_secret_from_vault(&vault, &profile_id, &profile.config).await?;
     drop(vault);
 
     let service = health_service.lock().await;
     let result = service
-        .test_profile_connection(&profile, &credentials.password, options)
+        .test_profile_connection(&profile, &credentials.password, ssh_secret.as_deref(), options)
         .await;
     Ok(result)
 }
@@ -163,10 +166,15 @@ pub async fn batch_test_profiles(
 
         // Retrieve password from credential vault
         let vault = credential_vault.lock().await;
-        let credentials_result = vault.retrieve_credentials(&profile_id).await;
+        let credentials_result = match vault.retrieve_credentials(&profile_id).await {
+            Ok(c) => ssh_secret_from_vault(&vault, &profile_id, &profile.config)
+                .await
+                .map(|ssh_secret| (c, ssh_secret)),
+            Err(e) => Err(format!("Failed to retrieve credentials: {}", e)),
+        };
         drop(vault);
 
-        let credentials = match credentials_result {
+        let (credentials, ssh_secret) = match credentials_result {
             Ok(c) => c,
             Err(e) => {
                 results.push((
@@ -174,7 +182,7 @@ pub async fn batch_test_profiles(
                     ConnectionTestResult {
                         success: false,
                         response_time_ms: None,
-                        error_message: Some(format!("Failed to retrieve credentials: {}", e)),
+                        error_message: Some(e),
                         error_code: Some("CREDENTIALS_NOT_FOUND".to_string()),
                         server_version: None,
                         database_name: None,
@@ -189,7 +197,7 @@ pub async fn batch_test_profiles(
         // Test the connection
         let service = health_service.lock().await;
         let result = service
-            .test_profile_connection(&profile, &credentials.password, options.clone())
+            .test_profile_connection(&profile, &credentials.password, ssh_secret.as_deref(), options.clone())
             .await;
         drop(service);
 
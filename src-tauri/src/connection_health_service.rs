@@ -1,6 +1,6 @@
 use crate::connection_profile::{
     AdvancedConnectionConfig, ConnectionHealth, HealthCheckResult, HealthStatus,
-    ConnectionProfile
+    ConnectionProfile, SshAuthMethod
 };
 use chrono::Utc;
 use serde::{Deserialize, Serialize};
@@ -8,7 +8,7 @@ use std::collections::HashMap;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 use tokio::sync::Mutex;
-use tokio_postgres::{Client, NoTls, Error as PostgresError};
+use tokio_postgres::Client;
 
 /// Connection testing and validation service
 pub struct ConnectionHealthService {
@@ -53,6 +53,7 @@ pub enum ConnectionValidationError {
     InvalidSSLConfig(String),
     InvalidTimeout(String),
     InvalidCustomParameter(String, String),
+    InvalidSshTunnel(String),
 }
 
 /// Connection test options
@@ -94,6 +95,18 @@ impl ConnectionHealthService {
         config: &AdvancedConnectionConfig,
         password: &str,
         options: Option<ConnectionTestOptions>,
+    ) -> ConnectionTestResult {
+        self.test_connection_with_ssh_secret(config, password, None, options).await
+    }
+
+    /// Test a connection, opening the profile's SSH tunnel with `ssh_secret`
+    /// (password or key passphrase) when one is configured
+    pub async fn test_connection_with_ssh_secret(
+        &self,
+        config: &AdvancedConnectionConfig,
+        password: &str,
+        ssh_secret: Option<&str>,
+        options: Option<ConnectionTestOptions>,
     ) -> ConnectionTestResult {
         let options = options.unwrap_or_default();
         let start_time = Instant::now();
@@ -117,7 +130,7 @@ impl ConnectionHealthService {
         let retry_delay = Duration::from_millis(options.retry_delay_ms.unwrap_or(1000));
 
         for attempt in 1..=retry_attempts {
-            match self.attempt_connection(config, password, &options).await {
+            match self.attempt_connection(config, password, ssh_secret, &options).await {
                 Ok(result) => {
                     let response_time = start_time.elapsed().as_millis() as u64;
                     return ConnectionTestResult {
@@ -167,14 +180,17 @@ impl ConnectionHealthService {
         }
     }
 
-    /// Test connection for a profile
+    /// Test connection for a profile. `ssh_secret` is used when the profile goes through an SSH tunnel.
     pub async fn test_profile_connection(
         &self,
         profile: &ConnectionProfile,
         password: &str,
+        ssh_secret: Option<&str>,
         options: Option<ConnectionTestOptions>,
     ) -> ConnectionTestResult {
-        let result = self.test_connection(&profile.config, password, options).await;
+        let result = self
+            .test_connection_with_ssh_secret(&profile.config, password, ssh_secret, options)
+            .await;
         
         // Store the result in health history
         let health_result = HealthCheckResult {
@@ -281,6 +297,32 @@ impl ConnectionHealthService {
             }
         }
 
+        // Validate SSH tunnel
+        if let Some(tunnel) = &config.ssh_tunnel {
+            if tunnel.host.trim().is_empty() {
+                errors.push(ConnectionValidationError::InvalidSshTunnel(
+                    "SSH host cannot be empty".to_string(),
+                ));
+            }
+            if tunnel.username.trim().is_empty() {
+                errors.push(ConnectionValidationError::InvalidSshTunnel(
+                    "SSH username cannot be empty".to_string(),
+                ));
+            }
+            if let SshAuthMethod::PrivateKey { key_path } = &tunnel.auth {
+                if !std::path::Path::new(key_path).exists() {
+                    errors.push(ConnectionValidationError::InvalidSshTunnel(
+                        format!("SSH private key file not found: {}", key_path),
+                    ));
+                }
+            }
+            if config.connection_string_template.is_some() {
+                errors.push(ConnectionValidationError::InvalidSshTunnel(
+                    "SSH tunnels cannot be combined with a connection string template".to_string(),
+                ));
+            }
+        }
+
         // Validate custom parameters
         for (key, value) in &config.custom_parameters {
             if key.trim().is_empty() {
@@ -356,13 +398,19 @@ impl ConnectionHealthService {
         &self,
         config: &AdvancedConnectionConfig,
         password: &str,
+        ssh_secret: Option<&str>,
         options: &ConnectionTestOptions,
-    ) -> Result<ConnectionSuccessResult, PostgresError> {
-        let connection_string = config.to_connection_string(password);
+    ) -> Result<ConnectionSuccessResult, String> {
+        // The tunnel lives until the end of the attempt
+        let tunnel = crate::ssh_tunnel::open_for_config(config, ssh_secret).await?;
+        let pg_config = match &tunnel {
+            Some(tunnel) => config.to_tunneled_pg_config(password, tunnel.local_addr())?,
+            None => config.to_pg_config(password)?,
+        };
+        let tls = crate::tls_config::make_tls_connector(&config.ssl_config)?;
         
-        // Attempt connection without timeout for now
-        // TODO: Implement proper timeout handling in a future version
-        let (client, connection) = tokio_postgres::connect(&connection_string, NoTls).await?;
+        // The connect timeout comes from the profile's connection_timeout
+        let (client, connection) = pg_config.connect(tls).await.map_err(|e| e.to_string())?;
         
         // Spawn the connection task
         tokio::spawn(async move {
@@ -379,6 +427,7 @@ impl ConnectionHealthService {
 
         // Get server information
         let server_version = self.get_server_version(&client).await;
+        let ssl_used = crate::tls_config::ssl_in_use(&client).await;
         
         Ok(ConnectionSuccessResult {
             server_version,
@@ -387,7 +436,7 @@ impl ConnectionHealthService {
                 port: config.port,
                 database: config.database.clone(),
                 username: config.username.clone(),
-                ssl_used: false, // TODO: Detect actual SSL usage
+                ssl_used,
                 server_encoding: None, // TODO: Get actual encoding
                 client_encoding: None, // TODO: Get actual encoding
             },
@@ -413,11 +462,16 @@ impl ConnectionHealthService {
     }
 
     /// Analyze connection error and provide troubleshooting hints
-    fn analyze_connection_error(&self, error: &PostgresError) -> (String, Vec<String>) {
+    fn analyze_connection_error<E: std::fmt::Display + ?Sized>(&self, error: &E) -> (String, Vec<String>) {
         let error_str = error.to_string().to_lowercase();
         let mut hints = Vec::new();
 
-        let error_code = if error_str.contains("connection refused") {
+        let error_code = if error_str.contains("ssh") {
+            hints.push("Check that the SSH bastion is reachable and the credentials are correct".to_string());
+            hints.push("Verify the bastion's host key in known_hosts".to_string());
+            hints.push("Check that the bastion can reach the database host and port".to_string());
+            "SSH_TUNNEL_ERROR"
+        } else if error_str.contains("connection refused") {
             hints.push("Check if PostgreSQL server is running".to_string());
             hints.push("Verify the host and port are correct".to_string());
             hints.push("Check firewall settings".to_string());
@@ -484,6 +538,9 @@ impl ConnectionHealthService {
                 ConnectionValidationError::InvalidCustomParameter(_, _) => {
                     hints.push("Check custom parameter names and values".to_string());
                 }
+                ConnectionValidationError::InvalidSshTunnel(_) => {
+                    hints.push("Check the SSH bastion host, username and private key path".to_string());
+                }
             }
         }
 
@@ -501,6 +558,7 @@ struct ConnectionSuccessResult {
 mod tests {
     use super::*;
     use crate::connection_profile::{SSLConfig, SSLMode, Environment, ConnectionMetadata};
+    use tokio_postgres::Error as PostgresError;
 
     fn create_test_config() -> AdvancedConnectionConfig {
         AdvancedConnectionConfig {
@@ -522,6 +580,7 @@ mod tests {
             },
             custom_parameters: std::collections::HashMap::new(),
             connection_string_template: None,
+            ssh_tunnel: None,
         }
     }
 
@@ -27,6 +27,7 @@ mod tests {
             },
             custom_parameters: std::collections::HashMap::new(),
             connection_string_template: None,
+            ssh_tunnel: None,
         }
     }
 
@@ -426,7 +427,7 @@ mod tests {
         let service = ConnectionHealthService::new();
         let profile = create_test_profile();
 
-        let result = service.test_profile_connection(&profile, "password", None).await;
+        let result = service.test_profile_connection(&profile, "password", None, None).await;
         // This would test against a real database and store history
         // The result depends on whether a real database is available
     }
@@ -1,9 +1,9 @@
 use std::sync::Arc;
 use tokio::sync::Mutex;
-use deadpool_postgres::{Config, Pool, Runtime};
+use deadpool_postgres::{Manager, ManagerConfig, Pool, RecyclingMethod, Runtime};
 use postgres_native_tls::MakeTlsConnector;
 use serde::{Deserialize, Serialize};
-use url::Url;
+use std::time::Duration;
 
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -26,6 +26,8 @@ impl Default for PoolConfig {
 pub struct ConnectionPool {
     pool: Arc<Mutex<Option<Pool>>>,
     connection_string: Option<String>,
+    /// Connector the pool's connections were opened with, needed to cancel their statements
+    tls: Option<MakeTlsConnector>,
     config: PoolConfig,
 }
 
@@ -35,6 +37,7 @@ impl ConnectionPool {
         Self {
             pool: Arc::new(Mutex::new(None)),
             connection_string: None,
+            tls: None,
             config,
         }
     }
@@ -42,38 +45,40 @@ impl ConnectionPool {
     pub async fn initialize(&mut self, connection_string: String) -> Result<(), String> {
         println!("🦀 [ConnectionPool] Initializing connection pool...");
         
-        // Create deadpool config
-        let mut pool_config = Config::new();
-        
-        // Parse the connection string manually
-        let url = Url::parse(&connection_string)
+        let pg_config = connection_string
+            .parse::<tokio_postgres::Config>()
             .map_err(|e| format!("Failed to parse connection string: {}", e))?;
         
-        pool_config.host = url.host_str().map(|s| s.to_string());
-        pool_config.port = url.port();
-        pool_config.dbname = Some(url.path().trim_start_matches('/').to_string());
-        pool_config.user = Some(url.username().to_string());
-        pool_config.password = url.password().map(|s| s.to_string());
-
-        // Set pool size - use default configuration and only set what we need
-        let mut pool_cfg = deadpool_postgres::PoolConfig::default();
-        pool_cfg.max_size = self.config.max_size;
-        pool_cfg.timeouts.wait = Some(std::time::Duration::from_secs(self.config.connection_timeout_secs));
-        pool_cfg.timeouts.create = Some(std::time::Duration::from_secs(self.config.connection_timeout_secs));
-        pool_cfg.timeouts.recycle = Some(std::time::Duration::from_secs(self.config.idle_timeout_secs));
-        
-        pool_config.pool = Some(pool_cfg);
+        let tls = crate::tls_config::default_tls_connector()?;
+        self.initialize_with_config(pg_config, tls).await?;
+        self.connection_string = Some(connection_string);
+        Ok(())
+    }
 
-        // Create TLS connector for SSL connections (required by Neon)
-        println!("🦀 [ConnectionPool] Creating TLS connector for SSL connections...");
-        let connector = native_tls::TlsConnector::builder()
-            .danger_accept_invalid_certs(false)
-            .build()
-            .map_err(|e| format!("TLS setup failed: {}", e))?;
-        let tls = MakeTlsConnector::new(connector);
+    /// Initialize the pool from an already-built configuration and TLS connector (e.g. from a saved profile)
+    pub async fn initialize_with_config(
+        &mut self,
+        pg_config: tokio_postgres::Config,
+        tls: MakeTlsConnector,
+    ) -> Result<(), String> {
+        let manager = Manager::from_config(
+            pg_config,
+            tls.clone(),
+            ManagerConfig {
+                recycling_method: RecyclingMethod::Fast,
+            },
+        );
 
         // Create the pool
-        match pool_config.create_pool(Some(Runtime::Tokio1), tls) {
+        let pool_result = Pool::builder(manager)
+            .max_size(self.config.max_size)
+            .wait_timeout(Some(Duration::from_secs(self.config.connection_timeout_secs)))
+            .create_timeout(Some(Duration::from_secs(self.config.connection_timeout_secs)))
+            .recycle_timeout(Some(Duration::from_secs(self.config.idle_timeout_secs)))
+            .runtime(Runtime::Tokio1)
+            .build();
+
+        match pool_result {
             Ok(pool) => {
                 println!("🦀 [ConnectionPool] Connection pool created successfully");
                 
@@ -91,7 +96,8 @@ impl ConnectionPool {
 
                 let mut pool_guard = self.pool.lock().await;
                 *pool_guard = Some(pool);
-                self.connection_string = Some(connection_string);
+                self.connection_string = None;
+                self.tls = Some(tls);
                 
                 println!("🦀 [ConnectionPool] Connection pool initialized successfully");
                 Ok(())
@@ -108,6 +114,7 @@ impl ConnectionPool {
         let mut pool_guard = self.pool.lock().await;
         *pool_guard = None;
         self.connection_string = None;
+        self.tls = None;
         println!("🦀 [ConnectionPool] Connection pool disconnected successfully");
         Ok(())
     }
@@ -148,6 +155,10 @@ impl ConnectionPool {
         }
     }
 
+    pub fn tls_connector(&self) -> Option<MakeTlsConnector> {
+        self.tls.clone()
+    }
+
     pub async fn get_pool_status(&self) -> Result<PoolStatus, String> {
         let pool_guard = self.pool.lock().await;
         match pool_guard.as_ref() {
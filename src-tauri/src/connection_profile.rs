@@ -1,6 +1,7 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::net::SocketAddr;
 use std::time::Duration;
 
 /// Core connection profile containing all information needed to manage a database connection
@@ -48,6 +49,10 @@ pub struct AdvancedConnectionConfig {
     
     // Connection string template
     pub connection_string_template: Option<String>,
+    
+    // SSH tunnel through a bastion host
+    #[serde(default)]
+    pub ssh_tunnel: Option<SshTunnelConfig>,
 }
 
 /// SSL/TLS configuration options
@@ -59,6 +64,38 @@ pub struct SSLConfig {
     pub ca: Option<String>,
 }
 
+/// SSH bastion used to reach the database host through a local port forward
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SshTunnelConfig {
+    pub host: String,
+    #[serde(default = "default_ssh_port")]
+    pub port: u16,
+    pub username: String,
+    pub auth: SshAuthMethod,
+    /// Local port to listen on; a free port is picked when unset
+    #[serde(default)]
+    pub local_port: Option<u16>,
+    /// Reject hosts missing from known_hosts instead of trusting them on first use
+    #[serde(default)]
+    pub strict_host_key_checking: bool,
+    /// known_hosts file to use instead of `~/.ssh/known_hosts`
+    #[serde(default)]
+    pub known_hosts_path: Option<String>,
+}
+
+fn default_ssh_port() -> u16 {
+    22
+}
+
+/// How to authenticate against the SSH bastion. Passwords and key passphrases
+/// are kept in the credential vault, never in the profile.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SshAuthMethod {
+    Password,
+    PrivateKey { key_path: String },
+}
+
 /// SSL modes supported by PostgreSQL
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum SSLMode {
@@ -256,6 +293,7 @@ impl Default for AdvancedConnectionConfig {
             ssl_config: SSLConfig::default(),
             custom_parameters: HashMap::new(),
             connection_string_template: None,
+            ssh_tunnel: None,
         }
     }
 }
@@ -359,6 +397,81 @@ impl AdvancedConnectionConfig {
             conn_str
         }
     }
+
+    /// Build a `tokio_postgres::Config` without going through a URL, so passwords
+    /// containing URL-reserved characters need no escaping.
+    pub fn to_pg_config(&self, password: &str) -> Result<tokio_postgres::Config, String> {
+        self.build_pg_config(password, None)
+    }
+
+    /// Like [`to_pg_config`](Self::to_pg_config), but connect through a local SSH
+    /// port forward. The original host name is kept for TLS verification.
+    pub fn to_tunneled_pg_config(
+        &self,
+        password: &str,
+        tunnel_addr: SocketAddr,
+    ) -> Result<tokio_postgres::Config, String> {
+        self.build_pg_config(password, Some(tunnel_addr))
+    }
+
+    fn build_pg_config(
+        &self,
+        password: &str,
+        tunnel_addr: Option<SocketAddr>,
+    ) -> Result<tokio_postgres::Config, String> {
+        if self.connection_string_template.is_some() {
+            if tunnel_addr.is_some() {
+                return Err("SSH tunnels cannot be combined with a connection string template".to_string());
+            }
+            return self
+                .to_connection_string(password)
+                .parse::<tokio_postgres::Config>()
+                .map_err(|e| format!("Invalid connection string template: {}", e));
+        }
+
+        let port = tunnel_addr.map(|addr| addr.port()).unwrap_or(self.port);
+        let mut params = vec![
+            ("host".to_string(), self.host.clone()),
+            ("port".to_string(), port.to_string()),
+            ("dbname".to_string(), self.database.clone()),
+            ("user".to_string(), self.username.clone()),
+            ("password".to_string(), password.to_string()),
+            ("connect_timeout".to_string(), self.connection_timeout.as_secs().to_string()),
+        ];
+        if let Some(addr) = tunnel_addr {
+            params.push(("hostaddr".to_string(), addr.ip().to_string()));
+        }
+        // Enforce query_timeout on the server; user-supplied options come last so they win
+        let mut options = format!("-c statement_timeout={}", self.query_timeout.as_millis());
+        let mut custom: Vec<_> = self.custom_parameters.iter().collect();
+        custom.sort();
+        for (key, value) in custom {
+            if key == "options" {
+                options.push(' ');
+                options.push_str(value);
+            } else {
+                params.push((key.clone(), value.clone()));
+            }
+        }
+        params.push(("options".to_string(), options));
+
+        params
+            .iter()
+            .map(|(key, value)| format!("{}={}", key, quote_conninfo_value(value)))
+            .collect::<Vec<_>>()
+            .join(" ")
+            .parse::<tokio_postgres::Config>()
+            .map(|mut config| {
+                config.ssl_mode(crate::tls_config::pg_ssl_mode(&self.ssl_config.mode));
+                config
+            })
+            .map_err(|e| format!("Invalid connection parameters: {}", e))
+    }
+}
+
+/// Quote a value for a libpq key/value connection string
+fn quote_conninfo_value(value: &str) -> String {
+    format!("'{}'", value.replace('\\', "\\\\").replace('\'', "\\'"))
 }
 
 impl SSLMode {
@@ -455,4 +568,57 @@ mod tests {
         assert_eq!(config.connection_timeout, deserialized.connection_timeout);
         assert_eq!(config.query_timeout, deserialized.query_timeout);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_to_pg_config_keeps_special_characters() {
+        let config = AdvancedConnectionConfig {
+            host: "db.example.com".to_string(),
+            port: 6543,
+            database: "app db".to_string(),
+            username: "admin".to_string(),
+            ..Default::default()
+        };
+
+        let pg_config = config.to_pg_config("p@ss:w'o\\rd/?").unwrap();
+        assert_eq!(pg_config.get_user(), Some("admin"));
+        assert_eq!(pg_config.get_dbname(), Some("app db"));
+        assert_eq!(pg_config.get_ports(), &[6543]);
+        assert_eq!(pg_config.get_password(), Some("p@ss:w'o\\rd/?".as_bytes()));
+        assert_eq!(pg_config.get_connect_timeout(), Some(&config.connection_timeout));
+        assert_eq!(pg_config.get_options(), Some("-c statement_timeout=300000"));
+    }
+
+    #[test]
+    fn test_tunneled_pg_config_targets_local_forward() {
+        let config = AdvancedConnectionConfig {
+            host: "db.internal".to_string(),
+            port: 5432,
+            ..Default::default()
+        };
+
+        let tunnel_addr: SocketAddr = "127.0.0.1:40123".parse().unwrap();
+        let pg_config = config.to_tunneled_pg_config("secret", tunnel_addr).unwrap();
+        assert_eq!(pg_config.get_ports(), &[40123]);
+        assert_eq!(pg_config.get_hostaddrs(), &[tunnel_addr.ip()]);
+        assert_eq!(
+            pg_config.get_hosts(),
+            &[tokio_postgres::config::Host::Tcp("db.internal".to_string())]
+        );
+
+        let templated = AdvancedConnectionConfig {
+            connection_string_template: Some("host={host} port={port}".to_string()),
+            ..config
+        };
+        assert!(templated.to_tunneled_pg_config("secret", tunnel_addr).is_err());
+    }
+
+    #[test]
+    fn test_ssh_tunnel_config_defaults() {
+        let json = r#"{"host":"bastion","username":"deploy","auth":{"type":"private_key","key_path":"/keys/id_ed25519"}}"#;
+        let tunnel: SshTunnelConfig = serde_json::from_str(json).unwrap();
+        assert_eq!(tunnel.port, 22);
+        assert!(tunnel.local_port.is_none());
+        assert!(!tunnel.strict_host_key_checking);
+        assert!(matches!(tunnel.auth, SshAuthMethod::PrivateKey { ref key_path } if key_path == "/keys/id_ed25519"));
+    }
+}
@@ -24,6 +24,7 @@ pub async fn create_sample_connection_profile() -> Result<ConnectionProfile, Str
         },
         custom_parameters: HashMap::new(),
         connection_string_template: None,
+        ssh_tunnel: None,
     };
 
     let profile = ConnectionProfile::new("Sample Connection".to_string(), config);
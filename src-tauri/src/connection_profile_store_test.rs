@@ -22,6 +22,7 @@ mod tests {
             ssl_config: SSLConfig::default(),
             custom_parameters: std::collections::HashMap::new(),
             connection_string_template: None,
+            ssh_tunnel: None,
         };
         ConnectionProfile::new(name.to_string(), config)
     }
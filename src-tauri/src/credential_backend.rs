@@ -0,0 +1,297 @@
+use crate::credential_vault::{decrypt_bytes, encrypt_bytes, EncryptedCredentials, VaultError};
+use argon2::Argon2;
+use base64::{engine::general_purpose, Engine as _};
+use keyring::Entry;
+use rand::{rngs::OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use zeroize::Zeroize;
+
+/// Storage for the vault's named entries (master key, index, encrypted credentials)
+pub trait CredentialBackend: Send + Sync {
+    /// Short name used in logs and status messages
+    fn name(&self) -> &'static str;
+
+    /// Read an entry, returning `None` when it does not exist
+    fn get(&self, key: &str) -> Result<Option<String>, VaultError>;
+
+    /// Create or replace an entry
+    fn set(&self, key: &str, value: &str) -> Result<(), VaultError>;
+
+    /// Delete an entry, returning `false` when it did not exist
+    fn delete(&self, key: &str) -> Result<bool, VaultError>;
+}
+
+/// Backend storing each entry in the OS keyring
+pub struct KeyringBackend {
+    service_name: String,
+}
+
+impl KeyringBackend {
+    pub fn new(service_name: &str) -> Self {
+        Self {
+            service_name: service_name.to_string(),
+        }
+    }
+
+    /// Check whether the OS keyring can be reached (e.g. a Secret Service daemon is running)
+    pub fn is_available(&self) -> bool {
+        match Entry::new(&self.service_name, "availability_probe") {
+            Ok(entry) => matches!(entry.get_password(), Ok(_) | Err(keyring::Error::NoEntry)),
+            Err(_) => false,
+        }
+    }
+}
+
+impl CredentialBackend for KeyringBackend {
+    fn name(&self) -> &'static str {
+        "keyring"
+    }
+
+    fn get(&self, key: &str) -> Result<Option<String>, VaultError> {
+        let entry = Entry::new(&self.service_name, key)?;
+        match entry.get_password() {
+            Ok(value) => Ok(Some(value)),
+            Err(keyring::Error::NoEntry) => Ok(None),
+            Err(e) => Err(VaultError::KeyringError(e)),
+        }
+    }
+
+    fn set(&self, key: &str, value: &str) -> Result<(), VaultError> {
+        let entry = Entry::new(&self.service_name, key)?;
+        entry.set_password(value)?;
+        Ok(())
+    }
+
+    fn delete(&self, key: &str) -> Result<bool, VaultError> {
+        let entry = Entry::new(&self.service_name, key)?;
+        match entry.delete_password() {
+            Ok(_) => Ok(true),
+            Err(keyring::Error::NoEntry) => Ok(false),
+            Err(e) => Err(VaultError::KeyringError(e)),
+        }
+    }
+}
+
+/// On-disk layout of the encrypted vault file
+#[derive(Debug, Serialize, Deserialize)]
+struct VaultFile {
+    version: u32,
+    /// Base64-encoded Argon2id salt
+    salt: String,
+    payload: EncryptedCredentials,
+}
+
+const VAULT_FILE_VERSION: u32 = 1;
+
+/// Backend storing all entries in a single file encrypted with a passphrase-derived key
+pub struct EncryptedFileBackend {
+    path: PathBuf,
+    salt: [u8; 16],
+    key: [u8; 32],
+    entries: Mutex<BTreeMap<String, String>>,
+}
+
+impl EncryptedFileBackend {
+    /// Open the vault file at `path`, creating an empty vault if it does not exist yet
+    pub fn open<P: AsRef<Path>>(path: P, passphrase: &str) -> Result<Self, VaultError> {
+        if passphrase.is_empty() {
+            return Err(VaultError::BackendError("Vault passphrase cannot be empty".to_string()));
+        }
+
+        let path = path.as_ref().to_path_buf();
+        if !path.exists() {
+            let mut salt = [0u8; 16];
+            OsRng.fill_bytes(&mut salt);
+            let key = derive_key(passphrase, &salt)?;
+
+            log::info!("Creating encrypted credential file at {:?}", path);
+            return Ok(Self {
+                path,
+                salt,
+                key,
+                entries: Mutex::new(BTreeMap::new()),
+            });
+        }
+
+        let content = fs::read_to_string(&path)
+            .map_err(|e| VaultError::BackendError(format!("Failed to read vault file: {}", e)))?;
+        let file: VaultFile = serde_json::from_str(&content)?;
+        if file.version != VAULT_FILE_VERSION {
+            return Err(VaultError::BackendError(format!(
+                "Unsupported vault file version: {}",
+                file.version
+            )));
+        }
+
+        let salt_bytes = general_purpose::STANDARD
+            .decode(&file.salt)
+            .map_err(|_| VaultError::BackendError("Invalid vault file salt".to_string()))?;
+        let salt: [u8; 16] = salt_bytes
+            .try_into()
+            .map_err(|_| VaultError::BackendError("Invalid vault file salt".to_string()))?;
+
+        let mut key = derive_key(passphrase, &salt)?;
+        let mut plaintext = match decrypt_bytes(&file.payload, &key) {
+            Ok(plaintext) => plaintext,
+            Err(_) => {
+                key.zeroize();
+                return Err(VaultError::BackendError(
+                    "Invalid vault passphrase or corrupted vault file".to_string(),
+                ));
+            }
+        };
+        let entries = serde_json::from_slice(&plaintext);
+        plaintext.zeroize();
+
+        log::info!("Opened encrypted credential file at {:?}", path);
+        Ok(Self {
+            path,
+            salt,
+            key,
+            entries: Mutex::new(entries?),
+        })
+    }
+
+    /// Encrypt and write all entries, replacing the file atomically
+    fn persist(&self, entries: &BTreeMap<String, String>) -> Result<(), VaultError> {
+        let mut plaintext = serde_json::to_vec(entries)?;
+        let payload = encrypt_bytes(&plaintext, &self.key);
+        plaintext.zeroize();
+
+        let file = VaultFile {
+            version: VAULT_FILE_VERSION,
+            salt: general_purpose::STANDARD.encode(self.salt),
+            payload: payload?,
+        };
+        let content = serde_json::to_string_pretty(&file)?;
+
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|e| VaultError::BackendError(format!("Failed to create vault directory: {}", e)))?;
+        }
+
+        let temp_path = self.path.with_extension("tmp");
+        fs::write(&temp_path, content)
+            .map_err(|e| VaultError::BackendError(format!("Failed to write vault file: {}", e)))?;
+        fs::rename(&temp_path, &self.path)
+            .map_err(|e| VaultError::BackendError(format!("Failed to replace vault file: {}", e)))?;
+
+        Ok(())
+    }
+
+    fn lock_entries(&self) -> Result<std::sync::MutexGuard<'_, BTreeMap<String, String>>, VaultError> {
+        self.entries
+            .lock()
+            .map_err(|_| VaultError::BackendError("Vault file lock poisoned".to_string()))
+    }
+}
+
+impl CredentialBackend for EncryptedFileBackend {
+    fn name(&self) -> &'static str {
+        "encrypted-file"
+    }
+
+    fn get(&self, key: &str) -> Result<Option<String>, VaultError> {
+        Ok(self.lock_entries()?.get(key).cloned())
+    }
+
+    fn set(&self, key: &str, value: &str) -> Result<(), VaultError> {
+        let mut entries = self.lock_entries()?;
+        let previous = entries.insert(key.to_string(), value.to_string());
+
+        if let Err(e) = self.persist(&entries) {
+            // Keep memory consistent with what is on disk
+            match previous {
+                Some(previous) => entries.insert(key.to_string(), previous),
+                None => entries.remove(key),
+            };
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    fn delete(&self, key: &str) -> Result<bool, VaultError> {
+        let mut entries = self.lock_entries()?;
+        let previous = match entries.remove(key) {
+            Some(previous) => previous,
+            None => return Ok(false),
+        };
+
+        if let Err(e) = self.persist(&entries) {
+            entries.insert(key.to_string(), previous);
+            return Err(e);
+        }
+        Ok(true)
+    }
+}
+
+impl Drop for EncryptedFileBackend {
+    fn drop(&mut self) {
+        self.key.zeroize();
+    }
+}
+
+/// Derive the file encryption key from a passphrase using Argon2id
+fn derive_key(passphrase: &str, salt: &[u8]) -> Result<[u8; 32], VaultError> {
+    let mut key = [0u8; 32];
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| VaultError::BackendError(format!("Key derivation failed: {}", e)))?;
+    Ok(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_file_backend_round_trip() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("credentials.vault");
+
+        let backend = EncryptedFileBackend::open(&path, "correct horse").unwrap();
+        assert_eq!(backend.get("entry").unwrap(), None);
+
+        backend.set("entry", "value").unwrap();
+        assert_eq!(backend.get("entry").unwrap(), Some("value".to_string()));
+
+        assert!(backend.delete("entry").unwrap());
+        assert!(!backend.delete("entry").unwrap());
+        assert_eq!(backend.get("entry").unwrap(), None);
+    }
+
+    #[test]
+    fn test_file_backend_persists_encrypted_entries() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("credentials.vault");
+
+        {
+            let backend = EncryptedFileBackend::open(&path, "correct horse").unwrap();
+            backend.set("profile_1", "super-secret-value").unwrap();
+        }
+
+        let content = fs::read_to_string(&path).unwrap();
+        assert!(!content.contains("super-secret-value"));
+
+        let reopened = EncryptedFileBackend::open(&path, "correct horse").unwrap();
+        assert_eq!(reopened.get("profile_1").unwrap(), Some("super-secret-value".to_string()));
+    }
+
+    #[test]
+    fn test_file_backend_rejects_wrong_passphrase() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("credentials.vault");
+
+        let backend = EncryptedFileBackend::open(&path, "correct horse").unwrap();
+        backend.set("entry", "value").unwrap();
+
+        let result = EncryptedFileBackend::open(&path, "battery staple");
+        assert!(matches!(result, Err(VaultError::BackendError(_))));
+        assert!(EncryptedFileBackend::open(&path, "").is_err());
+    }
+}
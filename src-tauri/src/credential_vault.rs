@@ -3,11 +3,11 @@ use aes_gcm::{
     Aes256Gcm, Key, Nonce,
 };
 use base64::{engine::general_purpose, Engine as _};
+use crate::credential_backend::{CredentialBackend, KeyringBackend};
 use chrono::{DateTime, Utc};
-use keyring::Entry;
 use rand::RngCore;
 use serde::{Deserialize, Serialize};
-
+use std::collections::BTreeSet;
 use thiserror::Error;
 use zeroize::{Zeroize, ZeroizeOnDrop};
 
@@ -34,6 +34,12 @@ pub enum VaultError {
     
     #[error("Master key not found or invalid")]
     MasterKeyError,
+    
+    #[error("Credential backend error: {0}")]
+    BackendError(String),
+    
+    #[error("Stored master key does not match its recorded hash; the vault key may have been tampered with or corrupted")]
+    MasterKeyMismatch,
 }
 
 /// Secure credentials structure with automatic zeroization
@@ -56,7 +62,7 @@ impl ZeroizeOnDrop for Credentials {}
 
 /// Encrypted credentials stored in keyring
 #[derive(Debug, Serialize, Deserialize)]
-struct EncryptedCredentials {
+pub(crate) struct EncryptedCredentials {
     pub encrypted_data: String,
     pub nonce: String,
     pub encrypted_at: DateTime<Utc>,
@@ -67,23 +73,157 @@ struct EncryptedCredentials {
 struct MasterKeyInfo {
     pub key_hash: String,
     pub created_at: DateTime<Utc>,
+    /// Base64-encoded key material. Missing on entries written by older versions,
+    /// which never persisted the key itself.
+    #[serde(default)]
+    pub key_material: Option<String>,
+}
+
+impl MasterKeyInfo {
+    fn for_key(key: &[u8; 32]) -> Self {
+        Self {
+            key_hash: master_key_hash(key),
+            created_at: Utc::now(),
+            key_material: Some(general_purpose::STANDARD.encode(key)),
+        }
+    }
+
+    /// Decode the persisted key and verify it against the recorded hash
+    fn decode_key(&self) -> Result<Option<[u8; 32]>, VaultError> {
+        let encoded = match &self.key_material {
+            Some(encoded) => encoded,
+            None => return Ok(None),
+        };
+
+        let mut decoded = general_purpose::STANDARD
+            .decode(encoded)
+            .map_err(|_| VaultError::MasterKeyError)?;
+        if decoded.len() != 32 {
+            decoded.zeroize();
+            return Err(VaultError::MasterKeyError);
+        }
+
+        let mut key = [0u8; 32];
+        key.copy_from_slice(&decoded);
+        decoded.zeroize();
+
+        if master_key_hash(&key) != self.key_hash {
+            key.zeroize();
+            return Err(VaultError::MasterKeyMismatch);
+        }
+
+        Ok(Some(key))
+    }
+}
+
+/// Fingerprint used to verify the master key loaded from the keyring
+fn master_key_hash(key: &[u8; 32]) -> String {
+    format!("{:x}", md5::compute(key))
+}
+
+/// Keyring entry listing the profiles that have stored credentials.
+/// The keyring cannot enumerate entries, so this index is kept in sync manually.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct CredentialIndex {
+    pub profile_ids: BTreeSet<String>,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+/// Result of reconciling the credential index against known profiles
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CredentialReconciliation {
+    /// Credentials stored for profiles that no longer exist
+    pub orphaned: Vec<String>,
+    /// Index entries whose credentials are missing from the keyring
+    pub missing: Vec<String>,
+    /// Credentials found for known profiles that were not yet indexed
+    pub reindexed: Vec<String>,
+    /// Orphaned credentials that were deleted
+    pub purged: Vec<String>,
+}
+
+/// Outcome of rotating the master key for a single profile
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RotationStatus {
+    /// Credentials were re-encrypted with the new key
+    Rotated,
+    /// No credentials are stored for this profile
+    Skipped,
+    /// Credentials were left untouched because the rotation was aborted
+    Unchanged,
+    /// Credentials were re-encrypted, then restored after a later failure
+    RolledBack,
+    /// This profile caused the rotation to abort
+    Failed,
+}
+
+/// Per-profile result of a master key rotation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProfileRotationResult {
+    pub profile_id: String,
+    pub status: RotationStatus,
+    pub message: Option<String>,
+}
+
+/// Report returned by a master key rotation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RotationReport {
+    /// True when the new key was committed; otherwise the old key is still active
+    pub rotated: bool,
+    pub profiles: Vec<ProfileRotationResult>,
+    pub rotated_at: DateTime<Utc>,
+}
+
+impl RotationReport {
+    fn new(profile_ids: &[String], status: RotationStatus) -> Self {
+        Self {
+            rotated: false,
+            profiles: profile_ids
+                .iter()
+                .map(|id| ProfileRotationResult {
+                    profile_id: id.clone(),
+                    status: status.clone(),
+                    message: None,
+                })
+                .collect(),
+            rotated_at: Utc::now(),
+        }
+    }
+
+    fn set_status(&mut self, profile_id: &str, status: RotationStatus, message: Option<String>) {
+        if let Some(result) = self.profiles.iter_mut().find(|r| r.profile_id == profile_id) {
+            result.status = status;
+            result.message = message;
+        }
+    }
 }
 
 /// Credential vault for secure storage and retrieval of database credentials
 pub struct CredentialVault {
-    service_name: String,
+    backend: Box<dyn CredentialBackend>,
     master_key: Option<[u8; 32]>,
 }
 
 impl CredentialVault {
-    /// Create a new credential vault instance
+    /// Create a new credential vault instance backed by the OS keyring
     pub fn new(service_name: &str) -> Self {
+        Self::with_backend(Box::new(KeyringBackend::new(service_name)))
+    }
+
+    /// Create a credential vault on top of a specific storage backend
+    pub fn with_backend(backend: Box<dyn CredentialBackend>) -> Self {
         Self {
-            service_name: service_name.to_string(),
+            backend,
             master_key: None,
         }
     }
 
+    /// Name of the storage backend in use
+    pub fn backend_name(&self) -> &'static str {
+        self.backend.name()
+    }
+
     /// Initialize the vault by loading or creating the master key
     pub async fn initialize(&mut self) -> Result<(), VaultError> {
         self.load_or_create_master_key().await?;
@@ -92,42 +232,51 @@ impl CredentialVault {
 
     /// Load existing master key or create a new one
     async fn load_or_create_master_key(&mut self) -> Result<(), VaultError> {
-        let master_key_entry = Entry::new(&self.service_name, "master_key")?;
-        
-        match master_key_entry.get_password() {
-            Ok(key_data) => {
-                // Try to load existing master key
-                let _key_info: MasterKeyInfo = serde_json::from_str(&key_data)?;
+        match self.backend.get(MASTER_KEY_ENTRY)? {
+            Some(key_data) => {
+                let key_info: MasterKeyInfo = serde_json::from_str(&key_data)?;
                 
-                // For security, we generate a new key each time
-                // In a production system, you might want to derive from a user password
-                let mut key = [0u8; 32];
-                OsRng.fill_bytes(&mut key);
-                self.master_key = Some(key);
-                
-                log::info!("Loaded existing master key for credential vault");
+                match key_info.decode_key()? {
+                    Some(key) => {
+                        self.master_key = Some(key);
+                        log::info!("Loaded existing master key for credential vault");
+                    }
+                    None => {
+                        // Older vaults only stored the key hash, so anything encrypted
+                        // with that key is already unrecoverable. Replace it with a
+                        // persisted key so credentials survive from now on.
+                        log::warn!("Stored master key has no key material; generating a new persisted key");
+                        self.create_master_key()?;
+                    }
+                }
             }
-            Err(_) => {
-                // Create new master key
-                let mut key = [0u8; 32];
-                OsRng.fill_bytes(&mut key);
-                
-                let key_info = MasterKeyInfo {
-                    key_hash: format!("{:x}", md5::compute(&key)),
-                    created_at: Utc::now(),
-                };
-                
-                let key_data = serde_json::to_string(&key_info)?;
-                master_key_entry.set_password(&key_data)?;
-                
-                self.master_key = Some(key);
-                log::info!("Created new master key for credential vault");
+            None => {
+                self.create_master_key()?;
+                log::info!("Created new master key for credential vault in {} backend", self.backend.name());
             }
         }
         
         Ok(())
     }
 
+    /// Generate a new random master key and persist it in the backend
+    fn create_master_key(&mut self) -> Result<(), VaultError> {
+        let mut key = [0u8; 32];
+        OsRng.fill_bytes(&mut key);
+        
+        let key_data = serde_json::to_string(&MasterKeyInfo::for_key(&key))?;
+        if let Err(e) = self.backend.set(MASTER_KEY_ENTRY, &key_data) {
+            key.zeroize();
+            return Err(e);
+        }
+        
+        if let Some(ref mut old_key) = self.master_key {
+            old_key.zeroize();
+        }
+        self.master_key = Some(key);
+        Ok(())
+    }
+
     /// Store encrypted credentials for a profile
     pub async fn store_credentials(
         &self,
@@ -142,10 +291,14 @@ impl CredentialVault {
         // Encrypt credentials
         let encrypted = self.encrypt_data(credentials_json.as_bytes(), &master_key)?;
         
-        // Store in keyring
-        let entry = Entry::new(&self.service_name, &format!("profile_{}", profile_id))?;
+        // Store in backend
         let encrypted_json = serde_json::to_string(&encrypted)?;
-        entry.set_password(&encrypted_json)?;
+        self.backend.set(&profile_entry(profile_id), &encrypted_json)?;
+        
+        let mut index = self.load_index()?;
+        if index.profile_ids.insert(profile_id.to_string()) {
+            self.save_index(&mut index)?;
+        }
         
         log::info!("Stored encrypted credentials for profile: {}", profile_id);
         Ok(())
@@ -155,10 +308,10 @@ impl CredentialVault {
     pub async fn retrieve_credentials(&self, profile_id: &str) -> Result<Credentials, VaultError> {
         let master_key = self.master_key.ok_or(VaultError::MasterKeyError)?;
         
-        // Retrieve from keyring
-        let entry = Entry::new(&self.service_name, &format!("profile_{}", profile_id))?;
-        let encrypted_json = entry.get_password()
-            .map_err(|_| VaultError::ProfileNotFound(profile_id.to_string()))?;
+        // Retrieve from backend
+        let encrypted_json = self.backend
+            .get(&profile_entry(profile_id))?
+            .ok_or_else(|| VaultError::ProfileNotFound(profile_id.to_string()))?;
         
         // Deserialize encrypted data
         let encrypted: EncryptedCredentials = serde_json::from_str(&encrypted_json)?;
@@ -192,9 +345,17 @@ impl CredentialVault {
 
     /// Delete credentials for a profile
     pub async fn delete_credentials(&self, profile_id: &str) -> Result<(), VaultError> {
-        let entry = Entry::new(&self.service_name, &format!("profile_{}", profile_id))?;
-        entry.delete_password()
-            .map_err(|_| VaultError::ProfileNotFound(profile_id.to_string()))?;
+        let deleted = self.backend.delete(&profile_entry(profile_id))?;
+        
+        // Drop the index entry even if the credentials were already gone
+        let mut index = self.load_index()?;
+        if index.profile_ids.remove(profile_id) {
+            self.save_index(&mut index)?;
+        }
+        
+        if !deleted {
+            return Err(VaultError::ProfileNotFound(profile_id.to_string()));
+        }
         
         log::info!("Deleted credentials for profile: {}", profile_id);
         Ok(())
@@ -202,36 +363,88 @@ impl CredentialVault {
 
     /// List all stored profile IDs
     pub async fn list_stored_profiles(&self) -> Result<Vec<String>, VaultError> {
-        // Note: keyring doesn't provide a way to list entries
-        // In a real implementation, you might maintain a separate index
-        // For now, we'll return an empty list and rely on the application
-        // to track which profiles have stored credentials
-        Ok(Vec::new())
+        let index = self.load_index()?;
+        Ok(index.profile_ids.into_iter().collect())
+    }
+
+    /// Compare stored credentials with the profiles that still exist.
+    ///
+    /// Known profiles with unindexed credentials are added to the index, index
+    /// entries without credentials are dropped, and credentials for unknown
+    /// profiles are reported as orphaned (and deleted when `purge_orphans` is set).
+    pub async fn reconcile(
+        &self,
+        known_profile_ids: &[String],
+        purge_orphans: bool,
+    ) -> Result<CredentialReconciliation, VaultError> {
+        let mut index = self.load_index()?;
+        let mut result = CredentialReconciliation::default();
+        
+        for profile_id in known_profile_ids {
+            if !index.profile_ids.contains(profile_id) && self.has_credentials(profile_id).await {
+                index.profile_ids.insert(profile_id.clone());
+                result.reindexed.push(profile_id.clone());
+            }
+        }
+        
+        let indexed: Vec<String> = index.profile_ids.iter().cloned().collect();
+        for profile_id in indexed {
+            if !self.has_credentials(&profile_id).await {
+                index.profile_ids.remove(&profile_id);
+                result.missing.push(profile_id);
+            } else if !known_profile_ids.contains(&profile_id) {
+                result.orphaned.push(profile_id);
+            }
+        }
+        
+        if purge_orphans {
+            for profile_id in &result.orphaned {
+                match self.backend.delete(&profile_entry(profile_id)) {
+                    Ok(_) => {
+                        index.profile_ids.remove(profile_id);
+                        result.purged.push(profile_id.clone());
+                    }
+                    Err(e) => log::error!("Failed to purge orphaned credentials for {}: {}", profile_id, e),
+                }
+            }
+        }
+        
+        if !result.reindexed.is_empty() || !result.missing.is_empty() || !result.purged.is_empty() {
+            self.save_index(&mut index)?;
+        }
+        
+        log::info!(
+            "Reconciled credential index: {} orphaned, {} missing, {} reindexed, {} purged",
+            result.orphaned.len(),
+            result.missing.len(),
+            result.reindexed.len(),
+            result.purged.len()
+        );
+        Ok(result)
+    }
+
+    /// Load the credential index, treating a missing entry as empty
+    fn load_index(&self) -> Result<CredentialIndex, VaultError> {
+        match self.backend.get(CREDENTIAL_INDEX_ENTRY)? {
+            Some(json) => Ok(serde_json::from_str(&json)?),
+            None => Ok(CredentialIndex::default()),
+        }
+    }
+
+    /// Persist the credential index
+    fn save_index(&self, index: &mut CredentialIndex) -> Result<(), VaultError> {
+        index.updated_at = Some(Utc::now());
+        self.backend.set(CREDENTIAL_INDEX_ENTRY, &serde_json::to_string(index)?)
     }
 
     /// Check if credentials exist for a profile
     pub async fn has_credentials(&self, profile_id: &str) -> bool {
-        let entry = Entry::new(&self.service_name, &format!("profile_{}", profile_id));
-        match entry {
-            Ok(entry) => entry.get_password().is_ok(),
-            Err(_) => false,
-        }
+        matches!(self.backend.get(&profile_entry(profile_id)), Ok(Some(_)))
     }
 
     /// Encrypt data using AES-256-GCM
     fn encrypt_data(&self, data: &[u8], key: &[u8; 32]) -> Result<EncryptedCredentials, VaultError> {
-        let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key));
-        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
-        
-        let ciphertext = cipher
-            .encrypt(&nonce, data)
-            .map_err(|e| VaultError::EncryptionError(e.to_string()))?;
-        
-        Ok(EncryptedCredentials {
-            encrypted_data: general_purpose::STANDARD.encode(&ciphertext),
-            nonce: general_purpose::STANDARD.encode(&nonce),
-            encrypted_at: Utc::now(),
-        })
+        encrypt_bytes(data, key)
     }
 
     /// Decrypt data using AES-256-GCM
@@ -240,51 +453,175 @@ impl CredentialVault {
         encrypted: &EncryptedCredentials,
         key: &[u8; 32],
     ) -> Result<Vec<u8>, VaultError> {
-        let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key));
-        
-        let nonce_bytes = general_purpose::STANDARD
-            .decode(&encrypted.nonce)
-            .map_err(|e| VaultError::DecryptionError(format!("Invalid nonce: {}", e)))?;
-        
-        let nonce = Nonce::from_slice(&nonce_bytes);
-        
-        let ciphertext = general_purpose::STANDARD
-            .decode(&encrypted.encrypted_data)
-            .map_err(|e| VaultError::DecryptionError(format!("Invalid ciphertext: {}", e)))?;
-        
-        let plaintext = cipher
-            .decrypt(nonce, ciphertext.as_ref())
-            .map_err(|e| VaultError::DecryptionError(e.to_string()))?;
+        decrypt_bytes(encrypted, key)
+    }
+
+    /// Re-encrypt a stored credentials entry from one key to another
+    fn reencrypt_entry(
+        &self,
+        encrypted_json: &str,
+        old_key: &[u8; 32],
+        new_key: &[u8; 32],
+    ) -> Result<String, VaultError> {
+        let encrypted: EncryptedCredentials = serde_json::from_str(encrypted_json)?;
+        let mut plaintext = self.decrypt_data(&encrypted, old_key)?;
+        let reencrypted = self.encrypt_data(&plaintext, new_key);
+        plaintext.zeroize();
         
-        Ok(plaintext)
+        Ok(serde_json::to_string(&reencrypted?)?)
     }
 
-    /// Rotate the master key (re-encrypt all stored credentials)
-    pub async fn rotate_master_key(&mut self) -> Result<(), VaultError> {
-        // This is a complex operation that would require:
-        // 1. Decrypt all existing credentials with old key
-        // 2. Generate new master key
-        // 3. Re-encrypt all credentials with new key
-        // 4. Update master key in keyring
+    /// Rotate the master key, re-encrypting the credentials of every given profile.
+    ///
+    /// All entries are decrypted and re-encrypted in memory before anything is
+    /// written. If writing any entry or the new key fails, entries already
+    /// written are restored and the old key stays active.
+    pub async fn rotate_master_key(&mut self, profile_ids: &[String]) -> Result<RotationReport, VaultError> {
+        let old_key = self.master_key.ok_or(VaultError::MasterKeyError)?;
+        let mut report = RotationReport::new(profile_ids, RotationStatus::Unchanged);
         
-        // For now, we'll just generate a new key
-        // In production, you'd want to implement the full rotation
         let mut new_key = [0u8; 32];
         OsRng.fill_bytes(&mut new_key);
-        self.master_key = Some(new_key);
         
-        let key_info = MasterKeyInfo {
-            key_hash: format!("{:x}", md5::compute(&new_key)),
-            created_at: Utc::now(),
-        };
+        // Phase 1: re-encrypt everything in memory, aborting on the first failure
+        let mut pending: Vec<PendingRotation> = Vec::new();
+        for profile_id in profile_ids {
+            let result = match self.backend.get(&profile_entry(profile_id)) {
+                Ok(Some(old_json)) => self
+                    .reencrypt_entry(&old_json, &old_key, &new_key)
+                    .map(|new_json| Some((old_json, new_json))),
+                Ok(None) => Ok(None),
+                Err(e) => Err(e),
+            };
+            
+            match result {
+                Ok(Some((old_json, new_json))) => pending.push(PendingRotation {
+                    profile_id: profile_id.clone(),
+                    old_json,
+                    new_json,
+                }),
+                Ok(None) => report.set_status(profile_id, RotationStatus::Skipped, None),
+                Err(e) => {
+                    new_key.zeroize();
+                    log::error!("Aborting master key rotation, profile {} failed: {}", profile_id, e);
+                    report.set_status(profile_id, RotationStatus::Failed, Some(e.to_string()));
+                    return Ok(report);
+                }
+            }
+        }
         
-        let master_key_entry = Entry::new(&self.service_name, "master_key")?;
-        let key_data = serde_json::to_string(&key_info)?;
-        master_key_entry.set_password(&key_data)?;
+        // Phase 2: write the re-encrypted entries, restoring them on failure
+        for (index, item) in pending.iter().enumerate() {
+            if let Err(e) = self.backend.set(&profile_entry(&item.profile_id), &item.new_json) {
+                new_key.zeroize();
+                log::error!("Aborting master key rotation, profile {} failed: {}", item.profile_id, e);
+                report.set_status(&item.profile_id, RotationStatus::Failed, Some(e.to_string()));
+                self.restore_entries(&pending[..index], &mut report);
+                return Ok(report);
+            }
+        }
         
-        log::warn!("Master key rotated - existing credentials may need re-encryption");
-        Ok(())
+        // Phase 3: commit the new key last so a failure leaves the old key usable
+        let key_data = serde_json::to_string(&MasterKeyInfo::for_key(&new_key))?;
+        if let Err(e) = self.backend.set(MASTER_KEY_ENTRY, &key_data) {
+            new_key.zeroize();
+            log::error!("Failed to persist rotated master key, restoring credentials: {}", e);
+            self.restore_entries(&pending, &mut report);
+            return Err(e);
+        }
+        
+        for item in &pending {
+            report.set_status(&item.profile_id, RotationStatus::Rotated, None);
+        }
+        
+        if let Some(ref mut key) = self.master_key {
+            key.zeroize();
+        }
+        self.master_key = Some(new_key);
+        report.rotated = true;
+        report.rotated_at = Utc::now();
+        
+        log::info!("Master key rotated, re-encrypted credentials for {} profiles", pending.len());
+        Ok(report)
+    }
+
+    /// Write back the original entries after an aborted rotation
+    fn restore_entries(&self, written: &[PendingRotation], report: &mut RotationReport) {
+        for item in written {
+            match self.backend.set(&profile_entry(&item.profile_id), &item.old_json) {
+                Ok(_) => report.set_status(&item.profile_id, RotationStatus::RolledBack, None),
+                Err(e) => {
+                    log::error!("Failed to restore credentials for profile {}: {}", item.profile_id, e);
+                    report.set_status(
+                        &item.profile_id,
+                        RotationStatus::Failed,
+                        Some(format!("Rollback failed: {}", e)),
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// A credentials entry re-encrypted in memory but not yet committed
+struct PendingRotation {
+    profile_id: String,
+    old_json: String,
+    new_json: String,
+}
+
+/// Backend entry holding the master key
+const MASTER_KEY_ENTRY: &str = "master_key";
+
+/// Backend entry holding the credential index
+const CREDENTIAL_INDEX_ENTRY: &str = "credential_index";
+
+/// Backend entry holding the credentials of a profile
+fn profile_entry(profile_id: &str) -> String {
+    format!("profile_{}", profile_id)
+}
+
+/// Encrypt data using AES-256-GCM
+pub(crate) fn encrypt_bytes(data: &[u8], key: &[u8; 32]) -> Result<EncryptedCredentials, VaultError> {
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key));
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    
+    let ciphertext = cipher
+        .encrypt(&nonce, data)
+        .map_err(|e| VaultError::EncryptionError(e.to_string()))?;
+    
+    Ok(EncryptedCredentials {
+        encrypted_data: general_purpose::STANDARD.encode(&ciphertext),
+        nonce: general_purpose::STANDARD.encode(nonce),
+        encrypted_at: Utc::now(),
+    })
+}
+
+/// Decrypt data using AES-256-GCM
+pub(crate) fn decrypt_bytes(
+    encrypted: &EncryptedCredentials,
+    key: &[u8; 32],
+) -> Result<Vec<u8>, VaultError> {
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key));
+    
+    let nonce_bytes = general_purpose::STANDARD
+        .decode(&encrypted.nonce)
+        .map_err(|e| VaultError::DecryptionError(format!("Invalid nonce: {}", e)))?;
+    if nonce_bytes.len() != 12 {
+        return Err(VaultError::DecryptionError("Invalid nonce length".to_string()));
     }
+    
+    let nonce = Nonce::from_slice(&nonce_bytes);
+    
+    let ciphertext = general_purpose::STANDARD
+        .decode(&encrypted.encrypted_data)
+        .map_err(|e| VaultError::DecryptionError(format!("Invalid ciphertext: {}", e)))?;
+    
+    let plaintext = cipher
+        .decrypt(nonce, ciphertext.as_ref())
+        .map_err(|e| VaultError::DecryptionError(e.to_string()))?;
+    
+    Ok(plaintext)
 }
 
 impl Drop for CredentialVault {
@@ -299,8 +636,15 @@ impl Drop for CredentialVault {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::credential_backend::EncryptedFileBackend;
+    use tempfile::TempDir;
     use tokio;
 
+    fn file_vault(dir: &TempDir) -> CredentialVault {
+        let backend = EncryptedFileBackend::open(dir.path().join("credentials.vault"), "test passphrase").unwrap();
+        CredentialVault::with_backend(Box::new(backend))
+    }
+
     #[tokio::test]
     async fn test_credential_vault_initialization() {
         let mut vault = CredentialVault::new("test_app_credentials");
@@ -442,6 +786,192 @@ mod tests {
         let _ = vault.delete_credentials(profile_id).await;
     }
 
+    #[tokio::test]
+    async fn test_credentials_survive_vault_restart() {
+        let service_name = "test_app_restart";
+        let profile_id = "test_profile_restart";
+
+        let mut vault = CredentialVault::new(service_name);
+        vault.initialize().await.unwrap();
+        let credentials = Credentials {
+            username: "restart_user".to_string(),
+            password: "restart_pass".to_string(),
+            encrypted_at: Utc::now(),
+        };
+        vault.store_credentials(profile_id, credentials).await.unwrap();
+        drop(vault);
+
+        // A fresh instance must load the same master key
+        let mut restarted = CredentialVault::new(service_name);
+        restarted.initialize().await.unwrap();
+        let retrieved = restarted.retrieve_credentials(profile_id).await.unwrap();
+        assert_eq!(retrieved.username, "restart_user");
+        assert_eq!(retrieved.password, "restart_pass");
+
+        // Clean up
+        let _ = restarted.delete_credentials(profile_id).await;
+    }
+
+    #[test]
+    fn test_master_key_info_round_trip() {
+        let key = [7u8; 32];
+        let info = MasterKeyInfo::for_key(&key);
+        let json = serde_json::to_string(&info).unwrap();
+        let loaded: MasterKeyInfo = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(loaded.decode_key().unwrap(), Some(key));
+    }
+
+    #[test]
+    fn test_master_key_info_hash_mismatch() {
+        let mut info = MasterKeyInfo::for_key(&[7u8; 32]);
+        info.key_hash = master_key_hash(&[8u8; 32]);
+
+        assert!(matches!(info.decode_key(), Err(VaultError::MasterKeyMismatch)));
+    }
+
+    #[test]
+    fn test_legacy_master_key_info_has_no_key() {
+        let legacy = r#"{"key_hash":"abc","created_at":"2024-01-01T00:00:00Z"}"#;
+        let info: MasterKeyInfo = serde_json::from_str(legacy).unwrap();
+
+        assert_eq!(info.decode_key().unwrap(), None);
+    }
+
+    #[test]
+    fn test_reencrypt_entry_between_keys() {
+        let vault = CredentialVault::new("test_app_reencrypt");
+        let old_key = [1u8; 32];
+        let new_key = [2u8; 32];
+
+        let encrypted = vault.encrypt_data(b"secret", &old_key).unwrap();
+        let old_json = serde_json::to_string(&encrypted).unwrap();
+
+        let new_json = vault.reencrypt_entry(&old_json, &old_key, &new_key).unwrap();
+        let reencrypted: EncryptedCredentials = serde_json::from_str(&new_json).unwrap();
+
+        assert_eq!(vault.decrypt_data(&reencrypted, &new_key).unwrap(), b"secret");
+        assert!(vault.decrypt_data(&reencrypted, &old_key).is_err());
+        // Entries encrypted with a different key must not be rewritten
+        assert!(vault.reencrypt_entry(&old_json, &new_key, &old_key).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_rotate_master_key_reencrypts_credentials() {
+        let mut vault = CredentialVault::new("test_app_rotate");
+        vault.initialize().await.unwrap();
+
+        let profile_id = "test_profile_rotate";
+        let credentials = Credentials {
+            username: "rotate_user".to_string(),
+            password: "rotate_pass".to_string(),
+            encrypted_at: Utc::now(),
+        };
+        vault.store_credentials(profile_id, credentials).await.unwrap();
+        let old_key = vault.master_key.unwrap();
+
+        let ids = vec![profile_id.to_string(), "test_profile_missing".to_string()];
+        let report = vault.rotate_master_key(&ids).await.unwrap();
+
+        assert!(report.rotated);
+        assert_ne!(vault.master_key.unwrap(), old_key);
+        assert_eq!(report.profiles[0].status, RotationStatus::Rotated);
+        assert_eq!(report.profiles[1].status, RotationStatus::Skipped);
+
+        let retrieved = vault.retrieve_credentials(profile_id).await.unwrap();
+        assert_eq!(retrieved.password, "rotate_pass");
+
+        // Clean up
+        let _ = vault.delete_credentials(profile_id).await;
+    }
+
+    #[tokio::test]
+    async fn test_index_tracks_stored_profiles() {
+        let mut vault = CredentialVault::new("test_app_index");
+        vault.initialize().await.unwrap();
+
+        let credentials = Credentials {
+            username: "index_user".to_string(),
+            password: "index_pass".to_string(),
+            encrypted_at: Utc::now(),
+        };
+        vault.store_credentials("test_profile_kept", credentials.clone()).await.unwrap();
+        vault.store_credentials("test_profile_orphan", credentials).await.unwrap();
+
+        let listed = vault.list_stored_profiles().await.unwrap();
+        assert!(listed.contains(&"test_profile_kept".to_string()));
+        assert!(listed.contains(&"test_profile_orphan".to_string()));
+
+        let known = vec!["test_profile_kept".to_string()];
+        let result = vault.reconcile(&known, true).await.unwrap();
+        assert_eq!(result.orphaned, vec!["test_profile_orphan".to_string()]);
+        assert_eq!(result.purged, vec!["test_profile_orphan".to_string()]);
+        assert!(!vault.has_credentials("test_profile_orphan").await);
+
+        vault.delete_credentials("test_profile_kept").await.unwrap();
+        assert!(vault.list_stored_profiles().await.unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_credential_index_defaults_when_fields_missing() {
+        let index: CredentialIndex = serde_json::from_str(r#"{"profile_ids":["a","b"]}"#).unwrap();
+        assert_eq!(index.profile_ids.len(), 2);
+        assert!(index.updated_at.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_file_backend_vault_survives_restart() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let mut vault = file_vault(&dir);
+        vault.initialize().await.unwrap();
+        assert_eq!(vault.backend_name(), "encrypted-file");
+        let credentials = Credentials {
+            username: "file_user".to_string(),
+            password: "file_pass".to_string(),
+            encrypted_at: Utc::now(),
+        };
+        vault.store_credentials("profile_a", credentials).await.unwrap();
+        drop(vault);
+
+        let mut restarted = file_vault(&dir);
+        restarted.initialize().await.unwrap();
+        let retrieved = restarted.retrieve_credentials("profile_a").await.unwrap();
+        assert_eq!(retrieved.password, "file_pass");
+        assert_eq!(restarted.list_stored_profiles().await.unwrap(), vec!["profile_a".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn test_file_backend_rotation_and_reconcile() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut vault = file_vault(&dir);
+        vault.initialize().await.unwrap();
+
+        let credentials = Credentials {
+            username: "user".to_string(),
+            password: "pass".to_string(),
+            encrypted_at: Utc::now(),
+        };
+        vault.store_credentials("kept", credentials.clone()).await.unwrap();
+        vault.store_credentials("orphan", credentials).await.unwrap();
+
+        let ids = vault.list_stored_profiles().await.unwrap();
+        let report = vault.rotate_master_key(&ids).await.unwrap();
+        assert!(report.rotated);
+        assert!(report.profiles.iter().all(|p| p.status == RotationStatus::Rotated));
+        assert_eq!(vault.retrieve_credentials("kept").await.unwrap().password, "pass");
+
+        let result = vault.reconcile(&["kept".to_string()], true).await.unwrap();
+        assert_eq!(result.purged, vec!["orphan".to_string()]);
+        assert_eq!(vault.list_stored_profiles().await.unwrap(), vec!["kept".to_string()]);
+
+        // The rotated key must be the one loaded after a restart
+        drop(vault);
+        let mut restarted = file_vault(&dir);
+        restarted.initialize().await.unwrap();
+        assert_eq!(restarted.retrieve_credentials("kept").await.unwrap().username, "user");
+    }
+
     #[tokio::test]
     async fn test_profile_not_found_error() {
         let mut vault = CredentialVault::new("test_app_not_found");
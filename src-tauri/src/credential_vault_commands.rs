@@ -1,4 +1,8 @@
-use crate::credential_vault::{CredentialVault, Credentials, VaultError};
+use crate::connection_profile_store_commands::ConnectionProfileStoreState;
+use crate::credential_vault::{
+    CredentialReconciliation, CredentialVault, Credentials, RotationReport, VaultError,
+};
+use crate::ssh_tunnel::{is_ssh_secret_id, ssh_secret_id};
 use chrono::Utc;
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
@@ -42,10 +46,10 @@ pub async fn initialize_credential_vault(
     
     match vault.initialize().await {
         Ok(_) => {
-            log::info!("Credential vault initialized successfully");
+            log::info!("Credential vault initialized successfully ({} backend)", vault.backend_name());
             Ok(CredentialResponse {
                 success: true,
-                message: "Credential vault initialized successfully".to_string(),
+                message: format!("Credential vault initialized successfully ({} backend)", vault.backend_name()),
             })
         }
         Err(e) => {
@@ -177,6 +181,65 @@ pub async fn delete_profile_credentials(
     }
 }
 
+/// Store the SSH password or private key passphrase for a profile's tunnel
+#[tauri::command]
+pub async fn store_ssh_tunnel_secret(
+    vault_state: State<'_, CredentialVaultState>,
+    profile_id: String,
+    secret: String,
+) -> Result<CredentialResponse, String> {
+    let vault = vault_state.lock().await;
+    
+    let credentials = Credentials {
+        username: String::new(),
+        password: secret,
+        encrypted_at: Utc::now(),
+    };
+    
+    match vault.store_credentials(&ssh_secret_id(&profile_id), credentials).await {
+        Ok(_) => {
+            log::info!("Stored SSH tunnel secret for profile: {}", profile_id);
+            Ok(CredentialResponse {
+                success: true,
+                message: "SSH secret stored successfully".to_string(),
+            })
+        }
+        Err(e) => {
+            log::error!("Failed to store SSH tunnel secret for profile {}: {}", profile_id, e);
+            Err(format!("Failed to store SSH secret: {}", e))
+        }
+    }
+}
+
+/// Delete the SSH tunnel secret of a profile
+#[tauri::command]
+pub async fn delete_ssh_tunnel_secret(
+    vault_state: State<'_, CredentialVaultState>,
+    profile_id: String,
+) -> Result<CredentialResponse, String> {
+    let vault = vault_state.lock().await;
+    
+    match vault.delete_credentials(&ssh_secret_id(&profile_id)).await {
+        Ok(_) => {
+            log::info!("Deleted SSH tunnel secret for profile: {}", profile_id);
+            Ok(CredentialResponse {
+                success: true,
+                message: "SSH secret deleted successfully".to_string(),
+            })
+        }
+        Err(VaultError::ProfileNotFound(_)) => {
+            Ok(CredentialResponse {
+                success: false,
+                message: "No SSH secret found for this profile".to_string(),
+            })
+        }
+        Err(e) => {
+            log::error!("Failed to delete SSH tunnel secret for profile {}: {}", profile_id, e);
+            Err(format!("Failed to delete SSH secret: {}", e))
+        }
+    }
+}
+
 /// Check if credentials exist for a connection profile
 #[tauri::command]
 pub async fn has_profile_credentials(
@@ -191,7 +254,7 @@ pub async fn has_profile_credentials(
     Ok(has_credentials)
 }
 
-/// List all profiles that have stored credentials
+/// List all profiles that have stored credentials. SSH secrets are not profiles.
 #[tauri::command]
 pub async fn list_profiles_with_credentials(
     vault_state: State<'_, CredentialVaultState>,
@@ -200,6 +263,7 @@ pub async fn list_profiles_with_credentials(
     
     match vault.list_stored_profiles().await {
         Ok(profiles) => {
+            let profiles: Vec<String> = profiles.into_iter().filter(|id| !is_ssh_secret_id(id)).collect();
             log::info!("Listed {} profiles with stored credentials", profiles.len());
             Ok(profiles)
         }
@@ -210,24 +274,78 @@ pub async fn list_profiles_with_credentials(
     }
 }
 
-/// Rotate the master encryption key
+/// Rotate the master encryption key and re-encrypt all stored credentials
 #[tauri::command]
 pub async fn rotate_vault_master_key(
     vault_state: State<'_, CredentialVaultState>,
-) -> Result<CredentialResponse, String> {
+    store_state: State<'_, ConnectionProfileStoreState>,
+) -> Result<RotationReport, String> {
+    let known_ids = known_profile_ids(&store_state).await?;
     let mut vault = vault_state.lock().await;
     
-    match vault.rotate_master_key().await {
-        Ok(_) => {
-            log::warn!("Master key rotated successfully");
-            Ok(CredentialResponse {
-                success: true,
-                message: "Master key rotated successfully. Existing credentials may need re-encryption.".to_string(),
-            })
+    // Include profiles whose credentials predate the index
+    let mut profile_ids = vault
+        .list_stored_profiles()
+        .await
+        .map_err(|e| format!("Failed to read credential index: {}", e))?;
+    for id in known_ids {
+        if !profile_ids.contains(&id) {
+            profile_ids.push(id);
+        }
+    }
+    
+    match vault.rotate_master_key(&profile_ids).await {
+        Ok(report) => {
+            if report.rotated {
+                log::warn!("Master key rotated successfully");
+            } else {
+                log::error!("Master key rotation aborted, previous key kept");
+            }
+            Ok(report)
         }
         Err(e) => {
             log::error!("Failed to rotate master key: {}", e);
             Err(format!("Failed to rotate master key: {}", e))
         }
     }
-}
\ No newline at end of file
+}
+
+/// Detect (and optionally purge) credentials for profiles that no longer exist
+#[tauri::command]
+pub async fn reconcile_vault_credentials(
+    vault_state: State<'_, CredentialVaultState>,
+    store_state: State<'_, ConnectionProfileStoreState>,
+    purge_orphans: Option<bool>,
+) -> Result<CredentialReconciliation, String> {
+    let known_ids = known_profile_ids(&store_state).await?;
+    let vault = vault_state.lock().await;
+    
+    vault
+        .reconcile(&known_ids, purge_orphans.unwrap_or(false))
+        .await
+        .map_err(|e| {
+            log::error!("Failed to reconcile credential index: {}", e);
+            format!("Failed to reconcile credentials: {}", e)
+        })
+}
+
+/// IDs of every profile in the connection profile store
+async fn known_profile_ids(
+    store_state: &State<'_, ConnectionProfileStoreState>,
+) -> Result<Vec<String>, String> {
+    let store = store_state.lock().await;
+    let profiles = store
+        .get_all_profiles()
+        .await
+        .map_err(|e| format!("Failed to load profiles: {}", e))?;
+    
+    // Profiles with an SSH tunnel also own a vault entry for the SSH secret
+    let mut ids = Vec::new();
+    for profile in profiles {
+        if profile.config.ssh_tunnel.is_some() {
+            ids.push(ssh_secret_id(&profile.id));
+        }
+        ids.push(profile.id);
+    }
+    Ok(ids)
+}
@@ -0,0 +1,952 @@
+//! File formats rows can be exported to.
+//!
+//! Every format implements [`ExportFormat`], which turns decoded rows (see
+//! `value_codec`) into bytes written to any `Write`. Exports look formats up by name
+//! in [`FORMATS`], so a new writer only has to be added there.
+
+use crate::sql_builder::quote_literal;
+use crate::value_codec::ColumnType;
+use arrow_array::builder::{
+    BooleanBuilder, Date32Builder, Float32Builder, Float64Builder, Int16Builder, Int32Builder, Int64Builder,
+    StringBuilder, TimestampMicrosecondBuilder,
+};
+use arrow_array::{ArrayRef, RecordBatch, RecordBatchOptions};
+use arrow_schema::{DataType, Field, Schema, TimeUnit};
+use chrono::{Datelike, NaiveDate, NaiveDateTime};
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::file::properties::WriterProperties;
+use rust_xlsxwriter::{Format, Workbook};
+use serde_json::Value;
+use std::borrow::Cow;
+use std::io::Write;
+use std::sync::Arc;
+
+/// Rows buffered before they are handed to Arrow as one record batch
+const PARQUET_BATCH_ROWS: usize = 8192;
+const PARQUET_ROW_GROUP_ROWS: usize = 128 * 1024;
+/// `NaiveDate::num_days_from_ce` of 1970-01-01, where Arrow dates count from
+const UNIX_EPOCH_DAYS_FROM_CE: i32 = 719_163;
+/// Excel's limit, including the header row
+const XLSX_MAX_ROWS: u32 = 1_048_576;
+
+#[derive(Debug, Clone)]
+pub struct ExportFormatOptions {
+    /// Header row for CSV and XLSX; Markdown and HTML tables always have one
+    pub include_headers: bool,
+    pub delimiter: u8,
+    /// JSON: one field per line instead of one row per line
+    pub pretty_json: bool,
+}
+
+impl Default for ExportFormatOptions {
+    fn default() -> Self {
+        Self {
+            include_headers: true,
+            delimiter: b',',
+            pretty_json: false,
+        }
+    }
+}
+
+/// A file format rows can be exported to
+pub trait ExportFormat: Sync {
+    /// Name the format is selected by, matched case-insensitively
+    fn name(&self) -> &'static str;
+    /// File extension, without the dot
+    fn extension(&self) -> &'static str;
+    /// Binary files cannot be handed to the frontend as text
+    fn is_binary(&self) -> bool {
+        false
+    }
+    /// Options for `COPY ... TO STDOUT WITH (...)` when the server can write the format
+    /// itself; file exports then stream the server's output instead of decoding rows
+    fn copy_options(&self, _options: &ExportFormatOptions) -> Option<String> {
+        None
+    }
+    fn writer(
+        &self,
+        columns: &[ColumnType],
+        options: &ExportFormatOptions,
+        output: Box<dyn Write + Send>,
+    ) -> Result<Box<dyn RowWriter>, String>;
+}
+
+/// Writes the rows of one export
+pub trait RowWriter: Send {
+    fn write_row(&mut self, row: &[Value]) -> Result<(), String>;
+    /// Write whatever is still buffered and the end of the file
+    fn finish(self: Box<Self>) -> Result<(), String>;
+}
+
+pub const FORMATS: &[&dyn ExportFormat] = &[
+    &CsvFormat,
+    &JsonFormat,
+    &NdjsonFormat,
+    &ParquetFormat,
+    &XlsxFormat,
+    &MarkdownFormat,
+    &HtmlFormat,
+];
+
+pub fn find_format(name: &str) -> Result<&'static dyn ExportFormat, String> {
+    FORMATS
+        .iter()
+        .copied()
+        .find(|format| format.name().eq_ignore_ascii_case(name))
+        .ok_or_else(|| {
+            let names: Vec<&str> = FORMATS.iter().map(|format| format.name()).collect();
+            format!("Unsupported export format: {} (expected one of {})", name, names.join(", "))
+        })
+}
+
+/// Export rows that are already in memory
+pub fn render(
+    format: &dyn ExportFormat,
+    columns: &[ColumnType],
+    rows: &[Vec<Value>],
+    options: &ExportFormatOptions,
+) -> Result<Vec<u8>, String> {
+    let buffer = SharedBuffer::default();
+    let mut writer = format.writer(columns, options, Box::new(buffer.clone()))?;
+    for row in rows {
+        writer.write_row(row)?;
+    }
+    writer.finish()?;
+    Ok(buffer.take())
+}
+
+/// In-memory output whose contents can be taken while a writer still holds it, so
+/// exports can move finished bytes on to an async file as they go
+#[derive(Clone, Default)]
+pub struct SharedBuffer(Arc<std::sync::Mutex<Vec<u8>>>);
+
+impl SharedBuffer {
+    pub fn take(&self) -> Vec<u8> {
+        std::mem::take(&mut *self.0.lock().unwrap_or_else(|poisoned| poisoned.into_inner()))
+    }
+}
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+fn write_error(e: std::io::Error) -> String {
+    format!("Failed to write export: {}", e)
+}
+
+/// A value as plain text; NULL is empty
+fn cell_text(value: &Value) -> Cow<'_, str> {
+    match value {
+        Value::Null => Cow::Borrowed(""),
+        Value::String(text) => Cow::Borrowed(text),
+        other => Cow::Owned(other.to_string()),
+    }
+}
+
+/// How a column's values can be stored by formats with typed cells
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ValueKind {
+    Boolean,
+    Int16,
+    Int32,
+    Int64,
+    Float32,
+    Float64,
+    /// Arbitrary precision, kept as text where it would otherwise lose digits
+    Numeric,
+    Date,
+    Timestamp,
+    TimestampTz,
+    Text,
+}
+
+impl ValueKind {
+    fn of(column: &ColumnType) -> Self {
+        match column.type_name.as_str() {
+            "bool" => ValueKind::Boolean,
+            "int2" => ValueKind::Int16,
+            "int4" => ValueKind::Int32,
+            "int8" => ValueKind::Int64,
+            "float4" => ValueKind::Float32,
+            "float8" => ValueKind::Float64,
+            "numeric" | "money" => ValueKind::Numeric,
+            "date" => ValueKind::Date,
+            "timestamp" => ValueKind::Timestamp,
+            "timestamptz" => ValueKind::TimestampTz,
+            _ => ValueKind::Text,
+        }
+    }
+}
+
+fn value_i64(value: &Value) -> Option<i64> {
+    match value {
+        Value::Number(n) => n.as_i64(),
+        // int8 values beyond JavaScript's safe range arrive as strings
+        Value::String(text) => text.parse().ok(),
+        _ => None,
+    }
+}
+
+fn value_f64(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(n) => n.as_f64(),
+        // NaN, Infinity and numeric values
+        Value::String(text) => text.parse().ok(),
+        _ => None,
+    }
+}
+
+/// Undo `value_codec`'s ` BC` suffix, which chrono cannot parse
+fn split_era(text: &str) -> (&str, bool) {
+    match text.strip_suffix(" BC") {
+        Some(text) => (text, true),
+        None => (text, false),
+    }
+}
+
+fn parse_date(text: &str) -> Option<NaiveDate> {
+    let (text, bc) = split_era(text);
+    let date = NaiveDate::parse_from_str(text, "%Y-%m-%d").ok()?;
+    if bc {
+        date.with_year(1 - date.year())
+    } else {
+        Some(date)
+    }
+}
+
+/// Timestamps as `value_codec` renders them; timestamptz values are always in UTC
+fn parse_timestamp(text: &str) -> Option<NaiveDateTime> {
+    let (text, bc) = split_era(text.strip_suffix("+00").unwrap_or(text));
+    let timestamp = NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M:%S%.f").ok()?;
+    if bc {
+        timestamp.with_year(1 - timestamp.year())
+    } else {
+        Some(timestamp)
+    }
+}
+
+pub struct CsvFormat;
+
+impl ExportFormat for CsvFormat {
+    fn name(&self) -> &'static str {
+        "csv"
+    }
+
+    fn extension(&self) -> &'static str {
+        "csv"
+    }
+
+    fn copy_options(&self, options: &ExportFormatOptions) -> Option<String> {
+        Some(format!(
+            "FORMAT csv, HEADER {}, DELIMITER {}",
+            options.include_headers,
+            quote_literal(&(options.delimiter as char).to_string())
+        ))
+    }
+
+    fn writer(
+        &self,
+        columns: &[ColumnType],
+        options: &ExportFormatOptions,
+        output: Box<dyn Write + Send>,
+    ) -> Result<Box<dyn RowWriter>, String> {
+        let mut writer = csv::WriterBuilder::new().delimiter(options.delimiter).from_writer(output);
+        if options.include_headers {
+            writer
+                .write_record(columns.iter().map(|column| column.name.as_str()))
+                .map_err(|e| format!("Failed to write export: {}", e))?;
+        }
+        Ok(Box::new(CsvWriter(writer)))
+    }
+}
+
+struct CsvWriter(csv::Writer<Box<dyn Write + Send>>);
+
+impl RowWriter for CsvWriter {
+    fn write_row(&mut self, row: &[Value]) -> Result<(), String> {
+        self.0
+            .write_record(row.iter().map(|value| cell_text(value).into_owned()))
+            .map_err(|e| format!("Failed to write export: {}", e))
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<(), String> {
+        self.0.flush().map_err(write_error)
+    }
+}
+
+/// A row as a JSON object with its fields in column order
+fn json_object(columns: &[ColumnType], row: &[Value], pretty: bool) -> Result<String, String> {
+    let (open, separator, colon, close) = if pretty { ("{\n    ", ",\n    ", ": ", "\n  }") } else { ("{", ",", ":", "}") };
+    let mut object = String::from(open);
+    for (i, (column, value)) in columns.iter().zip(row).enumerate() {
+        if i > 0 {
+            object.push_str(separator);
+        }
+        object.push_str(&serde_json::to_string(&column.name).map_err(|e| e.to_string())?);
+        object.push_str(colon);
+        object.push_str(&serde_json::to_string(value).map_err(|e| e.to_string())?);
+    }
+    object.push_str(if columns.is_empty() { "}" } else { close });
+    Ok(object)
+}
+
+pub struct JsonFormat;
+
+impl ExportFormat for JsonFormat {
+    fn name(&self) -> &'static str {
+        "json"
+    }
+
+    fn extension(&self) -> &'static str {
+        "json"
+    }
+
+    fn writer(
+        &self,
+        columns: &[ColumnType],
+        options: &ExportFormatOptions,
+        mut output: Box<dyn Write + Send>,
+    ) -> Result<Box<dyn RowWriter>, String> {
+        output.write_all(b"[").map_err(write_error)?;
+        Ok(Box::new(JsonWriter {
+            output,
+            columns: columns.to_vec(),
+            pretty: options.pretty_json,
+            rows: 0,
+        }))
+    }
+}
+
+/// An array of row objects, one row per line
+struct JsonWriter {
+    output: Box<dyn Write + Send>,
+    columns: Vec<ColumnType>,
+    pretty: bool,
+    rows: u64,
+}
+
+impl RowWriter for JsonWriter {
+    fn write_row(&mut self, row: &[Value]) -> Result<(), String> {
+        let separator = if self.rows == 0 { "\n  " } else { ",\n  " };
+        let object = json_object(&self.columns, row, self.pretty)?;
+        write!(self.output, "{}{}", separator, object).map_err(write_error)?;
+        self.rows += 1;
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<(), String> {
+        self.output.write_all(b"\n]\n").map_err(write_error)?;
+        self.output.flush().map_err(write_error)
+    }
+}
+
+pub struct NdjsonFormat;
+
+impl ExportFormat for NdjsonFormat {
+    fn name(&self) -> &'static str {
+        "ndjson"
+    }
+
+    fn extension(&self) -> &'static str {
+        "ndjson"
+    }
+
+    fn writer(
+        &self,
+        columns: &[ColumnType],
+        _options: &ExportFormatOptions,
+        output: Box<dyn Write + Send>,
+    ) -> Result<Box<dyn RowWriter>, String> {
+        Ok(Box::new(NdjsonWriter {
+            output,
+            columns: columns.to_vec(),
+        }))
+    }
+}
+
+/// One compact row object per line
+struct NdjsonWriter {
+    output: Box<dyn Write + Send>,
+    columns: Vec<ColumnType>,
+}
+
+impl RowWriter for NdjsonWriter {
+    fn write_row(&mut self, row: &[Value]) -> Result<(), String> {
+        let object = json_object(&self.columns, row, false)?;
+        writeln!(self.output, "{}", object).map_err(write_error)
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<(), String> {
+        self.output.flush().map_err(write_error)
+    }
+}
+
+pub struct ParquetFormat;
+
+impl ParquetFormat {
+    /// Arrow type a column is stored as. Types without an exact Arrow equivalent,
+    /// numeric included, are stored as their PostgreSQL text.
+    fn data_type(kind: ValueKind) -> DataType {
+        match kind {
+            ValueKind::Boolean => DataType::Boolean,
+            ValueKind::Int16 => DataType::Int16,
+            ValueKind::Int32 => DataType::Int32,
+            ValueKind::Int64 => DataType::Int64,
+            ValueKind::Float32 => DataType::Float32,
+            ValueKind::Float64 => DataType::Float64,
+            ValueKind::Date => DataType::Date32,
+            ValueKind::Timestamp => DataType::Timestamp(TimeUnit::Microsecond, None),
+            ValueKind::TimestampTz => DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into())),
+            ValueKind::Numeric | ValueKind::Text => DataType::Utf8,
+        }
+    }
+}
+
+impl ExportFormat for ParquetFormat {
+    fn name(&self) -> &'static str {
+        "parquet"
+    }
+
+    fn extension(&self) -> &'static str {
+        "parquet"
+    }
+
+    fn is_binary(&self) -> bool {
+        true
+    }
+
+    fn writer(
+        &self,
+        columns: &[ColumnType],
+        _options: &ExportFormatOptions,
+        output: Box<dyn Write + Send>,
+    ) -> Result<Box<dyn RowWriter>, String> {
+        let kinds: Vec<ValueKind> = columns.iter().map(ValueKind::of).collect();
+        let fields: Vec<Field> = columns
+            .iter()
+            .zip(&kinds)
+            .map(|(column, &kind)| Field::new(&column.name, Self::data_type(kind), true))
+            .collect();
+        let schema = Arc::new(Schema::new(fields));
+        let properties = WriterProperties::builder()
+            .set_compression(Compression::SNAPPY)
+            .set_max_row_group_size(PARQUET_ROW_GROUP_ROWS)
+            .build();
+        let writer = ArrowWriter::try_new(output, schema.clone(), Some(properties))
+            .map_err(|e| format!("Failed to start Parquet file: {}", e))?;
+        Ok(Box::new(ParquetWriter {
+            writer,
+            schema,
+            columns: columns.iter().zip(kinds).map(|(column, kind)| ParquetColumn::new(&column.name, kind)).collect(),
+            buffered_rows: 0,
+        }))
+    }
+}
+
+enum ColumnBuilder {
+    Boolean(BooleanBuilder),
+    Int16(Int16Builder),
+    Int32(Int32Builder),
+    Int64(Int64Builder),
+    Float32(Float32Builder),
+    Float64(Float64Builder),
+    Date(Date32Builder),
+    Timestamp(TimestampMicrosecondBuilder),
+    Text(StringBuilder),
+}
+
+struct ParquetColumn {
+    name: String,
+    builder: ColumnBuilder,
+}
+
+impl ParquetColumn {
+    fn new(name: &str, kind: ValueKind) -> Self {
+        let builder = match kind {
+            ValueKind::Boolean => ColumnBuilder::Boolean(BooleanBuilder::new()),
+            ValueKind::Int16 => ColumnBuilder::Int16(Int16Builder::new()),
+            ValueKind::Int32 => ColumnBuilder::Int32(Int32Builder::new()),
+            ValueKind::Int64 => ColumnBuilder::Int64(Int64Builder::new()),
+            ValueKind::Float32 => ColumnBuilder::Float32(Float32Builder::new()),
+            ValueKind::Float64 => ColumnBuilder::Float64(Float64Builder::new()),
+            ValueKind::Date => ColumnBuilder::Date(Date32Builder::new()),
+            ValueKind::Timestamp => ColumnBuilder::Timestamp(TimestampMicrosecondBuilder::new()),
+            ValueKind::TimestampTz => ColumnBuilder::Timestamp(TimestampMicrosecondBuilder::new().with_timezone("UTC")),
+            ValueKind::Numeric | ValueKind::Text => ColumnBuilder::Text(StringBuilder::new()),
+        };
+        Self {
+            name: name.to_string(),
+            builder,
+        }
+    }
+
+    fn append_null(&mut self) {
+        match &mut self.builder {
+            ColumnBuilder::Boolean(builder) => builder.append_null(),
+            ColumnBuilder::Int16(builder) => builder.append_null(),
+            ColumnBuilder::Int32(builder) => builder.append_null(),
+            ColumnBuilder::Int64(builder) => builder.append_null(),
+            ColumnBuilder::Float32(builder) => builder.append_null(),
+            ColumnBuilder::Float64(builder) => builder.append_null(),
+            ColumnBuilder::Date(builder) => builder.append_null(),
+            ColumnBuilder::Timestamp(builder) => builder.append_null(),
+            ColumnBuilder::Text(builder) => builder.append_null(),
+        }
+    }
+
+    fn append(&mut self, value: &Value) -> Result<(), String> {
+        if value.is_null() {
+            self.append_null();
+            return Ok(());
+        }
+        let appended = match &mut self.builder {
+            ColumnBuilder::Boolean(builder) => value.as_bool().map(|b| builder.append_value(b)),
+            ColumnBuilder::Int16(builder) => {
+                value_i64(value).and_then(|n| i16::try_from(n).ok()).map(|n| builder.append_value(n))
+            }
+            ColumnBuilder::Int32(builder) => {
+                value_i64(value).and_then(|n| i32::try_from(n).ok()).map(|n| builder.append_value(n))
+            }
+            ColumnBuilder::Int64(builder) => value_i64(value).map(|n| builder.append_value(n)),
+            ColumnBuilder::Float32(builder) => value_f64(value).map(|n| builder.append_value(n as f32)),
+            ColumnBuilder::Float64(builder) => value_f64(value).map(|n| builder.append_value(n)),
+            ColumnBuilder::Date(builder) => value
+                .as_str()
+                .and_then(parse_date)
+                .map(|date| builder.append_value(date.num_days_from_ce() - UNIX_EPOCH_DAYS_FROM_CE)),
+            ColumnBuilder::Timestamp(builder) => value
+                .as_str()
+                .and_then(parse_timestamp)
+                .map(|timestamp| builder.append_value(timestamp.and_utc().timestamp_micros())),
+            ColumnBuilder::Text(builder) => {
+                builder.append_value(cell_text(value));
+                Some(())
+            }
+        };
+        appended.ok_or_else(|| format!("Column {}: {} cannot be stored in Parquet", self.name, value))
+    }
+
+    fn finish(&mut self) -> ArrayRef {
+        match &mut self.builder {
+            ColumnBuilder::Boolean(builder) => Arc::new(builder.finish()),
+            ColumnBuilder::Int16(builder) => Arc::new(builder.finish()),
+            ColumnBuilder::Int32(builder) => Arc::new(builder.finish()),
+            ColumnBuilder::Int64(builder) => Arc::new(builder.finish()),
+            ColumnBuilder::Float32(builder) => Arc::new(builder.finish()),
+            ColumnBuilder::Float64(builder) => Arc::new(builder.finish()),
+            ColumnBuilder::Date(builder) => Arc::new(builder.finish()),
+            ColumnBuilder::Timestamp(builder) => Arc::new(builder.finish()),
+            ColumnBuilder::Text(builder) => Arc::new(builder.finish()),
+        }
+    }
+}
+
+struct ParquetWriter {
+    writer: ArrowWriter<Box<dyn Write + Send>>,
+    schema: Arc<Schema>,
+    columns: Vec<ParquetColumn>,
+    buffered_rows: usize,
+}
+
+impl ParquetWriter {
+    fn flush_batch(&mut self) -> Result<(), String> {
+        if self.buffered_rows == 0 {
+            return Ok(());
+        }
+        let arrays = self.columns.iter_mut().map(ParquetColumn::finish).collect();
+        let options = RecordBatchOptions::new().with_row_count(Some(self.buffered_rows));
+        let batch = RecordBatch::try_new_with_options(self.schema.clone(), arrays, &options)
+            .map_err(|e| format!("Failed to build Parquet batch: {}", e))?;
+        self.writer
+            .write(&batch)
+            .map_err(|e| format!("Failed to write Parquet file: {}", e))?;
+        self.buffered_rows = 0;
+        Ok(())
+    }
+}
+
+impl RowWriter for ParquetWriter {
+    fn write_row(&mut self, row: &[Value]) -> Result<(), String> {
+        for (column, value) in self.columns.iter_mut().zip(row) {
+            column.append(value)?;
+        }
+        self.buffered_rows += 1;
+        if self.buffered_rows >= PARQUET_BATCH_ROWS {
+            self.flush_batch()?;
+        }
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<(), String> {
+        self.flush_batch()?;
+        self.writer
+            .close()
+            .map(|_| ())
+            .map_err(|e| format!("Failed to finish Parquet file: {}", e))
+    }
+}
+
+pub struct XlsxFormat;
+
+impl ExportFormat for XlsxFormat {
+    fn name(&self) -> &'static str {
+        "xlsx"
+    }
+
+    fn extension(&self) -> &'static str {
+        "xlsx"
+    }
+
+    fn is_binary(&self) -> bool {
+        true
+    }
+
+    fn writer(
+        &self,
+        columns: &[ColumnType],
+        options: &ExportFormatOptions,
+        output: Box<dyn Write + Send>,
+    ) -> Result<Box<dyn RowWriter>, String> {
+        let mut workbook = Workbook::new();
+        let sheet = workbook.add_worksheet();
+        let mut row = 0;
+        if options.include_headers {
+            let bold = Format::new().set_bold();
+            for (col, column) in columns.iter().enumerate() {
+                sheet
+                    .write_string_with_format(0, col as u16, &column.name, &bold)
+                    .map_err(|e| format!("Failed to write XLSX header: {}", e))?;
+            }
+            sheet.set_freeze_panes(1, 0).map_err(|e| format!("Failed to write XLSX header: {}", e))?;
+            row = 1;
+        }
+        Ok(Box::new(XlsxWriter {
+            workbook,
+            output,
+            kinds: columns.iter().map(ValueKind::of).collect(),
+            row,
+            date_format: Format::new().set_num_format("yyyy-mm-dd"),
+            timestamp_format: Format::new().set_num_format("yyyy-mm-dd hh:mm:ss"),
+        }))
+    }
+}
+
+/// A value as Excel stores it. Numbers and dates become real cells, so they sort and
+/// compute in Excel; anything that does not parse (NaN, infinity) stays text.
+enum XlsxCell<'a> {
+    Boolean(bool),
+    Number(f64),
+    Date(NaiveDate),
+    Timestamp(NaiveDateTime),
+    Text(Cow<'a, str>),
+}
+
+impl<'a> XlsxCell<'a> {
+    /// `None` for NULL, which is left as an empty cell
+    fn of(kind: ValueKind, value: &'a Value) -> Option<Self> {
+        let text = match value {
+            Value::Null => return None,
+            Value::Bool(b) => return Some(XlsxCell::Boolean(*b)),
+            Value::String(text) => Some(text.as_str()),
+            _ => None,
+        };
+        let cell = match kind {
+            ValueKind::Int16
+            | ValueKind::Int32
+            | ValueKind::Int64
+            | ValueKind::Float32
+            | ValueKind::Float64
+            | ValueKind::Numeric => value_f64(value).filter(|n| n.is_finite()).map(XlsxCell::Number),
+            ValueKind::Date => text.and_then(parse_date).map(XlsxCell::Date),
+            ValueKind::Timestamp | ValueKind::TimestampTz => text.and_then(parse_timestamp).map(XlsxCell::Timestamp),
+            ValueKind::Boolean | ValueKind::Text => None,
+        };
+        Some(cell.unwrap_or_else(|| XlsxCell::Text(cell_text(value))))
+    }
+}
+
+/// Builds the workbook in memory and writes it out on `finish`, as the format requires
+struct XlsxWriter {
+    workbook: Workbook,
+    output: Box<dyn Write + Send>,
+    kinds: Vec<ValueKind>,
+    row: u32,
+    date_format: Format,
+    timestamp_format: Format,
+}
+
+impl RowWriter for XlsxWriter {
+    fn write_row(&mut self, values: &[Value]) -> Result<(), String> {
+        if self.row >= XLSX_MAX_ROWS {
+            return Err(format!(
+                "XLSX sheets hold at most {} rows; export to CSV or Parquet instead",
+                XLSX_MAX_ROWS
+            ));
+        }
+        let row = self.row;
+        let sheet = self.workbook.worksheet_from_index(0).map_err(|e| e.to_string())?;
+        for (col, (value, &kind)) in values.iter().zip(&self.kinds).enumerate() {
+            let col = col as u16;
+            let written = match XlsxCell::of(kind, value) {
+                None => continue,
+                Some(XlsxCell::Boolean(b)) => sheet.write_boolean(row, col, b),
+                Some(XlsxCell::Number(n)) => sheet.write_number(row, col, n),
+                Some(XlsxCell::Date(date)) => sheet.write_datetime_with_format(row, col, date, &self.date_format),
+                Some(XlsxCell::Timestamp(timestamp)) => {
+                    sheet.write_datetime_with_format(row, col, timestamp, &self.timestamp_format)
+                }
+                Some(XlsxCell::Text(text)) => sheet.write_string(row, col, text),
+            };
+            written.map_err(|e| format!("Failed to write XLSX row {}: {}", row + 1, e))?;
+        }
+        self.row += 1;
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<(), String> {
+        let bytes = self
+            .workbook
+            .save_to_buffer()
+            .map_err(|e| format!("Failed to finish XLSX file: {}", e))?;
+        self.output.write_all(&bytes).map_err(write_error)?;
+        self.output.flush().map_err(write_error)
+    }
+}
+
+pub struct MarkdownFormat;
+
+impl MarkdownFormat {
+    fn escape(text: &str) -> String {
+        text.replace('\\', "\\\\")
+            .replace('|', "\\|")
+            .replace("\r\n", "<br>")
+            .replace('\n', "<br>")
+    }
+}
+
+impl ExportFormat for MarkdownFormat {
+    fn name(&self) -> &'static str {
+        "markdown"
+    }
+
+    fn extension(&self) -> &'static str {
+        "md"
+    }
+
+    fn writer(
+        &self,
+        columns: &[ColumnType],
+        _options: &ExportFormatOptions,
+        mut output: Box<dyn Write + Send>,
+    ) -> Result<Box<dyn RowWriter>, String> {
+        let header: Vec<String> = columns.iter().map(|column| Self::escape(&column.name)).collect();
+        writeln!(output, "| {} |", header.join(" | ")).map_err(write_error)?;
+        writeln!(output, "|{}", " --- |".repeat(columns.len())).map_err(write_error)?;
+        Ok(Box::new(MarkdownWriter(output)))
+    }
+}
+
+struct MarkdownWriter(Box<dyn Write + Send>);
+
+impl RowWriter for MarkdownWriter {
+    fn write_row(&mut self, row: &[Value]) -> Result<(), String> {
+        let cells: Vec<String> = row.iter().map(|value| MarkdownFormat::escape(&cell_text(value))).collect();
+        writeln!(self.0, "| {} |", cells.join(" | ")).map_err(write_error)
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<(), String> {
+        self.0.flush().map_err(write_error)
+    }
+}
+
+pub struct HtmlFormat;
+
+impl HtmlFormat {
+    fn escape(text: &str) -> String {
+        text.replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;")
+            .replace('"', "&quot;")
+    }
+}
+
+impl ExportFormat for HtmlFormat {
+    fn name(&self) -> &'static str {
+        "html"
+    }
+
+    fn extension(&self) -> &'static str {
+        "html"
+    }
+
+    fn writer(
+        &self,
+        columns: &[ColumnType],
+        _options: &ExportFormatOptions,
+        mut output: Box<dyn Write + Send>,
+    ) -> Result<Box<dyn RowWriter>, String> {
+        let header: String = columns
+            .iter()
+            .map(|column| format!("<th>{}</th>", Self::escape(&column.name)))
+            .collect();
+        write!(
+            output,
+            "<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"></head>\n<body>\n<table>\n<thead><tr>{}</tr></thead>\n<tbody>\n",
+            header
+        )
+        .map_err(write_error)?;
+        Ok(Box::new(HtmlWriter(output)))
+    }
+}
+
+struct HtmlWriter(Box<dyn Write + Send>);
+
+impl RowWriter for HtmlWriter {
+    fn write_row(&mut self, row: &[Value]) -> Result<(), String> {
+        let cells: String = row
+            .iter()
+            .map(|value| format!("<td>{}</td>", HtmlFormat::escape(&cell_text(value))))
+            .collect();
+        writeln!(self.0, "<tr>{}</tr>", cells).map_err(write_error)
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<(), String> {
+        self.0
+            .write_all(b"</tbody>\n</table>\n</body>\n</html>\n")
+            .map_err(write_error)?;
+        self.0.flush().map_err(write_error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn columns(types: &[(&str, &str)]) -> Vec<ColumnType> {
+        types
+            .iter()
+            .map(|(name, type_name)| ColumnType {
+                name: name.to_string(),
+                type_oid: 0,
+                type_name: type_name.to_string(),
+            })
+            .collect()
+    }
+
+    fn render_text(format: &str, columns: &[ColumnType], rows: &[Vec<Value>]) -> String {
+        let bytes = render(find_format(format).unwrap(), columns, rows, &ExportFormatOptions::default()).unwrap();
+        String::from_utf8(bytes).unwrap()
+    }
+
+    #[test]
+    fn test_find_format_is_case_insensitive() {
+        assert_eq!(find_format("NDJSON").unwrap().extension(), "ndjson");
+        assert!(find_format("Parquet").unwrap().is_binary());
+        let error = find_format("yaml").err().unwrap();
+        assert!(error.contains("markdown"), "{}", error);
+    }
+
+    #[test]
+    fn test_text_formats() {
+        let columns = columns(&[("b", "text"), ("a", "int4")]);
+        let rows = vec![vec![json!("x, \"y\""), json!(1)], vec![json!("a|<b>"), Value::Null]];
+
+        assert_eq!(render_text("csv", &columns, &rows), "b,a\n\"x, \"\"y\"\"\",1\na|<b>,\n");
+        // Fields stay in column order rather than being sorted
+        assert_eq!(
+            render_text("json", &columns, &rows),
+            "[\n  {\"b\":\"x, \\\"y\\\"\",\"a\":1},\n  {\"b\":\"a|<b>\",\"a\":null}\n]\n"
+        );
+        assert_eq!(render_text("json", &columns, &[]), "[\n]\n");
+        assert_eq!(
+            render_text("ndjson", &columns, &rows),
+            "{\"b\":\"x, \\\"y\\\"\",\"a\":1}\n{\"b\":\"a|<b>\",\"a\":null}\n"
+        );
+        assert_eq!(
+            render_text("markdown", &columns, &rows),
+            "| b | a |\n| --- | --- |\n| x, \"y\" | 1 |\n| a\\|<b> |  |\n"
+        );
+        let html = render_text("html", &columns, &rows);
+        assert!(html.contains("<thead><tr><th>b</th><th>a</th></tr></thead>"), "{}", html);
+        assert!(html.contains("<tr><td>a|&lt;b&gt;</td><td></td></tr>"), "{}", html);
+    }
+
+    #[test]
+    fn test_parses_codec_dates_and_timestamps() {
+        assert_eq!(parse_date("2024-02-29"), NaiveDate::from_ymd_opt(2024, 2, 29));
+        assert_eq!(parse_date("0044-03-15 BC"), NaiveDate::from_ymd_opt(-43, 3, 15));
+        assert_eq!(parse_date("infinity"), None);
+        assert_eq!(
+            parse_timestamp("2024-01-02 03:04:05.25+00"),
+            NaiveDate::from_ymd_opt(2024, 1, 2).and_then(|d| d.and_hms_milli_opt(3, 4, 5, 250))
+        );
+        assert_eq!(
+            parse_timestamp("2024-01-02 03:04:05"),
+            NaiveDate::from_ymd_opt(2024, 1, 2).and_then(|d| d.and_hms_opt(3, 4, 5))
+        );
+    }
+
+    #[test]
+    fn test_parquet_maps_column_types() {
+        let columns = columns(&[
+            ("id", "int8"),
+            ("ok", "bool"),
+            ("day", "date"),
+            ("at", "timestamptz"),
+            ("price", "numeric"),
+        ]);
+        let rows = vec![
+            vec![json!(1), json!(true), json!("1970-01-02"), json!("1970-01-01 00:00:01+00"), json!("1.50")],
+            vec![json!("9007199254740993"), Value::Null, Value::Null, Value::Null, Value::Null],
+        ];
+        let bytes = render(&ParquetFormat, &columns, &rows, &ExportFormatOptions::default()).unwrap();
+        assert_eq!(&bytes[..4], b"PAR1");
+
+        let reader = parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder::try_new(bytes::Bytes::from(bytes))
+            .unwrap()
+            .build()
+            .unwrap();
+        let batches: Vec<RecordBatch> = reader.map(|batch| batch.unwrap()).collect();
+        let schema = batches[0].schema();
+        assert_eq!(schema.field(0).data_type(), &DataType::Int64);
+        assert_eq!(schema.field(2).data_type(), &DataType::Date32);
+        assert_eq!(schema.field(3).data_type(), &DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into())));
+        assert_eq!(schema.field(4).data_type(), &DataType::Utf8);
+
+        let ids = batches[0].column(0).as_any().downcast_ref::<arrow_array::Int64Array>().unwrap();
+        assert_eq!(ids.value(1), 9_007_199_254_740_993);
+        let days = batches[0].column(2).as_any().downcast_ref::<arrow_array::Date32Array>().unwrap();
+        assert_eq!(days.value(0), 1);
+        let at = batches[0]
+            .column(3)
+            .as_any()
+            .downcast_ref::<arrow_array::TimestampMicrosecondArray>()
+            .unwrap();
+        assert_eq!(at.value(0), 1_000_000);
+    }
+
+    #[test]
+    fn test_parquet_rejects_mistyped_values() {
+        let columns = columns(&[("n", "int2")]);
+        let error = render(&ParquetFormat, &columns, &[vec![json!(70000)]], &ExportFormatOptions::default()).unwrap_err();
+        assert!(error.contains("Column n"), "{}", error);
+    }
+
+    #[test]
+    fn test_xlsx_writes_a_workbook() {
+        let columns = columns(&[("n", "numeric"), ("day", "date"), ("note", "text")]);
+        let rows = vec![vec![json!("2.5"), json!("2024-01-01"), json!("hi")], vec![Value::Null, json!("infinity"), Value::Null]];
+        let bytes = render(&XlsxFormat, &columns, &rows, &ExportFormatOptions::default()).unwrap();
+        // XLSX files are zip archives
+        assert_eq!(&bytes[..2], b"PK");
+    }
+}
@@ -10,21 +10,50 @@ mod connection_profile_store;
 mod connection_profile_store_commands;
 #[cfg(test)]
 mod connection_profile_store_test;
+mod credential_backend;
 mod credential_vault;
 mod credential_vault_commands;
+mod export_format;
+mod production_guard;
+mod query_history;
+mod query_history_commands;
+mod query_plan;
+mod query_stream;
+mod saved_query;
+mod saved_query_commands;
+mod saved_query_store;
+mod schema_diff;
+mod session_registry;
 mod simple_db;
+mod sql_builder;
+mod sql_import;
+mod sql_script;
+mod sql_splitter;
+mod ssh_tunnel;
+mod table_export;
+mod table_import;
+mod tls_config;
+mod transaction_session;
+mod value_codec;
 
 use commands::*;
 use connection_health_commands::*;
 use connection_health_service::ConnectionHealthService;
-use connection_pool::{ConnectionPool, PoolConfig};
+use connection_pool::PoolConfig;
 use connection_profile_commands::*;
 use connection_profile_store::ConnectionProfileStore;
 use connection_profile_store_commands::*;
+use credential_backend::{EncryptedFileBackend, KeyringBackend};
 use credential_vault::CredentialVault;
 use credential_vault_commands::*;
+use query_history::QueryHistoryStore;
+use query_history_commands::*;
+use query_stream::QueryStreamRegistry;
+use saved_query_commands::*;
+use saved_query_store::SavedQueryStore;
 use serde::{Deserialize, Serialize};
-use simple_db::SimpleDatabase;
+use session_registry::SessionRegistry;
+use table_export::TableExportRegistry;
 use std::sync::Arc;
 use tokio::sync::Mutex;
 
@@ -48,6 +77,46 @@ async fn initialize_database_logger() -> Result<(), String> {
     Ok(())
 }
 
+/// Pick the credential storage backend.
+///
+/// `POSTGRESQL_QUERY_TOOL_CREDENTIAL_BACKEND` selects `keyring` (default) or `file`.
+/// The encrypted file backend needs `POSTGRESQL_QUERY_TOOL_VAULT_PASSPHRASE` and is
+/// also used automatically when the OS keyring is unavailable and a passphrase is set.
+fn create_credential_vault(app_data_dir: &str) -> CredentialVault {
+    const SERVICE_NAME: &str = "postgresql_query_tool";
+    
+    let requested = std::env::var("POSTGRESQL_QUERY_TOOL_CREDENTIAL_BACKEND")
+        .unwrap_or_else(|_| "keyring".to_string());
+    let passphrase = std::env::var("POSTGRESQL_QUERY_TOOL_VAULT_PASSPHRASE").ok();
+    let vault_path = std::path::Path::new(app_data_dir)
+        .join(SERVICE_NAME)
+        .join("credentials.vault");
+    
+    let use_file = match requested.to_lowercase().as_str() {
+        "file" => true,
+        "keyring" => passphrase.is_some() && !KeyringBackend::new(SERVICE_NAME).is_available(),
+        other => {
+            eprintln!("Unknown credential backend '{}', using keyring", other);
+            false
+        }
+    };
+    
+    if use_file {
+        match passphrase {
+            Some(passphrase) => match EncryptedFileBackend::open(&vault_path, &passphrase) {
+                Ok(backend) => {
+                    println!("🦀 [Rust] Using encrypted file credential backend at {:?}", vault_path);
+                    return CredentialVault::with_backend(Box::new(backend));
+                }
+                Err(e) => eprintln!("Failed to open encrypted credential file, using keyring: {}", e),
+            },
+            None => eprintln!("POSTGRESQL_QUERY_TOOL_VAULT_PASSPHRASE is not set, using keyring"),
+        }
+    }
+    
+    CredentialVault::new(SERVICE_NAME)
+}
+
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     // Initialize logging
@@ -56,24 +125,26 @@ pub fn run() {
     println!("🦀 [Rust] Starting PostgreSQL Query Tool");
     log::info!("Starting PostgreSQL Query Tool");
 
-    println!("🦀 [Rust] Creating database connection and connection pool...");
-    let simple_db = Arc::new(Mutex::new(SimpleDatabase::new()));
+    println!("🦀 [Rust] Creating database session registry...");
 
-    // Create connection pool with optimized settings
+    // Every session gets its own connection pool with these settings
     let pool_config = PoolConfig {
         max_size: 20, // Increased pool size for better performance
         connection_timeout_secs: 30,
         idle_timeout_secs: 300, // 5 minutes idle timeout
     };
-    let connection_pool = Arc::new(Mutex::new(ConnectionPool::new(pool_config)));
-    
-    // Create credential vault
-    let credential_vault = Arc::new(Mutex::new(CredentialVault::new("postgresql_query_tool")));
+    let session_registry = Arc::new(Mutex::new(SessionRegistry::new(pool_config)));
+    let query_streams = Arc::new(Mutex::new(QueryStreamRegistry::new()));
+    let table_exports = Arc::new(Mutex::new(TableExportRegistry::new()));
     
-    // Create connection profile store
     let app_data_dir = std::env::var("APPDATA")
         .or_else(|_| std::env::var("HOME").map(|home| format!("{}/.config", home)))
         .unwrap_or_else(|_| ".".to_string());
+    
+    // Create credential vault
+    let credential_vault = Arc::new(Mutex::new(create_credential_vault(&app_data_dir)));
+    
+    // Create connection profile store
     let profiles_path = std::path::Path::new(&app_data_dir)
         .join("postgresql_query_tool")
         .join("connection_profiles.json");
@@ -86,34 +157,75 @@ pub fn run() {
         }
     };
     
+    // Create query history store
+    let history_path = std::path::Path::new(&app_data_dir)
+        .join("postgresql_query_tool")
+        .join("query_history.json");
+    
+    let query_history = match QueryHistoryStore::open(&history_path) {
+        Ok(store) => Arc::new(Mutex::new(store)),
+        Err(e) => {
+            eprintln!("Failed to initialize query history store: {}", e);
+            std::process::exit(1);
+        }
+    };
+    
+    // Create saved query library
+    let saved_queries_path = std::path::Path::new(&app_data_dir)
+        .join("postgresql_query_tool")
+        .join("saved_queries.json");
+    
+    let saved_queries = match SavedQueryStore::open(&saved_queries_path) {
+        Ok(store) => Arc::new(Mutex::new(store)),
+        Err(e) => {
+            eprintln!("Failed to initialize saved query library: {}", e);
+            std::process::exit(1);
+        }
+    };
+    
     // Create connection health service
     let connection_health_service = Arc::new(Mutex::new(ConnectionHealthService::new()));
     
-    println!("🦀 [Rust] Database connection, pool, credential vault, profile store, and health service created successfully");
+    println!("🦀 [Rust] Session registry, credential vault, profile store, query history, saved queries, and health service created successfully");
 
     tauri::Builder::default()
         .plugin(tauri_plugin_opener::init())
         .plugin(tauri_plugin_dialog::init())
-        .manage(simple_db)
-        .manage(connection_pool)
+        .manage(session_registry)
+        .manage(query_streams)
+        .manage(table_exports)
         .manage(credential_vault)
         .manage(connection_profile_store)
         .manage(connection_health_service)
+        .manage(query_history)
+        .manage(saved_queries)
         .invoke_handler(tauri::generate_handler![
             greet,
             initialize_database_logger,
             connect_database,
+            connect_profile,
             disconnect_database,
+            list_sessions,
+            close_session,
             is_connected,
             get_connection_status,
             get_pool_status,
             execute_query,
+            execute_saved_query,
+            check_saved_query,
+            execute_script,
+            explain_query,
+            cancel_query,
+            check_operation,
+            unlock_production_writes,
+            lock_production_writes,
             get_tables,
             get_table_columns,
             get_detailed_table_columns,
             get_enum_values,
             build_safe_query,
             query_table,
+            get_row_identifier,
             get_table_foreign_keys,
             get_table_indexes,
             get_all_indexes,
@@ -121,6 +233,7 @@ pub fn run() {
             drop_index,
             get_views,
             get_stored_procedures,
+            compare_schemas,
             get_materialized_views,
             update_row,
             insert_row,
@@ -128,13 +241,26 @@ pub fn run() {
             begin_transaction,
             commit_transaction,
             rollback_transaction,
+            create_savepoint,
+            rollback_to_savepoint,
+            release_savepoint,
+            get_transaction_state,
             execute_transaction,
             execute_streaming_query,
+            start_query_stream,
+            ack_query_stream,
+            cancel_query_stream,
             export_table_sql,
             export_table_csv_json,
+            export_query_results,
+            export_existing_results,
+            start_table_export,
+            start_query_export,
+            cancel_table_export,
             export_query_result_sql,
             import_sql_file,
             import_sql_from_file,
+            import_table_data,
             save_export_to_file,
             // Connection Profile Management Commands
             create_sample_connection_profile,
@@ -149,9 +275,12 @@ pub fn run() {
             retrieve_profile_credentials,
             update_profile_credentials,
             delete_profile_credentials,
+            store_ssh_tunnel_secret,
+            delete_ssh_tunnel_secret,
             has_profile_credentials,
             list_profiles_with_credentials,
             rotate_vault_master_key,
+            reconcile_vault_credentials,
             // Connection Profile Store Commands
             initialize_profile_store,
             create_connection_profile,
@@ -182,7 +311,23 @@ pub fn run() {
             calculate_profile_uptime,
             batch_test_profiles,
             quick_connection_test,
-            get_connection_troubleshooting_suggestions
+            get_connection_troubleshooting_suggestions,
+            // Query History Commands
+            search_query_history,
+            pin_query_history_entry,
+            purge_query_history,
+            get_history_retention,
+            set_history_retention,
+            // Saved Query Commands
+            search_saved_queries,
+            get_saved_query,
+            create_saved_query,
+            update_saved_query,
+            delete_saved_query,
+            get_saved_query_folders,
+            get_saved_query_tags,
+            export_saved_queries,
+            import_saved_queries
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
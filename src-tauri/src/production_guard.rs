@@ -0,0 +1,653 @@
+//! Write protection for sessions connected to a production profile.
+//!
+//! Every guarded command classifies what it is about to do. On production, reads run
+//! freely; any write needs a single-use confirmation token obtained from
+//! `check_operation` for that exact operation, and destructive operations are refused
+//! outright until the session's writes have been unlocked for a while. Classification
+//! is lexical, so unconfirmed reads also run read-only on the server, which refuses
+//! whatever the classification missed. Other environments are not restricted.
+
+use crate::sql_splitter::split_statements;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// How long a confirmation token stays valid
+const CONFIRMATION_TTL_SECONDS: i64 = 300;
+const DEFAULT_UNLOCK_MINUTES: u64 = 15;
+const MAX_UNLOCK_MINUTES: u64 = 60;
+
+/// What a statement can do to the database, from harmless to hardest to undo
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StatementClass {
+    Read,
+    Dml,
+    Ddl,
+    /// DROP, TRUNCATE, UPDATE or DELETE without WHERE, and ALTER ... DROP
+    Destructive,
+}
+
+/// How an operation the guard let through has to run
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    /// An unconfirmed read on production, to be run in a read-only transaction
+    ReadOnly,
+    ReadWrite,
+}
+
+impl Access {
+    pub fn is_read_only(self) -> bool {
+        self == Access::ReadOnly
+    }
+}
+
+/// Which edit a `GuardOperation::RowChange` makes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RowOperation {
+    Insert,
+    Update,
+    Delete,
+}
+
+/// Something a guarded command is about to do
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum GuardOperation {
+    /// SQL written by the user: a query or a whole script
+    Sql { sql: String },
+    /// Inserting, updating or deleting one row from the data grid. `key` holds the row's
+    /// key values, and is empty for inserts.
+    RowChange {
+        schema: Option<String>,
+        table: String,
+        key: Vec<serde_json::Value>,
+        operation: RowOperation,
+    },
+    /// The operations of one `execute_transaction` call
+    RowChanges { operations: Vec<serde_json::Value> },
+    /// Importing a file, which may run arbitrary SQL
+    Import,
+    CreateIndex,
+    DropIndex,
+}
+
+impl GuardOperation {
+    pub fn class(&self) -> StatementClass {
+        match self {
+            GuardOperation::Sql { sql } => classify_sql(sql),
+            GuardOperation::RowChange { .. } => StatementClass::Dml,
+            GuardOperation::RowChanges { .. } if self.deletes_several_rows() => StatementClass::Destructive,
+            GuardOperation::RowChanges { .. } => StatementClass::Dml,
+            GuardOperation::CreateIndex => StatementClass::Ddl,
+            GuardOperation::Import | GuardOperation::DropIndex => StatementClass::Destructive,
+        }
+    }
+
+    /// More than one delete in a transaction counts as a bulk delete
+    fn deletes_several_rows(&self) -> bool {
+        match self {
+            GuardOperation::RowChanges { operations } => {
+                operations
+                    .iter()
+                    .filter(|operation| operation.get("type").and_then(|v| v.as_str()) == Some("delete"))
+                    .count()
+                    > 1
+            }
+            _ => false,
+        }
+    }
+
+    fn describe(&self) -> &'static str {
+        match self {
+            GuardOperation::Sql { .. } => match self.class() {
+                StatementClass::Read => "This query",
+                StatementClass::Dml => "This data change",
+                StatementClass::Ddl => "This schema change",
+                StatementClass::Destructive => "This destructive statement",
+            },
+            GuardOperation::RowChange { .. } => "Editing rows",
+            GuardOperation::RowChanges { .. } if self.deletes_several_rows() => "Deleting several rows",
+            GuardOperation::RowChanges { .. } => "Editing rows",
+            GuardOperation::Import => "Importing",
+            GuardOperation::CreateIndex => "Creating an index",
+            GuardOperation::DropIndex => "Dropping an index",
+        }
+    }
+}
+
+/// Whether an operation may run, and what it takes if it may not yet
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GuardDecision {
+    pub class: StatementClass,
+    pub production: bool,
+    /// Runs without a confirmation token
+    pub allowed: bool,
+    /// Refused until the session's writes are unlocked
+    pub requires_unlock: bool,
+    /// Pass this back with the operation to confirm it
+    pub confirmation_token: Option<String>,
+    pub token_expires_at: Option<DateTime<Utc>>,
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GuardStatus {
+    pub production: bool,
+    /// Destructive operations are possible until then
+    pub unlocked_until: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone)]
+struct PendingConfirmation {
+    operation: GuardOperation,
+    expires_at: DateTime<Utc>,
+}
+
+/// Per-session guard state: the unlock window and outstanding confirmations
+#[derive(Debug, Default)]
+pub struct ProductionGuard {
+    unlocked_until: Option<DateTime<Utc>>,
+    confirmations: HashMap<String, PendingConfirmation>,
+}
+
+impl ProductionGuard {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn is_unlocked(&self, now: DateTime<Utc>) -> bool {
+        self.unlocked_until.is_some_and(|until| until > now)
+    }
+
+    pub fn status(&self, production: bool, now: DateTime<Utc>) -> GuardStatus {
+        GuardStatus {
+            production,
+            unlocked_until: self.unlocked_until.filter(|_| production && self.is_unlocked(now)),
+        }
+    }
+
+    /// Allow destructive operations for `minutes`, 15 by default and at most an hour
+    pub fn unlock(&mut self, minutes: Option<u64>, now: DateTime<Utc>) {
+        let minutes = minutes.unwrap_or(DEFAULT_UNLOCK_MINUTES).clamp(1, MAX_UNLOCK_MINUTES);
+        self.unlocked_until = Some(now + Duration::minutes(minutes as i64));
+    }
+
+    pub fn lock(&mut self) {
+        self.unlocked_until = None;
+    }
+
+    fn blocked_message(operation: &GuardOperation) -> String {
+        format!(
+            "{} is blocked on production; unlock writes for this session first",
+            operation.describe()
+        )
+    }
+
+    /// Decide on an operation, handing out a confirmation token when one would let it run
+    pub fn decide(&mut self, production: bool, operation: &GuardOperation, now: DateTime<Utc>) -> GuardDecision {
+        let class = operation.class();
+        let mut decision = GuardDecision {
+            class,
+            production,
+            allowed: true,
+            requires_unlock: false,
+            confirmation_token: None,
+            token_expires_at: None,
+            message: None,
+        };
+        if !production || class == StatementClass::Read {
+            return decision;
+        }
+
+        decision.allowed = false;
+        if class == StatementClass::Destructive && !self.is_unlocked(now) {
+            decision.requires_unlock = true;
+            decision.message = Some(Self::blocked_message(operation));
+            return decision;
+        }
+
+        self.confirmations.retain(|_, pending| pending.expires_at > now);
+        let token = uuid::Uuid::new_v4().to_string();
+        let expires_at = now + Duration::seconds(CONFIRMATION_TTL_SECONDS);
+        self.confirmations.insert(
+            token.clone(),
+            PendingConfirmation {
+                operation: operation.clone(),
+                expires_at,
+            },
+        );
+        decision.confirmation_token = Some(token);
+        decision.token_expires_at = Some(expires_at);
+        decision.message = Some(format!("{} writes to a production database; confirm to run it", operation.describe()));
+        decision
+    }
+
+    /// Check that an operation may run now, using up its confirmation token
+    pub fn authorize(
+        &mut self,
+        production: bool,
+        operation: &GuardOperation,
+        token: Option<&str>,
+        now: DateTime<Utc>,
+    ) -> Result<Access, String> {
+        let class = operation.class();
+        if !production {
+            return Ok(Access::ReadWrite);
+        }
+        if class == StatementClass::Read {
+            return Ok(Access::ReadOnly);
+        }
+        if class == StatementClass::Destructive && !self.is_unlocked(now) {
+            return Err(Self::blocked_message(operation));
+        }
+
+        let token = token.ok_or_else(|| {
+            format!(
+                "{} writes to a production database and needs confirmation",
+                operation.describe()
+            )
+        })?;
+        match self.confirmations.remove(token) {
+            Some(pending) if pending.expires_at <= now => Err("The confirmation has expired; confirm again".to_string()),
+            Some(pending) if pending.operation == *operation => Ok(Access::ReadWrite),
+            _ => Err("The confirmation does not match this operation; confirm again".to_string()),
+        }
+    }
+}
+
+/// Classify SQL that may hold several statements by the most dangerous of them
+pub fn classify_sql(sql: &str) -> StatementClass {
+    match split_statements(sql) {
+        Ok(statements) => statements
+            .iter()
+            .map(|statement| classify_statement(&statement.sql))
+            .max()
+            .unwrap_or(StatementClass::Read),
+        // Only COPY data or psql meta-commands fail to split; treat them as writes at least
+        Err(_) => classify_statement(sql).max(StatementClass::Dml),
+    }
+}
+
+/// Functions a read-only transaction does not stop, and the setting that would end it
+const READ_ONLY_ESCAPES: &[&str] = &[
+    "set_config",
+    "transaction_read_only",
+    "pg_terminate_backend",
+    "pg_cancel_backend",
+    "pg_reload_conf",
+    "pg_rotate_logfile",
+    "pg_notify",
+    "dblink",
+];
+
+/// Classify one statement. Anything not recognised as a read counts as a write.
+pub fn classify_statement(sql: &str) -> StatementClass {
+    let tokens = tokens(sql);
+    let class = classify_tokens(&tokens);
+    if class == StatementClass::Read && escapes_read_only(sql, &tokens) {
+        return StatementClass::Dml;
+    }
+    class
+}
+
+/// Whether a read could act outside the read-only transaction it runs in. Names are
+/// matched anywhere, even inside quoted identifiers.
+fn escapes_read_only(sql: &str, tokens: &[String]) -> bool {
+    let sql = sql.to_lowercase();
+    READ_ONLY_ESCAPES.iter().any(|name| sql.contains(name))
+        || tokens.windows(2).any(|pair| pair[0] == "READ" && pair[1] == "WRITE")
+}
+
+fn classify_tokens(tokens: &[String]) -> StatementClass {
+    let Some(first) = tokens.first() else {
+        return StatementClass::Read;
+    };
+    let has = |word: &str| top_level(tokens).any(|token| token == word);
+    match first.as_str() {
+        // SELECT ... INTO creates a table
+        "SELECT" if has("INTO") => StatementClass::Ddl,
+        // Row locks need a read-write transaction
+        "SELECT" if locks_rows(tokens) => StatementClass::Dml,
+        // A holdable cursor runs its query when the transaction commits
+        "DECLARE" if has("HOLD") => StatementClass::Dml,
+        "SELECT" | "VALUES" | "TABLE" | "SHOW" | "FETCH" | "MOVE" | "CLOSE" | "DECLARE" | "LISTEN" | "UNLISTEN"
+        | "SET" | "RESET" | "DISCARD" | "DEALLOCATE" | "BEGIN" | "START" | "COMMIT" | "END" | "ROLLBACK"
+        | "ABORT" | "SAVEPOINT" | "RELEASE" => StatementClass::Read,
+        "WITH" => classify_with(tokens),
+        "EXPLAIN" => {
+            // Only EXPLAIN ANALYZE actually runs the statement
+            match tokens.iter().position(|token| is_statement_start(token)) {
+                Some(start) if tokens[..start].iter().any(|token| token == "ANALYZE" || token == "ANALYSE") => {
+                    classify_tokens(&tokens[start..])
+                }
+                _ => StatementClass::Read,
+            }
+        }
+        "COPY" => {
+            let mut words = top_level(tokens).skip_while(|token| *token != "FROM" && *token != "TO");
+            match (words.next(), words.next()) {
+                (Some("TO"), Some("STDOUT")) => StatementClass::Read,
+                // Writes a file or runs a program on the server
+                (Some("TO"), _) => StatementClass::Destructive,
+                _ => StatementClass::Dml,
+            }
+        }
+        "UPDATE" | "DELETE" if !has("WHERE") => StatementClass::Destructive,
+        "INSERT" | "UPDATE" | "DELETE" | "MERGE" | "LOCK" | "PREPARE" | "NOTIFY" => StatementClass::Dml,
+        // Run code the guard cannot see
+        "DO" | "CALL" | "EXECUTE" => StatementClass::Destructive,
+        "DROP" | "TRUNCATE" => StatementClass::Destructive,
+        "ALTER" if has("DROP") => StatementClass::Destructive,
+        _ => StatementClass::Ddl,
+    }
+}
+
+/// A `WITH` query is as dangerous as its main statement or any data-modifying CTE
+fn classify_with(tokens: &[String]) -> StatementClass {
+    let mut class = StatementClass::Read;
+    let mut depth = 0;
+    for (i, token) in tokens.iter().enumerate().skip(1) {
+        match token.as_str() {
+            "(" => depth += 1,
+            ")" => depth -= 1,
+            word if is_statement_start(word) => {
+                let nested = depth > 0 && tokens[i - 1] == "(";
+                if depth == 0 || nested {
+                    class = class.max(classify_tokens(&tokens[i..]));
+                }
+                if depth == 0 {
+                    break;
+                }
+            }
+            _ => {}
+        }
+    }
+    class
+}
+
+/// `SELECT ... FOR UPDATE`, `FOR SHARE` and their variants
+fn locks_rows(tokens: &[String]) -> bool {
+    let words: Vec<&str> = top_level(tokens).collect();
+    words
+        .windows(2)
+        .any(|pair| pair[0] == "FOR" && matches!(pair[1], "UPDATE" | "SHARE" | "NO" | "KEY"))
+}
+
+fn is_statement_start(token: &str) -> bool {
+    matches!(
+        token,
+        "SELECT" | "INSERT" | "UPDATE" | "DELETE" | "MERGE" | "VALUES" | "TABLE" | "WITH" | "CREATE" | "EXECUTE"
+    )
+}
+
+/// Words outside parentheses, ending where an enclosing parenthesis closes
+fn top_level(tokens: &[String]) -> impl Iterator<Item = &str> {
+    let mut depth = 0i32;
+    tokens
+        .iter()
+        .map(move |token| {
+            match token.as_str() {
+                "(" => depth += 1,
+                ")" => depth -= 1,
+                _ => {}
+            }
+            (depth, token.as_str())
+        })
+        .take_while(|(depth, _)| *depth >= 0)
+        .filter(|(depth, token)| *depth == 0 && *token != ")")
+        .map(|(_, token)| token)
+}
+
+/// Upper-cased words and parentheses, skipping literals, quoted identifiers and comments
+fn tokens(sql: &str) -> Vec<String> {
+    lex(sql)
+        .into_iter()
+        .filter(|token| token.text.starts_with(|c: char| c.is_alphanumeric() || c == '_' || c == '(' || c == ')'))
+        .map(|token| token.text.to_uppercase())
+        .collect()
+}
+
+/// A word, parenthesis, bracket, `:` or `::` of the SQL text
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Token<'a> {
+    pub text: &'a str,
+    /// Byte offset in the SQL
+    pub start: usize,
+}
+
+/// Tokens outside literals, quoted identifiers and comments
+pub(crate) fn lex(sql: &str) -> Vec<Token<'_>> {
+    let chars: Vec<char> = sql.chars().collect();
+    let offsets: Vec<usize> = sql.char_indices().map(|(offset, _)| offset).chain([sql.len()]).collect();
+    let token = |start: usize, end: usize| Token { text: &sql[offsets[start]..offsets[end]], start: offsets[start] };
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+        match c {
+            '-' if next == Some('-') => {
+                while i < chars.len() && chars[i] != '\n' {
+                    i += 1;
+                }
+            }
+            '/' if next == Some('*') => {
+                let mut depth = 0;
+                while i < chars.len() {
+                    if chars[i] == '/' && chars.get(i + 1) == Some(&'*') {
+                        depth += 1;
+                        i += 2;
+                    } else if chars[i] == '*' && chars.get(i + 1) == Some(&'/') {
+                        depth -= 1;
+                        i += 2;
+                        if depth == 0 {
+                            break;
+                        }
+                    } else {
+                        i += 1;
+                    }
+                }
+            }
+            '\'' => {
+                // E'...' strings may escape quotes with a backslash
+                let escapes =
+                    i > 0 && matches!(chars[i - 1], 'e' | 'E') && tokens.last().is_some_and(|token: &Token| token.text.eq_ignore_ascii_case("e"));
+                i += 1;
+                while i < chars.len() {
+                    match chars[i] {
+                        '\\' if escapes => i += 2,
+                        '\'' if chars.get(i + 1) == Some(&'\'') => i += 2,
+                        '\'' => break,
+                        _ => i += 1,
+                    }
+                }
+                i += 1;
+            }
+            '"' => {
+                i += 1;
+                while i < chars.len() && chars[i] != '"' {
+                    i += 1;
+                }
+                i += 1;
+            }
+            '$' => {
+                let tag_end = chars[i + 1..]
+                    .iter()
+                    .position(|&c| !(c.is_alphanumeric() || c == '_'))
+                    .map(|offset| i + 1 + offset);
+                match tag_end {
+                    Some(end) if chars[end] == '$' && !chars[i + 1].is_ascii_digit() => {
+                        let tag: String = chars[i..=end].iter().collect();
+                        let body: String = chars[end + 1..].iter().collect();
+                        i = match body.find(&tag) {
+                            Some(close) => end + 1 + body[..close].chars().count() + tag.chars().count(),
+                            None => chars.len(),
+                        };
+                    }
+                    _ => i += 1,
+                }
+            }
+            ':' if next == Some(':') => {
+                tokens.push(token(i, i + 2));
+                i += 2;
+            }
+            '(' | ')' | '[' | ']' | ':' => {
+                tokens.push(token(i, i + 1));
+                i += 1;
+            }
+            c if c.is_alphanumeric() || c == '_' => {
+                let start = i;
+                while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_' || chars[i] == '$') {
+                    i += 1;
+                }
+                tokens.push(token(start, i));
+            }
+            _ => i += 1,
+        }
+    }
+    tokens
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classifies_statements() {
+        use StatementClass::*;
+        let cases = [
+            ("SELECT * FROM t WHERE a = 'DROP TABLE x'", Read),
+            ("select 1 -- delete from t", Read),
+            ("SELECT * INTO copy FROM t", Ddl),
+            ("SELECT * FROM t FOR UPDATE", Dml),
+            ("SELECT * FROM t FOR KEY SHARE", Dml),
+            ("EXPLAIN DELETE FROM t", Read),
+            ("EXPLAIN (ANALYZE, BUFFERS) DELETE FROM t", Destructive),
+            ("EXPLAIN ANALYSE DELETE FROM t WHERE id = 1", Dml),
+            ("SELECT pg_terminate_backend(123)", Dml),
+            ("SELECT \"set_config\"('default_transaction_read_only', 'off', false)", Dml),
+            ("SET SESSION CHARACTERISTICS AS TRANSACTION READ WRITE", Dml),
+            ("BEGIN READ WRITE", Dml),
+            ("DECLARE c CURSOR WITH HOLD FOR SELECT 1", Dml),
+            ("INSERT INTO t VALUES (1)", Dml),
+            ("UPDATE t SET a = 1 WHERE id = 2", Dml),
+            ("UPDATE t SET a = (SELECT b FROM u WHERE u.id = 1)", Destructive),
+            ("DELETE FROM t", Destructive),
+            ("delete from \"where\"", Destructive),
+            ("DELETE FROM t WHERE id = 1", Dml),
+            ("WITH gone AS (DELETE FROM t RETURNING *) SELECT count(*) FROM gone", Destructive),
+            ("WITH x AS (SELECT 1) UPDATE t SET a = 1 FROM x WHERE t.id = x.id", Dml),
+            ("WITH x AS (SELECT 1) SELECT * FROM x", Read),
+            ("COPY t TO STDOUT", Read),
+            ("COPY (SELECT a FROM t) TO STDOUT", Read),
+            ("COPY t TO '/server/path'", Destructive),
+            ("COPY t TO PROGRAM 'gzip > /tmp/t.gz'", Destructive),
+            ("COPY t FROM STDIN", Dml),
+            ("CREATE INDEX i ON t (a)", Ddl),
+            ("ALTER TABLE t ADD COLUMN b int", Ddl),
+            ("ALTER TABLE t DROP COLUMN b", Destructive),
+            ("DO $$ BEGIN DROP TABLE t; END $$", Destructive),
+            ("CALL archive_orders()", Destructive),
+            ("EXECUTE stmt(1)", Destructive),
+            ("TRUNCATE t", Destructive),
+            ("DROP TABLE t", Destructive),
+            ("VACUUM t", Ddl),
+        ];
+        for (sql, class) in cases {
+            assert_eq!(classify_statement(sql), class, "{}", sql);
+        }
+    }
+
+    #[test]
+    fn test_scripts_take_their_most_dangerous_statement() {
+        assert_eq!(classify_sql("SELECT 1; SELECT 2;"), StatementClass::Read);
+        assert_eq!(classify_sql("SELECT 1; DROP TABLE t; INSERT INTO t VALUES (1)"), StatementClass::Destructive);
+        assert_eq!(classify_sql("COPY t FROM stdin;\n1\n\\.\n"), StatementClass::Dml);
+        assert_eq!(classify_sql("SELECT E'it\\'s; DELETE FROM t'"), StatementClass::Read);
+    }
+
+    #[test]
+    fn test_other_environments_are_not_restricted() {
+        let mut guard = ProductionGuard::new();
+        let now = Utc::now();
+        let drop = GuardOperation::DropIndex;
+        assert!(guard.decide(false, &drop, now).allowed);
+        assert!(guard.authorize(false, &drop, None, now).is_ok());
+        let read = GuardOperation::Sql {
+            sql: "SELECT 1".to_string(),
+        };
+        assert_eq!(guard.authorize(false, &read, None, now), Ok(Access::ReadWrite));
+        // Unconfirmed reads on production run read-only
+        assert_eq!(guard.authorize(true, &read, None, now), Ok(Access::ReadOnly));
+    }
+
+    #[test]
+    fn test_writes_need_a_matching_single_use_token() {
+        let mut guard = ProductionGuard::new();
+        let now = Utc::now();
+        let insert = GuardOperation::Sql {
+            sql: "INSERT INTO t VALUES (1)".to_string(),
+        };
+        assert!(guard.authorize(true, &insert, None, now).is_err());
+
+        let decision = guard.decide(true, &insert, now);
+        assert!(!decision.allowed && !decision.requires_unlock);
+        let token = decision.confirmation_token.unwrap();
+        let other = GuardOperation::Sql {
+            sql: "INSERT INTO t VALUES (2)".to_string(),
+        };
+        assert!(guard.authorize(true, &other, Some(&token), now).is_err());
+
+        let token = guard.decide(true, &insert, now).confirmation_token.unwrap();
+        assert_eq!(guard.authorize(true, &insert, Some(&token), now), Ok(Access::ReadWrite));
+        assert!(guard.authorize(true, &insert, Some(&token), now).is_err());
+
+        let token = guard.decide(true, &insert, now).confirmation_token.unwrap();
+        let later = now + Duration::seconds(CONFIRMATION_TTL_SECONDS + 1);
+        assert!(guard.authorize(true, &insert, Some(&token), later).is_err());
+    }
+
+    #[test]
+    fn test_row_change_tokens_are_bound_to_the_row() {
+        let mut guard = ProductionGuard::new();
+        let now = Utc::now();
+        let edit = |key: i64, operation: RowOperation| GuardOperation::RowChange {
+            schema: Some("public".to_string()),
+            table: "t".to_string(),
+            key: vec![serde_json::json!(key)],
+            operation,
+        };
+        let token = guard.decide(true, &edit(1, RowOperation::Update), now).confirmation_token.unwrap();
+        assert!(guard.authorize(true, &edit(2, RowOperation::Update), Some(&token), now).is_err());
+        let token = guard.decide(true, &edit(1, RowOperation::Update), now).confirmation_token.unwrap();
+        assert!(guard.authorize(true, &edit(1, RowOperation::Delete), Some(&token), now).is_err());
+        let token = guard.decide(true, &edit(1, RowOperation::Update), now).confirmation_token.unwrap();
+        assert_eq!(guard.authorize(true, &edit(1, RowOperation::Update), Some(&token), now), Ok(Access::ReadWrite));
+
+        let delete = serde_json::json!({ "type": "delete" });
+        let bulk = GuardOperation::RowChanges { operations: vec![delete.clone(), delete] };
+        assert_eq!(bulk.class(), StatementClass::Destructive);
+    }
+
+    #[test]
+    fn test_destructive_operations_need_an_unlock() {
+        let mut guard = ProductionGuard::new();
+        let now = Utc::now();
+        let decision = guard.decide(true, &GuardOperation::Import, now);
+        assert!(decision.requires_unlock);
+        assert!(decision.confirmation_token.is_none());
+
+        guard.unlock(Some(5), now);
+        assert!(guard.status(true, now).unlocked_until.is_some());
+        let token = guard.decide(true, &GuardOperation::Import, now).confirmation_token.unwrap();
+        assert!(guard.authorize(true, &GuardOperation::Import, Some(&token), now).is_ok());
+
+        let token = guard.decide(true, &GuardOperation::Import, now).confirmation_token.unwrap();
+        let expired = now + Duration::minutes(6);
+        assert!(guard.authorize(true, &GuardOperation::Import, Some(&token), expired).is_err());
+        guard.lock();
+        assert!(guard.decide(true, &GuardOperation::Import, now).requires_unlock);
+    }
+}
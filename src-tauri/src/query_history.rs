@@ -0,0 +1,445 @@
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+/// Tauri state for the query history store
+pub type QueryHistoryState = Arc<Mutex<QueryHistoryStore>>;
+
+const STORAGE_VERSION: &str = "1.0.0";
+const DEFAULT_LIMIT: usize = 100;
+
+/// One executed statement
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct QueryHistoryEntry {
+    pub id: String,
+    /// `None` for sessions opened from a raw connection string
+    pub profile_id: Option<String>,
+    pub session_id: String,
+    pub sql: String,
+    pub executed_at: DateTime<Utc>,
+    pub duration_ms: u64,
+    pub row_count: Option<u64>,
+    pub success: bool,
+    pub error: Option<String>,
+    #[serde(default)]
+    pub pinned: bool,
+}
+
+/// What to record for a statement; the store assigns the id and timestamp
+#[derive(Debug, Clone)]
+pub struct NewHistoryEntry {
+    pub profile_id: Option<String>,
+    pub session_id: String,
+    pub sql: String,
+    pub duration_ms: u64,
+    pub row_count: Option<u64>,
+    pub error: Option<String>,
+}
+
+/// Search criteria; every field is optional and they combine with AND
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HistoryFilter {
+    /// Case-insensitive substring of the SQL text
+    pub query: Option<String>,
+    pub profile_id: Option<String>,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    pub success: Option<bool>,
+    pub pinned_only: bool,
+    pub limit: Option<usize>,
+    pub offset: Option<usize>,
+}
+
+impl HistoryFilter {
+    fn matches(&self, entry: &QueryHistoryEntry) -> bool {
+        if let Some(query) = &self.query {
+            if !entry.sql.to_lowercase().contains(&query.to_lowercase()) {
+                return false;
+            }
+        }
+        if let Some(profile_id) = &self.profile_id {
+            if entry.profile_id.as_ref() != Some(profile_id) {
+                return false;
+            }
+        }
+        if self.from.is_some_and(|from| entry.executed_at < from)
+            || self.to.is_some_and(|to| entry.executed_at > to)
+        {
+            return false;
+        }
+        if self.success.is_some_and(|success| entry.success != success) {
+            return false;
+        }
+        !self.pinned_only || entry.pinned
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryPage {
+    /// Newest first
+    pub entries: Vec<QueryHistoryEntry>,
+    /// Matches before `limit`/`offset` were applied
+    pub total: usize,
+}
+
+/// How much unpinned history to keep. Pinned entries are never removed by retention.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct RetentionPolicy {
+    /// Unpinned entries kept per profile; `None` keeps all
+    pub max_entries_per_profile: Option<usize>,
+    /// Unpinned entries older than this are dropped; `None` keeps them forever
+    pub max_age_days: Option<u32>,
+}
+
+impl Default for RetentionPolicy {
+    fn default() -> Self {
+        Self {
+            max_entries_per_profile: Some(1000),
+            max_age_days: Some(90),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct StoredHistory {
+    version: String,
+    retention: RetentionPolicy,
+    entries: Vec<QueryHistoryEntry>,
+}
+
+/// Writes the history file in order, so a slow older save never overwrites a newer one
+struct HistoryWriter {
+    storage_path: PathBuf,
+    /// Generation of the last snapshot written
+    written: std::sync::Mutex<u64>,
+}
+
+impl HistoryWriter {
+    fn write(&self, generation: u64, json: &str) -> Result<(), String> {
+        let mut written = self.written.lock().unwrap_or_else(|e| e.into_inner());
+        if *written >= generation {
+            return Ok(());
+        }
+
+        // Write to a temporary file first so a crash never leaves a truncated history
+        let temp_path = self.storage_path.with_extension("tmp");
+        fs::write(&temp_path, json).map_err(|e| format!("Failed to write query history: {}", e))?;
+        fs::rename(&temp_path, &self.storage_path)
+            .map_err(|e| format!("Failed to write query history: {}", e))?;
+        *written = generation;
+        Ok(())
+    }
+}
+
+/// A snapshot of the history to be written to disk, without holding the store
+pub struct PendingSave {
+    writer: Arc<HistoryWriter>,
+    generation: u64,
+    json: String,
+}
+
+impl PendingSave {
+    /// Write the snapshot unless a newer one was written already. Blocks on file IO.
+    pub fn write(self) -> Result<(), String> {
+        self.writer.write(self.generation, &self.json)
+    }
+}
+
+/// File-backed history of executed statements, oldest first in memory
+pub struct QueryHistoryStore {
+    writer: Arc<HistoryWriter>,
+    /// Bumped for every snapshot taken
+    generation: u64,
+    entries: Vec<QueryHistoryEntry>,
+    retention: RetentionPolicy,
+}
+
+impl QueryHistoryStore {
+    /// Open the store, loading existing history if the file is present
+    pub fn open<P: AsRef<Path>>(storage_path: P) -> Result<Self, String> {
+        let storage_path = storage_path.as_ref().to_path_buf();
+        if let Some(parent) = storage_path.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create history directory: {}", e))?;
+        }
+
+        let mut store = Self {
+            writer: Arc::new(HistoryWriter {
+                storage_path: storage_path.clone(),
+                written: std::sync::Mutex::new(0),
+            }),
+            generation: 0,
+            entries: Vec::new(),
+            retention: RetentionPolicy::default(),
+        };
+
+        if storage_path.exists() {
+            let content = fs::read_to_string(&storage_path)
+                .map_err(|e| format!("Failed to read query history: {}", e))?;
+            let stored: StoredHistory = serde_json::from_str(&content)
+                .map_err(|e| format!("Failed to parse query history: {}", e))?;
+            if stored.version != STORAGE_VERSION {
+                return Err(format!("Unsupported query history version: {}", stored.version));
+            }
+            store.entries = stored.entries;
+            store.retention = stored.retention;
+        }
+
+        Ok(store)
+    }
+
+    /// Append statements and apply retention. The returned snapshot is written by the
+    /// caller, so statements do not wait on the disk while holding the store.
+    pub fn record_all(&mut self, entries: Vec<NewHistoryEntry>) -> Result<PendingSave, String> {
+        let now = Utc::now();
+        self.entries.extend(entries.into_iter().map(|entry| Self::to_entry(entry, now)));
+        self.prune(now);
+        self.snapshot()
+    }
+
+    #[cfg(test)]
+    fn record_at(&mut self, entry: NewHistoryEntry, now: DateTime<Utc>) -> Result<QueryHistoryEntry, String> {
+        let entry = Self::to_entry(entry, now);
+        self.entries.push(entry.clone());
+        self.prune(now);
+        self.snapshot()?.write()?;
+        Ok(entry)
+    }
+
+    fn to_entry(entry: NewHistoryEntry, now: DateTime<Utc>) -> QueryHistoryEntry {
+        QueryHistoryEntry {
+            id: uuid::Uuid::new_v4().to_string(),
+            profile_id: entry.profile_id,
+            session_id: entry.session_id,
+            sql: entry.sql,
+            executed_at: now,
+            duration_ms: entry.duration_ms,
+            row_count: entry.row_count,
+            success: entry.error.is_none(),
+            error: entry.error,
+            pinned: false,
+        }
+    }
+
+    pub fn search(&self, filter: &HistoryFilter) -> HistoryPage {
+        let matches: Vec<&QueryHistoryEntry> = self
+            .entries
+            .iter()
+            .rev()
+            .filter(|entry| filter.matches(entry))
+            .collect();
+        let total = matches.len();
+        let entries = matches
+            .into_iter()
+            .skip(filter.offset.unwrap_or(0))
+            .take(filter.limit.unwrap_or(DEFAULT_LIMIT))
+            .cloned()
+            .collect();
+        HistoryPage { entries, total }
+    }
+
+    /// Pin or unpin an entry. Like `record_all`, the snapshot is written by the caller.
+    pub fn set_pinned(&mut self, id: &str, pinned: bool) -> Result<(QueryHistoryEntry, PendingSave), String> {
+        let entry = self
+            .entries
+            .iter_mut()
+            .find(|entry| entry.id == id)
+            .ok_or_else(|| format!("History entry '{}' not found", id))?;
+        entry.pinned = pinned;
+        let entry = entry.clone();
+        Ok((entry, self.snapshot()?))
+    }
+
+    /// Delete entries matching `filter`, keeping pinned ones unless `include_pinned`.
+    /// Returns how many were removed, and a snapshot when any were.
+    pub fn purge(&mut self, filter: &HistoryFilter, include_pinned: bool) -> Result<(usize, Option<PendingSave>), String> {
+        let before = self.entries.len();
+        self.entries
+            .retain(|entry| !(filter.matches(entry) && (include_pinned || !entry.pinned)));
+        let removed = before - self.entries.len();
+        let pending = if removed > 0 { Some(self.snapshot()?) } else { None };
+        Ok((removed, pending))
+    }
+
+    pub fn retention(&self) -> RetentionPolicy {
+        self.retention
+    }
+
+    /// Replace the retention policy and apply it right away
+    pub fn set_retention(&mut self, retention: RetentionPolicy) -> Result<(usize, PendingSave), String> {
+        self.retention = retention;
+        let removed = self.prune(Utc::now());
+        Ok((removed, self.snapshot()?))
+    }
+
+    fn prune(&mut self, now: DateTime<Utc>) -> usize {
+        let before = self.entries.len();
+
+        if let Some(days) = self.retention.max_age_days {
+            let cutoff = now - Duration::days(i64::from(days));
+            self.entries.retain(|entry| entry.pinned || entry.executed_at >= cutoff);
+        }
+
+        if let Some(max) = self.retention.max_entries_per_profile {
+            // Walk newest first so the most recent entries of each profile survive
+            let mut seen: std::collections::HashMap<Option<String>, usize> = std::collections::HashMap::new();
+            let mut keep = vec![true; self.entries.len()];
+            for (index, entry) in self.entries.iter().enumerate().rev() {
+                if entry.pinned {
+                    continue;
+                }
+                let count = seen.entry(entry.profile_id.clone()).or_insert(0);
+                *count += 1;
+                keep[index] = *count <= max;
+            }
+            let mut keep = keep.into_iter();
+            self.entries.retain(|_| keep.next().unwrap_or(true));
+        }
+
+        before - self.entries.len()
+    }
+
+    fn snapshot(&mut self) -> Result<PendingSave, String> {
+        let stored = StoredHistory {
+            version: STORAGE_VERSION.to_string(),
+            retention: self.retention,
+            entries: self.entries.clone(),
+        };
+        let json = serde_json::to_string(&stored)
+            .map_err(|e| format!("Failed to serialize query history: {}", e))?;
+        self.generation += 1;
+        Ok(PendingSave {
+            writer: self.writer.clone(),
+            generation: self.generation,
+            json,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn new_entry(profile_id: Option<&str>, sql: &str, error: Option<&str>) -> NewHistoryEntry {
+        NewHistoryEntry {
+            profile_id: profile_id.map(str::to_string),
+            session_id: "default".to_string(),
+            sql: sql.to_string(),
+            duration_ms: 5,
+            row_count: Some(1),
+            error: error.map(str::to_string),
+        }
+    }
+
+    fn record(store: &mut QueryHistoryStore, entry: NewHistoryEntry) -> QueryHistoryEntry {
+        store.record_at(entry, Utc::now()).unwrap()
+    }
+
+    fn open(dir: &TempDir) -> QueryHistoryStore {
+        QueryHistoryStore::open(dir.path().join("history.json")).unwrap()
+    }
+
+    #[test]
+    fn records_and_reloads_history() {
+        let dir = TempDir::new().unwrap();
+        let mut store = open(&dir);
+        record(&mut store, new_entry(Some("a"), "SELECT 1", None));
+        let failed = record(&mut store, new_entry(Some("a"), "SELEC 2", Some("syntax error")));
+        assert!(!failed.success);
+
+        let store = open(&dir);
+        let page = store.search(&HistoryFilter::default());
+        assert_eq!(page.total, 2);
+        assert_eq!(page.entries[0].sql, "SELEC 2");
+        assert_eq!(page.entries[0].error.as_deref(), Some("syntax error"));
+    }
+
+    #[test]
+    fn filters_by_text_profile_and_date() {
+        let dir = TempDir::new().unwrap();
+        let mut store = open(&dir);
+        let start = Utc::now() - Duration::days(3);
+        store.record_at(new_entry(Some("a"), "SELECT * FROM users", None), start).unwrap();
+        store.record_at(new_entry(Some("b"), "select * from Users", None), start + Duration::days(1)).unwrap();
+        store.record_at(new_entry(None, "DELETE FROM orders", None), start + Duration::days(2)).unwrap();
+
+        let by_text = store.search(&HistoryFilter { query: Some("USERS".to_string()), ..Default::default() });
+        assert_eq!(by_text.total, 2);
+
+        let by_profile = store.search(&HistoryFilter { profile_id: Some("b".to_string()), ..Default::default() });
+        assert_eq!(by_profile.entries.len(), 1);
+        assert_eq!(by_profile.entries[0].profile_id.as_deref(), Some("b"));
+
+        let by_date = store.search(&HistoryFilter {
+            from: Some(start + Duration::hours(12)),
+            to: Some(start + Duration::hours(36)),
+            ..Default::default()
+        });
+        assert_eq!(by_date.total, 1);
+        assert_eq!(by_date.entries[0].profile_id.as_deref(), Some("b"));
+
+        let paged = store.search(&HistoryFilter { limit: Some(1), offset: Some(1), ..Default::default() });
+        assert_eq!(paged.total, 3);
+        assert_eq!(paged.entries[0].profile_id.as_deref(), Some("b"));
+    }
+
+    #[test]
+    fn older_snapshots_never_overwrite_newer_ones() {
+        let dir = TempDir::new().unwrap();
+        let mut store = open(&dir);
+        let first = store.record_all(vec![new_entry(Some("a"), "SELECT 1", None)]).unwrap();
+        let second = store.record_all(vec![new_entry(Some("a"), "SELECT 2", None)]).unwrap();
+        second.write().unwrap();
+        first.write().unwrap();
+
+        assert_eq!(open(&dir).search(&HistoryFilter::default()).total, 2);
+    }
+
+    #[test]
+    fn purge_keeps_pinned_entries() {
+        let dir = TempDir::new().unwrap();
+        let mut store = open(&dir);
+        let keep = record(&mut store, new_entry(Some("a"), "SELECT 1", None));
+        record(&mut store, new_entry(Some("a"), "SELECT 2", None));
+        store.set_pinned(&keep.id, true).unwrap();
+
+        let (removed, pending) = store.purge(&HistoryFilter::default(), false).unwrap();
+        assert_eq!(removed, 1);
+        pending.unwrap().write().unwrap();
+        let page = open(&dir).search(&HistoryFilter::default());
+        assert_eq!(page.entries, vec![QueryHistoryEntry { pinned: true, ..keep }]);
+
+        assert_eq!(store.purge(&HistoryFilter::default(), true).unwrap().0, 1);
+        assert!(store.purge(&HistoryFilter::default(), true).unwrap().1.is_none());
+        assert!(store.set_pinned("missing", true).is_err());
+    }
+
+    #[test]
+    fn retention_limits_unpinned_entries_per_profile() {
+        let dir = TempDir::new().unwrap();
+        let mut store = open(&dir);
+        store
+            .set_retention(RetentionPolicy { max_entries_per_profile: Some(2), max_age_days: Some(30) })
+            .unwrap();
+
+        // Dropped by age once newer statements are recorded
+        store.record_at(new_entry(Some("c"), "SELECT 'old'", None), Utc::now() - Duration::days(60)).unwrap();
+
+        let pinned = record(&mut store, new_entry(Some("a"), "SELECT 0", None));
+        store.set_pinned(&pinned.id, true).unwrap();
+        for i in 1..=3 {
+            record(&mut store, new_entry(Some("a"), &format!("SELECT {}", i), None));
+        }
+        store.record_all(vec![new_entry(Some("b"), "SELECT 'b'", None)]).unwrap();
+
+        let a = store.search(&HistoryFilter { profile_id: Some("a".to_string()), ..Default::default() });
+        let sql: Vec<&str> = a.entries.iter().map(|e| e.sql.as_str()).collect();
+        assert_eq!(sql, vec!["SELECT 3", "SELECT 2", "SELECT 0"]);
+        assert_eq!(store.search(&HistoryFilter::default()).total, 4);
+    }
+}
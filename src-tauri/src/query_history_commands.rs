@@ -0,0 +1,69 @@
+use crate::query_history::*;
+use tauri::State;
+
+/// Write a history snapshot on the blocking pool, after the store is unlocked
+async fn write_history(pending: PendingSave) -> Result<(), String> {
+    tokio::task::spawn_blocking(move || pending.write())
+        .await
+        .map_err(|e| format!("Failed to save query history: {}", e))?
+}
+
+/// Search history, newest first
+#[tauri::command]
+pub async fn search_query_history(
+    history: State<'_, QueryHistoryState>,
+    filter: Option<HistoryFilter>,
+) -> Result<HistoryPage, String> {
+    let history = history.lock().await;
+    Ok(history.search(&filter.unwrap_or_default()))
+}
+
+/// Pin or unpin a history entry; pinned entries survive purges and retention
+#[tauri::command]
+pub async fn pin_query_history_entry(
+    history: State<'_, QueryHistoryState>,
+    id: String,
+    pinned: bool,
+) -> Result<QueryHistoryEntry, String> {
+    let (entry, pending) = history.lock().await.set_pinned(&id, pinned)?;
+    write_history(pending).await?;
+    Ok(entry)
+}
+
+/// Delete matching history entries and return how many were removed
+#[tauri::command]
+pub async fn purge_query_history(
+    history: State<'_, QueryHistoryState>,
+    filter: Option<HistoryFilter>,
+    include_pinned: Option<bool>,
+) -> Result<usize, String> {
+    println!("🦀 [Command] purge_query_history called");
+    let (removed, pending) = history
+        .lock()
+        .await
+        .purge(&filter.unwrap_or_default(), include_pinned.unwrap_or(false))?;
+    if let Some(pending) = pending {
+        write_history(pending).await?;
+    }
+    println!("🦀 [Command] Purged {} history entries", removed);
+    Ok(removed)
+}
+
+#[tauri::command]
+pub async fn get_history_retention(
+    history: State<'_, QueryHistoryState>,
+) -> Result<RetentionPolicy, String> {
+    let history = history.lock().await;
+    Ok(history.retention())
+}
+
+/// Replace the retention policy and return how many entries it removed
+#[tauri::command]
+pub async fn set_history_retention(
+    history: State<'_, QueryHistoryState>,
+    retention: RetentionPolicy,
+) -> Result<usize, String> {
+    let (removed, pending) = history.lock().await.set_retention(retention)?;
+    write_history(pending).await?;
+    Ok(removed)
+}
@@ -0,0 +1,486 @@
+//! `EXPLAIN (FORMAT JSON)` plans as a typed tree, with hotspots flagged per node.
+//!
+//! The statement is explained on a pooled connection inside a transaction that is always
+//! rolled back, so `ANALYZE` on a write shows real timings without keeping its changes and
+//! a transaction the user opened on the shared connection is never touched.
+
+use crate::production_guard::{classify_statement, Access, StatementClass};
+use crate::session_registry::DatabaseSession;
+use crate::simple_db::{self, QueryCanceller};
+use crate::sql_builder::{qualified_name, quote_ident};
+use crate::sql_splitter::split_statements;
+use crate::value_codec::{self, SessionTimeZone};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// Seq scans over tables with at least this many rows are flagged
+const LARGE_TABLE_ROWS: f64 = 10_000.0;
+/// Estimates off by this factor or more are flagged...
+const MISESTIMATE_FACTOR: f64 = 10.0;
+/// ...unless both sides are below this many rows
+const MISESTIMATE_MIN_ROWS: f64 = 100.0;
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExplainOptions {
+    /// Run the statement and report actual times and row counts
+    pub analyze: bool,
+    pub buffers: bool,
+    pub verbose: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HotspotKind {
+    /// Sequential scan over a large table
+    SeqScan,
+    /// Actual rows far from the planner's estimate
+    RowMisestimate,
+    /// Sort, hash or other work spilled to temporary files
+    DiskSpill,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Hotspot {
+    /// `PlanNode::id` of the node it is about
+    pub node_id: usize,
+    pub kind: HotspotKind,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PlanNode {
+    /// Position in a depth-first walk of the tree, starting at 0 for the root
+    pub id: usize,
+    pub node_type: String,
+    pub relation_name: Option<String>,
+    pub schema: Option<String>,
+    pub alias: Option<String>,
+    pub index_name: Option<String>,
+    pub join_type: Option<String>,
+    pub parent_relationship: Option<String>,
+    pub startup_cost: f64,
+    pub total_cost: f64,
+    pub plan_rows: f64,
+    pub plan_width: f64,
+    /// Actual values are per loop and only present with ANALYZE
+    pub actual_startup_time_ms: Option<f64>,
+    pub actual_total_time_ms: Option<f64>,
+    pub actual_rows: Option<f64>,
+    pub actual_loops: Option<f64>,
+    /// Time spent in this node itself across all loops, excluding its children
+    pub self_time_ms: Option<f64>,
+    pub rows_removed_by_filter: Option<f64>,
+    pub filter: Option<String>,
+    pub index_cond: Option<String>,
+    pub sort_method: Option<String>,
+    pub sort_space_type: Option<String>,
+    pub sort_space_used_kb: Option<f64>,
+    pub hash_batches: Option<f64>,
+    pub shared_hit_blocks: Option<f64>,
+    pub shared_read_blocks: Option<f64>,
+    pub temp_written_blocks: Option<f64>,
+    /// Every other field of the node, as the server reported it
+    pub details: Map<String, Value>,
+    pub children: Vec<PlanNode>,
+}
+
+impl PlanNode {
+    fn walk<'a>(&'a self, nodes: &mut Vec<&'a PlanNode>) {
+        nodes.push(self);
+        for child in &self.children {
+            child.walk(nodes);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueryPlan {
+    pub plan: PlanNode,
+    pub planning_time_ms: Option<f64>,
+    pub execution_time_ms: Option<f64>,
+    pub analyzed: bool,
+    /// ANALYZE ran a statement that writes; its changes were rolled back
+    pub rolled_back: bool,
+    /// In tree order
+    pub hotspots: Vec<Hotspot>,
+    /// The server's JSON output
+    pub raw: Value,
+}
+
+/// `EXPLAIN (...)` for a single statement
+pub fn explain_sql(query: &str, options: &ExplainOptions) -> Result<String, String> {
+    let statements = split_statements(query)?;
+    let statement = match statements.as_slice() {
+        [statement] => statement,
+        [] => return Err("Nothing to explain".to_string()),
+        _ => return Err("EXPLAIN takes a single statement".to_string()),
+    };
+    if statement.sql.split_whitespace().next().is_some_and(|word| word.eq_ignore_ascii_case("EXPLAIN")) {
+        return Err("Pass the statement without EXPLAIN".to_string());
+    }
+
+    let mut flags = vec!["FORMAT JSON"];
+    if options.analyze {
+        flags.push("ANALYZE");
+    }
+    if options.buffers {
+        flags.push("BUFFERS");
+    }
+    if options.verbose {
+        flags.push("VERBOSE");
+    }
+    Ok(format!("EXPLAIN ({}) {}", flags.join(", "), statement.sql))
+}
+
+/// Explain `query` on a pooled connection of `session`. Cancelling `execution_id` stops it.
+pub async fn run_explain(
+    session: &DatabaseSession,
+    query: &str,
+    options: &ExplainOptions,
+    execution_id: &str,
+    timeout: Option<Duration>,
+    access: Access,
+) -> Result<QueryPlan, String> {
+    let sql = explain_sql(query, options)?;
+    let writes = classify_statement(query) > StatementClass::Read;
+    let timeout = match timeout {
+        Some(timeout) => timeout,
+        None => session.simple_db.lock().await.query_timeout(),
+    };
+
+    let (mut client, tls) = {
+        let pool = session.pool.lock().await;
+        (pool.get_connection().await?, pool.tls_connector())
+    };
+    if let Some(tls) = tls {
+        session
+            .register_query(execution_id, QueryCanceller::new(client.cancel_token(), tls))
+            .await?;
+    }
+
+    let explain_error = |e: tokio_postgres::Error| format!("EXPLAIN failed: {}", simple_db::db_error_message(&e));
+    let result = async {
+        let transaction = client
+            .build_transaction()
+            .read_only(access.is_read_only())
+            .start()
+            .await
+            .map_err(explain_error)?;
+        transaction
+            .batch_execute(&format!("SET LOCAL statement_timeout = {}", timeout.as_millis()))
+            .await
+            .map_err(explain_error)?;
+        let row = transaction.query_one(&sql, &[]).await.map_err(explain_error)?;
+        // The plan is JSON, so the time zone never comes into play
+        let raw = value_codec::decode_row(&row, &SessionTimeZone::default()).into_iter().next().unwrap_or(Value::Null);
+
+        let mut table_rows = HashMap::new();
+        for (schema, relation) in seq_scan_relations(&raw) {
+            let name = match &schema {
+                Some(schema) => qualified_name(schema, &relation),
+                None => quote_ident(&relation),
+            };
+            let estimate = transaction
+                .query_opt("SELECT reltuples::float8 FROM pg_class WHERE oid = to_regclass($1)", &[&name])
+                .await
+                .map_err(explain_error)?;
+            // reltuples is -1 for tables that were never analyzed
+            if let Some(rows) = estimate.map(|row| row.get::<_, f64>(0)).filter(|&rows| rows >= 0.0) {
+                table_rows.insert((schema, relation), rows);
+            }
+        }
+
+        transaction.rollback().await.map_err(explain_error)?;
+        Ok::<_, String>((raw, table_rows))
+    }
+    .await;
+    session.finish_query(execution_id).await;
+
+    let (raw, table_rows) = result?;
+    let mut plan = parse_plan(raw, &table_rows)?;
+    plan.rolled_back = plan.analyzed && writes;
+    Ok(plan)
+}
+
+/// `(schema, relation)` of every seq scan; schema is only known with VERBOSE
+fn seq_scan_relations(raw: &Value) -> Vec<(Option<String>, String)> {
+    fn visit(node: &Value, found: &mut Vec<(Option<String>, String)>) {
+        if node["Node Type"].as_str().is_some_and(is_seq_scan) {
+            if let Some(relation) = node["Relation Name"].as_str() {
+                let key = (node["Schema"].as_str().map(str::to_string), relation.to_string());
+                if !found.contains(&key) {
+                    found.push(key);
+                }
+            }
+        }
+        for child in node["Plans"].as_array().into_iter().flatten() {
+            visit(child, found);
+        }
+    }
+
+    let mut found = Vec::new();
+    if let Some(plan) = raw.get(0).and_then(|entry| entry.get("Plan")) {
+        visit(plan, &mut found);
+    }
+    found
+}
+
+fn is_seq_scan(node_type: &str) -> bool {
+    node_type == "Seq Scan" || node_type == "Parallel Seq Scan"
+}
+
+/// Turn the output of `EXPLAIN (FORMAT JSON)` into a plan tree and flag its hotspots.
+/// `table_rows` holds the estimated size of seq-scanned tables.
+pub fn parse_plan(raw: Value, table_rows: &HashMap<(Option<String>, String), f64>) -> Result<QueryPlan, String> {
+    let entry = raw
+        .get(0)
+        .and_then(Value::as_object)
+        .ok_or("EXPLAIN returned no plan")?;
+    let root = entry
+        .get("Plan")
+        .and_then(Value::as_object)
+        .ok_or("EXPLAIN returned no plan")?;
+
+    let mut next_id = 0;
+    let plan = parse_node(root, &mut next_id);
+    let analyzed = plan.actual_loops.is_some();
+
+    let mut nodes = Vec::new();
+    plan.walk(&mut nodes);
+    let hotspots = nodes.into_iter().flat_map(|node| node_hotspots(node, table_rows)).collect();
+
+    Ok(QueryPlan {
+        planning_time_ms: entry.get("Planning Time").and_then(Value::as_f64),
+        execution_time_ms: entry.get("Execution Time").and_then(Value::as_f64),
+        analyzed,
+        rolled_back: false,
+        hotspots,
+        plan,
+        raw,
+    })
+}
+
+fn parse_node(node: &Map<String, Value>, next_id: &mut usize) -> PlanNode {
+    let mut details = node.clone();
+    let mut text = |key: &str| details.remove(key).and_then(|v| v.as_str().map(str::to_string));
+    let id = *next_id;
+    *next_id += 1;
+
+    let mut parsed = PlanNode {
+        id,
+        node_type: text("Node Type").unwrap_or_default(),
+        relation_name: text("Relation Name"),
+        schema: text("Schema"),
+        alias: text("Alias"),
+        index_name: text("Index Name"),
+        join_type: text("Join Type"),
+        parent_relationship: text("Parent Relationship"),
+        filter: text("Filter"),
+        index_cond: text("Index Cond"),
+        sort_method: text("Sort Method"),
+        sort_space_type: text("Sort Space Type"),
+        ..Default::default()
+    };
+
+    let mut number = |key: &str| details.remove(key).and_then(|v| v.as_f64());
+    parsed.startup_cost = number("Startup Cost").unwrap_or(0.0);
+    parsed.total_cost = number("Total Cost").unwrap_or(0.0);
+    parsed.plan_rows = number("Plan Rows").unwrap_or(0.0);
+    parsed.plan_width = number("Plan Width").unwrap_or(0.0);
+    parsed.actual_startup_time_ms = number("Actual Startup Time");
+    parsed.actual_total_time_ms = number("Actual Total Time");
+    parsed.actual_rows = number("Actual Rows");
+    parsed.actual_loops = number("Actual Loops");
+    parsed.rows_removed_by_filter = number("Rows Removed by Filter");
+    parsed.sort_space_used_kb = number("Sort Space Used");
+    parsed.hash_batches = number("Hash Batches");
+    parsed.shared_hit_blocks = number("Shared Hit Blocks");
+    parsed.shared_read_blocks = number("Shared Read Blocks");
+    parsed.temp_written_blocks = number("Temp Written Blocks");
+
+    let children = details.remove("Plans");
+    parsed.children = children
+        .iter()
+        .flat_map(|plans| plans.as_array().into_iter().flatten())
+        .filter_map(Value::as_object)
+        .map(|child| parse_node(child, next_id))
+        .collect();
+    parsed.details = details;
+
+    parsed.self_time_ms = parsed.actual_total_time_ms.zip(parsed.actual_loops).map(|(time, loops)| {
+        let children: f64 = parsed
+            .children
+            .iter()
+            .filter_map(|child| child.actual_total_time_ms.zip(child.actual_loops))
+            .map(|(time, loops)| time * loops)
+            .sum();
+        (time * loops - children).max(0.0)
+    });
+    parsed
+}
+
+fn node_hotspots(node: &PlanNode, table_rows: &HashMap<(Option<String>, String), f64>) -> Vec<Hotspot> {
+    let mut hotspots = Vec::new();
+    let mut flag = |kind, message| hotspots.push(Hotspot { node_id: node.id, kind, message });
+    let executed = node.actual_loops.is_some_and(|loops| loops > 0.0);
+
+    if is_seq_scan(&node.node_type) {
+        if let Some(relation) = &node.relation_name {
+            let known = table_rows.get(&(node.schema.clone(), relation.clone())).copied();
+            // Without a size estimate fall back to what the scan actually read
+            let read = match (node.actual_rows, node.actual_loops) {
+                (Some(rows), Some(loops)) => (rows + node.rows_removed_by_filter.unwrap_or(0.0)) * loops,
+                _ => node.plan_rows,
+            };
+            let rows = known.unwrap_or(read);
+            if rows >= LARGE_TABLE_ROWS {
+                flag(
+                    HotspotKind::SeqScan,
+                    format!("Sequential scan on {} (~{} rows); an index may help", relation, rows.round()),
+                );
+            }
+        }
+    }
+
+    if let (Some(actual), true) = (node.actual_rows, executed) {
+        let estimate = node.plan_rows;
+        let (low, high) = if actual < estimate { (actual, estimate) } else { (estimate, actual) };
+        if high >= MISESTIMATE_MIN_ROWS && high / low.max(1.0) >= MISESTIMATE_FACTOR {
+            let direction = if actual > estimate { "under" } else { "over" };
+            flag(
+                HotspotKind::RowMisestimate,
+                format!(
+                    "Planner {}estimated rows: expected {}, got {} ({:.0}x); statistics may be stale",
+                    direction,
+                    estimate.round(),
+                    actual.round(),
+                    high / low.max(1.0)
+                ),
+            );
+        }
+    }
+
+    let spill = if node.sort_space_type.as_deref() == Some("Disk") {
+        Some(format!(
+            "Sort spilled {} kB to disk; consider raising work_mem",
+            node.sort_space_used_kb.unwrap_or(0.0).round()
+        ))
+    } else if node.hash_batches.is_some_and(|batches| batches > 1.0) {
+        Some(format!(
+            "Hash used {} batches and spilled to disk; consider raising work_mem",
+            node.hash_batches.unwrap_or(0.0).round()
+        ))
+    } else if node.temp_written_blocks.is_some_and(|blocks| blocks > 0.0) {
+        Some(format!("Wrote {} temporary blocks to disk", node.temp_written_blocks.unwrap_or(0.0).round()))
+    } else {
+        None
+    };
+    if let Some(message) = spill {
+        flag(HotspotKind::DiskSpill, message);
+    }
+
+    hotspots
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn analyzed_plan() -> Value {
+        json!([{
+            "Plan": {
+                "Node Type": "Sort",
+                "Startup Cost": 100.5, "Total Cost": 110.0, "Plan Rows": 50, "Plan Width": 8,
+                "Actual Startup Time": 40.0, "Actual Total Time": 45.0, "Actual Rows": 20000, "Actual Loops": 1,
+                "Sort Key": ["o.created_at"],
+                "Sort Method": "external merge", "Sort Space Used": 2048, "Sort Space Type": "Disk",
+                "Plans": [{
+                    "Node Type": "Seq Scan", "Parent Relationship": "Outer",
+                    "Relation Name": "orders", "Alias": "o",
+                    "Startup Cost": 0.0, "Total Cost": 90.0, "Plan Rows": 50, "Plan Width": 8,
+                    "Actual Startup Time": 0.1, "Actual Total Time": 30.0, "Actual Rows": 20000, "Actual Loops": 1,
+                    "Filter": "(status = 'open'::text)", "Rows Removed by Filter": 5000
+                }]
+            },
+            "Planning Time": 0.2,
+            "Execution Time": 46.0,
+            "Triggers": []
+        }])
+    }
+
+    #[test]
+    fn test_explain_sql_takes_one_statement() {
+        let options = ExplainOptions { analyze: true, buffers: true, verbose: false };
+        assert_eq!(
+            explain_sql("SELECT 1;", &options).unwrap(),
+            "EXPLAIN (FORMAT JSON, ANALYZE, BUFFERS) SELECT 1"
+        );
+        assert!(explain_sql("SELECT 1; SELECT 2", &options).is_err());
+        assert!(explain_sql("explain SELECT 1", &options).is_err());
+        assert!(explain_sql("  ", &options).is_err());
+    }
+
+    #[test]
+    fn test_parses_plan_tree() {
+        let plan = parse_plan(analyzed_plan(), &HashMap::new()).unwrap();
+        assert!(plan.analyzed);
+        assert_eq!(plan.execution_time_ms, Some(46.0));
+
+        let root = &plan.plan;
+        assert_eq!((root.id, root.node_type.as_str()), (0, "Sort"));
+        assert_eq!(root.self_time_ms, Some(15.0));
+        assert_eq!(root.details["Sort Key"], json!(["o.created_at"]));
+        assert!(!root.details.contains_key("Plans"));
+
+        let scan = &root.children[0];
+        assert_eq!(scan.id, 1);
+        assert_eq!(scan.relation_name.as_deref(), Some("orders"));
+        assert_eq!(scan.actual_rows, Some(20000.0));
+        assert_eq!(scan.rows_removed_by_filter, Some(5000.0));
+        assert_eq!(scan.self_time_ms, Some(30.0));
+    }
+
+    #[test]
+    fn test_flags_hotspots() {
+        let plan = parse_plan(analyzed_plan(), &HashMap::new()).unwrap();
+        let kinds: Vec<(usize, HotspotKind)> = plan.hotspots.iter().map(|h| (h.node_id, h.kind)).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                (0, HotspotKind::RowMisestimate),
+                (0, HotspotKind::DiskSpill),
+                (1, HotspotKind::SeqScan),
+                (1, HotspotKind::RowMisestimate),
+            ]
+        );
+        assert_eq!(plan.hotspots[1].message, "Sort spilled 2048 kB to disk; consider raising work_mem");
+
+        // A known small table is not flagged, whatever the scan read
+        let small = HashMap::from([((None, "orders".to_string()), 500.0)]);
+        let plan = parse_plan(analyzed_plan(), &small).unwrap();
+        assert!(!plan.hotspots.iter().any(|h| h.kind == HotspotKind::SeqScan));
+    }
+
+    #[test]
+    fn test_plain_explain_has_no_actuals() {
+        let raw = json!([{ "Plan": {
+            "Node Type": "Seq Scan", "Relation Name": "events", "Schema": "public",
+            "Startup Cost": 0.0, "Total Cost": 1000.0, "Plan Rows": 50000, "Plan Width": 4
+        } }]);
+        assert_eq!(
+            seq_scan_relations(&raw),
+            vec![(Some("public".to_string()), "events".to_string())]
+        );
+
+        let plan = parse_plan(raw, &HashMap::new()).unwrap();
+        assert!(!plan.analyzed);
+        assert_eq!(plan.plan.self_time_ms, None);
+        let kinds: Vec<HotspotKind> = plan.hotspots.iter().map(|h| h.kind).collect();
+        assert_eq!(kinds, vec![HotspotKind::SeqScan]);
+        assert!(parse_plan(json!([]), &HashMap::new()).is_err());
+    }
+}
@@ -0,0 +1,381 @@
+use crate::production_guard::Access;
+use crate::session_registry::DatabaseSession;
+use crate::value_codec::{self, ColumnType};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{Mutex, Semaphore};
+
+/// Event carrying one batch of rows
+pub const QUERY_STREAM_BATCH_EVENT: &str = "query-stream-batch";
+/// Event sent once when a stream completes, fails or is cancelled
+pub const QUERY_STREAM_END_EVENT: &str = "query-stream-end";
+
+const DEFAULT_BATCH_SIZE: usize = 1000;
+const MAX_BATCH_SIZE: usize = 50_000;
+const DEFAULT_MAX_IN_FLIGHT: usize = 2;
+
+/// A stream is abandoned when the frontend stops acknowledging batches for this long
+const ACK_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Tauri state for running query streams
+pub type QueryStreamRegistryState = Arc<Mutex<QueryStreamRegistry>>;
+
+/// Options for `start_query_stream`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct QueryStreamOptions {
+    /// Rows fetched from the cursor per batch
+    pub batch_size: Option<usize>,
+    /// Batches the frontend may have unacknowledged before fetching pauses
+    pub max_in_flight: Option<usize>,
+}
+
+impl QueryStreamOptions {
+    fn batch_size(&self) -> usize {
+        self.batch_size.unwrap_or(DEFAULT_BATCH_SIZE).clamp(1, MAX_BATCH_SIZE)
+    }
+
+    fn max_in_flight(&self) -> usize {
+        self.max_in_flight.unwrap_or(DEFAULT_MAX_IN_FLIGHT).max(1)
+    }
+}
+
+/// One batch of rows. `columns` is repeated so every batch is self-describing.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueryStreamBatch {
+    pub stream_id: String,
+    pub batch_index: u64,
+    pub columns: Vec<String>,
+    pub column_types: Vec<ColumnType>,
+    pub rows: Vec<Vec<serde_json::Value>>,
+    pub rows_sent: u64,
+    /// Set on the last batch of a completed stream
+    pub done: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QueryStreamStatus {
+    Completed,
+    Cancelled,
+    Failed,
+}
+
+/// Final event of a stream
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueryStreamEnd {
+    pub stream_id: String,
+    pub status: QueryStreamStatus,
+    pub total_rows: u64,
+    pub batches: u64,
+    pub execution_time_ms: u64,
+    pub error: Option<String>,
+}
+
+/// Where stream events go; the app emits them as Tauri events
+pub trait QueryStreamSink: Send + Sync + 'static {
+    fn send_batch(&self, batch: QueryStreamBatch) -> Result<(), String>;
+    fn finish(&self, end: QueryStreamEnd);
+}
+
+impl<R: tauri::Runtime> QueryStreamSink for tauri::AppHandle<R> {
+    fn send_batch(&self, batch: QueryStreamBatch) -> Result<(), String> {
+        use tauri::Emitter;
+        self.emit(QUERY_STREAM_BATCH_EVENT, batch)
+            .map_err(|e| format!("Failed to emit batch: {}", e))
+    }
+
+    fn finish(&self, end: QueryStreamEnd) {
+        use tauri::Emitter;
+        if let Err(e) = self.emit(QUERY_STREAM_END_EVENT, end) {
+            log::error!("Failed to emit query stream end: {}", e);
+        }
+    }
+}
+
+/// Flow control shared between a running stream and the registry.
+///
+/// Each emitted batch consumes a credit and each acknowledgement returns one,
+/// so at most `max_in_flight` batches are waiting in the frontend.
+#[derive(Clone)]
+pub struct StreamControl {
+    credits: Arc<Semaphore>,
+    outstanding: Arc<AtomicUsize>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl StreamControl {
+    fn new(max_in_flight: usize) -> Self {
+        Self {
+            credits: Arc::new(Semaphore::new(max_in_flight)),
+            outstanding: Arc::new(AtomicUsize::new(0)),
+            cancelled: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Wait until another batch may be sent. Returns `false` once the stream is cancelled.
+    async fn acquire(&self, ack_timeout: Duration) -> Result<bool, String> {
+        match tokio::time::timeout(ack_timeout, self.credits.acquire()).await {
+            Ok(Ok(permit)) => {
+                permit.forget();
+                self.outstanding.fetch_add(1, Ordering::SeqCst);
+                Ok(!self.is_cancelled())
+            }
+            // The semaphore is closed on cancel
+            Ok(Err(_)) => Ok(false),
+            Err(_) => Err(format!(
+                "Stream abandoned: no batch acknowledged within {}s",
+                ack_timeout.as_secs()
+            )),
+        }
+    }
+
+    /// Return the credit of one delivered batch. Extra acknowledgements are ignored.
+    fn ack(&self) -> bool {
+        let acked = self
+            .outstanding
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+            .is_ok();
+        if acked {
+            self.credits.add_permits(1);
+        }
+        acked
+    }
+
+    fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+        self.credits.close();
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+}
+
+/// Running streams keyed by stream id
+#[derive(Default)]
+pub struct QueryStreamRegistry {
+    streams: HashMap<String, StreamControl>,
+}
+
+impl QueryStreamRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a new stream and return its id
+    pub fn register(&mut self, options: &QueryStreamOptions) -> (String, StreamControl) {
+        let stream_id = uuid::Uuid::new_v4().to_string();
+        let control = StreamControl::new(options.max_in_flight());
+        self.streams.insert(stream_id.clone(), control.clone());
+        (stream_id, control)
+    }
+
+    /// Acknowledge a delivered batch so the stream may fetch the next one
+    pub fn ack(&self, stream_id: &str) -> Result<bool, String> {
+        self.streams
+            .get(stream_id)
+            .map(|control| control.ack())
+            .ok_or_else(|| format!("Query stream '{}' not found", stream_id))
+    }
+
+    /// Stop a stream before its next fetch. Returns `false` if it is not running.
+    pub fn cancel(&mut self, stream_id: &str) -> bool {
+        match self.streams.remove(stream_id) {
+            Some(control) => {
+                control.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn remove(&mut self, stream_id: &str) {
+        self.streams.remove(stream_id);
+    }
+}
+
+/// Stream a query's rows in batches from a server-side cursor.
+///
+/// The query is bound to a portal inside a transaction on a pooled connection and
+/// fetched `batch_size` rows at a time, so the server never re-runs the query
+/// and the backend never holds more than `max_in_flight` batches. Returns the final
+/// event it sent.
+#[allow(clippy::too_many_arguments)]
+pub async fn run_query_stream<S: QueryStreamSink>(
+    session: Arc<DatabaseSession>,
+    registry: QueryStreamRegistryState,
+    stream_id: String,
+    query: String,
+    options: QueryStreamOptions,
+    access: Access,
+    control: StreamControl,
+    sink: S,
+) -> QueryStreamEnd {
+    let start_time = Instant::now();
+    let mut progress = StreamProgress::default();
+
+    let result = stream_batches(&session, &stream_id, &query, &options, access, &control, &sink, &mut progress).await;
+    registry.lock().await.remove(&stream_id);
+
+    let (status, error) = match result {
+        Ok(()) if control.is_cancelled() => (QueryStreamStatus::Cancelled, None),
+        Ok(()) => (QueryStreamStatus::Completed, None),
+        Err(e) => (QueryStreamStatus::Failed, Some(e)),
+    };
+    log::info!(
+        "Query stream {} finished: {:?}, {} rows in {} batches",
+        stream_id,
+        status,
+        progress.rows_sent,
+        progress.batches
+    );
+
+    let end = QueryStreamEnd {
+        stream_id,
+        status,
+        total_rows: progress.rows_sent,
+        batches: progress.batches,
+        execution_time_ms: start_time.elapsed().as_millis() as u64,
+        error,
+    };
+    sink.finish(end.clone());
+    end
+}
+
+#[derive(Default)]
+struct StreamProgress {
+    rows_sent: u64,
+    batches: u64,
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn stream_batches<S: QueryStreamSink>(
+    session: &DatabaseSession,
+    stream_id: &str,
+    query: &str,
+    options: &QueryStreamOptions,
+    access: Access,
+    control: &StreamControl,
+    sink: &S,
+    progress: &mut StreamProgress,
+) -> Result<(), String> {
+    let batch_size = options.batch_size();
+    let mut client = session.pool.lock().await.get_connection().await?;
+    let zone = value_codec::read_time_zone(&client).await;
+    let transaction = client
+        .build_transaction()
+        .read_only(access.is_read_only())
+        .start()
+        .await
+        .map_err(|e| format!("Failed to start transaction: {}", e))?;
+    let statement = transaction
+        .prepare(query)
+        .await
+        .map_err(|e| format!("Query failed: {}", e))?;
+    let portal = transaction
+        .bind(&statement, &[])
+        .await
+        .map_err(|e| format!("Query failed: {}", e))?;
+    let columns: Vec<String> = statement
+        .columns()
+        .iter()
+        .map(|column| column.name().to_string())
+        .collect();
+    let column_types = value_codec::column_types(statement.columns());
+
+    loop {
+        if !control.acquire(ACK_TIMEOUT).await? {
+            // Dropping the transaction rolls it back and closes the cursor
+            return Ok(());
+        }
+
+        let rows = transaction
+            .query_portal(&portal, batch_size as i32)
+            .await
+            .map_err(|e| format!("Query failed: {}", e))?;
+        let done = rows.len() < batch_size;
+        progress.rows_sent += rows.len() as u64;
+
+        sink.send_batch(QueryStreamBatch {
+            stream_id: stream_id.to_string(),
+            batch_index: progress.batches,
+            columns: columns.clone(),
+            column_types: column_types.clone(),
+            rows: rows.iter().map(|row| value_codec::decode_row(row, &zone)).collect(),
+            rows_sent: progress.rows_sent,
+            done,
+        })?;
+        progress.batches += 1;
+
+        if done {
+            break;
+        }
+    }
+
+    transaction
+        .commit()
+        .await
+        .map_err(|e| format!("Failed to commit transaction: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_credits_limit_batches_in_flight() {
+        let control = StreamControl::new(2);
+        let timeout = Duration::from_millis(50);
+
+        assert!(control.acquire(timeout).await.unwrap());
+        assert!(control.acquire(timeout).await.unwrap());
+        // Third batch must wait for an acknowledgement
+        assert!(control.acquire(timeout).await.is_err());
+
+        assert!(control.ack());
+        assert!(control.acquire(timeout).await.unwrap());
+
+        // Acknowledging more batches than were sent does not widen the window
+        assert!(control.ack());
+        assert!(control.ack());
+        assert!(!control.ack());
+        assert!(control.acquire(timeout).await.unwrap());
+        assert!(control.acquire(timeout).await.unwrap());
+        assert!(control.acquire(timeout).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_cancel_wakes_waiting_stream() {
+        let mut registry = QueryStreamRegistry::new();
+        let options = QueryStreamOptions {
+            batch_size: None,
+            max_in_flight: Some(1),
+        };
+        let (stream_id, control) = registry.register(&options);
+        assert!(control.acquire(ACK_TIMEOUT).await.unwrap());
+
+        let waiting = tokio::spawn({
+            let control = control.clone();
+            async move { control.acquire(ACK_TIMEOUT).await }
+        });
+        assert!(registry.cancel(&stream_id));
+        assert!(!waiting.await.unwrap().unwrap());
+
+        assert!(!registry.cancel(&stream_id));
+        assert!(registry.ack(&stream_id).is_err());
+    }
+
+    #[test]
+    fn test_options_are_clamped() {
+        let options = QueryStreamOptions {
+            batch_size: Some(0),
+            max_in_flight: Some(0),
+        };
+        assert_eq!(options.batch_size(), 1);
+        assert_eq!(options.max_in_flight(), 1);
+        assert_eq!(QueryStreamOptions::default().batch_size(), DEFAULT_BATCH_SIZE);
+    }
+}
@@ -0,0 +1,520 @@
+//! Saved queries and their `:name` parameters.
+//!
+//! Parameters are written as `:name` in the SQL text. At execution time each one becomes a
+//! positional placeholder bound as text and cast to the parameter's type on the server, so
+//! values are never spliced into the SQL. `::` casts and anything inside string literals,
+//! quoted identifiers, dollar-quoted bodies and comments are left alone.
+//!
+//! On disk a saved query is a plain `.sql` file whose leading comment block holds its
+//! metadata, so the file still runs as-is in psql:
+//!
+//! ```sql
+//! -- ---
+//! -- name: Long running queries
+//! -- tags: locks, performance
+//! -- param: min_seconds integer = 30
+//! -- ---
+//! SELECT pid, query FROM pg_stat_activity WHERE now() - query_start > make_interval(secs => :min_seconds);
+//! ```
+
+use crate::production_guard::lex;
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+
+const FRONT_MATTER_FENCE: &str = "-- ---";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ParameterType {
+    Text,
+    Integer,
+    Numeric,
+    Boolean,
+    Date,
+    Timestamp,
+    Timestamptz,
+    Uuid,
+    Json,
+}
+
+impl ParameterType {
+    pub const ALL: [ParameterType; 9] = [
+        ParameterType::Text,
+        ParameterType::Integer,
+        ParameterType::Numeric,
+        ParameterType::Boolean,
+        ParameterType::Date,
+        ParameterType::Timestamp,
+        ParameterType::Timestamptz,
+        ParameterType::Uuid,
+        ParameterType::Json,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ParameterType::Text => "text",
+            ParameterType::Integer => "integer",
+            ParameterType::Numeric => "numeric",
+            ParameterType::Boolean => "boolean",
+            ParameterType::Date => "date",
+            ParameterType::Timestamp => "timestamp",
+            ParameterType::Timestamptz => "timestamptz",
+            ParameterType::Uuid => "uuid",
+            ParameterType::Json => "json",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|ty| ty.name().eq_ignore_ascii_case(name))
+    }
+
+    /// Server-side type the text parameter is cast to
+    fn sql_type(self) -> &'static str {
+        match self {
+            ParameterType::Integer => "bigint",
+            ParameterType::Json => "jsonb",
+            other => other.name(),
+        }
+    }
+
+    /// Check a value and put it in the form the server expects
+    fn normalize(self, value: &str) -> Result<String, String> {
+        if self == ParameterType::Text {
+            return Ok(value.to_string());
+        }
+        let value = value.trim();
+        let valid = match self {
+            ParameterType::Text => true,
+            ParameterType::Integer => value.parse::<i64>().is_ok(),
+            ParameterType::Numeric => value.parse::<f64>().is_ok_and(f64::is_finite),
+            ParameterType::Boolean => {
+                return match value.to_lowercase().as_str() {
+                    "true" | "t" | "yes" | "y" | "on" | "1" => Ok("true".to_string()),
+                    "false" | "f" | "no" | "n" | "off" | "0" => Ok("false".to_string()),
+                    _ => Err(format!("'{}' is not a boolean", value)),
+                };
+            }
+            ParameterType::Date => NaiveDate::parse_from_str(value, "%Y-%m-%d").is_ok(),
+            ParameterType::Timestamp => parse_naive_timestamp(value).is_some(),
+            ParameterType::Timestamptz => {
+                DateTime::parse_from_rfc3339(value).is_ok() || parse_naive_timestamp(value).is_some()
+            }
+            ParameterType::Uuid => uuid::Uuid::parse_str(value).is_ok(),
+            ParameterType::Json => serde_json::from_str::<Value>(value).is_ok(),
+        };
+        if valid {
+            Ok(value.to_string())
+        } else {
+            Err(format!("'{}' is not a valid {}", value, self.name()))
+        }
+    }
+}
+
+fn parse_naive_timestamp(value: &str) -> Option<NaiveDateTime> {
+    ["%Y-%m-%d %H:%M:%S%.f", "%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%d %H:%M", "%Y-%m-%dT%H:%M"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(value, format).ok())
+        .or_else(|| NaiveDate::parse_from_str(value, "%Y-%m-%d").ok()?.and_hms_opt(0, 0, 0))
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QueryParameter {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub param_type: ParameterType,
+    /// Used when the caller passes no value; without one the parameter is required
+    #[serde(default)]
+    pub default_value: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SavedQuery {
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    pub sql: String,
+    /// `/`-separated path, e.g. `Diagnostics/Locks`
+    #[serde(default)]
+    pub folder: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Profile the UI should suggest running the query against
+    #[serde(default)]
+    pub default_profile_id: Option<String>,
+    /// In order of first use in `sql`
+    #[serde(default)]
+    pub parameters: Vec<QueryParameter>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl SavedQuery {
+    pub fn new(name: String, sql: String) -> Self {
+        let now = Utc::now();
+        Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            name,
+            description: None,
+            sql,
+            folder: None,
+            tags: Vec::new(),
+            default_profile_id: None,
+            parameters: Vec::new(),
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    /// Check the query and make `parameters` match the placeholders in `sql`:
+    /// undeclared placeholders become text parameters and unused declarations are dropped.
+    pub fn normalize(&mut self) -> Result<(), String> {
+        self.name = self.name.trim().to_string();
+        if self.name.is_empty() {
+            return Err("Saved query name cannot be empty".to_string());
+        }
+        if self.sql.trim().is_empty() {
+            return Err("Saved query SQL cannot be empty".to_string());
+        }
+        self.folder = self
+            .folder
+            .as_deref()
+            .map(|folder| folder.split('/').map(str::trim).filter(|part| !part.is_empty()).collect::<Vec<_>>().join("/"))
+            .filter(|folder| !folder.is_empty());
+        self.tags = self.tags.iter().map(|tag| tag.trim().to_string()).filter(|tag| !tag.is_empty()).collect();
+        let mut seen = std::collections::HashSet::new();
+        self.tags.retain(|tag| seen.insert(tag.clone()));
+
+        let mut declared: HashMap<String, QueryParameter> = HashMap::new();
+        for parameter in self.parameters.drain(..) {
+            if let Some(default) = &parameter.default_value {
+                parameter
+                    .param_type
+                    .normalize(default)
+                    .map_err(|e| format!("Default for :{}: {}", parameter.name, e))?;
+            }
+            declared.insert(parameter.name.clone(), parameter);
+        }
+
+        let mut parameters: Vec<QueryParameter> = Vec::new();
+        for placeholder in find_placeholders(&self.sql) {
+            if parameters.iter().any(|p| p.name == placeholder.name) {
+                continue;
+            }
+            parameters.push(declared.remove(&placeholder.name).unwrap_or(QueryParameter {
+                name: placeholder.name,
+                param_type: ParameterType::Text,
+                default_value: None,
+            }));
+        }
+        self.parameters = parameters;
+        Ok(())
+    }
+
+    /// Replace `:name` placeholders with `$n` casts and collect the bound values.
+    /// Missing values fall back to the parameter's default; JSON `null` binds NULL.
+    pub fn bind(&self, values: &HashMap<String, Value>) -> Result<(String, Vec<Option<String>>), String> {
+        let mut params: Vec<Option<String>> = Vec::with_capacity(self.parameters.len());
+        for parameter in &self.parameters {
+            let value = match values.get(&parameter.name) {
+                Some(Value::Null) => None,
+                Some(value) => Some(value_text(value, parameter.param_type)?),
+                None => match &parameter.default_value {
+                    Some(default) => Some(default.clone()),
+                    None => return Err(format!("Missing value for parameter :{}", parameter.name)),
+                },
+            };
+            let value = value
+                .map(|value| parameter.param_type.normalize(&value))
+                .transpose()
+                .map_err(|e| format!("Parameter :{}: {}", parameter.name, e))?;
+            params.push(value);
+        }
+
+        let mut sql = String::with_capacity(self.sql.len());
+        let mut last = 0;
+        for placeholder in find_placeholders(&self.sql) {
+            let index = self
+                .parameters
+                .iter()
+                .position(|p| p.name == placeholder.name)
+                .ok_or_else(|| format!("Parameter :{} is not declared", placeholder.name))?;
+            let ty = self.parameters[index].param_type;
+            sql.push_str(&self.sql[last..placeholder.start]);
+            sql.push_str(&format!("(${}::text::{})", index + 1, ty.sql_type()));
+            last = placeholder.end;
+        }
+        sql.push_str(&self.sql[last..]);
+        Ok((sql, params))
+    }
+
+    /// Render as a `.sql` file with front-matter
+    pub fn to_sql_file(&self) -> String {
+        let mut out = String::new();
+        out.push_str(FRONT_MATTER_FENCE);
+        out.push('\n');
+        let mut field = |key: &str, value: &str| {
+            // Values are single-line; the SQL body is where multi-line text belongs
+            out.push_str(&format!("-- {}: {}\n", key, value.replace(['\r', '\n'], " ")));
+        };
+        field("id", &self.id);
+        field("name", &self.name);
+        if let Some(description) = &self.description {
+            field("description", description);
+        }
+        if let Some(folder) = &self.folder {
+            field("folder", folder);
+        }
+        if !self.tags.is_empty() {
+            field("tags", &self.tags.join(", "));
+        }
+        if let Some(profile_id) = &self.default_profile_id {
+            field("default_profile", profile_id);
+        }
+        for parameter in &self.parameters {
+            let mut value = format!("{} {}", parameter.name, parameter.param_type.name());
+            if let Some(default) = &parameter.default_value {
+                value.push_str(&format!(" = {}", default));
+            }
+            field("param", &value);
+        }
+        field("created_at", &self.created_at.to_rfc3339());
+        field("updated_at", &self.updated_at.to_rfc3339());
+        out.push_str(FRONT_MATTER_FENCE);
+        out.push('\n');
+        out.push_str(self.sql.trim_end());
+        out.push('\n');
+        out
+    }
+
+    /// Parse a `.sql` file. Without front-matter the whole file is the SQL and
+    /// `fallback_name` names the query.
+    pub fn from_sql_file(content: &str, fallback_name: &str) -> Result<Self, String> {
+        let content = content.strip_prefix('\u{feff}').unwrap_or(content);
+        let mut query = SavedQuery::new(fallback_name.to_string(), String::new());
+        let mut lines = content.lines();
+
+        let body = if content.lines().next().map(str::trim_end) == Some(FRONT_MATTER_FENCE) {
+            lines.next();
+            let mut closed = false;
+            for line in lines.by_ref() {
+                let line = line.trim_end();
+                if line == FRONT_MATTER_FENCE {
+                    closed = true;
+                    break;
+                }
+                let Some((key, value)) = line.strip_prefix("--").and_then(|rest| rest.split_once(':')) else {
+                    continue;
+                };
+                query.apply_field(key.trim(), value.trim())?;
+            }
+            if !closed {
+                return Err("Front-matter is not closed with '-- ---'".to_string());
+            }
+            lines.collect::<Vec<_>>().join("\n")
+        } else {
+            content.to_string()
+        };
+
+        query.sql = body.trim().to_string();
+        query.normalize()?;
+        Ok(query)
+    }
+
+    fn apply_field(&mut self, key: &str, value: &str) -> Result<(), String> {
+        let optional = |value: &str| Some(value.to_string()).filter(|v| !v.is_empty());
+        match key {
+            "id" if !value.is_empty() => self.id = value.to_string(),
+            "name" if !value.is_empty() => self.name = value.to_string(),
+            "description" => self.description = optional(value),
+            "folder" => self.folder = optional(value),
+            "tags" => self.tags = value.split(',').map(|tag| tag.trim().to_string()).collect(),
+            "default_profile" => self.default_profile_id = optional(value),
+            "param" => self.parameters.push(parse_param_field(value)?),
+            "created_at" | "updated_at" => {
+                let timestamp = DateTime::parse_from_rfc3339(value)
+                    .map_err(|e| format!("Invalid {} '{}': {}", key, value, e))?
+                    .with_timezone(&Utc);
+                if key == "created_at" {
+                    self.created_at = timestamp;
+                } else {
+                    self.updated_at = timestamp;
+                }
+            }
+            // Unknown keys are ignored so newer files still import
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+/// `name type [= default]`
+fn parse_param_field(value: &str) -> Result<QueryParameter, String> {
+    let (declaration, default_value) = match value.split_once('=') {
+        Some((declaration, default)) => (declaration, Some(default.trim().to_string())),
+        None => (value, None),
+    };
+    let mut words = declaration.split_whitespace();
+    let name = words
+        .next()
+        .map(|name| name.trim_start_matches(':').to_string())
+        .filter(|name| is_parameter_name(name))
+        .ok_or_else(|| format!("Invalid parameter declaration '{}'", value))?;
+    let param_type = match words.next() {
+        Some(ty) => ParameterType::from_name(ty).ok_or_else(|| format!("Unknown parameter type '{}'", ty))?,
+        None => ParameterType::Text,
+    };
+    Ok(QueryParameter { name, param_type, default_value })
+}
+
+fn is_parameter_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_alphabetic() || c == '_') && chars.all(|c| c.is_alphanumeric() || c == '_')
+}
+
+/// JSON value as the text sent to the server
+fn value_text(value: &Value, param_type: ParameterType) -> Result<String, String> {
+    match value {
+        Value::String(s) => Ok(s.clone()),
+        Value::Number(n) => Ok(n.to_string()),
+        Value::Bool(b) => Ok(b.to_string()),
+        Value::Array(_) | Value::Object(_) if param_type == ParameterType::Json => Ok(value.to_string()),
+        _ => Err(format!("A {} parameter cannot take {}", param_type.name(), value)),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Placeholder {
+    pub name: String,
+    /// Byte range of `:name` in the SQL
+    pub start: usize,
+    pub end: usize,
+}
+
+/// Find `:name` placeholders outside literals, quoted identifiers, comments and array
+/// subscripts like `a[lo:hi]`
+pub fn find_placeholders(sql: &str) -> Vec<Placeholder> {
+    let tokens = lex(sql);
+    let mut placeholders = Vec::new();
+    let mut brackets = 0usize;
+    for (i, token) in tokens.iter().enumerate() {
+        match token.text {
+            "[" => brackets += 1,
+            "]" => brackets = brackets.saturating_sub(1),
+            ":" if brackets == 0 => {
+                let Some(name) = tokens.get(i + 1) else { continue };
+                if name.start == token.start + 1 && name.text.starts_with(|c: char| c.is_alphabetic() || c == '_') {
+                    placeholders.push(Placeholder {
+                        name: name.text.to_string(),
+                        start: token.start,
+                        end: name.start + name.text.len(),
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+    placeholders
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn names(sql: &str) -> Vec<String> {
+        find_placeholders(sql).into_iter().map(|p| p.name).collect()
+    }
+
+    #[test]
+    fn test_finds_placeholders_outside_literals() {
+        assert_eq!(names("SELECT * FROM t WHERE a = :a AND b::text = :b_2"), vec!["a", "b_2"]);
+        assert_eq!(
+            names("SELECT ':no', \"col:no\", E'\\':no', $$ :no $$, $f$ :no $f$ -- :no\n/* :no */ FROM t WHERE x = :yes"),
+            vec!["yes"]
+        );
+        assert_eq!(names("SELECT arr[1:2], arr[lo:hi], arr[:n][m:], now()::date"), Vec::<String>::new());
+        assert_eq!(names("SELECT arr[1:2] FROM t WHERE id = :id"), vec!["id"]);
+    }
+
+    #[test]
+    fn test_normalize_syncs_parameters_with_sql() {
+        let mut query = SavedQuery::new(" Locks ".to_string(), "SELECT :pid, :name, :pid".to_string());
+        query.folder = Some("/Diagnostics//Locks/".to_string());
+        query.parameters = vec![
+            QueryParameter { name: "pid".to_string(), param_type: ParameterType::Integer, default_value: None },
+            QueryParameter { name: "unused".to_string(), param_type: ParameterType::Date, default_value: None },
+        ];
+        query.normalize().unwrap();
+
+        assert_eq!(query.name, "Locks");
+        assert_eq!(query.folder.as_deref(), Some("Diagnostics/Locks"));
+        let parameters: Vec<(&str, ParameterType)> =
+            query.parameters.iter().map(|p| (p.name.as_str(), p.param_type)).collect();
+        assert_eq!(parameters, vec![("pid", ParameterType::Integer), ("name", ParameterType::Text)]);
+
+        query.parameters[0].default_value = Some("abc".to_string());
+        assert!(query.normalize().is_err());
+    }
+
+    #[test]
+    fn test_binds_typed_parameters() {
+        let mut query = SavedQuery::new(
+            "q".to_string(),
+            "SELECT * FROM t WHERE id = :id AND active = :active AND name = :name OR id = :id".to_string(),
+        );
+        query.parameters = vec![
+            QueryParameter { name: "id".to_string(), param_type: ParameterType::Integer, default_value: None },
+            QueryParameter { name: "active".to_string(), param_type: ParameterType::Boolean, default_value: Some("yes".to_string()) },
+        ];
+        query.normalize().unwrap();
+
+        let values = HashMap::from([("id".to_string(), json!(42)), ("name".to_string(), Value::Null)]);
+        let (sql, params) = query.bind(&values).unwrap();
+        assert_eq!(
+            sql,
+            "SELECT * FROM t WHERE id = ($1::text::bigint) AND active = ($2::text::boolean) AND name = ($3::text::text) OR id = ($1::text::bigint)"
+        );
+        assert_eq!(params, vec![Some("42".to_string()), Some("true".to_string()), None]);
+
+        let bad = HashMap::from([("id".to_string(), json!("4x")), ("name".to_string(), json!("a"))]);
+        assert_eq!(query.bind(&bad).unwrap_err(), "Parameter :id: '4x' is not a valid integer");
+        let missing = HashMap::from([("name".to_string(), json!("a"))]);
+        assert_eq!(query.bind(&missing).unwrap_err(), "Missing value for parameter :id");
+    }
+
+    #[test]
+    fn test_sql_file_round_trip() {
+        let mut query = SavedQuery::new("Long running".to_string(), "SELECT pid\nFROM pg_stat_activity\nWHERE state = :state;".to_string());
+        query.description = Some("Sessions that\nrun too long".to_string());
+        query.folder = Some("Diagnostics".to_string());
+        query.tags = vec!["perf".to_string(), "locks".to_string()];
+        query.default_profile_id = Some("profile-1".to_string());
+        query.parameters = vec![QueryParameter {
+            name: "state".to_string(),
+            param_type: ParameterType::Text,
+            default_value: Some("active".to_string()),
+        }];
+        query.normalize().unwrap();
+
+        let file = query.to_sql_file();
+        assert!(file.starts_with("-- ---\n-- id: "));
+        assert!(file.contains("-- param: state text = active\n"));
+
+        let parsed = SavedQuery::from_sql_file(&file, "ignored").unwrap();
+        assert_eq!(parsed.description.as_deref(), Some("Sessions that run too long"));
+        assert_eq!(SavedQuery { description: query.description.clone(), ..parsed }, query);
+    }
+
+    #[test]
+    fn test_plain_sql_file_uses_fallback_name() {
+        let parsed = SavedQuery::from_sql_file("SELECT * FROM t WHERE id = :id\n", "by-id").unwrap();
+        assert_eq!(parsed.name, "by-id");
+        assert_eq!(parsed.parameters.len(), 1);
+
+        assert!(SavedQuery::from_sql_file("-- ---\n-- name: x\nSELECT 1", "x").is_err());
+        assert!(SavedQuery::from_sql_file("-- ---\n-- param: x money\n-- ---\nSELECT :x", "x").is_err());
+    }
+}
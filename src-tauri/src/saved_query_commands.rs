@@ -0,0 +1,90 @@
+use crate::saved_query::SavedQuery;
+use crate::saved_query_store::*;
+use std::path::Path;
+use tauri::State;
+
+/// Search the saved query library; no criteria lists every query
+#[tauri::command]
+pub async fn search_saved_queries(
+    store: State<'_, SavedQueryStoreState>,
+    search: Option<SavedQuerySearch>,
+) -> Result<Vec<SavedQuery>, String> {
+    let store = store.lock().await;
+    Ok(store.search(&search.unwrap_or_default()))
+}
+
+#[tauri::command]
+pub async fn get_saved_query(
+    store: State<'_, SavedQueryStoreState>,
+    id: String,
+) -> Result<SavedQuery, String> {
+    let store = store.lock().await;
+    store.get(&id)
+}
+
+/// Save a new query; parameters are synced with the `:name` placeholders in its SQL
+#[tauri::command]
+pub async fn create_saved_query(
+    store: State<'_, SavedQueryStoreState>,
+    query: SavedQuery,
+) -> Result<SavedQuery, String> {
+    let mut store = store.lock().await;
+    store.create(query)
+}
+
+#[tauri::command]
+pub async fn update_saved_query(
+    store: State<'_, SavedQueryStoreState>,
+    id: String,
+    query: SavedQuery,
+) -> Result<SavedQuery, String> {
+    let mut store = store.lock().await;
+    store.update(&id, query)
+}
+
+#[tauri::command]
+pub async fn delete_saved_query(
+    store: State<'_, SavedQueryStoreState>,
+    id: String,
+) -> Result<SavedQuery, String> {
+    let mut store = store.lock().await;
+    store.delete(&id)
+}
+
+#[tauri::command]
+pub async fn get_saved_query_folders(
+    store: State<'_, SavedQueryStoreState>,
+) -> Result<Vec<String>, String> {
+    let store = store.lock().await;
+    Ok(store.folders())
+}
+
+#[tauri::command]
+pub async fn get_saved_query_tags(
+    store: State<'_, SavedQueryStoreState>,
+) -> Result<Vec<String>, String> {
+    let store = store.lock().await;
+    Ok(store.tags())
+}
+
+/// Write the library to `directory` as `.sql` files with front-matter
+#[tauri::command]
+pub async fn export_saved_queries(
+    store: State<'_, SavedQueryStoreState>,
+    directory: String,
+) -> Result<usize, String> {
+    println!("🦀 [Command] export_saved_queries called for: {}", directory);
+    let store = store.lock().await;
+    store.export_to_dir(Path::new(&directory))
+}
+
+/// Load every `.sql` file below `directory` into the library
+#[tauri::command]
+pub async fn import_saved_queries(
+    store: State<'_, SavedQueryStoreState>,
+    directory: String,
+) -> Result<SavedQueryImportSummary, String> {
+    println!("🦀 [Command] import_saved_queries called for: {}", directory);
+    let mut store = store.lock().await;
+    store.import_from_dir(Path::new(&directory))
+}
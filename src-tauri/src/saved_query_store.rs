@@ -0,0 +1,382 @@
+use crate::saved_query::SavedQuery;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+/// Tauri state for the saved query library
+pub type SavedQueryStoreState = Arc<Mutex<SavedQueryStore>>;
+
+const STORAGE_VERSION: &str = "1.0.0";
+
+/// Search criteria; every field is optional and they combine with AND
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SavedQuerySearch {
+    /// Case-insensitive substring of the name, description or SQL
+    pub query: Option<String>,
+    /// Queries in this folder or any folder below it
+    pub folder: Option<String>,
+    /// Queries carrying all of these tags
+    pub tags: Vec<String>,
+    pub default_profile_id: Option<String>,
+}
+
+impl SavedQuerySearch {
+    fn matches(&self, query: &SavedQuery) -> bool {
+        if let Some(text) = &self.query {
+            let text = text.to_lowercase();
+            let found = query.name.to_lowercase().contains(&text)
+                || query.sql.to_lowercase().contains(&text)
+                || query.description.as_ref().is_some_and(|d| d.to_lowercase().contains(&text));
+            if !found {
+                return false;
+            }
+        }
+        if let Some(folder) = self.folder.as_deref().map(|f| f.trim_matches('/')).filter(|f| !f.is_empty()) {
+            let inside = query
+                .folder
+                .as_deref()
+                .is_some_and(|f| f == folder || f.starts_with(&format!("{}/", folder)));
+            if !inside {
+                return false;
+            }
+        }
+        if !self.tags.iter().all(|tag| query.tags.contains(tag)) {
+            return false;
+        }
+        self.default_profile_id.is_none() || query.default_profile_id == self.default_profile_id
+    }
+}
+
+/// Outcome of importing a directory of `.sql` files
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SavedQueryImportSummary {
+    pub created: usize,
+    pub updated: usize,
+    /// `path: reason` for files that were skipped
+    pub errors: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct StoredQueries {
+    version: String,
+    queries: Vec<SavedQuery>,
+}
+
+/// File-backed library of saved queries
+pub struct SavedQueryStore {
+    storage_path: PathBuf,
+    queries: Vec<SavedQuery>,
+}
+
+impl SavedQueryStore {
+    /// Open the library, loading existing queries if the file is present
+    pub fn open<P: AsRef<Path>>(storage_path: P) -> Result<Self, String> {
+        let storage_path = storage_path.as_ref().to_path_buf();
+        if let Some(parent) = storage_path.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create saved query directory: {}", e))?;
+        }
+
+        let mut store = Self {
+            storage_path,
+            queries: Vec::new(),
+        };
+
+        if store.storage_path.exists() {
+            let content = fs::read_to_string(&store.storage_path)
+                .map_err(|e| format!("Failed to read saved queries: {}", e))?;
+            let stored: StoredQueries = serde_json::from_str(&content)
+                .map_err(|e| format!("Failed to parse saved queries: {}", e))?;
+            if stored.version != STORAGE_VERSION {
+                return Err(format!("Unsupported saved query version: {}", stored.version));
+            }
+            store.queries = stored.queries;
+        }
+
+        Ok(store)
+    }
+
+    pub fn get(&self, id: &str) -> Result<SavedQuery, String> {
+        self.queries
+            .iter()
+            .find(|query| query.id == id)
+            .cloned()
+            .ok_or_else(|| format!("Saved query '{}' not found", id))
+    }
+
+    /// Matching queries sorted by folder, then name
+    pub fn search(&self, search: &SavedQuerySearch) -> Vec<SavedQuery> {
+        let mut queries: Vec<SavedQuery> = self.queries.iter().filter(|q| search.matches(q)).cloned().collect();
+        queries.sort_by(|a, b| (&a.folder, a.name.to_lowercase()).cmp(&(&b.folder, b.name.to_lowercase())));
+        queries
+    }
+
+    pub fn create(&mut self, mut query: SavedQuery) -> Result<SavedQuery, String> {
+        if query.id.is_empty() {
+            query.id = uuid::Uuid::new_v4().to_string();
+        }
+        if self.queries.iter().any(|q| q.id == query.id) {
+            return Err(format!("Saved query '{}' already exists", query.id));
+        }
+        query.normalize()?;
+        let now = Utc::now();
+        query.created_at = now;
+        query.updated_at = now;
+        self.queries.push(query.clone());
+        self.save()?;
+        Ok(query)
+    }
+
+    pub fn update(&mut self, id: &str, mut query: SavedQuery) -> Result<SavedQuery, String> {
+        let index = self
+            .queries
+            .iter()
+            .position(|q| q.id == id)
+            .ok_or_else(|| format!("Saved query '{}' not found", id))?;
+        query.normalize()?;
+        query.id = id.to_string();
+        query.created_at = self.queries[index].created_at;
+        query.updated_at = Utc::now();
+        self.queries[index] = query.clone();
+        self.save()?;
+        Ok(query)
+    }
+
+    pub fn delete(&mut self, id: &str) -> Result<SavedQuery, String> {
+        let index = self
+            .queries
+            .iter()
+            .position(|q| q.id == id)
+            .ok_or_else(|| format!("Saved query '{}' not found", id))?;
+        let query = self.queries.remove(index);
+        self.save()?;
+        Ok(query)
+    }
+
+    pub fn folders(&self) -> Vec<String> {
+        let mut folders: Vec<String> = self.queries.iter().filter_map(|q| q.folder.clone()).collect();
+        folders.sort();
+        folders.dedup();
+        folders
+    }
+
+    pub fn tags(&self) -> Vec<String> {
+        let mut tags: Vec<String> = self.queries.iter().flat_map(|q| q.tags.iter().cloned()).collect();
+        tags.sort();
+        tags.dedup();
+        tags
+    }
+
+    /// Write every query as `<folder>/<name>.sql` under `dir`. Returns the number of files written.
+    pub fn export_to_dir(&self, dir: &Path) -> Result<usize, String> {
+        let mut used: HashSet<PathBuf> = HashSet::new();
+        for query in &self.queries {
+            let mut folder = dir.to_path_buf();
+            for part in query.folder.iter().flat_map(|f| f.split('/')) {
+                folder.push(file_name_part(part));
+            }
+            fs::create_dir_all(&folder)
+                .map_err(|e| format!("Failed to create directory {:?}: {}", folder, e))?;
+
+            let stem = file_name_part(&query.name);
+            let mut path = folder.join(format!("{}.sql", stem));
+            let mut suffix = 2;
+            while !used.insert(path.clone()) {
+                path = folder.join(format!("{}-{}.sql", stem, suffix));
+                suffix += 1;
+            }
+            fs::write(&path, query.to_sql_file())
+                .map_err(|e| format!("Failed to write {:?}: {}", path, e))?;
+        }
+        println!("🦀 [SavedQueries] Exported {} queries to {:?}", self.queries.len(), dir);
+        Ok(self.queries.len())
+    }
+
+    /// Read every `.sql` file below `dir`. Files whose id matches a saved query replace it;
+    /// the rest are added. Files without a folder in their front-matter take it from their
+    /// directory. Unreadable files are reported and skipped.
+    pub fn import_from_dir(&mut self, dir: &Path) -> Result<SavedQueryImportSummary, String> {
+        if !dir.is_dir() {
+            return Err(format!("{:?} is not a directory", dir));
+        }
+
+        let mut files = Vec::new();
+        collect_sql_files(dir, &mut files)?;
+        files.sort();
+
+        let mut summary = SavedQueryImportSummary::default();
+        for path in files {
+            let relative = path.strip_prefix(dir).unwrap_or(&path);
+            let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("query");
+            let parsed = fs::read_to_string(&path)
+                .map_err(|e| e.to_string())
+                .and_then(|content| SavedQuery::from_sql_file(&content, stem));
+            let mut query = match parsed {
+                Ok(query) => query,
+                Err(e) => {
+                    summary.errors.push(format!("{}: {}", relative.display(), e));
+                    continue;
+                }
+            };
+            if query.folder.is_none() {
+                let folder: Vec<String> = relative
+                    .parent()
+                    .into_iter()
+                    .flat_map(|p| p.components())
+                    .map(|c| c.as_os_str().to_string_lossy().into_owned())
+                    .collect();
+                query.folder = Some(folder.join("/")).filter(|f| !f.is_empty());
+            }
+
+            match self.queries.iter_mut().find(|q| q.id == query.id) {
+                Some(existing) => {
+                    query.created_at = existing.created_at;
+                    *existing = query;
+                    summary.updated += 1;
+                }
+                None => {
+                    self.queries.push(query);
+                    summary.created += 1;
+                }
+            }
+        }
+
+        if summary.created + summary.updated > 0 {
+            self.save()?;
+        }
+        println!(
+            "🦀 [SavedQueries] Imported {:?}: {} created, {} updated, {} skipped",
+            dir, summary.created, summary.updated, summary.errors.len()
+        );
+        Ok(summary)
+    }
+
+    fn save(&self) -> Result<(), String> {
+        let stored = StoredQueries {
+            version: STORAGE_VERSION.to_string(),
+            queries: self.queries.clone(),
+        };
+        let json = serde_json::to_string_pretty(&stored)
+            .map_err(|e| format!("Failed to serialize saved queries: {}", e))?;
+
+        // Write to a temporary file first so a crash never leaves a truncated library
+        let temp_path = self.storage_path.with_extension("tmp");
+        fs::write(&temp_path, json).map_err(|e| format!("Failed to write saved queries: {}", e))?;
+        fs::rename(&temp_path, &self.storage_path)
+            .map_err(|e| format!("Failed to write saved queries: {}", e))
+    }
+}
+
+fn collect_sql_files(dir: &Path, files: &mut Vec<PathBuf>) -> Result<(), String> {
+    let entries = fs::read_dir(dir).map_err(|e| format!("Failed to read {:?}: {}", dir, e))?;
+    for entry in entries {
+        let path = entry.map_err(|e| format!("Failed to read {:?}: {}", dir, e))?.path();
+        if path.is_dir() {
+            collect_sql_files(&path, files)?;
+        } else if path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("sql")) {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// A name that is safe as a single path component on every platform
+fn file_name_part(name: &str) -> String {
+    let cleaned: String = name
+        .trim()
+        .chars()
+        .map(|c| if c.is_control() || "<>:\"/\\|?*".contains(c) { '_' } else { c })
+        .collect();
+    let cleaned = cleaned.trim_matches(|c| c == '.' || c == ' ');
+    if cleaned.is_empty() { "_".to_string() } else { cleaned.to_string() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn saved(name: &str, folder: Option<&str>, tags: &[&str], sql: &str) -> SavedQuery {
+        let mut query = SavedQuery::new(name.to_string(), sql.to_string());
+        query.folder = folder.map(str::to_string);
+        query.tags = tags.iter().map(|t| t.to_string()).collect();
+        query
+    }
+
+    #[test]
+    fn test_crud_and_reload() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("saved_queries.json");
+        let mut store = SavedQueryStore::open(&path).unwrap();
+
+        let created = store.create(saved("Locks", Some("Diagnostics"), &["locks"], "SELECT * FROM pg_locks")).unwrap();
+        assert!(store.create(created.clone()).is_err());
+        assert!(store.create(saved(" ", None, &[], "SELECT 1")).is_err());
+
+        let mut changed = created.clone();
+        changed.sql = "SELECT * FROM pg_locks WHERE pid = :pid".to_string();
+        let updated = store.update(&created.id, changed).unwrap();
+        assert_eq!(updated.parameters.len(), 1);
+        assert_eq!(updated.created_at, created.created_at);
+
+        let store = SavedQueryStore::open(&path).unwrap();
+        assert_eq!(store.get(&created.id).unwrap(), updated);
+
+        let mut store = store;
+        store.delete(&created.id).unwrap();
+        assert!(store.get(&created.id).is_err());
+    }
+
+    #[test]
+    fn test_search_by_text_folder_and_tags() {
+        let dir = TempDir::new().unwrap();
+        let mut store = SavedQueryStore::open(dir.path().join("q.json")).unwrap();
+        store.create(saved("Locks", Some("Diagnostics/Locks"), &["locks", "perf"], "SELECT 1")).unwrap();
+        store.create(saved("Bloat", Some("Diagnostics"), &["perf"], "SELECT 2")).unwrap();
+        store.create(saved("Users", Some("DiagnosticsOld"), &[], "SELECT * FROM users")).unwrap();
+
+        let names = |search: SavedQuerySearch| -> Vec<String> {
+            store.search(&search).into_iter().map(|q| q.name).collect()
+        };
+        assert_eq!(names(SavedQuerySearch { folder: Some("Diagnostics".to_string()), ..Default::default() }), vec!["Bloat", "Locks"]);
+        assert_eq!(names(SavedQuerySearch { tags: vec!["perf".to_string(), "locks".to_string()], ..Default::default() }), vec!["Locks"]);
+        assert_eq!(names(SavedQuerySearch { query: Some("USERS".to_string()), ..Default::default() }), vec!["Users"]);
+        assert_eq!(store.folders(), vec!["Diagnostics", "Diagnostics/Locks", "DiagnosticsOld"]);
+        assert_eq!(store.tags(), vec!["locks", "perf"]);
+    }
+
+    #[test]
+    fn test_export_and_import_directory() {
+        let dir = TempDir::new().unwrap();
+        let mut store = SavedQueryStore::open(dir.path().join("a.json")).unwrap();
+        let locks = store.create(saved("Locks", Some("Diagnostics/Locks"), &["locks"], "SELECT :pid")).unwrap();
+        store.create(saved("a/b", None, &[], "SELECT 1")).unwrap();
+        store.create(saved("a/b", None, &[], "SELECT 2")).unwrap();
+
+        let export_dir = dir.path().join("export");
+        assert_eq!(store.export_to_dir(&export_dir).unwrap(), 3);
+        assert!(export_dir.join("Diagnostics/Locks/Locks.sql").exists());
+        assert!(export_dir.join("a_b.sql").exists());
+        assert!(export_dir.join("a_b-2.sql").exists());
+
+        // A hand-written file without front-matter takes its name and folder from its path
+        fs::create_dir_all(export_dir.join("Reports")).unwrap();
+        fs::write(export_dir.join("Reports/daily.sql"), "SELECT count(*) FROM orders").unwrap();
+        fs::write(export_dir.join("broken.sql"), "-- ---\n-- name: broken\nSELECT 1").unwrap();
+
+        let mut other = SavedQueryStore::open(dir.path().join("b.json")).unwrap();
+        let summary = other.import_from_dir(&export_dir).unwrap();
+        assert_eq!((summary.created, summary.updated, summary.errors.len()), (4, 0, 1));
+        assert_eq!(other.get(&locks.id).unwrap(), locks);
+        let daily = other.search(&SavedQuerySearch { folder: Some("Reports".to_string()), ..Default::default() });
+        assert_eq!(daily[0].name, "daily");
+
+        let summary = other.import_from_dir(&export_dir).unwrap();
+        assert_eq!((summary.created, summary.updated), (1, 3));
+    }
+}
@@ -0,0 +1,1280 @@
+//! Compare the schemas of two connections and generate SQL that brings the target in line
+//! with the source.
+//!
+//! Snapshots are built from the same catalog queries the schema browser uses. The diff is
+//! described from the target's point of view: `added` objects exist only in the source and
+//! will be created, `removed` objects exist only in the target and will be dropped.
+//!
+//! Migration steps are ordered so dependencies hold: views, foreign keys and indexes that go
+//! away are dropped first, then tables and columns change, then routines, indexes, foreign
+//! keys and views are created. Views that read from other views are dropped before and
+//! created after them.
+
+use crate::simple_db::{DetailedColumnInfo, ForeignKeyInfo, IndexInfo, SimpleDatabase, StoredProcedureInfo, ViewInfo};
+use crate::sql_builder::{qualified_name, quote_ident};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TableSnapshot {
+    pub schema: String,
+    pub name: String,
+    pub columns: Vec<DetailedColumnInfo>,
+    pub indexes: Vec<IndexInfo>,
+    /// One row per constrained column, as `get_table_foreign_keys` returns them
+    pub foreign_keys: Vec<ForeignKeyInfo>,
+}
+
+/// A view that reads from another view
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ViewDependency {
+    pub schema: String,
+    pub name: String,
+    pub depends_on_schema: String,
+    pub depends_on_name: String,
+}
+
+type ViewKey = (String, String);
+
+impl ViewDependency {
+    fn view(&self) -> ViewKey {
+        (self.schema.clone(), self.name.clone())
+    }
+
+    fn depends_on(&self) -> ViewKey {
+        (self.depends_on_schema.clone(), self.depends_on_name.clone())
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SchemaSnapshot {
+    pub tables: Vec<TableSnapshot>,
+    pub views: Vec<ViewInfo>,
+    pub routines: Vec<StoredProcedureInfo>,
+    #[serde(default)]
+    pub view_dependencies: Vec<ViewDependency>,
+    /// Schema and name of every type outside the system schemas, in any schema
+    #[serde(default)]
+    pub types: Vec<(String, String)>,
+}
+
+/// Views whose rewrite rule references another view
+const VIEW_DEPENDENCIES_SQL: &str = "
+    SELECT DISTINCT vn.nspname::text, v.relname::text, dn.nspname::text, d.relname::text
+    FROM pg_depend dep
+    JOIN pg_rewrite rw ON rw.oid = dep.objid
+    JOIN pg_class v ON v.oid = rw.ev_class
+    JOIN pg_namespace vn ON vn.oid = v.relnamespace
+    JOIN pg_class d ON d.oid = dep.refobjid
+    JOIN pg_namespace dn ON dn.oid = d.relnamespace
+    WHERE dep.classid = 'pg_rewrite'::regclass
+        AND dep.refclassid = 'pg_class'::regclass
+        AND v.relkind = 'v' AND d.relkind = 'v' AND d.oid <> v.oid
+";
+
+const TYPES_SQL: &str = "
+    SELECT n.nspname::text, t.typname::text
+    FROM pg_type t
+    JOIN pg_namespace n ON n.oid = t.typnamespace
+    WHERE n.nspname NOT IN ('pg_catalog', 'information_schema')
+        AND n.nspname NOT LIKE 'pg_toast%'
+        AND n.nspname NOT LIKE 'pg_temp%'
+";
+
+/// Rows of text columns, skipping any with a NULL
+fn text_rows(rows: Vec<Vec<serde_json::Value>>) -> Vec<Vec<String>> {
+    rows.into_iter()
+        .filter_map(|row| {
+            row.into_iter()
+                .map(|value| match value {
+                    serde_json::Value::String(s) => Some(s),
+                    _ => None,
+                })
+                .collect()
+        })
+        .collect()
+}
+
+impl SchemaSnapshot {
+    /// Read tables, views and routines, limited to `schemas` when given
+    pub async fn capture(db: &SimpleDatabase, schemas: Option<&[String]>) -> Result<Self, String> {
+        let included = |schema: &str| schemas.is_none_or(|schemas| schemas.iter().any(|s| s == schema));
+
+        let mut seen = BTreeSet::new();
+        let mut tables = Vec::new();
+        for table in db.get_tables().await? {
+            if !included(&table.schema) || !seen.insert((table.schema.clone(), table.name.clone())) {
+                continue;
+            }
+            let schema = Some(table.schema.as_str());
+            tables.push(TableSnapshot {
+                columns: db.get_detailed_table_columns(&table.name, schema).await?,
+                indexes: db.get_table_indexes(&table.name, schema).await?,
+                foreign_keys: db.get_table_foreign_keys(&table.name, schema).await?,
+                schema: table.schema,
+                name: table.name,
+            });
+        }
+
+        let views = db.get_views().await?.into_iter().filter(|v| included(&v.schema)).collect();
+        let routines = db.get_stored_procedures().await?.into_iter().filter(|r| included(&r.schema)).collect();
+        let view_dependencies = text_rows(db.execute_query(VIEW_DEPENDENCIES_SQL).await?.rows)
+            .into_iter()
+            .filter(|row| row.len() == 4 && included(&row[0]) && included(&row[2]))
+            .map(|row| ViewDependency {
+                schema: row[0].clone(),
+                name: row[1].clone(),
+                depends_on_schema: row[2].clone(),
+                depends_on_name: row[3].clone(),
+            })
+            .collect();
+        let types = text_rows(db.execute_query(TYPES_SQL).await?.rows)
+            .into_iter()
+            .filter(|row| row.len() == 2)
+            .map(|row| (row[0].clone(), row[1].clone()))
+            .collect();
+        Ok(Self { tables, views, routines, view_dependencies, types })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ObjectKind {
+    Table,
+    Column,
+    PrimaryKey,
+    Index,
+    ForeignKey,
+    View,
+    Routine,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeKind {
+    /// Only in the source
+    Added,
+    /// Only in the target
+    Removed,
+    Changed,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SchemaChange {
+    pub object: ObjectKind,
+    pub change: ChangeKind,
+    pub schema: String,
+    /// Table the column, key or index belongs to
+    pub table: Option<String>,
+    pub name: String,
+    /// What differs, as `what: target -> source`
+    pub differences: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MigrationStep {
+    pub description: String,
+    pub sql: String,
+    /// Drops data or objects
+    pub destructive: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SchemaDiff {
+    pub changes: Vec<SchemaChange>,
+    /// In the order they must run
+    pub migration: Vec<MigrationStep>,
+    /// Things the script cannot do safely on its own
+    pub warnings: Vec<String>,
+    /// The whole migration as one transactional script
+    pub script: String,
+}
+
+fn render_script(migration: &[MigrationStep], warnings: &[String]) -> String {
+    if migration.is_empty() {
+        return "-- The schemas are identical\n".to_string();
+    }
+    let mut script = String::from("BEGIN;\n");
+    for warning in warnings {
+        script.push_str(&format!("\n-- WARNING: {}", warning));
+    }
+    for step in migration {
+        script.push_str(&format!("\n-- {}\n{};\n", step.description, step.sql));
+    }
+    script.push_str("\nCOMMIT;\n");
+    script
+}
+
+/// When a migration step has to run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Phase {
+    DropViews,
+    DropForeignKeys,
+    DropIndexes,
+    DropPrimaryKeys,
+    DropRoutines,
+    CreateSchemas,
+    // Before tables, so column defaults and checks can call them
+    CreateRoutines,
+    CreateTables,
+    AlterColumns,
+    DropColumns,
+    DropTables,
+    AddPrimaryKeys,
+    CreateIndexes,
+    AddForeignKeys,
+    CreateViews,
+}
+
+/// A foreign key with all its columns
+#[derive(Debug, Clone, PartialEq)]
+struct ForeignKey {
+    name: String,
+    columns: Vec<String>,
+    referenced_schema: String,
+    referenced_table: String,
+    referenced_columns: Vec<String>,
+}
+
+/// Rows come one per column pair, in key order
+fn group_foreign_keys(rows: &[ForeignKeyInfo]) -> BTreeMap<String, ForeignKey> {
+    let mut keys: BTreeMap<String, ForeignKey> = BTreeMap::new();
+    for row in rows {
+        let key = keys.entry(row.name.clone()).or_insert_with(|| ForeignKey {
+            name: row.name.clone(),
+            columns: Vec::new(),
+            referenced_schema: row.referenced_schema.clone(),
+            referenced_table: row.referenced_table.clone(),
+            referenced_columns: Vec::new(),
+        });
+        key.columns.push(row.column_name.clone());
+        key.referenced_columns.push(row.referenced_column.clone());
+    }
+    keys
+}
+
+struct DiffBuilder {
+    changes: Vec<SchemaChange>,
+    steps: Vec<(Phase, MigrationStep)>,
+    warnings: Vec<String>,
+    /// Types that exist on the target, as schema and name
+    target_types: BTreeSet<(String, String)>,
+}
+
+impl DiffBuilder {
+    fn change(&mut self, object: ObjectKind, change: ChangeKind, schema: &str, table: Option<&str>, name: &str, differences: Vec<String>) {
+        self.changes.push(SchemaChange {
+            object,
+            change,
+            schema: schema.to_string(),
+            table: table.map(str::to_string),
+            name: name.to_string(),
+            differences,
+        });
+    }
+
+    fn step(&mut self, phase: Phase, description: String, sql: String) {
+        self.steps.push((phase, MigrationStep { description, sql, destructive: false }));
+    }
+
+    fn destructive_step(&mut self, phase: Phase, description: String, sql: String) {
+        self.steps.push((phase, MigrationStep { description, sql, destructive: true }));
+    }
+
+    fn warn(&mut self, warning: String) {
+        if !self.warnings.contains(&warning) {
+            self.warnings.push(warning);
+        }
+    }
+
+    /// Warn when the type of a column about to be created or changed is missing on the target
+    fn check_type(&mut self, column: &DetailedColumnInfo) {
+        if let Some((schema, name)) = user_type(column) {
+            if !self.target_types.contains(&(schema.clone(), name.clone())) {
+                self.warn(format!(
+                    "Type {}.{} does not exist on the target; create it before running the migration",
+                    schema, name
+                ));
+            }
+        }
+    }
+}
+
+/// Diff `target` against `source` and plan the migration of `target`
+pub fn diff_schemas(source: &SchemaSnapshot, target: &SchemaSnapshot) -> SchemaDiff {
+    let mut diff = DiffBuilder {
+        changes: Vec::new(),
+        steps: Vec::new(),
+        warnings: Vec::new(),
+        target_types: target.types.iter().cloned().collect(),
+    };
+
+    diff_tables(&mut diff, source, target);
+    diff_routines(&mut diff, source, target);
+    diff_views(&mut diff, source, target);
+
+    // Schemas that only the source uses have to exist before anything is created in them
+    let target_schemas: BTreeSet<&str> = target
+        .tables
+        .iter()
+        .map(|t| t.schema.as_str())
+        .chain(target.views.iter().map(|v| v.schema.as_str()))
+        .chain(target.routines.iter().map(|r| r.schema.as_str()))
+        .collect();
+    let new_schemas: BTreeSet<String> = diff
+        .changes
+        .iter()
+        .filter(|c| c.change == ChangeKind::Added && !target_schemas.contains(c.schema.as_str()))
+        .map(|c| c.schema.clone())
+        .collect();
+    for schema in new_schemas {
+        diff.step(
+            Phase::CreateSchemas,
+            format!("Create schema {}", schema),
+            format!("CREATE SCHEMA IF NOT EXISTS {}", quote_ident(&schema)),
+        );
+    }
+
+    // Stable, so steps keep the order they were planned in within a phase
+    diff.steps.sort_by_key(|(phase, _)| *phase);
+    let migration: Vec<MigrationStep> = diff.steps.into_iter().map(|(_, step)| step).collect();
+    SchemaDiff {
+        script: render_script(&migration, &diff.warnings),
+        changes: diff.changes,
+        migration,
+        warnings: diff.warnings,
+    }
+}
+
+fn diff_tables(diff: &mut DiffBuilder, source: &SchemaSnapshot, target: &SchemaSnapshot) {
+    let key = |t: &TableSnapshot| (t.schema.clone(), t.name.clone());
+    let source_tables: BTreeMap<_, _> = source.tables.iter().map(|t| (key(t), t)).collect();
+    let target_tables: BTreeMap<_, _> = target.tables.iter().map(|t| (key(t), t)).collect();
+
+    for ((schema, name), table) in &source_tables {
+        match target_tables.get(&(schema.clone(), name.clone())) {
+            Some(existing) => diff_table(diff, table, existing),
+            None => {
+                diff.change(ObjectKind::Table, ChangeKind::Added, schema, None, name, Vec::new());
+                create_table(diff, table);
+            }
+        }
+    }
+
+    for ((schema, name), table) in &target_tables {
+        if source_tables.contains_key(&(schema.clone(), name.clone())) {
+            continue;
+        }
+        diff.change(ObjectKind::Table, ChangeKind::Removed, schema, None, name, Vec::new());
+        // Its foreign keys go with it; keys pointing at it from other tables are dropped first
+        for fk in group_foreign_keys(&table.foreign_keys).values() {
+            diff.step(
+                Phase::DropForeignKeys,
+                format!("Drop foreign key {} on {}.{}", fk.name, schema, name),
+                format!("ALTER TABLE {} DROP CONSTRAINT {}", qualified_name(schema, name), quote_ident(&fk.name)),
+            );
+        }
+        diff.destructive_step(
+            Phase::DropTables,
+            format!("Drop table {}.{}", schema, name),
+            format!("DROP TABLE {}", qualified_name(schema, name)),
+        );
+    }
+}
+
+fn create_table(diff: &mut DiffBuilder, table: &TableSnapshot) {
+    let table_name = qualified_name(&table.schema, &table.name);
+    for column in &table.columns {
+        diff.check_type(column);
+    }
+    let mut lines: Vec<String> = table.columns.iter().map(|c| format!("    {}", column_definition(c))).collect();
+    if let Some((name, columns)) = primary_key(table) {
+        lines.push(format!("    CONSTRAINT {} PRIMARY KEY ({})", quote_ident(&name), columns.join(", ")));
+    }
+    diff.step(
+        Phase::CreateTables,
+        format!("Create table {}.{}", table.schema, table.name),
+        format!("CREATE TABLE {} (\n{}\n)", table_name, lines.join(",\n")),
+    );
+
+    for index in table.indexes.iter().filter(|i| !i.is_primary) {
+        create_index(diff, &table.schema, &table.name, index);
+    }
+    for fk in group_foreign_keys(&table.foreign_keys).values() {
+        add_foreign_key(diff, table, fk);
+    }
+}
+
+fn diff_table(diff: &mut DiffBuilder, source: &TableSnapshot, target: &TableSnapshot) {
+    let (schema, table) = (source.schema.as_str(), source.name.as_str());
+    let table_name = qualified_name(schema, table);
+
+    for column in &source.columns {
+        match target.columns.iter().find(|c| c.name == column.name) {
+            None => {
+                diff.change(ObjectKind::Column, ChangeKind::Added, schema, Some(table), &column.name, Vec::new());
+                diff.check_type(column);
+                if !column.is_nullable && column.default_value.is_none() {
+                    diff.warn(format!(
+                        "Adding NOT NULL column {}.{}.{} without a default fails if the table has rows",
+                        schema, table, column.name
+                    ));
+                }
+                diff.step(
+                    Phase::AlterColumns,
+                    format!("Add column {} to {}.{}", column.name, schema, table),
+                    format!("ALTER TABLE {} ADD COLUMN {}", table_name, column_definition(column)),
+                );
+            }
+            Some(existing) => diff_column(diff, source, column, existing),
+        }
+    }
+
+    for column in &target.columns {
+        if source.columns.iter().any(|c| c.name == column.name) {
+            continue;
+        }
+        diff.change(ObjectKind::Column, ChangeKind::Removed, schema, Some(table), &column.name, Vec::new());
+        diff.destructive_step(
+            Phase::DropColumns,
+            format!("Drop column {} from {}.{}", column.name, schema, table),
+            format!("ALTER TABLE {} DROP COLUMN {}", table_name, quote_ident(&column.name)),
+        );
+    }
+
+    diff_primary_key(diff, source, target);
+    diff_indexes(diff, source, target);
+    diff_foreign_keys(diff, source, target);
+}
+
+fn diff_column(diff: &mut DiffBuilder, table: &TableSnapshot, source: &DetailedColumnInfo, target: &DetailedColumnInfo) {
+    let table_name = qualified_name(&table.schema, &table.name);
+    let column = quote_ident(&source.name);
+    let mut differences = Vec::new();
+    let mut statements = Vec::new();
+
+    let (source_type, target_type) = (column_type(source), column_type(target));
+    if source_type != target_type {
+        diff.check_type(source);
+        differences.push(format!("type: {} -> {}", target_type, source_type));
+        statements.push(format!("ALTER COLUMN {} TYPE {} USING {}::{}", column, source_type, column, source_type));
+    }
+    if source.default_value != target.default_value {
+        differences.push(format!(
+            "default: {} -> {}",
+            target.default_value.as_deref().unwrap_or("none"),
+            source.default_value.as_deref().unwrap_or("none")
+        ));
+        statements.push(match &source.default_value {
+            Some(default) => format!("ALTER COLUMN {} SET DEFAULT {}", column, default),
+            None => format!("ALTER COLUMN {} DROP DEFAULT", column),
+        });
+    }
+    if source.is_nullable != target.is_nullable {
+        let describe = |nullable: bool| if nullable { "NULL" } else { "NOT NULL" };
+        differences.push(format!("nullability: {} -> {}", describe(target.is_nullable), describe(source.is_nullable)));
+        statements.push(if source.is_nullable {
+            format!("ALTER COLUMN {} DROP NOT NULL", column)
+        } else {
+            format!("ALTER COLUMN {} SET NOT NULL", column)
+        });
+    }
+
+    if differences.is_empty() {
+        return;
+    }
+    let description = format!("Alter column {} of {}.{} ({})", source.name, table.schema, table.name, differences.join(", "));
+    diff.change(ObjectKind::Column, ChangeKind::Changed, &table.schema, Some(&table.name), &source.name, differences);
+    let sql = format!("ALTER TABLE {}\n    {}", table_name, statements.join(",\n    "));
+    // A type change rewrites the values and can fail or lose precision
+    if source_type != target_type {
+        diff.destructive_step(Phase::AlterColumns, description, sql);
+    } else {
+        diff.step(Phase::AlterColumns, description, sql);
+    }
+}
+
+/// Name and SQL-formatted columns of the table's primary key
+fn primary_key(table: &TableSnapshot) -> Option<(String, Vec<String>)> {
+    let index = table.indexes.iter().find(|i| i.is_primary);
+    let flagged: Vec<String> = table.columns.iter().filter(|c| c.is_primary_key).map(|c| quote_ident(&c.name)).collect();
+    match index {
+        // The index definition lists the key columns in key order, already quoted
+        Some(index) if !index.columns.is_empty() => Some((index.name.clone(), index.columns.clone())),
+        Some(index) => Some((index.name.clone(), flagged)),
+        None if !flagged.is_empty() => Some((format!("{}_pkey", table.name), flagged)),
+        None => None,
+    }
+}
+
+fn diff_primary_key(diff: &mut DiffBuilder, source: &TableSnapshot, target: &TableSnapshot) {
+    let (schema, table) = (source.schema.as_str(), source.name.as_str());
+    let table_name = qualified_name(schema, table);
+    let (source_key, target_key) = (primary_key(source), primary_key(target));
+    let columns = |key: &Option<(String, Vec<String>)>| key.as_ref().map(|(_, columns)| columns.clone());
+    if columns(&source_key) == columns(&target_key) {
+        return;
+    }
+
+    let describe = |key: &Option<(String, Vec<String>)>| match key {
+        Some((_, columns)) => format!("({})", columns.join(", ")),
+        None => "none".to_string(),
+    };
+    let change = match (&source_key, &target_key) {
+        (Some(_), None) => ChangeKind::Added,
+        (None, Some(_)) => ChangeKind::Removed,
+        _ => ChangeKind::Changed,
+    };
+    let name = source_key.as_ref().or(target_key.as_ref()).map(|(name, _)| name.clone()).unwrap_or_default();
+    let differences = vec![format!("columns: {} -> {}", describe(&target_key), describe(&source_key))];
+    diff.change(ObjectKind::PrimaryKey, change, schema, Some(table), &name, differences);
+
+    if let Some((name, _)) = &target_key {
+        diff.destructive_step(
+            Phase::DropPrimaryKeys,
+            format!("Drop primary key {} on {}.{}", name, schema, table),
+            format!("ALTER TABLE {} DROP CONSTRAINT {}", table_name, quote_ident(name)),
+        );
+    }
+    if let Some((name, columns)) = &source_key {
+        diff.step(
+            Phase::AddPrimaryKeys,
+            format!("Add primary key {} on {}.{}", name, schema, table),
+            format!("ALTER TABLE {} ADD CONSTRAINT {} PRIMARY KEY ({})", table_name, quote_ident(name), columns.join(", ")),
+        );
+    }
+}
+
+/// Indexes behind UNIQUE and EXCLUDE constraints are created through their constraint
+fn create_index(diff: &mut DiffBuilder, schema: &str, table: &str, index: &IndexInfo) {
+    match &index.constraint_definition {
+        Some(definition) => diff.step(
+            Phase::CreateIndexes,
+            format!("Add constraint {} on {}.{}", index.name, schema, table),
+            format!("ALTER TABLE {} ADD CONSTRAINT {} {}", qualified_name(schema, table), quote_ident(&index.name), definition),
+        ),
+        None => diff.step(
+            Phase::CreateIndexes,
+            format!("Create index {} on {}.{}", index.name, schema, table),
+            index.definition.clone(),
+        ),
+    }
+}
+
+/// Indexes behind UNIQUE and EXCLUDE constraints can only go with their constraint
+fn drop_index(diff: &mut DiffBuilder, schema: &str, table: &str, index: &IndexInfo) {
+    match &index.constraint_definition {
+        Some(_) => diff.step(
+            Phase::DropIndexes,
+            format!("Drop constraint {} on {}.{}", index.name, schema, table),
+            format!("ALTER TABLE {} DROP CONSTRAINT {}", qualified_name(schema, table), quote_ident(&index.name)),
+        ),
+        None => diff.step(
+            Phase::DropIndexes,
+            format!("Drop index {} on {}.{}", index.name, schema, table),
+            format!("DROP INDEX {}", qualified_name(schema, &index.name)),
+        ),
+    }
+}
+
+fn diff_indexes(diff: &mut DiffBuilder, source: &TableSnapshot, target: &TableSnapshot) {
+    let (schema, table) = (source.schema.as_str(), source.name.as_str());
+
+    for index in source.indexes.iter().filter(|i| !i.is_primary) {
+        match target.indexes.iter().find(|i| i.name == index.name && !i.is_primary) {
+            None => {
+                diff.change(ObjectKind::Index, ChangeKind::Added, schema, Some(table), &index.name, Vec::new());
+                create_index(diff, schema, table, index);
+            }
+            Some(existing)
+                if existing.definition != index.definition
+                    || existing.constraint_definition != index.constraint_definition =>
+            {
+                let differences = vec![format!("definition: {} -> {}", existing.definition, index.definition)];
+                diff.change(ObjectKind::Index, ChangeKind::Changed, schema, Some(table), &index.name, differences);
+                drop_index(diff, schema, table, existing);
+                create_index(diff, schema, table, index);
+            }
+            Some(_) => {}
+        }
+    }
+    for index in target.indexes.iter().filter(|i| !i.is_primary) {
+        if !source.indexes.iter().any(|i| i.name == index.name && !i.is_primary) {
+            diff.change(ObjectKind::Index, ChangeKind::Removed, schema, Some(table), &index.name, Vec::new());
+            drop_index(diff, schema, table, index);
+        }
+    }
+}
+
+fn add_foreign_key(diff: &mut DiffBuilder, table: &TableSnapshot, fk: &ForeignKey) {
+    let quote_all = |columns: &[String]| columns.iter().map(|c| quote_ident(c)).collect::<Vec<_>>().join(", ");
+    diff.step(
+        Phase::AddForeignKeys,
+        format!("Add foreign key {} on {}.{}", fk.name, table.schema, table.name),
+        format!(
+            "ALTER TABLE {} ADD CONSTRAINT {} FOREIGN KEY ({}) REFERENCES {} ({})",
+            qualified_name(&table.schema, &table.name),
+            quote_ident(&fk.name),
+            quote_all(&fk.columns),
+            qualified_name(&fk.referenced_schema, &fk.referenced_table),
+            quote_all(&fk.referenced_columns)
+        ),
+    );
+}
+
+fn diff_foreign_keys(diff: &mut DiffBuilder, source: &TableSnapshot, target: &TableSnapshot) {
+    let (schema, table) = (source.schema.as_str(), source.name.as_str());
+    let source_keys = group_foreign_keys(&source.foreign_keys);
+    let target_keys = group_foreign_keys(&target.foreign_keys);
+    let drop = |diff: &mut DiffBuilder, fk: &ForeignKey| {
+        diff.step(
+            Phase::DropForeignKeys,
+            format!("Drop foreign key {} on {}.{}", fk.name, schema, table),
+            format!("ALTER TABLE {} DROP CONSTRAINT {}", qualified_name(schema, table), quote_ident(&fk.name)),
+        );
+    };
+    let describe = |fk: &ForeignKey| {
+        format!(
+            "({}) -> {}.{}({})",
+            fk.columns.join(", "),
+            fk.referenced_schema,
+            fk.referenced_table,
+            fk.referenced_columns.join(", ")
+        )
+    };
+
+    for (name, fk) in &source_keys {
+        match target_keys.get(name) {
+            None => {
+                diff.change(ObjectKind::ForeignKey, ChangeKind::Added, schema, Some(table), name, Vec::new());
+                add_foreign_key(diff, source, fk);
+            }
+            Some(existing) if existing != fk => {
+                let differences = vec![format!("definition: {} -> {}", describe(existing), describe(fk))];
+                diff.change(ObjectKind::ForeignKey, ChangeKind::Changed, schema, Some(table), name, differences);
+                drop(diff, existing);
+                add_foreign_key(diff, source, fk);
+            }
+            Some(_) => {}
+        }
+    }
+    for (name, fk) in &target_keys {
+        if !source_keys.contains_key(name) {
+            diff.change(ObjectKind::ForeignKey, ChangeKind::Removed, schema, Some(table), name, Vec::new());
+            drop(diff, fk);
+        }
+    }
+}
+
+/// Routines are identified by schema, name and arguments, since they can be overloaded
+fn routine_key(routine: &StoredProcedureInfo) -> (String, String, String) {
+    (routine.schema.clone(), routine.name.clone(), routine.argument_types.join(", "))
+}
+
+fn diff_routines(diff: &mut DiffBuilder, source: &SchemaSnapshot, target: &SchemaSnapshot) {
+    let source_routines: BTreeMap<_, _> = source.routines.iter().map(|r| (routine_key(r), r)).collect();
+    let target_routines: BTreeMap<_, _> = target.routines.iter().map(|r| (routine_key(r), r)).collect();
+    let signature = |(_, name, args): &(String, String, String)| format!("{}({})", name, args);
+
+    for (key, routine) in &source_routines {
+        let change = match target_routines.get(key) {
+            None => ChangeKind::Added,
+            Some(existing) if normalize_sql(&existing.definition) != normalize_sql(&routine.definition) => {
+                ChangeKind::Changed
+            }
+            Some(_) => continue,
+        };
+        let differences = match change {
+            ChangeKind::Changed => vec!["definition".to_string()],
+            _ => Vec::new(),
+        };
+        diff.change(ObjectKind::Routine, change, &routine.schema, None, &signature(key), differences);
+        // pg_get_functiondef produces CREATE OR REPLACE, which also covers changed bodies
+        diff.step(
+            Phase::CreateRoutines,
+            format!("Create or replace {}.{}", routine.schema, signature(key)),
+            routine.definition.trim().trim_end_matches(';').to_string(),
+        );
+    }
+
+    for (key, routine) in &target_routines {
+        if source_routines.contains_key(key) {
+            continue;
+        }
+        diff.change(ObjectKind::Routine, ChangeKind::Removed, &routine.schema, None, &signature(key), Vec::new());
+        let kind = if routine.definition.to_uppercase().contains("CREATE OR REPLACE PROCEDURE") {
+            "PROCEDURE"
+        } else {
+            "FUNCTION"
+        };
+        // DROP takes the argument list without defaults
+        let arguments: Vec<&str> = routine
+            .argument_types
+            .iter()
+            .map(|arg| arg.split(" DEFAULT ").next().unwrap_or(arg).trim())
+            .collect();
+        diff.destructive_step(
+            Phase::DropRoutines,
+            format!("Drop {}.{}", routine.schema, signature(key)),
+            format!("DROP {} {}({})", kind, qualified_name(&routine.schema, &routine.name), arguments.join(", ")),
+        );
+    }
+}
+
+fn diff_views(diff: &mut DiffBuilder, source: &SchemaSnapshot, target: &SchemaSnapshot) {
+    let key = |v: &ViewInfo| (v.schema.clone(), v.name.clone());
+    let source_views: BTreeMap<_, _> = source.views.iter().map(|v| (key(v), v)).collect();
+    let target_views: BTreeMap<_, _> = target.views.iter().map(|v| (key(v), v)).collect();
+    let mut drops = BTreeSet::new();
+    let mut creates = BTreeSet::new();
+
+    for (view_key, view) in &source_views {
+        let (schema, name) = view_key;
+        let existing = target_views.get(view_key);
+        let change = match existing {
+            None => ChangeKind::Added,
+            Some(existing) if normalize_sql(&existing.definition) != normalize_sql(&view.definition) => {
+                ChangeKind::Changed
+            }
+            Some(_) => continue,
+        };
+        if view.definition.trim().is_empty() {
+            diff.warn(format!(
+                "The definition of view {}.{} is not visible to the source connection; recreate it by hand",
+                schema, name
+            ));
+            continue;
+        }
+        let differences = match change {
+            ChangeKind::Changed => vec!["definition".to_string()],
+            _ => Vec::new(),
+        };
+        diff.change(ObjectKind::View, change, schema, None, name, differences);
+        // Dropped and recreated rather than replaced, so column changes and the columns
+        // it depends on can change in between
+        if existing.is_some() {
+            drops.insert(view_key.clone());
+        }
+        creates.insert(view_key.clone());
+    }
+
+    for (schema, name) in target_views.keys() {
+        if !source_views.contains_key(&(schema.clone(), name.clone())) {
+            diff.change(ObjectKind::View, ChangeKind::Removed, schema, None, name, Vec::new());
+            drops.insert((schema.clone(), name.clone()));
+        }
+    }
+
+    // Views reading from a dropped view cannot stay; they are recreated from the source
+    loop {
+        let dependents: BTreeSet<ViewKey> = target
+            .view_dependencies
+            .iter()
+            .filter(|dependency| drops.contains(&dependency.depends_on()) && !drops.contains(&dependency.view()))
+            .map(ViewDependency::view)
+            .collect();
+        if dependents.is_empty() {
+            break;
+        }
+        for view_key in dependents {
+            match source_views.get(&view_key) {
+                Some(view) if !view.definition.trim().is_empty() => {
+                    creates.insert(view_key.clone());
+                }
+                _ => diff.warn(format!(
+                    "View {}.{} depends on a view that is dropped; recreate it by hand",
+                    view_key.0, view_key.1
+                )),
+            }
+            drops.insert(view_key);
+        }
+    }
+
+    // Dependents first, so no view is dropped while another still reads from it
+    for (schema, name) in dependency_order(&drops, &target.view_dependencies).into_iter().rev() {
+        diff.step(
+            Phase::DropViews,
+            format!("Drop view {}.{}", schema, name),
+            format!("DROP VIEW {}", qualified_name(&schema, &name)),
+        );
+    }
+    for view_key in dependency_order(&creates, &source.view_dependencies) {
+        let view = source_views[&view_key];
+        diff.step(
+            Phase::CreateViews,
+            format!("Create view {}.{}", view.schema, view.name),
+            format!(
+                "CREATE VIEW {} AS\n{}",
+                qualified_name(&view.schema, &view.name),
+                view.definition.trim().trim_end_matches(';')
+            ),
+        );
+    }
+}
+
+/// `views` ordered so each comes after the views it reads from
+fn dependency_order(views: &BTreeSet<ViewKey>, dependencies: &[ViewDependency]) -> Vec<ViewKey> {
+    fn visit(
+        view: &ViewKey,
+        views: &BTreeSet<ViewKey>,
+        dependencies: &[ViewDependency],
+        visited: &mut BTreeSet<ViewKey>,
+        ordered: &mut Vec<ViewKey>,
+    ) {
+        if !visited.insert(view.clone()) {
+            return;
+        }
+        for dependency in dependencies.iter().filter(|dependency| dependency.view() == *view) {
+            let depends_on = dependency.depends_on();
+            if views.contains(&depends_on) {
+                visit(&depends_on, views, dependencies, visited, ordered);
+            }
+        }
+        ordered.push(view.clone());
+    }
+
+    let mut visited = BTreeSet::new();
+    let mut ordered = Vec::new();
+    for view in views {
+        visit(view, views, dependencies, &mut visited, &mut ordered);
+    }
+    ordered
+}
+
+/// Compare definitions regardless of whitespace and a trailing semicolon
+fn normalize_sql(sql: &str) -> String {
+    sql.trim().trim_end_matches(';').split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Schema and name of the column's (element) type when it is not built in
+fn user_type(column: &DetailedColumnInfo) -> Option<(String, String)> {
+    let name = match column.data_type.as_str() {
+        "USER-DEFINED" => column.udt_name.as_str(),
+        // Array element types are the udt name without its leading underscore
+        "ARRAY" => column.udt_name.trim_start_matches('_'),
+        _ => return None,
+    };
+    let built_in = column.udt_schema.is_empty() || column.udt_schema == "pg_catalog";
+    (!built_in).then(|| (column.udt_schema.clone(), name.to_string()))
+}
+
+/// The column's type as it would be written in DDL
+fn column_type(column: &DetailedColumnInfo) -> String {
+    match (column.data_type.as_str(), user_type(column)) {
+        ("USER-DEFINED", Some((schema, name))) => qualified_name(&schema, &name),
+        ("USER-DEFINED", None) => quote_ident(&column.udt_name),
+        ("ARRAY", Some((schema, name))) => format!("{}[]", qualified_name(&schema, &name)),
+        ("ARRAY", None) => format!("{}[]", column.udt_name.trim_start_matches('_')),
+        _ => built_in_type(column),
+    }
+}
+
+fn built_in_type(column: &DetailedColumnInfo) -> String {
+    match column.data_type.as_str() {
+        "character varying" | "character" | "bit" | "bit varying" => match column.character_maximum_length {
+            Some(length) => format!("{}({})", column.data_type, length),
+            None => column.data_type.clone(),
+        },
+        "numeric" => match (column.numeric_precision, column.numeric_scale) {
+            (Some(precision), Some(scale)) => format!("numeric({},{})", precision, scale),
+            _ => "numeric".to_string(),
+        },
+        other => other.to_string(),
+    }
+}
+
+/// `name type [NOT NULL] [DEFAULT ...]`; integer columns fed by their own sequence become serials
+fn column_definition(column: &DetailedColumnInfo) -> String {
+    let is_sequence_default = column.default_value.as_deref().is_some_and(|d| d.starts_with("nextval("));
+    let serial = match column.data_type.as_str() {
+        "smallint" if is_sequence_default => Some("smallserial"),
+        "integer" if is_sequence_default => Some("serial"),
+        "bigint" if is_sequence_default => Some("bigserial"),
+        _ => None,
+    };
+
+    let mut definition = format!("{} {}", quote_ident(&column.name), serial.map_or_else(|| column_type(column), str::to_string));
+    if !column.is_nullable {
+        definition.push_str(" NOT NULL");
+    }
+    if let (Some(default), None) = (&column.default_value, serial) {
+        definition.push_str(&format!(" DEFAULT {}", default));
+    }
+    definition
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn column(name: &str, data_type: &str, nullable: bool, default: Option<&str>, pk: bool) -> DetailedColumnInfo {
+        DetailedColumnInfo {
+            name: name.to_string(),
+            data_type: data_type.to_string(),
+            udt_name: data_type.to_string(),
+            udt_schema: "pg_catalog".to_string(),
+            is_enum: false,
+            is_nullable: nullable,
+            default_value: default.map(str::to_string),
+            character_maximum_length: None,
+            numeric_precision: None,
+            numeric_scale: None,
+            is_primary_key: pk,
+            is_foreign_key: false,
+        }
+    }
+
+    fn index(table: &str, name: &str, definition: &str, primary: bool) -> IndexInfo {
+        IndexInfo {
+            name: name.to_string(),
+            table_name: table.to_string(),
+            schema_name: "public".to_string(),
+            columns: Vec::new(),
+            is_unique: primary,
+            is_primary: primary,
+            index_type: "btree".to_string(),
+            definition: definition.to_string(),
+            size_bytes: None,
+            constraint_definition: None,
+        }
+    }
+
+    fn table(name: &str, columns: Vec<DetailedColumnInfo>) -> TableSnapshot {
+        TableSnapshot {
+            schema: "public".to_string(),
+            name: name.to_string(),
+            columns,
+            indexes: Vec::new(),
+            foreign_keys: Vec::new(),
+        }
+    }
+
+    fn users() -> TableSnapshot {
+        let mut users = table(
+            "users",
+            vec![
+                column("id", "integer", false, Some("nextval('users_id_seq'::regclass)"), true),
+                column("email", "text", false, None, false),
+            ],
+        );
+        users.indexes.push(index("users", "users_pkey", "CREATE UNIQUE INDEX users_pkey ON public.users USING btree (id)", true));
+        users.indexes[0].columns = vec!["id".to_string()];
+        users
+    }
+
+    fn sql(diff: &SchemaDiff) -> Vec<&str> {
+        diff.migration.iter().map(|step| step.sql.as_str()).collect()
+    }
+
+    #[test]
+    fn test_identical_schemas_have_no_changes() {
+        let snapshot = SchemaSnapshot { tables: vec![users()], ..Default::default() };
+        let diff = diff_schemas(&snapshot, &snapshot.clone());
+        assert!(diff.changes.is_empty());
+        assert_eq!(diff.script, "-- The schemas are identical\n");
+    }
+
+    #[test]
+    fn test_new_tables_are_created_before_their_indexes_and_keys() {
+        let mut orders = table(
+            "orders",
+            vec![column("id", "bigint", false, None, true), column("user_id", "integer", true, None, false)],
+        );
+        orders.indexes.push(index("orders", "orders_user_idx", "CREATE INDEX orders_user_idx ON public.orders USING btree (user_id)", false));
+        orders.foreign_keys.push(ForeignKeyInfo {
+            name: "orders_user_fk".to_string(),
+            table_name: "orders".to_string(),
+            column_name: "user_id".to_string(),
+            referenced_schema: "public".to_string(),
+            referenced_table: "users".to_string(),
+            referenced_column: "id".to_string(),
+        });
+        let source = SchemaSnapshot { tables: vec![orders, users()], ..Default::default() };
+        let target = SchemaSnapshot::default();
+
+        let diff = diff_schemas(&source, &target);
+        let added: Vec<&str> = diff.changes.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(added, vec!["orders", "users"]);
+        assert_eq!(
+            sql(&diff),
+            vec![
+                "CREATE SCHEMA IF NOT EXISTS \"public\"",
+                "CREATE TABLE \"public\".\"orders\" (\n    \"id\" bigint NOT NULL,\n    \"user_id\" integer,\n    CONSTRAINT \"orders_pkey\" PRIMARY KEY (\"id\")\n)",
+                "CREATE TABLE \"public\".\"users\" (\n    \"id\" serial NOT NULL,\n    \"email\" text NOT NULL,\n    CONSTRAINT \"users_pkey\" PRIMARY KEY (id)\n)",
+                "CREATE INDEX orders_user_idx ON public.orders USING btree (user_id)",
+                "ALTER TABLE \"public\".\"orders\" ADD CONSTRAINT \"orders_user_fk\" FOREIGN KEY (\"user_id\") REFERENCES \"public\".\"users\" (\"id\")",
+            ]
+        );
+        assert!(diff.migration.iter().all(|step| !step.destructive));
+    }
+
+    #[test]
+    fn test_column_changes_and_drops() {
+        let mut source_users = users();
+        source_users.columns[1] = column("email", "character varying", true, Some("''::character varying"), false);
+        source_users.columns[1].character_maximum_length = Some(320);
+        source_users.columns.push(column("created_at", "timestamp with time zone", false, Some("now()"), false));
+        let mut target_users = users();
+        target_users.columns.push(column("legacy", "text", true, None, false));
+        let source = SchemaSnapshot { tables: vec![source_users], ..Default::default() };
+        let target = SchemaSnapshot { tables: vec![target_users], ..Default::default() };
+
+        let diff = diff_schemas(&source, &target);
+        let changes: Vec<(ObjectKind, ChangeKind, &str)> =
+            diff.changes.iter().map(|c| (c.object, c.change, c.name.as_str())).collect();
+        assert_eq!(
+            changes,
+            vec![
+                (ObjectKind::Column, ChangeKind::Changed, "email"),
+                (ObjectKind::Column, ChangeKind::Added, "created_at"),
+                (ObjectKind::Column, ChangeKind::Removed, "legacy"),
+            ]
+        );
+        assert_eq!(diff.changes[0].differences[0], "type: text -> character varying(320)");
+        assert_eq!(
+            sql(&diff),
+            vec![
+                "ALTER TABLE \"public\".\"users\"\n    ALTER COLUMN \"email\" TYPE character varying(320) USING \"email\"::character varying(320),\n    ALTER COLUMN \"email\" SET DEFAULT ''::character varying,\n    ALTER COLUMN \"email\" DROP NOT NULL",
+                "ALTER TABLE \"public\".\"users\" ADD COLUMN \"created_at\" timestamp with time zone NOT NULL DEFAULT now()",
+                "ALTER TABLE \"public\".\"users\" DROP COLUMN \"legacy\"",
+            ]
+        );
+        assert!(diff.migration[0].destructive);
+        assert!(!diff.migration[1].destructive);
+        assert!(diff.migration[2].destructive);
+    }
+
+    #[test]
+    fn test_removed_objects_are_dropped_first() {
+        let mut comments = table("comments", vec![column("user_id", "integer", true, None, false)]);
+        comments.foreign_keys.push(ForeignKeyInfo {
+            name: "comments_user_fk".to_string(),
+            table_name: "comments".to_string(),
+            column_name: "user_id".to_string(),
+            referenced_schema: "public".to_string(),
+            referenced_table: "users".to_string(),
+            referenced_column: "id".to_string(),
+        });
+        let view = |definition: &str| ViewInfo {
+            name: "active_users".to_string(),
+            schema: "public".to_string(),
+            definition: definition.to_string(),
+            is_updatable: false,
+            check_option: None,
+        };
+        let routine = StoredProcedureInfo {
+            name: "touch".to_string(),
+            schema: "public".to_string(),
+            language: "plpgsql".to_string(),
+            return_type: Some("void".to_string()),
+            argument_types: vec!["id integer".to_string(), "force boolean DEFAULT false".to_string()],
+            definition: "CREATE OR REPLACE FUNCTION public.touch(id integer, force boolean DEFAULT false)\n RETURNS void\nAS $$ $$".to_string(),
+            is_security_definer: false,
+        };
+
+        let source = SchemaSnapshot {
+            tables: vec![users()],
+            views: vec![view(" SELECT users.id\n   FROM users;")],
+            ..Default::default()
+        };
+        let target = SchemaSnapshot {
+            tables: vec![users(), comments],
+            views: vec![view("SELECT users.id FROM users WHERE true;")],
+            routines: vec![routine],
+            ..Default::default()
+        };
+
+        let diff = diff_schemas(&source, &target);
+        assert_eq!(
+            sql(&diff),
+            vec![
+                "DROP VIEW \"public\".\"active_users\"",
+                "ALTER TABLE \"public\".\"comments\" DROP CONSTRAINT \"comments_user_fk\"",
+                "DROP FUNCTION \"public\".\"touch\"(id integer, force boolean)",
+                "DROP TABLE \"public\".\"comments\"",
+                "CREATE VIEW \"public\".\"active_users\" AS\nSELECT users.id\n   FROM users",
+            ]
+        );
+        assert!(diff.script.starts_with("BEGIN;\n"));
+        assert!(diff.script.ends_with("\nCOMMIT;\n"));
+    }
+
+    #[test]
+    fn test_routines_are_created_before_tables() {
+        let routine = StoredProcedureInfo {
+            name: "next_code".to_string(),
+            schema: "public".to_string(),
+            language: "sql".to_string(),
+            return_type: Some("text".to_string()),
+            argument_types: Vec::new(),
+            definition: "CREATE OR REPLACE FUNCTION public.next_code()\n RETURNS text\nAS $$ SELECT 'x' $$;".to_string(),
+            is_security_definer: false,
+        };
+        let codes = table("codes", vec![column("code", "text", false, Some("next_code()"), false)]);
+        let source = SchemaSnapshot { tables: vec![codes], routines: vec![routine], ..Default::default() };
+
+        let diff = diff_schemas(&source, &SchemaSnapshot::default());
+        let steps: Vec<&str> = diff.migration.iter().map(|step| step.description.as_str()).collect();
+        assert_eq!(steps, vec!["Create schema public", "Create or replace public.next_code()", "Create table public.codes"]);
+    }
+
+    #[test]
+    fn test_primary_key_and_index_changes() {
+        let mut source_users = users();
+        source_users.indexes.push(index("users", "users_email_idx", "CREATE UNIQUE INDEX users_email_idx ON public.users USING btree (email)", false));
+        let mut target_users = users();
+        target_users.indexes[0].columns = vec!["id".to_string(), "email".to_string()];
+        target_users.indexes.push(index("users", "users_email_idx", "CREATE INDEX users_email_idx ON public.users USING btree (email)", false));
+        let source = SchemaSnapshot { tables: vec![source_users], ..Default::default() };
+        let target = SchemaSnapshot { tables: vec![target_users], ..Default::default() };
+
+        let diff = diff_schemas(&source, &target);
+        assert_eq!(
+            sql(&diff),
+            vec![
+                "DROP INDEX \"public\".\"users_email_idx\"",
+                "ALTER TABLE \"public\".\"users\" DROP CONSTRAINT \"users_pkey\"",
+                "ALTER TABLE \"public\".\"users\" ADD CONSTRAINT \"users_pkey\" PRIMARY KEY (id)",
+                "CREATE UNIQUE INDEX users_email_idx ON public.users USING btree (email)",
+            ]
+        );
+        assert_eq!(diff.changes[0].differences, vec!["columns: (id, email) -> (id)"]);
+    }
+
+    #[test]
+    fn test_dependent_views_are_dropped_and_created_in_order() {
+        let view = |name: &str, definition: &str| ViewInfo {
+            name: name.to_string(),
+            schema: "public".to_string(),
+            definition: definition.to_string(),
+            is_updatable: false,
+            check_option: None,
+        };
+        // a_summary reads from b_active, so it sorts before the view it depends on
+        let dependencies = vec![ViewDependency {
+            schema: "public".to_string(),
+            name: "a_summary".to_string(),
+            depends_on_schema: "public".to_string(),
+            depends_on_name: "b_active".to_string(),
+        }];
+        let summary = view("a_summary", "SELECT count(*) AS count FROM b_active");
+        let source = SchemaSnapshot {
+            tables: vec![users()],
+            views: vec![summary.clone(), view("b_active", "SELECT users.id FROM users WHERE users.id > 0")],
+            view_dependencies: dependencies.clone(),
+            ..Default::default()
+        };
+        let target = SchemaSnapshot {
+            tables: vec![users()],
+            views: vec![summary, view("b_active", "SELECT users.id FROM users")],
+            view_dependencies: dependencies,
+            ..Default::default()
+        };
+
+        let diff = diff_schemas(&source, &target);
+        // Only b_active changed; a_summary has to make way for it
+        assert_eq!(diff.changes.len(), 1);
+        assert_eq!(
+            sql(&diff),
+            vec![
+                "DROP VIEW \"public\".\"a_summary\"",
+                "DROP VIEW \"public\".\"b_active\"",
+                "CREATE VIEW \"public\".\"b_active\" AS\nSELECT users.id FROM users WHERE users.id > 0",
+                "CREATE VIEW \"public\".\"a_summary\" AS\nSELECT count(*) AS count FROM b_active",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_user_defined_types_are_schema_qualified() {
+        let mut source_users = users();
+        let mut mood = column("mood", "USER-DEFINED", true, None, false);
+        mood.udt_name = "mood".to_string();
+        mood.udt_schema = "app".to_string();
+        let mut tags = column("tags", "ARRAY", true, None, false);
+        tags.udt_name = "_tag".to_string();
+        tags.udt_schema = "app".to_string();
+        let mut ids = column("ids", "ARRAY", true, None, false);
+        ids.udt_name = "_int4".to_string();
+        source_users.columns.extend([mood, tags, ids]);
+        let source = SchemaSnapshot { tables: vec![source_users], ..Default::default() };
+        let target = SchemaSnapshot {
+            tables: vec![users()],
+            types: vec![("app".to_string(), "tag".to_string())],
+            ..Default::default()
+        };
+
+        let diff = diff_schemas(&source, &target);
+        assert_eq!(
+            sql(&diff),
+            vec![
+                "ALTER TABLE \"public\".\"users\" ADD COLUMN \"mood\" \"app\".\"mood\"",
+                "ALTER TABLE \"public\".\"users\" ADD COLUMN \"tags\" \"app\".\"tag\"[]",
+                "ALTER TABLE \"public\".\"users\" ADD COLUMN \"ids\" int4[]",
+            ]
+        );
+        assert_eq!(
+            diff.warnings,
+            vec!["Type app.mood does not exist on the target; create it before running the migration"]
+        );
+    }
+
+    #[test]
+    fn test_unique_constraints_are_changed_through_the_table() {
+        let constraint = |name: &str, definition: &str, constraint: &str| IndexInfo {
+            constraint_definition: Some(constraint.to_string()),
+            ..index("users", name, definition, false)
+        };
+        let mut source_users = users();
+        source_users.indexes.push(constraint(
+            "users_email_name_key",
+            "CREATE UNIQUE INDEX users_email_name_key ON public.users USING btree (email, name)",
+            "UNIQUE (email, name)",
+        ));
+        let mut target_users = users();
+        target_users.indexes.push(constraint(
+            "users_email_key",
+            "CREATE UNIQUE INDEX users_email_key ON public.users USING btree (email)",
+            "UNIQUE (email)",
+        ));
+        let source = SchemaSnapshot { tables: vec![source_users], ..Default::default() };
+        let target = SchemaSnapshot { tables: vec![target_users], ..Default::default() };
+
+        let diff = diff_schemas(&source, &target);
+        assert_eq!(
+            sql(&diff),
+            vec![
+                "ALTER TABLE \"public\".\"users\" DROP CONSTRAINT \"users_email_key\"",
+                "ALTER TABLE \"public\".\"users\" ADD CONSTRAINT \"users_email_name_key\" UNIQUE (email, name)",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_foreign_keys_keep_column_order_and_referenced_schema() {
+        let key_column = |column: &str, referenced: &str| ForeignKeyInfo {
+            name: "orders_account_fk".to_string(),
+            table_name: "orders".to_string(),
+            column_name: column.to_string(),
+            referenced_schema: "billing".to_string(),
+            referenced_table: "accounts".to_string(),
+            referenced_column: referenced.to_string(),
+        };
+        let mut orders = table(
+            "orders",
+            vec![column("region", "text", false, None, false), column("account", "integer", false, None, false)],
+        );
+        orders.foreign_keys = vec![key_column("account", "id"), key_column("region", "region")];
+        let source = SchemaSnapshot { tables: vec![orders.clone()], ..Default::default() };
+        orders.foreign_keys.clear();
+        let target = SchemaSnapshot { tables: vec![orders], ..Default::default() };
+
+        let diff = diff_schemas(&source, &target);
+        assert_eq!(
+            sql(&diff),
+            vec![
+                "ALTER TABLE \"public\".\"orders\" ADD CONSTRAINT \"orders_account_fk\" FOREIGN KEY (\"account\", \"region\") REFERENCES \"billing\".\"accounts\" (\"id\", \"region\")",
+            ]
+        );
+    }
+}
@@ -0,0 +1,356 @@
+use crate::connection_pool::{ConnectionPool, PoolConfig};
+use crate::connection_profile::Environment;
+use crate::production_guard::{Access, GuardDecision, GuardOperation, GuardStatus, ProductionGuard};
+use crate::simple_db::{QueryCanceller, SimpleDatabase};
+use crate::ssh_tunnel::SshTunnel;
+use crate::transaction_session::{TransactionInfo, TransactionOptions, TransactionSession, TransactionState};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+/// Session used by commands that do not pass a `session_id`
+pub const DEFAULT_SESSION_ID: &str = "default";
+
+/// Tauri state for the session registry
+pub type SessionRegistryState = Arc<Mutex<SessionRegistry>>;
+
+/// Profile a session was opened from
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionProfile {
+    pub profile_id: String,
+    pub profile_name: String,
+    pub environment: Environment,
+}
+
+/// Summary of an open session for the frontend
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionInfo {
+    pub id: String,
+    pub connected: bool,
+    pub profile: Option<SessionProfile>,
+    pub transaction_state: TransactionState,
+    pub guard: GuardStatus,
+    pub created_at: DateTime<Utc>,
+}
+
+/// One named database session with its own client and connection pool
+pub struct DatabaseSession {
+    pub id: String,
+    pub simple_db: Arc<Mutex<SimpleDatabase>>,
+    pub pool: Arc<Mutex<ConnectionPool>>,
+    profile: Mutex<Option<SessionProfile>>,
+    tunnel: Mutex<Option<SshTunnel>>,
+    /// Statements currently executing, keyed by execution id
+    running_queries: Mutex<HashMap<String, QueryCanceller>>,
+    /// Interactive transaction, pinned to a connection of its own
+    transaction: Mutex<Option<Arc<TransactionSession>>>,
+    /// Write protection, enforced when the profile is a production one
+    guard: Mutex<ProductionGuard>,
+    created_at: DateTime<Utc>,
+}
+
+impl DatabaseSession {
+    fn new(id: &str, pool_config: PoolConfig) -> Self {
+        Self {
+            id: id.to_string(),
+            simple_db: Arc::new(Mutex::new(SimpleDatabase::new())),
+            pool: Arc::new(Mutex::new(ConnectionPool::new(pool_config))),
+            profile: Mutex::new(None),
+            tunnel: Mutex::new(None),
+            running_queries: Mutex::new(HashMap::new()),
+            transaction: Mutex::new(None),
+            guard: Mutex::new(ProductionGuard::new()),
+            created_at: Utc::now(),
+        }
+    }
+
+    /// Record which profile this session is connected to (`None` for ad-hoc connections)
+    pub async fn set_profile(&self, profile: Option<SessionProfile>) {
+        *self.profile.lock().await = profile;
+        // A new connection starts locked, without confirmations from the previous one
+        *self.guard.lock().await = ProductionGuard::new();
+    }
+
+    pub async fn profile(&self) -> Option<SessionProfile> {
+        self.profile.lock().await.clone()
+    }
+
+    async fn is_production(&self) -> bool {
+        matches!(
+            self.profile.lock().await.as_ref().map(|profile| &profile.environment),
+            Some(Environment::Production)
+        )
+    }
+
+    /// Whether `operation` may run, with a confirmation token when it needs one
+    pub async fn check_operation(&self, operation: &GuardOperation) -> GuardDecision {
+        let production = self.is_production().await;
+        self.guard.lock().await.decide(production, operation, Utc::now())
+    }
+
+    /// Refuse `operation` unless the production guard lets it run, and say how it must run
+    pub async fn authorize(
+        &self,
+        operation: &GuardOperation,
+        confirmation_token: Option<&str>,
+    ) -> Result<Access, String> {
+        let production = self.is_production().await;
+        self.guard
+            .lock()
+            .await
+            .authorize(production, operation, confirmation_token, Utc::now())
+    }
+
+    /// Allow destructive operations for a while. `confirmation` must repeat the
+    /// profile's name, so it cannot be done by accident.
+    pub async fn unlock_writes(&self, confirmation: &str, minutes: Option<u64>) -> Result<GuardStatus, String> {
+        let profile = self.profile().await;
+        let profile = match profile {
+            Some(profile) if matches!(profile.environment, Environment::Production) => profile,
+            _ => return Err("This session is not connected to a production profile".to_string()),
+        };
+        if confirmation.trim() != profile.profile_name {
+            return Err(format!("Type the profile name '{}' to unlock writes", profile.profile_name));
+        }
+        let mut guard = self.guard.lock().await;
+        guard.unlock(minutes, Utc::now());
+        Ok(guard.status(true, Utc::now()))
+    }
+
+    pub async fn lock_writes(&self) -> GuardStatus {
+        let production = self.is_production().await;
+        let mut guard = self.guard.lock().await;
+        guard.lock();
+        guard.status(production, Utc::now())
+    }
+
+    pub async fn guard_status(&self) -> GuardStatus {
+        let production = self.is_production().await;
+        self.guard.lock().await.status(production, Utc::now())
+    }
+
+    /// Keep the SSH tunnel the session's connections go through, closing any previous one
+    pub async fn set_tunnel(&self, tunnel: Option<SshTunnel>) {
+        *self.tunnel.lock().await = tunnel;
+    }
+
+    /// Track a running statement so it can be cancelled by its execution id
+    pub async fn register_query(&self, execution_id: &str, canceller: QueryCanceller) -> Result<(), String> {
+        let mut running = self.running_queries.lock().await;
+        if running.contains_key(execution_id) {
+            return Err(format!("Execution '{}' is already running", execution_id));
+        }
+        running.insert(execution_id.to_string(), canceller);
+        Ok(())
+    }
+
+    pub async fn finish_query(&self, execution_id: &str) {
+        self.running_queries.lock().await.remove(execution_id);
+    }
+
+    /// Cancel a running statement on the server. Returns `false` if this session is not running it.
+    pub async fn cancel_query(&self, execution_id: &str) -> Result<bool, String> {
+        let canceller = self.running_queries.lock().await.get(execution_id).cloned();
+        match canceller {
+            Some(canceller) => {
+                canceller.cancel().await?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Start an interactive transaction on a connection taken from the pool, so
+    /// statements run on the shared client stay outside it
+    pub async fn begin_transaction(&self, options: TransactionOptions) -> Result<TransactionInfo, String> {
+        let mut slot = self.transaction.lock().await;
+        if slot.is_some() {
+            return Err("A transaction is already open in this session".to_string());
+        }
+
+        let (client, tls) = {
+            let pool = self.pool.lock().await;
+            (pool.get_connection().await?, pool.tls_connector())
+        };
+        let query_timeout = self.simple_db.lock().await.query_timeout();
+        let transaction = TransactionSession::begin(client, tls, options, query_timeout).await?;
+        let info = transaction.info();
+        *slot = Some(Arc::new(transaction));
+        Ok(info)
+    }
+
+    /// The open transaction
+    pub async fn current_transaction(&self) -> Result<Arc<TransactionSession>, String> {
+        self.transaction
+            .lock()
+            .await
+            .clone()
+            .ok_or_else(|| "No transaction is open in this session".to_string())
+    }
+
+    /// The open transaction, if it is the one `transaction_id` refers to
+    pub async fn transaction(&self, transaction_id: &str) -> Result<Arc<TransactionSession>, String> {
+        let transaction = self.current_transaction().await?;
+        if transaction.id != transaction_id {
+            return Err(format!("Transaction '{}' is no longer open", transaction_id));
+        }
+        Ok(transaction)
+    }
+
+    /// Commit or roll back the open transaction. Either way it is over afterwards and
+    /// its connection goes back to the pool.
+    pub async fn end_transaction(&self, commit: bool) -> Result<TransactionInfo, String> {
+        let transaction = self
+            .transaction
+            .lock()
+            .await
+            .take()
+            .ok_or_else(|| "No transaction is open in this session".to_string())?;
+        if commit {
+            transaction.commit().await?;
+        } else {
+            transaction.rollback().await?;
+        }
+        Ok(TransactionInfo::idle())
+    }
+
+    pub async fn transaction_info(&self) -> TransactionInfo {
+        match self.transaction.lock().await.as_ref() {
+            Some(transaction) => transaction.info(),
+            None => TransactionInfo::idle(),
+        }
+    }
+
+    /// Close the client, the pool and the SSH tunnel if there is one
+    pub async fn disconnect(&self) {
+        if let Some(transaction) = self.transaction.lock().await.take() {
+            let _ = transaction.rollback().await;
+        }
+        let _ = self.simple_db.lock().await.disconnect().await;
+        let _ = self.pool.lock().await.disconnect().await;
+        self.set_tunnel(None).await;
+        self.set_profile(None).await;
+    }
+
+    pub async fn info(&self) -> SessionInfo {
+        SessionInfo {
+            id: self.id.clone(),
+            connected: self.simple_db.lock().await.is_connected(),
+            profile: self.profile().await,
+            transaction_state: self.transaction_info().await.state,
+            guard: self.guard_status().await,
+            created_at: self.created_at,
+        }
+    }
+}
+
+/// Registry of open database sessions keyed by session id
+pub struct SessionRegistry {
+    sessions: HashMap<String, Arc<DatabaseSession>>,
+    pool_config: PoolConfig,
+}
+
+impl SessionRegistry {
+    pub fn new(pool_config: PoolConfig) -> Self {
+        Self {
+            sessions: HashMap::new(),
+            pool_config,
+        }
+    }
+
+    /// Look up an existing session. The default session is created on first use.
+    pub fn get(&mut self, session_id: Option<&str>) -> Result<Arc<DatabaseSession>, String> {
+        let id = session_id.unwrap_or(DEFAULT_SESSION_ID);
+        if id == DEFAULT_SESSION_ID {
+            return Ok(self.get_or_create(Some(id)));
+        }
+
+        self.sessions
+            .get(id)
+            .cloned()
+            .ok_or_else(|| format!("Session '{}' not found", id))
+    }
+
+    /// Look up a session, creating it if it does not exist yet
+    pub fn get_or_create(&mut self, session_id: Option<&str>) -> Arc<DatabaseSession> {
+        let id = session_id.unwrap_or(DEFAULT_SESSION_ID);
+        let pool_config = self.pool_config.clone();
+
+        self.sessions
+            .entry(id.to_string())
+            .or_insert_with(|| {
+                println!("🦀 [SessionRegistry] Creating session: {}", id);
+                Arc::new(DatabaseSession::new(id, pool_config))
+            })
+            .clone()
+    }
+
+    /// Remove a session from the registry, returning it so the caller can disconnect it
+    pub fn remove(&mut self, session_id: &str) -> Option<Arc<DatabaseSession>> {
+        self.sessions.remove(session_id)
+    }
+
+    /// All sessions, sorted by id
+    pub fn sessions(&self) -> Vec<Arc<DatabaseSession>> {
+        let mut sessions: Vec<_> = self.sessions.values().cloned().collect();
+        sessions.sort_by(|a, b| a.id.cmp(&b.id));
+        sessions
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_default_session_created_on_demand() {
+        let mut registry = SessionRegistry::new(PoolConfig::default());
+        assert!(registry.sessions().is_empty());
+
+        let session = registry.get(None).unwrap();
+        assert_eq!(session.id, DEFAULT_SESSION_ID);
+        assert!(Arc::ptr_eq(&session, &registry.get(Some(DEFAULT_SESSION_ID)).unwrap()));
+        assert_eq!(registry.sessions().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_named_sessions_are_independent() {
+        let mut registry = SessionRegistry::new(PoolConfig::default());
+        assert!(registry.get(Some("staging")).is_err());
+
+        let staging = registry.get_or_create(Some("staging"));
+        let production = registry.get_or_create(Some("production"));
+        assert!(!Arc::ptr_eq(&staging.simple_db, &production.simple_db));
+
+        staging
+            .set_profile(Some(SessionProfile {
+                profile_id: "p1".to_string(),
+                profile_name: "Staging".to_string(),
+                environment: Environment::Staging,
+            }))
+            .await;
+        let info = registry.get(Some("staging")).unwrap().info().await;
+        assert!(!info.connected);
+        assert_eq!(info.transaction_state, TransactionState::Idle);
+        assert_eq!(info.profile.unwrap().profile_id, "p1");
+
+        let ids: Vec<_> = registry.sessions().iter().map(|s| s.id.clone()).collect();
+        assert_eq!(ids, vec!["production".to_string(), "staging".to_string()]);
+
+        assert!(registry.remove("staging").is_some());
+        assert!(registry.get(Some("staging")).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_transaction_needs_a_connection() {
+        let mut registry = SessionRegistry::new(PoolConfig::default());
+        let session = registry.get(None).unwrap();
+
+        assert!(session.begin_transaction(TransactionOptions::default()).await.is_err());
+        assert_eq!(session.transaction_info().await.state, TransactionState::Idle);
+        assert!(session.end_transaction(true).await.is_err());
+        assert!(session.transaction("missing").await.is_err());
+    }
+}
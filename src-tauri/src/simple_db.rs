@@ -1,9 +1,16 @@
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::Mutex;
-use tokio_postgres::Client;
+use tokio_postgres::types::ToSql;
+use tokio_postgres::{CancelToken, Client};
 use postgres_native_tls::MakeTlsConnector;
 use serde::{Deserialize, Serialize};
-use base64;
+use crate::production_guard::Access;
+use crate::sql_builder::{self, DmlStatement, RowChangeResult, RowIdentifier, RowIdentifierKind, RowVersionCheck};
+use crate::sql_script::{self, ScriptOptions, ScriptResult};
+use crate::sql_splitter;
+use crate::table_import::{self, TableImportRequest, TableImportSummary};
+use crate::value_codec::{self, ColumnType, SessionTimeZone};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SimpleQueryResult {
@@ -11,6 +18,15 @@ pub struct SimpleQueryResult {
     pub rows: Vec<Vec<serde_json::Value>>,
     pub row_count: usize,
     pub execution_time_ms: u64,
+    /// OID and type name of each column, in `columns` order
+    #[serde(default)]
+    pub column_types: Vec<ColumnType>,
+    /// Rows changed by a statement that returns no result set
+    #[serde(default)]
+    pub rows_affected: Option<u64>,
+    /// Columns that identify each row for editing, when the rows come from one table
+    #[serde(default)]
+    pub row_identifier: Option<RowIdentifier>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -19,7 +35,7 @@ pub struct SimpleColumn {
     pub data_type: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct TableInfo {
     pub name: String,
     pub schema: String,
@@ -34,11 +50,15 @@ pub struct ColumnInfo {
     pub default_value: Option<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct DetailedColumnInfo {
     pub name: String,
     pub data_type: String,
     pub udt_name: String, // User-defined type name (for enums, custom types)
+    #[serde(default)]
+    pub udt_schema: String,
+    #[serde(default)]
+    pub is_enum: bool,
     pub is_nullable: bool,
     pub default_value: Option<String>,
     pub character_maximum_length: Option<i32>,
@@ -48,16 +68,18 @@ pub struct DetailedColumnInfo {
     pub is_foreign_key: bool,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ForeignKeyInfo {
     pub name: String,
     pub table_name: String,
     pub column_name: String,
+    #[serde(default)]
+    pub referenced_schema: String,
     pub referenced_table: String,
     pub referenced_column: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct IndexInfo {
     pub name: String,
     pub table_name: String,
@@ -68,9 +90,12 @@ pub struct IndexInfo {
     pub index_type: String,
     pub definition: String,
     pub size_bytes: Option<u64>,
+    /// `UNIQUE (...)` or `EXCLUDE ...` when the index belongs to that constraint
+    #[serde(default)]
+    pub constraint_definition: Option<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ViewInfo {
     pub name: String,
     pub schema: String,
@@ -79,7 +104,7 @@ pub struct ViewInfo {
     pub check_option: Option<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct StoredProcedureInfo {
     pub name: String,
     pub schema: String,
@@ -111,9 +136,157 @@ pub struct CreateIndexOptions {
     pub where_clause: Option<String>,
 }
 
+/// Error text including the server's message, which `tokio_postgres::Error` hides as "db error"
+pub(crate) fn db_error_message(e: &tokio_postgres::Error) -> String {
+    match e.as_db_error() {
+        Some(db_error) => db_error.to_string(),
+        None => e.to_string(),
+    }
+}
+
+/// Query timeout for connections that do not come from a profile
+pub const DEFAULT_QUERY_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// How long the client waits past `statement_timeout` before giving up on the server
+const CLIENT_TIMEOUT_GRACE: Duration = Duration::from_secs(5);
+
+/// Cancels the statement currently running on a connection
+#[derive(Clone)]
+pub struct QueryCanceller {
+    token: CancelToken,
+    tls: MakeTlsConnector,
+}
+
+impl QueryCanceller {
+    pub fn new(token: CancelToken, tls: MakeTlsConnector) -> Self {
+        Self { token, tls }
+    }
+
+    /// Ask the server to cancel the running statement. Succeeds even if nothing is running.
+    pub async fn cancel(&self) -> Result<(), String> {
+        self.token
+            .cancel_query(self.tls.clone())
+            .await
+            .map_err(|e| format!("Failed to cancel query: {}", e))
+    }
+}
+
+/// Run one statement on `client` and decode its rows. The client gives up `CLIENT_TIMEOUT_GRACE`
+/// after `timeout` (the server's `statement_timeout` should fire first) and cancels the statement.
+/// `params` bind `$1`, `$2`, ... as text; `None` binds NULL. `timestamptz` values are
+/// rendered in `zone`, the connection's time zone.
+pub(crate) async fn run_query(
+    client: &Client,
+    query: &str,
+    params: &[Option<String>],
+    timeout: Duration,
+    tls: Option<&MakeTlsConnector>,
+    zone: &SessionTimeZone,
+) -> Result<SimpleQueryResult, String> {
+    let start_time = std::time::Instant::now();
+    let param_refs: Vec<&(dyn ToSql + Sync)> = params.iter().map(|p| p as &(dyn ToSql + Sync)).collect();
+
+    // Prepare first so column types are known even when no rows come back
+    let run_query = async {
+        let statement = client.prepare(query).await?;
+        // Statements without a result set report how many rows they changed instead
+        if statement.columns().is_empty() {
+            let rows_affected = client.execute(&statement, &param_refs).await?;
+            return Ok((statement, Vec::new(), Some(rows_affected)));
+        }
+        let rows = client.query(&statement, &param_refs).await?;
+        Ok::<_, tokio_postgres::Error>((statement, rows, None))
+    };
+
+    let query_result = match tokio::time::timeout(timeout + CLIENT_TIMEOUT_GRACE, run_query).await {
+        Ok(result) => result,
+        Err(_) => {
+            // The server did not answer in time; make sure it stops working on the statement
+            if let Some(tls) = tls {
+                let _ = client.cancel_token().cancel_query(tls.clone()).await;
+            }
+            println!("🦀 [SimpleDB] Query timed out after {:?}", timeout);
+            return Err(format!("Query timed out after {:?}", timeout));
+        }
+    };
+
+    match query_result {
+        Ok((statement, rows, rows_affected)) => {
+            let execution_time = start_time.elapsed().as_millis() as u64;
+            println!("🦀 [SimpleDB] Query executed successfully in {}ms, {} rows returned", execution_time, rows.len());
+            
+            let columns = statement.columns().iter().map(|col| col.name().to_string()).collect();
+
+            // Convert rows to Vec<Vec<Value>> format
+            let json_rows: Vec<Vec<serde_json::Value>> = rows.iter().map(|row| value_codec::decode_row(row, zone)).collect();
+
+            let result = SimpleQueryResult {
+                columns,
+                rows: json_rows,
+                row_count: rows.len(),
+                execution_time_ms: execution_time,
+                column_types: value_codec::column_types(statement.columns()),
+                rows_affected,
+                row_identifier: None,
+            };
+
+            println!("🦀 [SimpleDB] Query result prepared: {} columns, {} rows", result.columns.len(), result.rows.len());
+            Ok(result)
+        },
+        Err(e) => {
+            let execution_time = start_time.elapsed().as_millis() as u64;
+            println!("🦀 [SimpleDB] Query failed after {}ms: {}", execution_time, e);
+            match e.as_db_error() {
+                Some(db_error) if db_error.code() == &tokio_postgres::error::SqlState::QUERY_CANCELED => {
+                    if db_error.message().contains("statement timeout") {
+                        Err(format!("Query timed out after {:?}", timeout))
+                    } else {
+                        Err("Query was cancelled".to_string())
+                    }
+                }
+                _ => Err(format!("Query execution failed: {}", e)),
+            }
+        }
+    }
+}
+
+/// Make the transactions the server starts from now on read-only, or go back to the
+/// session's own default (a read-only role stays read-only)
+async fn set_default_read_only(client: &Client, read_only: bool) -> Result<(), String> {
+    let sql = if read_only {
+        "SET default_transaction_read_only = on"
+    } else {
+        "RESET default_transaction_read_only"
+    };
+    client
+        .batch_execute(sql)
+        .await
+        .map_err(|e| format!("Failed to set read-only mode: {}", e))
+}
+
+/// `SET` and `RESET` may change the session's `TimeZone`, which is then read again
+pub(crate) fn changes_settings(sql: &str) -> bool {
+    matches!(sql_splitter::leading_keyword(sql).as_str(), "SET" | "RESET")
+}
+
+/// A single-row edit ready to run, with the lookup that reports a conflict when a checked edit misses
+#[derive(Debug, Clone)]
+pub struct RowEdit {
+    action: &'static str,
+    pub statement: DmlStatement,
+    current_row: Option<DmlStatement>,
+    check: RowVersionCheck,
+}
+
 pub struct SimpleDatabase {
     client: Arc<Mutex<Option<Client>>>,
     connection_string: Option<String>,
+    tls: Option<MakeTlsConnector>,
+    query_timeout: Duration,
+    /// `statement_timeout` currently set on the server session
+    statement_timeout: Mutex<Option<Duration>>,
+    /// The server session's `TimeZone`, which `timestamptz` values are rendered in
+    time_zone: Mutex<SessionTimeZone>,
 }
 
 impl SimpleDatabase {
@@ -122,9 +295,22 @@ impl SimpleDatabase {
         Self {
             client: Arc::new(Mutex::new(None)),
             connection_string: None,
+            tls: None,
+            query_timeout: DEFAULT_QUERY_TIMEOUT,
+            statement_timeout: Mutex::new(None),
+            time_zone: Mutex::new(SessionTimeZone::default()),
         }
     }
 
+    /// Timeout applied to queries that do not specify their own
+    pub fn set_query_timeout(&mut self, timeout: Duration) {
+        self.query_timeout = timeout;
+    }
+
+    pub fn query_timeout(&self) -> Duration {
+        self.query_timeout
+    }
+
     pub async fn connect(&mut self, connection_string: String) -> Result<(), String> {
         println!("🦀 [SimpleDB] Connecting with connection string length: {}", connection_string.len());
         
@@ -140,17 +326,21 @@ impl SimpleDatabase {
             }
         };
 
-        // Create TLS connector for SSL connections (required by Neon)
-        println!("🦀 [SimpleDB] Creating TLS connector for SSL connection...");
-        let connector = native_tls::TlsConnector::builder()
-            .danger_accept_invalid_certs(false)
-            .build()
-            .map_err(|e| format!("TLS setup failed: {}", e))?;
-        let tls = MakeTlsConnector::new(connector);
+        let tls = crate::tls_config::default_tls_connector()?;
+        self.connect_with_config(config, tls).await?;
+        self.connection_string = Some(connection_string);
+        Ok(())
+    }
 
+    /// Connect using an already-built configuration and TLS connector (e.g. from a saved profile)
+    pub async fn connect_with_config(
+        &mut self,
+        config: tokio_postgres::Config,
+        tls: MakeTlsConnector,
+    ) -> Result<(), String> {
         // Connect to database
         println!("🦀 [SimpleDB] Attempting to connect to PostgreSQL with TLS...");
-        match config.connect(tls).await {
+        match config.connect(tls.clone()).await {
             Ok((client, connection)) => {
                 println!("🦀 [SimpleDB] PostgreSQL connection established!");
                 
@@ -161,11 +351,15 @@ impl SimpleDatabase {
                     }
                 });
 
+                *self.time_zone.lock().await = value_codec::read_time_zone(&client).await;
+
                 // Store client
                 println!("🦀 [SimpleDB] Storing client...");
                 let mut client_guard = self.client.lock().await;
                 *client_guard = Some(client);
-                self.connection_string = Some(connection_string);
+                self.connection_string = None;
+                self.tls = Some(tls);
+                *self.statement_timeout.lock().await = None;
                 
                 println!("🦀 [SimpleDB] Connection completed successfully!");
                 Ok(())
@@ -182,6 +376,7 @@ impl SimpleDatabase {
         let mut client_guard = self.client.lock().await;
         *client_guard = None;
         self.connection_string = None;
+        self.tls = None;
         println!("🦀 [SimpleDB] Disconnected successfully");
         Ok(())
     }
@@ -201,10 +396,53 @@ impl SimpleDatabase {
         }
     }
 
+    /// Handle for cancelling whatever statement is running on this connection
+    pub async fn canceller(&self) -> Result<QueryCanceller, String> {
+        let client_guard = self.client.lock().await;
+        match (client_guard.as_ref(), &self.tls) {
+            (Some(client), Some(tls)) => Ok(QueryCanceller {
+                token: client.cancel_token(),
+                tls: tls.clone(),
+            }),
+            _ => Err("Not connected to database".to_string()),
+        }
+    }
+
     pub async fn execute_query(&self, query: &str) -> Result<SimpleQueryResult, String> {
+        self.execute_query_with_timeout(query, None).await
+    }
+
+    /// Run a query with a server-side `statement_timeout` and a client-side deadline.
+    /// Uses the connection's query timeout when `timeout` is `None`.
+    pub async fn execute_query_with_timeout(
+        &self,
+        query: &str,
+        timeout: Option<Duration>,
+    ) -> Result<SimpleQueryResult, String> {
+        self.execute_query_with_params(query, &[], timeout).await
+    }
+
+    /// Like `execute_query_with_timeout`, binding `params` to `$1`, `$2`, ... as text
+    pub async fn execute_query_with_params(
+        &self,
+        query: &str,
+        params: &[Option<String>],
+        timeout: Option<Duration>,
+    ) -> Result<SimpleQueryResult, String> {
+        self.execute_query_with_access(query, params, timeout, Access::ReadWrite).await
+    }
+
+    /// Like `execute_query_with_params`, in a read-only transaction when `access` says so
+    pub async fn execute_query_with_access(
+        &self,
+        query: &str,
+        params: &[Option<String>],
+        timeout: Option<Duration>,
+        access: Access,
+    ) -> Result<SimpleQueryResult, String> {
         println!("🦀 [SimpleDB] execute_query called with: {}", query);
         
-        let start_time = std::time::Instant::now();
+        let timeout = timeout.unwrap_or(self.query_timeout);
         
         let client_guard = self.client.lock().await;
         let client = match client_guard.as_ref() {
@@ -218,274 +456,67 @@ impl SimpleDatabase {
             }
         };
 
-        match client.query(query, &[]).await {
-            Ok(rows) => {
-                let execution_time = start_time.elapsed().as_millis() as u64;
-                println!("🦀 [SimpleDB] Query executed successfully in {}ms, {} rows returned", execution_time, rows.len());
-                
-                // Get column names
-                let columns = if !rows.is_empty() {
-                    rows[0].columns().iter().map(|col| col.name().to_string()).collect()
-                } else {
-                    Vec::new()
-                };
+        self.apply_statement_timeout(client, timeout).await?;
+        let zone = *self.time_zone.lock().await;
+        if access.is_read_only() {
+            set_default_read_only(client, true).await?;
+        }
+        let result = run_query(client, query, params, timeout, self.tls.as_ref(), &zone).await;
+        if access.is_read_only() {
+            set_default_read_only(client, false).await?;
+        }
+        if result.is_ok() && changes_settings(query) {
+            *self.time_zone.lock().await = value_codec::read_time_zone(client).await;
+        }
+        result
+    }
 
-                // Convert rows to Vec<Vec<Value>> format
-                let mut json_rows = Vec::new();
-                for row in &rows {
-                    let mut json_row = Vec::new();
-                    for (i, column) in row.columns().iter().enumerate() {
-                        let value = match column.type_().name() {
-                            "int4" => {
-                                // Try i32 first, then i64
-                                match row.try_get::<_, Option<i32>>(i) {
-                                    Ok(Some(n)) => serde_json::Value::Number((n as i64).into()),
-                                    Ok(None) => serde_json::Value::Null,
-                                    Err(_) => {
-                                        match row.try_get::<_, Option<i64>>(i) {
-                                            Ok(Some(n)) => serde_json::Value::Number(n.into()),
-                                            Ok(None) => serde_json::Value::Null,
-                                            Err(_) => serde_json::Value::String("Error parsing int4".to_string()),
-                                        }
-                                    }
-                                }
-                            },
-                            "int8" => {
-                                match row.try_get::<_, Option<i64>>(i) {
-                                    Ok(Some(n)) => serde_json::Value::Number(n.into()),
-                                    Ok(None) => serde_json::Value::Null,
-                                    Err(_) => serde_json::Value::String("Error parsing int8".to_string()),
-                                }
-                            },
-                            "int2" => {
-                                match row.try_get::<_, Option<i16>>(i) {
-                                    Ok(Some(n)) => serde_json::Value::Number((n as i64).into()),
-                                    Ok(None) => serde_json::Value::Null,
-                                    Err(_) => serde_json::Value::String("Error parsing int2".to_string()),
-                                }
-                            },
-                            "float4" | "float8" | "numeric" => {
-                                match row.try_get::<_, Option<f64>>(i) {
-                                    Ok(Some(n)) => serde_json::Value::Number(serde_json::Number::from_f64(n).unwrap_or(serde_json::Number::from(0))),
-                                    Ok(None) => serde_json::Value::Null,
-                                    Err(_) => serde_json::Value::String("Error parsing float".to_string()),
-                                }
-                            },
-                            "bool" => {
-                                match row.try_get::<_, Option<bool>>(i) {
-                                    Ok(Some(b)) => serde_json::Value::Bool(b),
-                                    Ok(None) => serde_json::Value::Null,
-                                    Err(_) => serde_json::Value::String("Error parsing boolean".to_string()),
-                                }
-                            },
-                            "uuid" => {
-                                match row.try_get::<_, Option<String>>(i) {
-                                    Ok(Some(s)) => serde_json::Value::String(s),
-                                    Ok(None) => serde_json::Value::Null,
-                                    Err(_) => serde_json::Value::String("Error parsing UUID".to_string()),
-                                }
-                            },
-                            "timestamp" => {
-                                // Handle timestamp without timezone
-                                match row.try_get::<_, Option<chrono::NaiveDateTime>>(i) {
-                                    Ok(Some(dt)) => serde_json::Value::String(dt.format("%Y-%m-%d %H:%M:%S%.3f").to_string()),
-                                    Ok(None) => serde_json::Value::Null,
-                                    Err(_) => {
-                                        // Fallback to string parsing
-                                        match row.try_get::<_, Option<String>>(i) {
-                                            Ok(Some(s)) => serde_json::Value::String(s),
-                                            Ok(None) => serde_json::Value::Null,
-                                            Err(e) => {
-                                                println!("🦀 [SimpleDB] Warning: Failed to parse timestamp column {}: {}", column.name(), e);
-                                                serde_json::Value::String("Error parsing timestamp".to_string())
-                                            }
-                                        }
-                                    }
-                                }
-                            },
-                            "timestamptz" => {
-                                // Handle timestamp with timezone
-                                match row.try_get::<_, Option<chrono::DateTime<chrono::Utc>>>(i) {
-                                    Ok(Some(dt)) => serde_json::Value::String(dt.format("%Y-%m-%d %H:%M:%S%.3f %Z").to_string()),
-                                    Ok(None) => serde_json::Value::Null,
-                                    Err(_) => {
-                                        // Try as NaiveDateTime fallback
-                                        match row.try_get::<_, Option<chrono::NaiveDateTime>>(i) {
-                                            Ok(Some(dt)) => serde_json::Value::String(dt.format("%Y-%m-%d %H:%M:%S%.3f").to_string()),
-                                            Ok(None) => serde_json::Value::Null,
-                                            Err(_) => {
-                                                // Final fallback to string
-                                                match row.try_get::<_, Option<String>>(i) {
-                                                    Ok(Some(s)) => serde_json::Value::String(s),
-                                                    Ok(None) => serde_json::Value::Null,
-                                                    Err(e) => {
-                                                        println!("🦀 [SimpleDB] Warning: Failed to parse timestamptz column {}: {}", column.name(), e);
-                                                        serde_json::Value::String("Error parsing timestamptz".to_string())
-                                                    }
-                                                }
-                                            }
-                                        }
-                                    }
-                                }
-                            },
-                            "date" => {
-                                match row.try_get::<_, Option<chrono::NaiveDate>>(i) {
-                                    Ok(Some(d)) => serde_json::Value::String(d.format("%Y-%m-%d").to_string()),
-                                    Ok(None) => serde_json::Value::Null,
-                                    Err(_) => {
-                                        match row.try_get::<_, Option<String>>(i) {
-                                            Ok(Some(s)) => serde_json::Value::String(s),
-                                            Ok(None) => serde_json::Value::Null,
-                                            Err(e) => {
-                                                println!("🦀 [SimpleDB] Warning: Failed to parse date column {}: {}", column.name(), e);
-                                                serde_json::Value::String("Error parsing date".to_string())
-                                            }
-                                        }
-                                    }
-                                }
-                            },
-                            "time" | "timetz" => {
-                                match row.try_get::<_, Option<chrono::NaiveTime>>(i) {
-                                    Ok(Some(t)) => serde_json::Value::String(t.format("%H:%M:%S%.3f").to_string()),
-                                    Ok(None) => serde_json::Value::Null,
-                                    Err(_) => {
-                                        match row.try_get::<_, Option<String>>(i) {
-                                            Ok(Some(s)) => serde_json::Value::String(s),
-                                            Ok(None) => serde_json::Value::Null,
-                                            Err(e) => {
-                                                println!("🦀 [SimpleDB] Warning: Failed to parse time column {}: {}", column.name(), e);
-                                                serde_json::Value::String("Error parsing time".to_string())
-                                            }
-                                        }
-                                    }
-                                }
-                            },
-                            "json" | "jsonb" => {
-                                match row.try_get::<_, Option<String>>(i) {
-                                    Ok(Some(s)) => {
-                                        // Try to parse as JSON, fallback to string
-                                        match serde_json::from_str::<serde_json::Value>(&s) {
-                                            Ok(json_val) => json_val,
-                                            Err(_) => serde_json::Value::String(s),
-                                        }
-                                    },
-                                    Ok(None) => serde_json::Value::Null,
-                                    Err(_) => serde_json::Value::String("Error parsing JSON".to_string()),
-                                }
-                            },
-                            // Handle array types
-                            type_name if type_name.starts_with("_") => {
-                                // PostgreSQL array types start with underscore
-                                match row.try_get::<_, Option<String>>(i) {
-                                    Ok(Some(s)) => {
-                                        // Try to parse as JSON array, fallback to string
-                                        if s.starts_with('{') && s.ends_with('}') {
-                                            // Convert PostgreSQL array format to JSON array
-                                            let json_array = s.replace('{', "[").replace('}', "]");
-                                            match serde_json::from_str::<serde_json::Value>(&json_array) {
-                                                Ok(arr) => arr,
-                                                Err(_) => serde_json::Value::String(s),
-                                            }
-                                        } else {
-                                            serde_json::Value::String(s)
-                                        }
-                                    },
-                                    Ok(None) => serde_json::Value::Null,
-                                    Err(e) => {
-                                        println!("🦀 [SimpleDB] Warning: Failed to parse array column {}: {}", column.name(), e);
-                                        serde_json::Value::String(format!("Error parsing array: {}", type_name))
-                                    }
-                                }
-                            },
-                            // Handle text and varchar types
-                            "text" | "varchar" | "char" | "bpchar" => {
-                                match row.try_get::<_, Option<String>>(i) {
-                                    Ok(Some(s)) => serde_json::Value::String(s),
-                                    Ok(None) => serde_json::Value::Null,
-                                    Err(e) => {
-                                        println!("🦀 [SimpleDB] Warning: Failed to parse text column {}: {}", column.name(), e);
-                                        serde_json::Value::String("Error parsing text".to_string())
-                                    }
-                                }
-                            },
-                            // Handle bytea (binary data)
-                            "bytea" => {
-                                match row.try_get::<_, Option<Vec<u8>>>(i) {
-                                    Ok(Some(bytes)) => {
-                                        // Convert to base64 for JSON representation
-                                        use base64::Engine;
-                                        serde_json::Value::String(base64::engine::general_purpose::STANDARD.encode(&bytes))
-                                    },
-                                    Ok(None) => serde_json::Value::Null,
-                                    Err(_) => {
-                                        // Fallback to string
-                                        match row.try_get::<_, Option<String>>(i) {
-                                            Ok(Some(s)) => serde_json::Value::String(s),
-                                            Ok(None) => serde_json::Value::Null,
-                                            Err(e) => {
-                                                println!("🦀 [SimpleDB] Warning: Failed to parse bytea column {}: {}", column.name(), e);
-                                                serde_json::Value::String("Error parsing bytea".to_string())
-                                            }
-                                        }
-                                    }
-                                }
-                            },
-                            // Handle interval type
-                            "interval" => {
-                                match row.try_get::<_, Option<String>>(i) {
-                                    Ok(Some(s)) => serde_json::Value::String(s),
-                                    Ok(None) => serde_json::Value::Null,
-                                    Err(e) => {
-                                        println!("🦀 [SimpleDB] Warning: Failed to parse interval column {}: {}", column.name(), e);
-                                        serde_json::Value::String("Error parsing interval".to_string())
-                                    }
-                                }
-                            },
-                            _ => {
-                                // For custom types (enums, etc.) and other unknown types
-                                println!("🦀 [SimpleDB] Handling unknown/custom type: {} for column {}", column.type_().name(), column.name());
-                                match row.try_get::<_, Option<String>>(i) {
-                                    Ok(Some(s)) => serde_json::Value::String(s),
-                                    Ok(None) => serde_json::Value::Null,
-                                    Err(e) => {
-                                        println!("🦀 [SimpleDB] Warning: Unable to parse column {} of type {} as string: {}", column.name(), column.type_().name(), e);
-                                        // Try to get the raw value as bytes and convert to string
-                                        match row.try_get::<_, Option<Vec<u8>>>(i) {
-                                            Ok(Some(bytes)) => {
-                                                let len = bytes.len();
-                                                match String::from_utf8(bytes) {
-                                                    Ok(s) => serde_json::Value::String(s),
-                                                    Err(_) => serde_json::Value::String(format!("[Binary data: {} bytes]", len)),
-                                                }
-                                            },
-                                            Ok(None) => serde_json::Value::Null,
-                                            Err(_) => serde_json::Value::String(format!("[Unknown type: {}]", column.type_().name())),
-                                        }
-                                    }
-                                }
-                            }
-                        };
-                        json_row.push(value);
-                    }
-                    json_rows.push(json_row);
-                }
+    /// Split `script` into statements and run them in order on this connection, in
+    /// read-only transactions when `access` says so
+    pub async fn execute_script(
+        &self,
+        script: &str,
+        options: &ScriptOptions,
+        access: Access,
+    ) -> Result<ScriptResult, String> {
+        let statements = sql_splitter::split_statements(script)?;
+        println!("🦀 [SimpleDB] execute_script called with {} statements", statements.len());
+        let sets_time_zone = statements.iter().any(|statement| changes_settings(&statement.sql));
+
+        let timeout = options.timeout_ms.map(Duration::from_millis).unwrap_or(self.query_timeout);
+        let client_guard = self.client.lock().await;
+        let client = client_guard.as_ref().ok_or("Not connected to database")?;
 
-                let result = SimpleQueryResult {
-                    columns,
-                    rows: json_rows,
-                    row_count: rows.len(),
-                    execution_time_ms: execution_time,
-                };
+        self.apply_statement_timeout(client, timeout).await?;
+        if access.is_read_only() {
+            set_default_read_only(client, true).await?;
+        }
+        let zone = *self.time_zone.lock().await;
+        let result = sql_script::run_script(client, statements, options.on_error, timeout, self.tls.as_ref(), &zone).await;
+        // The script may have changed statement_timeout itself
+        *self.statement_timeout.lock().await = None;
+        if sets_time_zone {
+            *self.time_zone.lock().await = value_codec::read_time_zone(client).await;
+        }
+        if access.is_read_only() {
+            set_default_read_only(client, false).await?;
+        }
+        result
+    }
 
-                println!("🦀 [SimpleDB] Query result prepared: {} columns, {} rows", result.columns.len(), result.rows.len());
-                Ok(result)
-            },
-            Err(e) => {
-                let execution_time = start_time.elapsed().as_millis() as u64;
-                println!("🦀 [SimpleDB] Query failed after {}ms: {}", execution_time, e);
-                Err(format!("Query execution failed: {}", e))
-            }
+    /// Set `statement_timeout` on the server session unless it already has this value
+    async fn apply_statement_timeout(&self, client: &Client, timeout: Duration) -> Result<(), String> {
+        let mut applied = self.statement_timeout.lock().await;
+        if *applied == Some(timeout) {
+            return Ok(());
         }
+
+        client
+            .batch_execute(&format!("SET statement_timeout = {}", timeout.as_millis()))
+            .await
+            .map_err(|e| format!("Failed to set statement timeout: {}", e))?;
+        *applied = Some(timeout);
+        Ok(())
     }
 
     pub async fn build_safe_query(&self, table_name: &str, schema_name: Option<&str>) -> Result<String, String> {
@@ -495,9 +526,64 @@ impl SimpleDatabase {
         Ok(query)
     }
 
+    /// First rows of a table, with the row identifier the grid needs to edit them.
+    /// Tables keyed by `ctid` get it as an extra first column.
     pub async fn query_table(&self, table_name: &str, schema_name: Option<&str>) -> Result<SimpleQueryResult, String> {
-        let query = self.build_safe_query(table_name, schema_name).await?;
-        self.execute_query(&query).await
+        let row_identifier = self.get_row_identifier(table_name, schema_name).await?;
+        let query = match &row_identifier {
+            Some(identifier) if identifier.kind == RowIdentifierKind::Ctid => format!(
+                "SELECT ctid, * FROM {} LIMIT 100",
+                sql_builder::qualified_name(schema_name.unwrap_or("public"), table_name)
+            ),
+            _ => self.build_safe_query(table_name, schema_name).await?,
+        };
+
+        let mut result = self.execute_query(&query).await?;
+        result.row_identifier = row_identifier;
+        Ok(result)
+    }
+
+    /// Best way to identify rows of a table for editing: primary key, then a unique index
+    /// over NOT NULL columns, then `ctid` for plain tables. `None` for views and other
+    /// relations that cannot be edited row by row.
+    pub async fn get_row_identifier(&self, table_name: &str, schema_name: Option<&str>) -> Result<Option<RowIdentifier>, String> {
+        println!("🦀 [SimpleDB] get_row_identifier called for table: {}", table_name);
+
+        let schema = schema_name.unwrap_or("public");
+        let relkind = {
+            let client_guard = self.client.lock().await;
+            let client = match client_guard.as_ref() {
+                Some(client) => client,
+                None => return Err("Not connected to database".to_string()),
+            };
+            let rows = client
+                .query(
+                    "SELECT c.relkind::text FROM pg_class c
+                     JOIN pg_namespace n ON n.oid = c.relnamespace
+                     WHERE n.nspname = $1 AND c.relname = $2",
+                    &[&schema, &table_name],
+                )
+                .await
+                .map_err(|e| format!("Failed to look up table: {}", db_error_message(&e)))?;
+            match rows.first() {
+                Some(row) => row.get::<_, String>(0),
+                None => return Err(format!("Table {} not found", sql_builder::qualified_name(schema, table_name))),
+            }
+        };
+
+        // Only plain and partitioned tables can be edited by key; a ctid is only
+        // unique within a single plain table
+        if relkind != "r" && relkind != "p" {
+            return Ok(None);
+        }
+
+        let columns = self.get_detailed_table_columns(table_name, schema_name).await?;
+        let indexes = self.get_table_indexes(table_name, schema_name).await?;
+        let identifier = sql_builder::choose_row_identifier(&columns, &indexes, relkind == "r");
+        if let Some(identifier) = &identifier {
+            println!("🦀 [SimpleDB] Rows identified by {:?}: {:?}", identifier.kind, identifier.columns);
+        }
+        Ok(identifier)
     }
 
     pub async fn get_tables(&self) -> Result<Vec<TableInfo>, String> {
@@ -550,8 +636,9 @@ impl SimpleDatabase {
         println!("🦀 [SimpleDB] get_table_columns called for table: {}", table_name);
         
         let schema = schema_name.unwrap_or("public");
-        let query = format!(
-            "SELECT 
+        // information_schema names are sql_identifier; the casts make the parameters text
+        let query = "
+            SELECT 
                 c.column_name,
                 c.data_type,
                 c.is_nullable,
@@ -572,15 +659,15 @@ impl SimpleDatabase {
                     ON tc.constraint_name = ku.constraint_name
                     AND tc.table_schema = ku.table_schema
                 WHERE tc.constraint_type = 'PRIMARY KEY'
-                    AND tc.table_schema = '{}'
-                    AND tc.table_name = '{}'
+                    AND tc.table_schema = $1::text
+                    AND tc.table_name = $2::text
             ) pk ON c.column_name = pk.column_name
-            WHERE c.table_schema = '{}' AND c.table_name = '{}'
-            ORDER BY c.ordinal_position",
-            schema, table_name, schema, table_name
-        );
+            WHERE c.table_schema = $1::text AND c.table_name = $2::text
+            ORDER BY c.ordinal_position
+        ";
+        let params = [Some(schema.to_string()), Some(table_name.to_string())];
         
-        let result = self.execute_query(&query).await?;
+        let result = self.execute_query_with_params(query, &params, None).await?;
         println!("🦀 [SimpleDB] Found {} columns", result.rows.len());
         
         let mut columns = Vec::new();
@@ -621,8 +708,9 @@ impl SimpleDatabase {
         println!("🦀 [SimpleDB] get_detailed_table_columns called for table: {}", table_name);
         
         let schema = schema_name.unwrap_or("public");
-        let query = format!(
-            "SELECT 
+        // information_schema names are sql_identifier; the casts make the parameters text
+        let query = "
+            SELECT 
                 c.column_name,
                 c.data_type,
                 c.udt_name,
@@ -638,7 +726,13 @@ impl SimpleDatabase {
                 CASE 
                     WHEN fk.column_name IS NOT NULL THEN true 
                     ELSE false 
-                END as is_foreign_key
+                END as is_foreign_key,
+                c.udt_schema,
+                EXISTS (
+                    SELECT 1 FROM pg_type t
+                    JOIN pg_namespace n ON n.oid = t.typnamespace
+                    WHERE n.nspname = c.udt_schema AND t.typname = c.udt_name AND t.typtype = 'e'
+                ) as is_enum
             FROM information_schema.columns c
             LEFT JOIN (
                 SELECT ku.column_name
@@ -647,8 +741,8 @@ impl SimpleDatabase {
                     ON tc.constraint_name = ku.constraint_name
                     AND tc.table_schema = ku.table_schema
                 WHERE tc.constraint_type = 'PRIMARY KEY'
-                    AND tc.table_schema = '{}'
-                    AND tc.table_name = '{}'
+                    AND tc.table_schema = $1::text
+                    AND tc.table_name = $2::text
             ) pk ON c.column_name = pk.column_name
             LEFT JOIN (
                 SELECT ku.column_name
@@ -657,15 +751,15 @@ impl SimpleDatabase {
                     ON tc.constraint_name = ku.constraint_name
                     AND tc.table_schema = ku.table_schema
                 WHERE tc.constraint_type = 'FOREIGN KEY'
-                    AND tc.table_schema = '{}'
-                    AND tc.table_name = '{}'
+                    AND tc.table_schema = $1::text
+                    AND tc.table_name = $2::text
             ) fk ON c.column_name = fk.column_name
-            WHERE c.table_schema = '{}' AND c.table_name = '{}'
-            ORDER BY c.ordinal_position",
-            schema, table_name, schema, table_name, schema, table_name
-        );
+            WHERE c.table_schema = $1::text AND c.table_name = $2::text
+            ORDER BY c.ordinal_position
+        ";
+        let params = [Some(schema.to_string()), Some(table_name.to_string())];
         
-        let result = self.execute_query(&query).await?;
+        let result = self.execute_query_with_params(query, &params, None).await?;
         println!("🦀 [SimpleDB] Found {} detailed columns", result.rows.len());
         
         let mut columns = Vec::new();
@@ -712,11 +806,18 @@ impl SimpleDatabase {
                     serde_json::Value::Bool(b) => *b,
                     _ => false,
                 };
+                let udt_schema = match row.get(10) {
+                    Some(serde_json::Value::String(s)) => s.clone(),
+                    _ => "pg_catalog".to_string(),
+                };
+                let is_enum = matches!(row.get(11), Some(serde_json::Value::Bool(true)));
                 
                 columns.push(DetailedColumnInfo {
                     name,
                     data_type,
                     udt_name,
+                    udt_schema,
+                    is_enum,
                     is_nullable,
                     default_value,
                     character_maximum_length,
@@ -767,32 +868,33 @@ impl SimpleDatabase {
         println!("🦀 [SimpleDB] get_table_foreign_keys called for table: {}", table_name);
         
         let schema = schema_name.unwrap_or("public");
-        let query = format!(
-            "SELECT 
-                tc.constraint_name,
-                kcu.column_name,
-                ccu.table_name AS foreign_table_name,
-                ccu.column_name AS foreign_column_name
-            FROM 
-                information_schema.table_constraints AS tc 
-                JOIN information_schema.key_column_usage AS kcu
-                  ON tc.constraint_name = kcu.constraint_name
-                  AND tc.table_schema = kcu.table_schema
-                JOIN information_schema.constraint_column_usage AS ccu
-                  ON ccu.constraint_name = tc.constraint_name
-                  AND ccu.table_schema = tc.table_schema
-            WHERE tc.constraint_type = 'FOREIGN KEY' 
-                AND tc.table_schema = '{}' 
-                AND tc.table_name = '{}'",
-            schema, table_name
-        );
+        // One row per column pair, in key order; the referenced table may be in another schema
+        let query = "
+            SELECT 
+                c.conname::text AS constraint_name,
+                a.attname::text AS column_name,
+                rc.relname::text AS foreign_table_name,
+                ra.attname::text AS foreign_column_name,
+                rn.nspname::text AS foreign_schema_name
+            FROM pg_constraint c
+            JOIN pg_class t ON t.oid = c.conrelid
+            JOIN pg_namespace n ON n.oid = t.relnamespace
+            JOIN pg_class rc ON rc.oid = c.confrelid
+            JOIN pg_namespace rn ON rn.oid = rc.relnamespace
+            CROSS JOIN LATERAL unnest(c.conkey, c.confkey) WITH ORDINALITY AS k(attnum, foreign_attnum, position)
+            JOIN pg_attribute a ON a.attrelid = c.conrelid AND a.attnum = k.attnum
+            JOIN pg_attribute ra ON ra.attrelid = c.confrelid AND ra.attnum = k.foreign_attnum
+            WHERE c.contype = 'f' AND n.nspname = $1 AND t.relname = $2
+            ORDER BY c.conname, k.position
+        ";
+        let params = [Some(schema.to_string()), Some(table_name.to_string())];
         
-        let result = self.execute_query(&query).await?;
+        let result = self.execute_query_with_params(query, &params, None).await?;
         println!("🦀 [SimpleDB] Found {} foreign keys", result.rows.len());
         
         let mut foreign_keys = Vec::new();
         for row in result.rows {
-            if row.len() >= 4 {
+            if row.len() >= 5 {
                 let name = match &row[0] {
                     serde_json::Value::String(s) => s.clone(),
                     _ => continue,
@@ -809,11 +911,16 @@ impl SimpleDatabase {
                     serde_json::Value::String(s) => s.clone(),
                     _ => continue,
                 };
+                let referenced_schema = match &row[4] {
+                    serde_json::Value::String(s) => s.clone(),
+                    _ => continue,
+                };
                 
                 foreign_keys.push(ForeignKeyInfo {
                     name,
                     table_name: table_name.to_string(),
                     column_name,
+                    referenced_schema,
                     referenced_table,
                     referenced_column,
                 });
@@ -824,38 +931,18 @@ impl SimpleDatabase {
         Ok(foreign_keys)
     }
 
-    pub async fn update_row(&self, table_name: &str, schema_name: Option<&str>, primary_key_columns: &[String], primary_key_values: &[serde_json::Value], column_updates: &std::collections::HashMap<String, serde_json::Value>) -> Result<u64, String> {
-        println!("🦀 [SimpleDB] update_row called for table: {}.{}", schema_name.unwrap_or("public"), table_name);
-        
-        if primary_key_columns.is_empty() {
-            return Err("No primary key columns provided for update".to_string());
+    /// Column metadata used to bind edited values with their proper types
+    async fn table_columns(&self, table_name: &str, schema_name: Option<&str>) -> Result<Vec<DetailedColumnInfo>, String> {
+        let columns = self.get_detailed_table_columns(table_name, schema_name).await?;
+        if columns.is_empty() {
+            return Err(format!("Table {} not found", sql_builder::qualified_name(schema_name.unwrap_or("public"), table_name)));
         }
+        Ok(columns)
+    }
 
-        if column_updates.is_empty() {
-            return Err("No columns to update".to_string());
-        }
-
-        let schema = schema_name.unwrap_or("public");
-        
-        // Build SET clause
-        let set_clauses: Vec<String> = column_updates.iter().enumerate().map(|(i, (col, _))| {
-            format!("\"{}\" = ${}", col, primary_key_columns.len() + i + 1)
-        }).collect();
-        
-        // Build WHERE clause for primary key
-        let where_clauses: Vec<String> = primary_key_columns.iter().enumerate().map(|(i, col)| {
-            format!("\"{}\" = ${}", col, i + 1)
-        }).collect();
-
-        let query = format!(
-            "UPDATE \"{}\".\"{}\" SET {} WHERE {}",
-            schema,
-            table_name,
-            set_clauses.join(", "),
-            where_clauses.join(" AND ")
-        );
-
-        println!("🦀 [SimpleDB] Generated UPDATE query: {}", query);
+    /// Run a row edit on the connection and return the affected rows
+    async fn execute_dml(&self, statement: &DmlStatement, action: &str) -> Result<RowChangeResult, String> {
+        println!("🦀 [SimpleDB] Generated {} query: {}", action, statement.sql);
 
         let client_guard = self.client.lock().await;
         let client = match client_guard.as_ref() {
@@ -863,263 +950,153 @@ impl SimpleDatabase {
             None => return Err("Not connected to database".to_string()),
         };
 
-        // Convert values to strings for now (simplified approach)
-        let mut string_params: Vec<String> = Vec::new();
-        
-        // Add primary key values
-        for value in primary_key_values {
-            string_params.push(match value {
-                serde_json::Value::String(s) => s.clone(),
-                serde_json::Value::Number(n) => n.to_string(),
-                serde_json::Value::Bool(b) => b.to_string(),
-                serde_json::Value::Null => "NULL".to_string(),
-                _ => value.to_string(),
-            });
-        }
-        
-        // Add update values
-        for (_, value) in column_updates {
-            string_params.push(match value {
-                serde_json::Value::String(s) => s.clone(),
-                serde_json::Value::Number(n) => n.to_string(),
-                serde_json::Value::Bool(b) => b.to_string(),
-                serde_json::Value::Null => "NULL".to_string(),
-                _ => value.to_string(),
-            });
-        }
-
-        // For now, use a simple string replacement approach
-        let mut final_query = query;
-        for (i, param) in string_params.iter().enumerate() {
-            let placeholder = format!("${}", i + 1);
-            let replacement = if param == "NULL" {
-                "NULL".to_string()
-            } else {
-                format!("'{}'", param.replace("'", "''"))
-            };
-            final_query = final_query.replace(&placeholder, &replacement);
-        }
-
-        println!("🦀 [SimpleDB] Final UPDATE query: {}", final_query);
-
-        match client.execute(&final_query, &[]).await {
-            Ok(rows_affected) => {
-                println!("🦀 [SimpleDB] UPDATE successful, {} rows affected", rows_affected);
-                Ok(rows_affected)
+        let zone = *self.time_zone.lock().await;
+        match statement.execute(client, &zone).await {
+            Ok(result) => {
+                println!("🦀 [SimpleDB] {} successful, {} rows affected", action, result.rows_affected);
+                Ok(result)
             },
             Err(e) => {
-                println!("🦀 [SimpleDB] UPDATE failed: {}", e);
-                Err(format!("UPDATE failed: {}", e))
+                let message = db_error_message(&e);
+                println!("🦀 [SimpleDB] {} failed: {}", action, message);
+                Err(format!("{} failed: {}", action, message))
             }
         }
     }
 
-    pub async fn insert_row(&self, table_name: &str, schema_name: Option<&str>, column_values: &std::collections::HashMap<String, serde_json::Value>) -> Result<u64, String> {
-        println!("🦀 [SimpleDB] insert_row called for table: {}.{}", schema_name.unwrap_or("public"), table_name);
-        
-        if column_values.is_empty() {
-            return Err("No columns provided for insert".to_string());
-        }
-
-        let schema = schema_name.unwrap_or("public");
-        
-        let columns: Vec<String> = column_values.keys().map(|col| format!("\"{}\"", col)).collect();
-        let values: Vec<String> = column_values.values().map(|value| {
-            match value {
-                serde_json::Value::String(s) => format!("'{}'", s.replace("'", "''")),
-                serde_json::Value::Number(n) => n.to_string(),
-                serde_json::Value::Bool(b) => b.to_string(),
-                serde_json::Value::Null => "NULL".to_string(),
-                _ => format!("'{}'", value.to_string().replace("'", "''")),
-            }
-        }).collect();
-
-        let query = format!(
-            "INSERT INTO \"{}\".\"{}\" ({}) VALUES ({})",
-            schema,
-            table_name,
-            columns.join(", "),
-            values.join(", ")
-        );
-
-        println!("🦀 [SimpleDB] Generated INSERT query: {}", query);
-
-        let client_guard = self.client.lock().await;
-        let client = match client_guard.as_ref() {
-            Some(client) => client,
-            None => return Err("Not connected to database".to_string()),
+    /// When a checked edit matched no row, load the row as it is now and report the conflict
+    async fn check_conflict(&self, result: RowChangeResult, edit: &RowEdit) -> Result<RowChangeResult, String> {
+        let current_row = match &edit.current_row {
+            Some(current_row) if result.rows_affected == 0 && edit.statement.checked => current_row,
+            _ => return Ok(result),
         };
 
-        match client.execute(&query, &[]).await {
-            Ok(rows_affected) => {
-                println!("🦀 [SimpleDB] INSERT successful, {} rows affected", rows_affected);
-                Ok(rows_affected)
-            },
-            Err(e) => {
-                println!("🦀 [SimpleDB] INSERT failed: {}", e);
-                Err(format!("INSERT failed: {}", e))
-            }
-        }
+        let current = self.execute_dml(current_row, "SELECT").await?;
+        println!("🦀 [SimpleDB] Row changed since it was read, edit not applied");
+        Ok(RowChangeResult::conflict(current, &edit.check))
     }
 
-    pub async fn delete_row(&self, table_name: &str, schema_name: Option<&str>, primary_key_columns: &[String], primary_key_values: &[serde_json::Value]) -> Result<u64, String> {
-        println!("🦀 [SimpleDB] delete_row called for table: {}.{}", schema_name.unwrap_or("public"), table_name);
+    /// Build an update of one row. With a non-empty `check` the update only applies if the
+    /// row is unchanged since it was read; otherwise the result carries the conflict.
+    pub async fn build_update_row(&self, table_name: &str, schema_name: Option<&str>, primary_key_columns: &[String], primary_key_values: &[serde_json::Value], column_updates: &std::collections::HashMap<String, serde_json::Value>, check: &RowVersionCheck) -> Result<RowEdit, String> {
+        println!("🦀 [SimpleDB] update_row called for table: {}.{}", schema_name.unwrap_or("public"), table_name);
         
         if primary_key_columns.is_empty() {
-            return Err("No primary key columns provided for delete".to_string());
+            return Err("No key columns provided for update; use the table's row identifier".to_string());
         }
 
-        let schema = schema_name.unwrap_or("public");
-        
-        // Build WHERE clause for primary key
-        let where_clauses: Vec<String> = primary_key_columns.iter().enumerate().map(|(i, col)| {
-            let value_str = match &primary_key_values[i] {
-                serde_json::Value::String(s) => format!("'{}'", s.replace("'", "''")),
-                serde_json::Value::Number(n) => n.to_string(),
-                serde_json::Value::Bool(b) => b.to_string(),
-                serde_json::Value::Null => "NULL".to_string(),
-                _ => format!("'{}'", primary_key_values[i].to_string().replace("'", "''")),
-            };
-            format!("\"{}\" = {}", col, value_str)
-        }).collect();
-
-        let query = format!(
-            "DELETE FROM \"{}\".\"{}\" WHERE {}",
-            schema,
-            table_name,
-            where_clauses.join(" AND ")
-        );
-
-        println!("🦀 [SimpleDB] Generated DELETE query: {}", query);
-
-        let client_guard = self.client.lock().await;
-        let client = match client_guard.as_ref() {
-            Some(client) => client,
-            None => return Err("Not connected to database".to_string()),
-        };
-
-        match client.execute(&query, &[]).await {
-            Ok(rows_affected) => {
-                println!("🦀 [SimpleDB] DELETE successful, {} rows affected", rows_affected);
-                Ok(rows_affected)
-            },
-            Err(e) => {
-                println!("🦀 [SimpleDB] DELETE failed: {}", e);
-                Err(format!("DELETE failed: {}", e))
-            }
-        }
+        let columns = self.table_columns(table_name, schema_name).await?;
+        let updates: Vec<(String, serde_json::Value)> = column_updates.iter().map(|(k, v)| (k.clone(), v.clone())).collect();
+        let statement = sql_builder::build_update(schema_name.unwrap_or("public"), table_name, &columns, primary_key_columns, primary_key_values, &updates, check)?;
+        let current_row = sql_builder::build_current_row(schema_name.unwrap_or("public"), table_name, &columns, primary_key_columns, primary_key_values, check)?;
+        Ok(RowEdit { action: "UPDATE", statement, current_row: Some(current_row), check: check.clone() })
     }
 
-    pub async fn begin_transaction(&self) -> Result<(), String> {
-        println!("🦀 [SimpleDB] begin_transaction called");
+    pub async fn build_insert_row(&self, table_name: &str, schema_name: Option<&str>, column_values: &std::collections::HashMap<String, serde_json::Value>) -> Result<RowEdit, String> {
+        println!("🦀 [SimpleDB] insert_row called for table: {}.{}", schema_name.unwrap_or("public"), table_name);
         
-        let client_guard = self.client.lock().await;
-        let client = match client_guard.as_ref() {
-            Some(client) => client,
-            None => return Err("Not connected to database".to_string()),
-        };
+        let columns = self.table_columns(table_name, schema_name).await?;
+        let values: Vec<(String, serde_json::Value)> = column_values.iter().map(|(k, v)| (k.clone(), v.clone())).collect();
+        let statement = sql_builder::build_insert(schema_name.unwrap_or("public"), table_name, &columns, &values)?;
+        Ok(RowEdit { action: "INSERT", statement, current_row: None, check: RowVersionCheck::default() })
+    }
 
-        match client.execute("BEGIN", &[]).await {
-            Ok(_) => {
-                println!("🦀 [SimpleDB] Transaction started successfully");
-                Ok(())
-            },
-            Err(e) => {
-                println!("🦀 [SimpleDB] Failed to start transaction: {}", e);
-                Err(format!("Failed to start transaction: {}", e))
-            }
+    /// Build a delete of one row, subject to the same `check` as `build_update_row`
+    pub async fn build_delete_row(&self, table_name: &str, schema_name: Option<&str>, primary_key_columns: &[String], primary_key_values: &[serde_json::Value], check: &RowVersionCheck) -> Result<RowEdit, String> {
+        println!("🦀 [SimpleDB] delete_row called for table: {}.{}", schema_name.unwrap_or("public"), table_name);
+        
+        if primary_key_columns.is_empty() {
+            return Err("No key columns provided for delete; use the table's row identifier".to_string());
         }
+
+        let columns = self.table_columns(table_name, schema_name).await?;
+        let statement = sql_builder::build_delete(schema_name.unwrap_or("public"), table_name, &columns, primary_key_columns, primary_key_values, check)?;
+        let current_row = sql_builder::build_current_row(schema_name.unwrap_or("public"), table_name, &columns, primary_key_columns, primary_key_values, check)?;
+        Ok(RowEdit { action: "DELETE", statement, current_row: Some(current_row), check: check.clone() })
     }
 
-    pub async fn commit_transaction(&self) -> Result<(), String> {
-        println!("🦀 [SimpleDB] commit_transaction called");
-        
-        let client_guard = self.client.lock().await;
-        let client = match client_guard.as_ref() {
-            Some(client) => client,
-            None => return Err("Not connected to database".to_string()),
-        };
+    /// Run a row edit built by one of the `build_*_row` methods
+    pub async fn apply_row_edit(&self, edit: &RowEdit) -> Result<RowChangeResult, String> {
+        let result = self.execute_dml(&edit.statement, edit.action).await?;
+        self.check_conflict(result, edit).await
+    }
 
-        match client.execute("COMMIT", &[]).await {
-            Ok(_) => {
-                println!("🦀 [SimpleDB] Transaction committed successfully");
-                Ok(())
-            },
-            Err(e) => {
-                println!("🦀 [SimpleDB] Failed to commit transaction: {}", e);
-                Err(format!("Failed to commit transaction: {}", e))
-            }
+    /// Build the statements for `execute_transaction`; the column lookups need the connection
+    pub async fn build_transaction(&self, operations: &[serde_json::Value]) -> Result<Vec<RowEdit>, String> {
+        let mut edits = Vec::new();
+        for (index, operation) in operations.iter().enumerate() {
+            let edit = self.build_single_operation(operation).await
+                .map_err(|e| format!("Invalid operation {}: {}", index + 1, e))?;
+            edits.push(edit);
         }
+        Ok(edits)
     }
 
-    pub async fn rollback_transaction(&self) -> Result<(), String> {
-        println!("🦀 [SimpleDB] rollback_transaction called");
-        
-        let client_guard = self.client.lock().await;
-        let client = match client_guard.as_ref() {
+    /// Run all operations in one database transaction; any failure rolls back every one.
+    /// A checked operation whose row changed also rolls back: the results then end at that
+    /// operation, whose result carries the conflict.
+    pub async fn execute_transaction(&self, edits: &[RowEdit]) -> Result<Vec<RowChangeResult>, String> {
+        println!("🦀 [SimpleDB] execute_transaction called with {} operations", edits.len());
+
+        let mut client_guard = self.client.lock().await;
+        let client = match client_guard.as_mut() {
             Some(client) => client,
             None => return Err("Not connected to database".to_string()),
         };
 
-        match client.execute("ROLLBACK", &[]).await {
-            Ok(_) => {
-                println!("🦀 [SimpleDB] Transaction rolled back successfully");
-                Ok(())
-            },
-            Err(e) => {
-                println!("🦀 [SimpleDB] Failed to rollback transaction: {}", e);
-                Err(format!("Failed to rollback transaction: {}", e))
-            }
-        }
-    }
-
-    pub async fn execute_transaction(&self, operations: Vec<serde_json::Value>) -> Result<Vec<u64>, String> {
-        println!("🦀 [SimpleDB] execute_transaction called with {} operations", operations.len());
-        
-        // Start transaction
-        self.begin_transaction().await?;
+        let zone = *self.time_zone.lock().await;
+        let transaction = client.transaction().await
+            .map_err(|e| format!("Failed to start transaction: {}", e))?;
         
         let mut results = Vec::new();
         
         // Execute each operation
-        for (index, operation) in operations.iter().enumerate() {
-            println!("🦀 [SimpleDB] Executing operation {}: {:?}", index + 1, operation);
+        for (index, edit) in edits.iter().enumerate() {
+            println!("🦀 [SimpleDB] Executing operation {}: {}", index + 1, edit.statement.sql);
             
-            match self.execute_single_operation(operation).await {
-                Ok(rows_affected) => {
-                    results.push(rows_affected);
-                    println!("🦀 [SimpleDB] Operation {} completed, {} rows affected", index + 1, rows_affected);
+            match edit.statement.execute(&transaction, &zone).await {
+                Ok(result) if result.rows_affected == 0 && edit.statement.checked => {
+                    println!("🦀 [SimpleDB] Operation {} hit a concurrent change, rolling back transaction", index + 1);
+                    let current_row = edit.current_row.as_ref().ok_or("Checked operation has no row lookup")?;
+                    let current = current_row.execute(&transaction, &zone).await
+                        .map_err(|e| format!("Transaction rolled back; failed to load the changed row: {}", db_error_message(&e)))?;
+                    // Dropping the transaction rolls it back
+                    results.push(RowChangeResult::conflict(current, &edit.check));
+                    return Ok(results);
+                },
+                Ok(result) => {
+                    println!("🦀 [SimpleDB] Operation {} completed, {} rows affected", index + 1, result.rows_affected);
+                    results.push(result);
                 },
                 Err(e) => {
-                    println!("🦀 [SimpleDB] Operation {} failed: {}, rolling back transaction", index + 1, e);
-                    // Rollback on any error
-                    if let Err(rollback_err) = self.rollback_transaction().await {
-                        println!("🦀 [SimpleDB] Rollback also failed: {}", rollback_err);
-                        return Err(format!("Operation failed: {}. Rollback also failed: {}", e, rollback_err));
-                    }
-                    return Err(format!("Transaction rolled back due to error in operation {}: {}", index + 1, e));
+                    // Dropping the transaction rolls it back
+                    let message = db_error_message(&e);
+                    println!("🦀 [SimpleDB] Operation {} failed: {}, rolling back transaction", index + 1, message);
+                    return Err(format!("Transaction rolled back due to error in operation {}: {}", index + 1, message));
                 }
             }
         }
         
         // Commit transaction if all operations succeeded
-        match self.commit_transaction().await {
+        match transaction.commit().await {
             Ok(_) => {
-                println!("🦀 [SimpleDB] Transaction committed successfully with {} operations", operations.len());
+                println!("🦀 [SimpleDB] Transaction committed successfully with {} operations", edits.len());
                 Ok(results)
             },
             Err(e) => {
                 println!("🦀 [SimpleDB] Failed to commit transaction: {}", e);
-                // Try to rollback
-                if let Err(rollback_err) = self.rollback_transaction().await {
-                    println!("🦀 [SimpleDB] Rollback after commit failure also failed: {}", rollback_err);
-                }
                 Err(format!("Failed to commit transaction: {}", e))
             }
         }
     }
 
-    async fn execute_single_operation(&self, operation: &serde_json::Value) -> Result<u64, String> {
+    /// Optional `original_values` / `row_version` fields of a transaction operation
+    fn row_version_check(operation: &serde_json::Value) -> Result<RowVersionCheck, String> {
+        serde_json::from_value(operation.clone())
+            .map_err(|e| format!("Invalid 'original_values' or 'row_version': {}", e))
+    }
+
+    async fn build_single_operation(&self, operation: &serde_json::Value) -> Result<RowEdit, String> {
         let op_type = operation.get("type")
             .and_then(|v| v.as_str())
             .ok_or("Operation missing 'type' field")?;
@@ -1145,14 +1122,18 @@ impl SimpleDatabase {
                     .ok_or("Update operation missing 'primary_key_values'")?
                     .clone();
                 
-                let column_updates: std::collections::HashMap<String, serde_json::Value> = operation.get("column_updates")
+                let column_updates: Vec<(String, serde_json::Value)> = operation.get("column_updates")
                     .and_then(|v| v.as_object())
                     .ok_or("Update operation missing 'column_updates'")?
                     .iter()
                     .map(|(k, v)| (k.clone(), v.clone()))
                     .collect();
 
-                self.update_row(table_name, schema_name, &primary_key_columns, &primary_key_values, &column_updates).await
+                let check = Self::row_version_check(operation)?;
+                let columns = self.table_columns(table_name, schema_name).await?;
+                let statement = sql_builder::build_update(schema_name.unwrap_or("public"), table_name, &columns, &primary_key_columns, &primary_key_values, &column_updates, &check)?;
+                let current_row = sql_builder::build_current_row(schema_name.unwrap_or("public"), table_name, &columns, &primary_key_columns, &primary_key_values, &check)?;
+                Ok(RowEdit { action: "UPDATE", statement, current_row: Some(current_row), check })
             },
             "insert" => {
                 let table_name = operation.get("table_name")
@@ -1162,14 +1143,16 @@ impl SimpleDatabase {
                 let schema_name = operation.get("schema_name")
                     .and_then(|v| v.as_str());
                 
-                let column_values: std::collections::HashMap<String, serde_json::Value> = operation.get("column_values")
+                let column_values: Vec<(String, serde_json::Value)> = operation.get("column_values")
                     .and_then(|v| v.as_object())
                     .ok_or("Insert operation missing 'column_values'")?
                     .iter()
                     .map(|(k, v)| (k.clone(), v.clone()))
                     .collect();
 
-                self.insert_row(table_name, schema_name, &column_values).await
+                let columns = self.table_columns(table_name, schema_name).await?;
+                let statement = sql_builder::build_insert(schema_name.unwrap_or("public"), table_name, &columns, &column_values)?;
+                Ok(RowEdit { action: "INSERT", statement, current_row: None, check: RowVersionCheck::default() })
             },
             "delete" => {
                 let table_name = operation.get("table_name")
@@ -1191,7 +1174,11 @@ impl SimpleDatabase {
                     .ok_or("Delete operation missing 'primary_key_values'")?
                     .clone();
 
-                self.delete_row(table_name, schema_name, &primary_key_columns, &primary_key_values).await
+                let check = Self::row_version_check(operation)?;
+                let columns = self.table_columns(table_name, schema_name).await?;
+                let statement = sql_builder::build_delete(schema_name.unwrap_or("public"), table_name, &columns, &primary_key_columns, &primary_key_values, &check)?;
+                let current_row = sql_builder::build_current_row(schema_name.unwrap_or("public"), table_name, &columns, &primary_key_columns, &primary_key_values, &check)?;
+                Ok(RowEdit { action: "DELETE", statement, current_row: Some(current_row), check })
             },
             _ => Err(format!("Unknown operation type: {}", op_type))
         }
@@ -1475,67 +1462,19 @@ impl SimpleDatabase {
     }
 
     // Import functionality
-    pub async fn import_sql_data(&self, sql_content: &str, table_name: Option<&str>, schema_name: Option<&str>, truncate_before: bool) -> Result<(usize, Vec<String>), String> {
-        println!("🦀 [SimpleDB] import_sql_data called");
-        
-        let client_guard = self.client.lock().await;
-        let client = match client_guard.as_ref() {
-            Some(client) => client,
-            None => return Err("Not connected to database".to_string()),
-        };
-
-        let mut rows_imported = 0;
-        let mut errors = Vec::new();
-
-        // If truncate_before is true and table_name is provided, truncate the table
-        if truncate_before {
-            if let Some(table) = table_name {
-                let schema = schema_name.unwrap_or("public");
-                let truncate_query = format!("TRUNCATE TABLE \"{}\".\"{}\" RESTART IDENTITY CASCADE", schema, table);
-                
-                match client.execute(&truncate_query, &[]).await {
-                    Ok(_) => println!("🦀 [SimpleDB] Table truncated successfully"),
-                    Err(e) => {
-                        let error_msg = format!("Failed to truncate table: {}", e);
-                        println!("🦀 [SimpleDB] {}", error_msg);
-                        errors.push(error_msg);
-                    }
-                }
-            }
-        }
-
-        // Split SQL content into individual statements
-        let statements: Vec<&str> = sql_content
-            .split(';')
-            .map(|s| s.trim())
-            .filter(|s| !s.is_empty() && !s.starts_with("--"))
-            .collect();
-
-        println!("🦀 [SimpleDB] Found {} SQL statements to execute", statements.len());
-
-        // Execute each statement
-        for (i, statement) in statements.iter().enumerate() {
-            if statement.trim().is_empty() {
-                continue;
-            }
-
-            println!("🦀 [SimpleDB] Executing statement {}: {}", i + 1, &statement[..std::cmp::min(100, statement.len())]);
-            
-            match client.execute(*statement, &[]).await {
-                Ok(affected_rows) => {
-                    rows_imported += affected_rows as usize;
-                    println!("🦀 [SimpleDB] Statement {} executed successfully, {} rows affected", i + 1, affected_rows);
-                },
-                Err(e) => {
-                    let error_msg = format!("Statement {}: {}", i + 1, e);
-                    println!("🦀 [SimpleDB] Error executing statement {}: {}", i + 1, e);
-                    errors.push(error_msg);
-                }
-            }
-        }
+    /// Import a CSV or JSON file's contents into a table
+    pub async fn import_table_data(&self, bytes: &[u8], request: &TableImportRequest) -> Result<TableImportSummary, String> {
+        println!("🦀 [SimpleDB] import_table_data called for table: {}", request.table);
 
-        println!("🦀 [SimpleDB] Import completed: {} rows imported, {} errors", rows_imported, errors.len());
-        Ok((rows_imported, errors))
+        let client_guard = self.client.lock().await;
+        let client = client_guard.as_ref().ok_or("Not connected to database")?;
+
+        *self.statement_timeout.lock().await = None;
+        client
+            .batch_execute("SET statement_timeout = 0")
+            .await
+            .map_err(|e| format!("Failed to set statement timeout: {}", e))?;
+        table_import::import_table(client, bytes, request).await
     }
 
     pub async fn export_query_result_as_sql(&self, query_result: &SimpleQueryResult, table_name: &str, schema_name: Option<&str>) -> Result<String, String> {
@@ -1603,11 +1542,13 @@ impl SimpleDatabase {
                     ELSE false 
                 END as is_primary,
                 am.amname as index_type,
-                pg_relation_size(quote_ident(i.schemaname)||'.'||quote_ident(i.indexname)) as size_bytes
+                pg_relation_size(quote_ident(i.schemaname)||'.'||quote_ident(i.indexname)) as size_bytes,
+                pg_get_constraintdef(uc.oid) as constraint_definition
             FROM pg_indexes i
-            LEFT JOIN pg_class pc ON pc.relname = i.indexname
+            LEFT JOIN pg_class pc ON pc.oid = to_regclass(quote_ident(i.schemaname)||'.'||quote_ident(i.indexname))
             LEFT JOIN pg_am am ON pc.relam = am.oid
             LEFT JOIN pg_constraint c ON c.conname = i.indexname AND c.contype = 'p'
+            LEFT JOIN pg_constraint uc ON uc.conindid = pc.oid AND uc.contype IN ('u', 'x')
             WHERE i.schemaname = '{}' AND i.tablename = '{}'
             ORDER BY i.indexname",
             schema, table_name
@@ -1651,6 +1592,10 @@ impl SimpleDatabase {
                     serde_json::Value::Number(n) => n.as_u64(),
                     _ => None,
                 };
+                let constraint_definition = match row.get(8) {
+                    Some(serde_json::Value::String(s)) => Some(s.clone()),
+                    _ => None,
+                };
                 
                 // Extract columns from definition
                 let columns = extract_columns_from_index_definition(&definition);
@@ -1665,6 +1610,7 @@ impl SimpleDatabase {
                     index_type,
                     definition,
                     size_bytes,
+                    constraint_definition,
                 });
             }
         }
@@ -1738,6 +1684,10 @@ impl SimpleDatabase {
                     serde_json::Value::Number(n) => n.as_u64(),
                     _ => None,
                 };
+                let constraint_definition = match row.get(8) {
+                    Some(serde_json::Value::String(s)) => Some(s.clone()),
+                    _ => None,
+                };
                 
                 // Extract columns from definition
                 let columns = extract_columns_from_index_definition(&definition);
@@ -1752,6 +1702,7 @@ impl SimpleDatabase {
                     index_type,
                     definition,
                     size_bytes,
+                    constraint_definition,
                 });
             }
         }
@@ -1960,16 +1911,15 @@ impl SimpleDatabase {
         Ok(materialized_views)
     }
 
-    pub async fn create_index(&self, options: &CreateIndexOptions) -> Result<String, String> {
-        println!("🦀 [SimpleDB] create_index called for index: {}", options.name);
-        
+    /// The `CREATE INDEX` statement `create_index` runs
+    pub fn create_index_sql(options: &CreateIndexOptions) -> String {
         let schema = options.schema_name.as_deref().unwrap_or("public");
         let unique_clause = if options.is_unique { "UNIQUE " } else { "" };
         let index_type = options.index_type.as_deref().unwrap_or("btree");
         let columns_clause = options.columns.join(", ");
         let where_clause = options.where_clause.as_deref().map(|w| format!(" WHERE {}", w)).unwrap_or_default();
         
-        let query = format!(
+        format!(
             "CREATE {}INDEX {} ON \"{}\".\"{}\" USING {} ({}){}",
             unique_clause,
             options.name,
@@ -1978,8 +1928,13 @@ impl SimpleDatabase {
             index_type,
             columns_clause,
             where_clause
-        );
+        )
+    }
+
+    pub async fn create_index(&self, options: &CreateIndexOptions) -> Result<String, String> {
+        println!("🦀 [SimpleDB] create_index called for index: {}", options.name);
         
+        let query = Self::create_index_sql(options);
         println!("🦀 [SimpleDB] Executing create index query: {}", query);
         
         match self.execute_query(&query).await {
@@ -1995,11 +1950,15 @@ impl SimpleDatabase {
         }
     }
 
+    /// The `DROP INDEX` statement `drop_index` runs
+    pub fn drop_index_sql(index_name: &str, schema_name: Option<&str>) -> String {
+        format!("DROP INDEX IF EXISTS \"{}\".\"{}\"", schema_name.unwrap_or("public"), index_name)
+    }
+
     pub async fn drop_index(&self, index_name: &str, schema_name: Option<&str>) -> Result<String, String> {
         println!("🦀 [SimpleDB] drop_index called for index: {}", index_name);
         
-        let schema = schema_name.unwrap_or("public");
-        let query = format!("DROP INDEX IF EXISTS \"{}\".\"{}\"", schema, index_name);
+        let query = Self::drop_index_sql(index_name, schema_name);
         
         println!("🦀 [SimpleDB] Executing drop index query: {}", query);
         
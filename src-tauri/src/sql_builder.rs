@@ -0,0 +1,858 @@
+//! Parameterized INSERT/UPDATE/DELETE statements for editing table rows.
+//!
+//! Values arrive as JSON from the grid. Each one is bound as a real parameter of the
+//! column's type; types without a native encoding here are sent as text and cast
+//! on the server, so no value is ever spliced into the SQL.
+
+use crate::simple_db::{DetailedColumnInfo, IndexInfo};
+use crate::value_codec::{self, ColumnType, SessionTimeZone};
+use base64::{engine::general_purpose, Engine as _};
+use bytes::BytesMut;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::error::Error;
+use tokio_postgres::types::{to_sql_checked, IsNull, Kind, ToSql, Type};
+use tokio_postgres::GenericClient;
+
+/// Column types bound as themselves; any other type is bound as text and cast
+const NATIVE_TYPES: &[&str] = &[
+    "bool", "int2", "int4", "int8", "float4", "float8", "text", "varchar", "bpchar", "name",
+    "json", "jsonb", "uuid", "bytea",
+];
+
+/// Column types without an `=` operator; original values are compared as text
+const TEXT_COMPARED_TYPES: &[&str] = &["xml", "point", "line", "lseg", "box", "path", "polygon", "circle"];
+
+/// Quote an identifier, doubling any embedded double quotes
+pub fn quote_ident(ident: &str) -> String {
+    format!("\"{}\"", ident.replace('"', "\"\""))
+}
+
+/// `"schema"."name"` with both parts quoted
+pub fn qualified_name(schema: &str, name: &str) -> String {
+    format!("{}.{}", quote_ident(schema), quote_ident(name))
+}
+
+/// Quote a string literal, doubling any embedded single quotes
+pub fn quote_literal(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+/// System column that locates a row physically; the identifier of last resort
+pub const CTID: &str = "ctid";
+
+/// How the rows of a table are identified when editing them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RowIdentifierKind {
+    PrimaryKey,
+    UniqueIndex,
+    Ctid,
+}
+
+/// Columns to send as `primary_key_columns` / `primary_key_values` when editing a row
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RowIdentifier {
+    pub kind: RowIdentifierKind,
+    pub columns: Vec<String>,
+    /// The unique index the columns come from
+    pub index_name: Option<String>,
+    /// Set when rows are identified by `ctid`, which changes whenever a row is rewritten
+    pub warning: Option<String>,
+}
+
+/// Pick the best row identifier: the primary key, then the narrowest unique index over
+/// NOT NULL columns, then `ctid` when `allow_ctid` (plain tables only).
+pub fn choose_row_identifier(columns: &[DetailedColumnInfo], indexes: &[IndexInfo], allow_ctid: bool) -> Option<RowIdentifier> {
+    let primary_key: Vec<String> = columns
+        .iter()
+        .filter(|column| column.is_primary_key)
+        .map(|column| column.name.clone())
+        .collect();
+    if !primary_key.is_empty() {
+        return Some(RowIdentifier {
+            kind: RowIdentifierKind::PrimaryKey,
+            columns: primary_key,
+            index_name: None,
+            warning: None,
+        });
+    }
+
+    let unique_index = indexes
+        .iter()
+        .filter(|index| index.is_unique && !index.is_primary)
+        .filter_map(|index| unique_index_columns(index, columns).map(|key| (index, key)))
+        .min_by(|(a, a_key), (b, b_key)| a_key.len().cmp(&b_key.len()).then_with(|| a.name.cmp(&b.name)));
+    if let Some((index, key)) = unique_index {
+        return Some(RowIdentifier {
+            kind: RowIdentifierKind::UniqueIndex,
+            columns: key,
+            index_name: Some(index.name.clone()),
+            warning: None,
+        });
+    }
+
+    allow_ctid.then(|| RowIdentifier {
+        kind: RowIdentifierKind::Ctid,
+        columns: vec![CTID.to_string()],
+        index_name: None,
+        warning: Some(
+            "This table has no primary key or unique index on NOT NULL columns. Rows are \
+             identified by ctid, which changes when a row is updated or the table is rewritten; \
+             reload the data before editing a row again."
+                .to_string(),
+        ),
+    })
+}
+
+/// Key columns of a unique index usable as a row identifier: a full (not partial) index
+/// over plain NOT NULL columns, no expressions
+fn unique_index_columns(index: &IndexInfo, columns: &[DetailedColumnInfo]) -> Option<Vec<String>> {
+    if !index.definition.starts_with("CREATE UNIQUE INDEX") || index.definition.contains(" WHERE ") {
+        return None;
+    }
+    let key: Vec<String> = index.columns.iter().map(|entry| index_column_name(entry)).collect::<Option<_>>()?;
+    let usable = !key.is_empty()
+        && key.iter().all(|name| columns.iter().any(|column| &column.name == name && !column.is_nullable));
+    usable.then_some(key)
+}
+
+/// Column name of an index entry as printed by `pg_get_indexdef`, `None` for anything
+/// but a bare or quoted identifier
+fn index_column_name(entry: &str) -> Option<String> {
+    let entry = entry.trim();
+    if let Some(quoted) = entry.strip_prefix('"').and_then(|rest| rest.strip_suffix('"')) {
+        // A lone quote inside means the entry is more than one identifier
+        return (!quoted.replace("\"\"", "").contains('"')).then(|| quoted.replace("\"\"", "\""));
+    }
+    let plain = !entry.is_empty()
+        && entry.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_' || c == '$')
+        && !entry.starts_with(|c: char| c.is_ascii_digit());
+    plain.then(|| entry.to_string())
+}
+
+/// Optimistic concurrency check for an edit: the row must still be as the user read it
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RowVersionCheck {
+    /// Column values as they were read; only these columns are compared
+    #[serde(default)]
+    pub original_values: Option<HashMap<String, Value>>,
+    /// The row's `xmin` when it was read
+    #[serde(default)]
+    pub row_version: Option<String>,
+}
+
+impl RowVersionCheck {
+    pub fn is_empty(&self) -> bool {
+        self.original_values.as_ref().is_none_or(|values| values.is_empty()) && self.row_version.is_none()
+    }
+}
+
+/// A statement with its parameters, ready to run
+#[derive(Debug, Clone)]
+pub struct DmlStatement {
+    pub sql: String,
+    pub params: Vec<Value>,
+    /// The statement only matches if a `RowVersionCheck` passes
+    pub checked: bool,
+    /// The last returned column is the row's `xmin`
+    pub returns_row_version: bool,
+}
+
+/// Outcome of a row edit, including the rows returned by `RETURNING *`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RowChangeResult {
+    pub rows_affected: u64,
+    pub columns: Vec<String>,
+    pub column_types: Vec<ColumnType>,
+    pub rows: Vec<Vec<Value>>,
+    /// New `xmin` of the edited row, when the edit was checked by row version
+    pub row_version: Option<String>,
+    /// Set instead of applying the edit when the row changed since it was read
+    pub conflict: Option<RowConflict>,
+}
+
+/// The row as it currently is on the server, returned when a checked edit did not apply
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RowConflict {
+    /// `None` when the row has been deleted
+    pub current_values: Option<Vec<Value>>,
+    pub current_row_version: Option<String>,
+    /// Columns of `original_values` whose server value differs
+    pub changed_columns: Vec<String>,
+}
+
+impl RowChangeResult {
+    /// Describe a conflict from the current row, as loaded by `build_current_row`
+    pub fn conflict(current: RowChangeResult, check: &RowVersionCheck) -> Self {
+        let current_values = current.rows.into_iter().next();
+        let changed_columns = match (&current_values, &check.original_values) {
+            (Some(values), Some(originals)) => {
+                let mut changed: Vec<String> = originals
+                    .iter()
+                    .filter(|(column, original)| {
+                        current
+                            .columns
+                            .iter()
+                            .position(|name| name == *column)
+                            .is_none_or(|index| values.get(index) != Some(*original))
+                    })
+                    .map(|(column, _)| column.clone())
+                    .collect();
+                changed.sort();
+                changed
+            }
+            _ => Vec::new(),
+        };
+
+        Self {
+            rows_affected: 0,
+            columns: current.columns,
+            column_types: current.column_types,
+            rows: Vec::new(),
+            row_version: None,
+            conflict: Some(RowConflict {
+                current_values,
+                current_row_version: current.row_version,
+                changed_columns,
+            }),
+        }
+    }
+}
+
+impl DmlStatement {
+    /// Run the statement on a client or inside a transaction
+    pub async fn execute<C: GenericClient>(&self, client: &C, zone: &SessionTimeZone) -> Result<RowChangeResult, tokio_postgres::Error> {
+        let statement = client.prepare(&self.sql).await?;
+        let params: Vec<JsonParam> = self.params.iter().map(JsonParam).collect();
+        let param_refs: Vec<&(dyn ToSql + Sync)> = params.iter().map(|p| p as &(dyn ToSql + Sync)).collect();
+        let rows = client.query(&statement, &param_refs).await?;
+
+        let mut columns = statement.columns();
+        let mut rows: Vec<Vec<Value>> = rows.iter().map(|row| value_codec::decode_row(row, zone)).collect();
+        let mut row_version = None;
+        if self.returns_row_version {
+            columns = &columns[..columns.len() - 1];
+            for row in rows.iter_mut() {
+                let version = row.pop();
+                if row_version.is_none() {
+                    row_version = version.and_then(|v| v.as_str().map(str::to_string));
+                }
+            }
+        }
+
+        Ok(RowChangeResult {
+            rows_affected: rows.len() as u64,
+            columns: columns.iter().map(|c| c.name().to_string()).collect(),
+            column_types: value_codec::column_types(columns),
+            rows,
+            row_version,
+            conflict: None,
+        })
+    }
+}
+
+/// Collects parameters and hands out their placeholders
+struct Params<'a> {
+    columns: &'a [DetailedColumnInfo],
+    values: Vec<Value>,
+}
+
+impl<'a> Params<'a> {
+    fn new(columns: &'a [DetailedColumnInfo]) -> Self {
+        Self { columns, values: Vec::new() }
+    }
+
+    fn column(&self, name: &str) -> Result<&'a DetailedColumnInfo, String> {
+        self.columns
+            .iter()
+            .find(|column| column.name == name)
+            .ok_or_else(|| format!("Column '{}' does not exist", name))
+    }
+
+    /// Add a value whose type the server infers and return its placeholder
+    fn push_untyped(&mut self, value: &Value) -> String {
+        self.values.push(value.clone());
+        format!("${}", self.values.len())
+    }
+
+    /// Add a value for `column` and return its placeholder, cast when needed
+    fn push(&mut self, column: &str, value: &Value) -> Result<String, String> {
+        let column = self.column(column)?;
+        let placeholder = self.push_untyped(value);
+
+        // Enums bind natively; other user-defined types (hstore, geometry, citext, ...)
+        // only have a text input, so they are cast through text like everything else
+        if NATIVE_TYPES.contains(&column.udt_name.as_str()) || column.is_enum {
+            Ok(placeholder)
+        } else if column.data_type == "USER-DEFINED" {
+            Ok(format!("{}::text::{}", placeholder, qualified_name(&column.udt_schema, &column.udt_name)))
+        } else {
+            Ok(format!("{}::text::{}", placeholder, quote_ident(&column.udt_name)))
+        }
+    }
+
+    /// `"col" = $n`, or `"col" IS NULL` for a NULL key value
+    fn key_condition(&mut self, column: &str, value: &Value) -> Result<String, String> {
+        if column == CTID {
+            if value.is_null() {
+                return Err("A ctid value is required".to_string());
+            }
+            return Ok(format!("ctid = {}::text::tid", self.push_untyped(value)));
+        }
+        if value.is_null() {
+            self.column(column)?;
+            return Ok(format!("{} IS NULL", quote_ident(column)));
+        }
+        Ok(format!("{} = {}", quote_ident(column), self.push(column, value)?))
+    }
+
+    /// Like `key_condition`, for types that may lack an `=` operator
+    fn original_value_condition(&mut self, column: &str, value: &Value) -> Result<String, String> {
+        let udt_name = self.column(column)?.udt_name.as_str();
+        if value.is_null() {
+            return Ok(format!("{} IS NULL", quote_ident(column)));
+        }
+        if udt_name == "json" {
+            return Ok(format!("{}::jsonb = {}", quote_ident(column), self.push(column, value)?));
+        }
+        if TEXT_COMPARED_TYPES.contains(&udt_name) {
+            return Ok(format!("{}::text = ({})::text", quote_ident(column), self.push(column, value)?));
+        }
+        self.key_condition(column, value)
+    }
+
+    fn where_clause(&mut self, key_columns: &[String], key_values: &[Value], check: &RowVersionCheck) -> Result<String, String> {
+        if key_columns.is_empty() {
+            return Err("No key columns provided".to_string());
+        }
+        if key_columns.len() != key_values.len() {
+            return Err(format!(
+                "Expected {} key values, got {}",
+                key_columns.len(),
+                key_values.len()
+            ));
+        }
+        let mut conditions = key_columns
+            .iter()
+            .zip(key_values)
+            .map(|(column, value)| self.key_condition(column, value))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if let Some(originals) = &check.original_values {
+            // Sorted so the statement text is stable
+            let mut originals: Vec<_> = originals.iter().collect();
+            originals.sort_by(|a, b| a.0.cmp(b.0));
+            for (column, value) in originals {
+                conditions.push(self.original_value_condition(column, value)?);
+            }
+        }
+        if let Some(version) = &check.row_version {
+            let placeholder = self.push_untyped(&Value::String(version.clone()));
+            conditions.push(format!("xmin::text = {}", placeholder));
+        }
+        Ok(conditions.join(" AND "))
+    }
+}
+
+/// Columns returned for an edited row: the new `ctid` first when rows are keyed by it,
+/// and the row version last when the edit is checked by it
+fn row_select_list(key_columns: &[String], check: &RowVersionCheck) -> String {
+    let mut list = String::new();
+    if key_columns.iter().any(|column| column == CTID) {
+        list.push_str("ctid, ");
+    }
+    list.push('*');
+    if check.row_version.is_some() {
+        list.push_str(", xmin::text");
+    }
+    list
+}
+
+pub fn build_update(
+    schema: &str,
+    table: &str,
+    columns: &[DetailedColumnInfo],
+    key_columns: &[String],
+    key_values: &[Value],
+    updates: &[(String, Value)],
+    check: &RowVersionCheck,
+) -> Result<DmlStatement, String> {
+    if updates.is_empty() {
+        return Err("No columns to update".to_string());
+    }
+
+    let mut params = Params::new(columns);
+    let set_clauses = updates
+        .iter()
+        .map(|(column, value)| Ok(format!("{} = {}", quote_ident(column), params.push(column, value)?)))
+        .collect::<Result<Vec<_>, String>>()?;
+    let where_clause = params.where_clause(key_columns, key_values, check)?;
+
+    Ok(DmlStatement {
+        sql: format!(
+            "UPDATE {} SET {} WHERE {} RETURNING {}",
+            qualified_name(schema, table),
+            set_clauses.join(", "),
+            where_clause,
+            row_select_list(key_columns, check)
+        ),
+        params: params.values,
+        checked: !check.is_empty(),
+        returns_row_version: check.row_version.is_some(),
+    })
+}
+
+pub fn build_insert(
+    schema: &str,
+    table: &str,
+    columns: &[DetailedColumnInfo],
+    values: &[(String, Value)],
+) -> Result<DmlStatement, String> {
+    if values.is_empty() {
+        return Err("No columns provided for insert".to_string());
+    }
+
+    let mut params = Params::new(columns);
+    let names: Vec<String> = values.iter().map(|(column, _)| quote_ident(column)).collect();
+    let placeholders = values
+        .iter()
+        .map(|(column, value)| params.push(column, value))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(DmlStatement {
+        sql: format!(
+            "INSERT INTO {} ({}) VALUES ({}) RETURNING *",
+            qualified_name(schema, table),
+            names.join(", "),
+            placeholders.join(", ")
+        ),
+        params: params.values,
+        checked: false,
+        returns_row_version: false,
+    })
+}
+
+pub fn build_delete(
+    schema: &str,
+    table: &str,
+    columns: &[DetailedColumnInfo],
+    key_columns: &[String],
+    key_values: &[Value],
+    check: &RowVersionCheck,
+) -> Result<DmlStatement, String> {
+    let mut params = Params::new(columns);
+    let where_clause = params.where_clause(key_columns, key_values, check)?;
+
+    Ok(DmlStatement {
+        sql: format!(
+            "DELETE FROM {} WHERE {} RETURNING {}",
+            qualified_name(schema, table),
+            where_clause,
+            row_select_list(key_columns, check)
+        ),
+        params: params.values,
+        checked: !check.is_empty(),
+        returns_row_version: check.row_version.is_some(),
+    })
+}
+
+/// Load a row by key as it is now, to report a conflict. Includes its `xmin` when
+/// the edit was checked by row version.
+pub fn build_current_row(
+    schema: &str,
+    table: &str,
+    columns: &[DetailedColumnInfo],
+    key_columns: &[String],
+    key_values: &[Value],
+    check: &RowVersionCheck,
+) -> Result<DmlStatement, String> {
+    let mut params = Params::new(columns);
+    let where_clause = params.where_clause(key_columns, key_values, &RowVersionCheck::default())?;
+    Ok(DmlStatement {
+        sql: format!(
+            "SELECT {} FROM {} WHERE {}",
+            row_select_list(key_columns, check),
+            qualified_name(schema, table),
+            where_clause
+        ),
+        params: params.values,
+        checked: false,
+        returns_row_version: check.row_version.is_some(),
+    })
+}
+
+/// A JSON value bound as the parameter type the server inferred
+#[derive(Debug)]
+struct JsonParam<'a>(&'a Value);
+
+type BoxError = Box<dyn Error + Sync + Send>;
+
+impl ToSql for JsonParam<'_> {
+    fn to_sql(&self, ty: &Type, out: &mut BytesMut) -> Result<IsNull, BoxError> {
+        let value = self.0;
+        if value.is_null() {
+            return Ok(IsNull::Yes);
+        }
+
+        match ty.kind() {
+            Kind::Domain(base) => return self.to_sql(base, out),
+            Kind::Enum(_) => return text_form(value).as_str().to_sql(ty, out),
+            _ => {}
+        }
+
+        match *ty {
+            Type::BOOL => match value {
+                Value::Bool(b) => b.to_sql(ty, out),
+                Value::String(s) => match s.to_lowercase().as_str() {
+                    "true" | "t" | "yes" | "y" | "on" | "1" => true.to_sql(ty, out),
+                    "false" | "f" | "no" | "n" | "off" | "0" => false.to_sql(ty, out),
+                    _ => Err(format!("invalid boolean '{}'", s).into()),
+                },
+                _ => Err(format!("cannot bind {} as boolean", value).into()),
+            },
+            Type::INT2 => i16::try_from(integer(value)?)?.to_sql(ty, out),
+            Type::INT4 => i32::try_from(integer(value)?)?.to_sql(ty, out),
+            Type::INT8 => integer(value)?.to_sql(ty, out),
+            Type::FLOAT4 => (float(value)? as f32).to_sql(ty, out),
+            Type::FLOAT8 => float(value)?.to_sql(ty, out),
+            Type::JSON | Type::JSONB => match value {
+                // Editors send JSON columns back as text
+                Value::String(s) => serde_json::from_str::<Value>(s)
+                    .unwrap_or_else(|_| value.clone())
+                    .to_sql(ty, out),
+                _ => value.to_sql(ty, out),
+            },
+            Type::UUID => uuid::Uuid::parse_str(&text_form(value))?.to_sql(ty, out),
+            Type::BYTEA => bytea(value)?.to_sql(ty, out),
+            _ if <&str as ToSql>::accepts(ty) => text_form(value).as_str().to_sql(ty, out),
+            _ => Err(format!("cannot bind a value to type {}", ty).into()),
+        }
+    }
+
+    fn accepts(_ty: &Type) -> bool {
+        true
+    }
+
+    to_sql_checked!();
+}
+
+fn integer(value: &Value) -> Result<i64, BoxError> {
+    match value {
+        Value::Number(n) => n
+            .as_i64()
+            .ok_or_else(|| format!("{} is not an integer", n).into()),
+        Value::String(s) => Ok(s.trim().parse()?),
+        _ => Err(format!("cannot bind {} as an integer", value).into()),
+    }
+}
+
+fn float(value: &Value) -> Result<f64, BoxError> {
+    match value {
+        Value::Number(n) => n
+            .as_f64()
+            .ok_or_else(|| format!("{} is not a number", n).into()),
+        Value::String(s) => Ok(s.trim().parse()?),
+        _ => Err(format!("cannot bind {} as a number", value).into()),
+    }
+}
+
+/// bytea values are shown base64-encoded; `\x` hex input is accepted too
+fn bytea(value: &Value) -> Result<Vec<u8>, BoxError> {
+    let text = text_form(value);
+    match text.strip_prefix("\\x") {
+        Some(hex) => (0..hex.len())
+            .step_by(2)
+            .map(|i| {
+                hex.get(i..i + 2)
+                    .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+                    .ok_or_else(|| "invalid hex in bytea value".into())
+            })
+            .collect(),
+        None => Ok(general_purpose::STANDARD.decode(text)?),
+    }
+}
+
+/// Text input form of a value; JSON arrays become PostgreSQL array literals
+pub(crate) fn text_form(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Array(items) => {
+            let elements: Vec<String> = items
+                .iter()
+                .map(|item| match item {
+                    Value::Null => "NULL".to_string(),
+                    Value::Array(_) => text_form(item),
+                    _ => format!(
+                        "\"{}\"",
+                        text_form(item).replace('\\', "\\\\").replace('"', "\\\"")
+                    ),
+                })
+                .collect();
+            format!("{{{}}}", elements.join(","))
+        }
+        _ => value.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn column(name: &str, data_type: &str, udt_name: &str) -> DetailedColumnInfo {
+        DetailedColumnInfo {
+            name: name.to_string(),
+            data_type: data_type.to_string(),
+            udt_name: udt_name.to_string(),
+            udt_schema: "pg_catalog".to_string(),
+            is_enum: false,
+            is_nullable: true,
+            default_value: None,
+            character_maximum_length: None,
+            numeric_precision: None,
+            numeric_scale: None,
+            is_primary_key: false,
+            is_foreign_key: false,
+        }
+    }
+
+    fn columns() -> Vec<DetailedColumnInfo> {
+        vec![
+            column("id", "integer", "int4"),
+            column("na\"me", "text", "text"),
+            column("price", "numeric", "numeric"),
+            column("tags", "ARRAY", "_text"),
+            DetailedColumnInfo { udt_schema: "public".to_string(), is_enum: true, ..column("mood", "USER-DEFINED", "mood") },
+            DetailedColumnInfo { udt_schema: "ext".to_string(), ..column("attrs", "USER-DEFINED", "hstore") },
+        ]
+    }
+
+    #[test]
+    fn test_quote_ident_escapes_double_quotes() {
+        assert_eq!(quote_ident("plain"), "\"plain\"");
+        assert_eq!(quote_ident("we\"ird"), "\"we\"\"ird\"");
+        assert_eq!(qualified_name("my schema", "t"), "\"my schema\".\"t\"");
+    }
+
+    #[test]
+    fn test_update_binds_every_value() {
+        let statement = build_update(
+            "public",
+            "items",
+            &columns(),
+            &["id".to_string()],
+            &[json!(10)],
+            &[
+                ("na\"me".to_string(), json!("costs $1")),
+                ("price".to_string(), json!("19.99")),
+                ("mood".to_string(), json!("happy")),
+                ("attrs".to_string(), json!("a=>1")),
+            ],
+            &RowVersionCheck::default(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            statement.sql,
+            "UPDATE \"public\".\"items\" SET \"na\"\"me\" = $1, \"price\" = $2::text::\"numeric\", \
+             \"mood\" = $3, \"attrs\" = $4::text::\"ext\".\"hstore\" WHERE \"id\" = $5 RETURNING *"
+        );
+        assert_eq!(statement.params, vec![json!("costs $1"), json!("19.99"), json!("happy"), json!("a=>1"), json!(10)]);
+    }
+
+    #[test]
+    fn test_insert_and_delete() {
+        let statement = build_insert(
+            "public",
+            "items",
+            &columns(),
+            &[("id".to_string(), json!(1)), ("tags".to_string(), json!(["a", null]))],
+        )
+        .unwrap();
+        assert_eq!(
+            statement.sql,
+            "INSERT INTO \"public\".\"items\" (\"id\", \"tags\") VALUES ($1, $2::text::\"_text\") RETURNING *"
+        );
+
+        let statement = build_delete(
+            "public",
+            "items",
+            &columns(),
+            &["id".to_string(), "na\"me".to_string()],
+            &[json!(1), Value::Null],
+            &RowVersionCheck::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            statement.sql,
+            "DELETE FROM \"public\".\"items\" WHERE \"id\" = $1 AND \"na\"\"me\" IS NULL RETURNING *"
+        );
+        assert_eq!(statement.params, vec![json!(1)]);
+    }
+
+    #[test]
+    fn test_rejects_unknown_columns_and_mismatched_keys() {
+        let err = build_insert("public", "items", &columns(), &[("nope".to_string(), json!(1))]).unwrap_err();
+        assert!(err.contains("nope"));
+
+        let err = build_delete("public", "items", &columns(), &["id".to_string()], &[], &RowVersionCheck::default())
+            .unwrap_err();
+        assert!(err.contains("key values"));
+    }
+
+    #[test]
+    fn test_checked_update_matches_original_values_and_version() {
+        let check = RowVersionCheck {
+            original_values: Some(HashMap::from([
+                ("price".to_string(), json!("19.99")),
+                ("na\"me".to_string(), Value::Null),
+            ])),
+            row_version: Some("1234".to_string()),
+        };
+        let statement = build_update(
+            "public",
+            "items",
+            &columns(),
+            &["id".to_string()],
+            &[json!(10)],
+            &[("price".to_string(), json!("20.00"))],
+            &check,
+        )
+        .unwrap();
+
+        assert_eq!(
+            statement.sql,
+            "UPDATE \"public\".\"items\" SET \"price\" = $1::text::\"numeric\" WHERE \"id\" = $2 \
+             AND \"na\"\"me\" IS NULL AND \"price\" = $3::text::\"numeric\" AND xmin::text = $4 \
+             RETURNING *, xmin::text"
+        );
+        assert_eq!(statement.params, vec![json!("20.00"), json!(10), json!("19.99"), json!("1234")]);
+        assert!(statement.checked);
+        assert!(statement.returns_row_version);
+    }
+
+    #[test]
+    fn test_conflict_lists_changed_columns() {
+        let check = RowVersionCheck {
+            original_values: Some(HashMap::from([
+                ("id".to_string(), json!(10)),
+                ("price".to_string(), json!("19.99")),
+            ])),
+            row_version: None,
+        };
+        let current = RowChangeResult {
+            rows_affected: 1,
+            columns: vec!["id".to_string(), "price".to_string()],
+            column_types: Vec::new(),
+            rows: vec![vec![json!(10), json!("24.99")]],
+            row_version: Some("99".to_string()),
+            conflict: None,
+        };
+
+        let result = RowChangeResult::conflict(current.clone(), &check);
+        assert_eq!(result.rows_affected, 0);
+        let conflict = result.conflict.unwrap();
+        assert_eq!(conflict.changed_columns, vec!["price".to_string()]);
+        assert_eq!(conflict.current_values, Some(vec![json!(10), json!("24.99")]));
+        assert_eq!(conflict.current_row_version, Some("99".to_string()));
+
+        // A deleted row has no current values
+        let deleted = RowChangeResult { rows: Vec::new(), ..current };
+        assert!(RowChangeResult::conflict(deleted, &check).conflict.unwrap().current_values.is_none());
+    }
+
+    fn unique_index(name: &str, definition: &str, columns: &[&str]) -> IndexInfo {
+        IndexInfo {
+            name: name.to_string(),
+            table_name: "items".to_string(),
+            schema_name: "public".to_string(),
+            columns: columns.iter().map(|c| c.to_string()).collect(),
+            is_unique: true,
+            is_primary: false,
+            index_type: "btree".to_string(),
+            definition: definition.to_string(),
+            size_bytes: None,
+            constraint_definition: None,
+        }
+    }
+
+    #[test]
+    fn test_row_identifier_prefers_primary_key_then_unique_index() {
+        let mut table = columns();
+        table[0].is_nullable = false;
+        table[1].is_nullable = false;
+        let indexes = vec![
+            unique_index("items_name_key", "CREATE UNIQUE INDEX items_name_key ON public.items USING btree (\"na\"\"me\")", &["\"na\"\"me\""]),
+            unique_index("items_id_name", "CREATE UNIQUE INDEX items_id_name ON public.items USING btree (id, \"na\"\"me\")", &["id", "\"na\"\"me\""]),
+        ];
+
+        let identifier = choose_row_identifier(&table, &indexes, true).unwrap();
+        assert_eq!(identifier.kind, RowIdentifierKind::UniqueIndex);
+        assert_eq!(identifier.columns, vec!["na\"me".to_string()]);
+        assert_eq!(identifier.index_name.as_deref(), Some("items_name_key"));
+
+        table[0].is_primary_key = true;
+        let identifier = choose_row_identifier(&table, &indexes, true).unwrap();
+        assert_eq!(identifier.kind, RowIdentifierKind::PrimaryKey);
+        assert_eq!(identifier.columns, vec!["id".to_string()]);
+    }
+
+    #[test]
+    fn test_row_identifier_falls_back_to_ctid() {
+        let mut table = columns();
+        table[0].is_nullable = false;
+        let indexes = vec![
+            // Nullable column, partial index and expression index are not usable
+            unique_index("a", "CREATE UNIQUE INDEX a ON public.items USING btree (price)", &["price"]),
+            unique_index("b", "CREATE UNIQUE INDEX b ON public.items USING btree (id) WHERE (id > 0)", &["id"]),
+            unique_index("c", "CREATE UNIQUE INDEX c ON public.items USING btree (abs(id))", &["abs(id)"]),
+        ];
+
+        let identifier = choose_row_identifier(&table, &indexes, true).unwrap();
+        assert_eq!(identifier.kind, RowIdentifierKind::Ctid);
+        assert_eq!(identifier.columns, vec![CTID.to_string()]);
+        assert!(identifier.warning.is_some());
+
+        assert!(choose_row_identifier(&table, &indexes, false).is_none());
+    }
+
+    #[test]
+    fn test_edit_by_ctid_returns_new_ctid() {
+        let statement = build_delete("public", "items", &columns(), &[CTID.to_string()], &[json!("(0,3)")], &RowVersionCheck::default())
+            .unwrap();
+        assert_eq!(
+            statement.sql,
+            "DELETE FROM \"public\".\"items\" WHERE ctid = $1::text::tid RETURNING ctid, *"
+        );
+        assert_eq!(statement.params, vec![json!("(0,3)")]);
+    }
+
+    #[test]
+    fn test_text_form_of_arrays() {
+        assert_eq!(text_form(&json!(["a,b", null, "q\""])), "{\"a,b\",NULL,\"q\\\"\"}");
+        assert_eq!(text_form(&json!([[1, 2], [3, 4]])), "{{\"1\",\"2\"},{\"3\",\"4\"}}");
+        assert_eq!(text_form(&json!(42)), "42");
+    }
+
+    #[test]
+    fn test_params_encode_by_inferred_type() {
+        let mut out = BytesMut::new();
+        JsonParam(&json!("7")).to_sql(&Type::INT4, &mut out).unwrap();
+        assert_eq!(&out[..], &7i32.to_be_bytes());
+
+        out.clear();
+        assert!(JsonParam(&json!(70000)).to_sql(&Type::INT2, &mut out).is_err());
+
+        out.clear();
+        JsonParam(&json!("3q2+7w==")).to_sql(&Type::BYTEA, &mut out).unwrap();
+        assert_eq!(&out[..], &[0xde, 0xad, 0xbe, 0xef]);
+
+        out.clear();
+        assert!(matches!(
+            JsonParam(&Value::Null).to_sql(&Type::TEXT, &mut out).unwrap(),
+            IsNull::Yes
+        ));
+    }
+}
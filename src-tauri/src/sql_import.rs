@@ -0,0 +1,358 @@
+//! Imports SQL files such as pg_dump output.
+//!
+//! The file is read in chunks and run statement by statement as the splitter completes
+//! them, so its size is not limited by memory. `COPY ... FROM stdin` data is streamed
+//! to the server as it is read.
+
+use crate::session_registry::DatabaseSession;
+use crate::simple_db;
+use crate::sql_script::OpenTransaction;
+use crate::sql_splitter::{leading_keyword, SqlItem, StatementSplitter};
+use crate::transaction_session::transaction_control_keyword;
+use bytes::Bytes;
+use futures_util::SinkExt;
+use serde::{Deserialize, Serialize};
+use std::pin::Pin;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio_postgres::{Client, CopyInSink, SimpleQueryMessage};
+
+/// Event reporting how far an import has got
+pub const SQL_IMPORT_PROGRESS_EVENT: &str = "sql-import-progress";
+
+const READ_CHUNK_SIZE: usize = 64 * 1024;
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+
+#[derive(Debug, Clone, Default)]
+pub struct SqlImportOptions {
+    /// Run the whole file in one transaction, rolled back on the first error
+    pub single_transaction: bool,
+    /// Stop at the first error instead of running the rest of the file
+    pub stop_on_error: bool,
+    /// Quoted table to empty before importing, inside the transaction if there is one
+    pub truncate_table: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SqlImportProgress {
+    pub import_id: String,
+    pub bytes_read: u64,
+    /// Size of the file, when known
+    pub total_bytes: Option<u64>,
+    pub statements_executed: usize,
+    pub rows_imported: u64,
+    pub errors: usize,
+    /// Set on the last event of an import
+    pub done: bool,
+}
+
+/// Where progress events go; the app emits them as Tauri events
+pub trait ImportProgressSink: Send + Sync {
+    fn progress(&self, progress: SqlImportProgress);
+}
+
+impl<R: tauri::Runtime> ImportProgressSink for tauri::AppHandle<R> {
+    fn progress(&self, progress: SqlImportProgress) {
+        use tauri::Emitter;
+        if let Err(e) = self.emit(SQL_IMPORT_PROGRESS_EVENT, progress) {
+            log::error!("Failed to emit import progress: {}", e);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SqlImportSummary {
+    pub statements_executed: usize,
+    /// Rows inserted by `INSERT` and `COPY` statements
+    pub rows_imported: u64,
+    pub errors: Vec<String>,
+    pub warnings: Vec<String>,
+    /// The import's transaction was rolled back, so none of it was applied
+    pub rolled_back: bool,
+}
+
+/// Decodes UTF-8 read in arbitrary chunks, carrying characters split between chunks over
+#[derive(Debug, Default)]
+struct Utf8Decoder {
+    partial: Vec<u8>,
+}
+
+impl Utf8Decoder {
+    fn decode(&mut self, bytes: &[u8]) -> Result<String, String> {
+        self.partial.extend_from_slice(bytes);
+        let valid = match std::str::from_utf8(&self.partial) {
+            Ok(_) => self.partial.len(),
+            Err(e) if e.error_len().is_none() => e.valid_up_to(),
+            Err(_) => return Err("The file is not valid UTF-8".to_string()),
+        };
+        let text = String::from_utf8_lossy(&self.partial[..valid]).into_owned();
+        self.partial.drain(..valid);
+        Ok(text)
+    }
+
+    fn finish(&self) -> Result<(), String> {
+        if self.partial.is_empty() {
+            Ok(())
+        } else {
+            Err("The file ends in the middle of a UTF-8 character".to_string())
+        }
+    }
+}
+
+struct Importer<'a> {
+    client: &'a Client,
+    options: &'a SqlImportOptions,
+    summary: SqlImportSummary,
+    /// Line and data sink of the COPY being loaded; `None` also while skipping the data
+    /// of a COPY that failed to start
+    copy: Option<(usize, Pin<Box<CopyInSink<Bytes>>>)>,
+    open_transaction: OpenTransaction,
+    stopped: bool,
+}
+
+impl Importer<'_> {
+    fn fail(&mut self, line: usize, error: String) {
+        self.record_error(format!("Line {}: {}", line, error));
+    }
+
+    fn record_error(&mut self, error: String) {
+        println!("🦀 [Import] {}", error);
+        self.summary.errors.push(error);
+        if self.options.single_transaction || self.options.stop_on_error {
+            self.stopped = true;
+        }
+    }
+
+    async fn handle(&mut self, item: SqlItem) {
+        if self.stopped {
+            return;
+        }
+
+        match item {
+            SqlItem::Statement(statement) => {
+                if self.options.single_transaction {
+                    if let Some(keyword) = transaction_control_keyword(&statement.sql) {
+                        self.fail(statement.line, format!("{} cannot run inside the import's transaction", keyword));
+                        return;
+                    }
+                }
+                match self.client.simple_query(&statement.sql).await {
+                    Ok(messages) => {
+                        self.summary.statements_executed += 1;
+                        self.open_transaction.update(&statement.sql);
+                        if leading_keyword(&statement.sql) == "INSERT" {
+                            self.summary.rows_imported += messages
+                                .iter()
+                                .map(|message| match message {
+                                    SimpleQueryMessage::CommandComplete(rows) => *rows,
+                                    _ => 0,
+                                })
+                                .sum::<u64>();
+                        }
+                    }
+                    Err(e) => self.fail(statement.line, simple_db::db_error_message(&e)),
+                }
+            }
+            SqlItem::Copy(statement) => match self.client.copy_in::<_, Bytes>(statement.sql.as_str()).await {
+                Ok(sink) => self.copy = Some((statement.line, Box::pin(sink))),
+                Err(e) => self.fail(statement.line, simple_db::db_error_message(&e)),
+            },
+            SqlItem::CopyData(data) => {
+                if let Some((line, sink)) = &mut self.copy {
+                    if let Err(e) = sink.send(Bytes::from(data)).await {
+                        let line = *line;
+                        // Dropping the sink aborts the COPY
+                        self.copy = None;
+                        self.fail(line, simple_db::db_error_message(&e));
+                    }
+                }
+            }
+            SqlItem::CopyEnd => {
+                if let Some((line, mut sink)) = self.copy.take() {
+                    match sink.as_mut().finish().await {
+                        Ok(rows) => {
+                            self.summary.statements_executed += 1;
+                            self.summary.rows_imported += rows;
+                        }
+                        Err(e) => self.fail(line, simple_db::db_error_message(&e)),
+                    }
+                }
+            }
+            SqlItem::MetaCommand(statement) => {
+                self.summary
+                    .warnings
+                    .push(format!("Line {}: skipped psql meta-command {}", statement.line, statement.sql));
+            }
+        }
+    }
+
+    fn progress(&self, import_id: &str, bytes_read: u64, total_bytes: Option<u64>, done: bool) -> SqlImportProgress {
+        SqlImportProgress {
+            import_id: import_id.to_string(),
+            bytes_read,
+            total_bytes,
+            statements_executed: self.summary.statements_executed,
+            rows_imported: self.summary.rows_imported,
+            errors: self.summary.errors.len(),
+            done,
+        }
+    }
+}
+
+/// Read SQL from `reader` and run it on `client`. Statement errors are collected in the
+/// summary; only failing to start or end the import's transaction is an error.
+pub async fn import_sql<R: AsyncRead + Unpin>(
+    client: &Client,
+    mut reader: R,
+    total_bytes: Option<u64>,
+    options: &SqlImportOptions,
+    import_id: &str,
+    progress: &dyn ImportProgressSink,
+) -> Result<SqlImportSummary, String> {
+    if options.single_transaction {
+        client
+            .batch_execute("BEGIN")
+            .await
+            .map_err(|e| format!("Failed to start transaction: {}", simple_db::db_error_message(&e)))?;
+    }
+
+    let mut importer = Importer {
+        client,
+        options,
+        summary: SqlImportSummary::default(),
+        copy: None,
+        open_transaction: OpenTransaction::default(),
+        stopped: false,
+    };
+
+    if let Some(table) = &options.truncate_table {
+        if let Err(e) = client
+            .batch_execute(&format!("TRUNCATE TABLE {} RESTART IDENTITY CASCADE", table))
+            .await
+        {
+            importer.record_error(format!("Failed to truncate table: {}", simple_db::db_error_message(&e)));
+        }
+    }
+
+    let mut splitter = StatementSplitter::new();
+    let mut decoder = Utf8Decoder::default();
+    let mut buffer = vec![0; READ_CHUNK_SIZE];
+    let mut bytes_read = 0u64;
+    let mut last_progress = Instant::now();
+    while !importer.stopped {
+        let read = match reader.read(&mut buffer).await {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(e) => {
+                importer.fail(splitter.line(), format!("Failed to read file: {}", e));
+                break;
+            }
+        };
+        bytes_read += read as u64;
+        let text = match decoder.decode(&buffer[..read]) {
+            Ok(text) => text,
+            Err(e) => {
+                importer.fail(splitter.line(), e);
+                break;
+            }
+        };
+        for item in splitter.push(&text) {
+            importer.handle(item).await;
+        }
+
+        if last_progress.elapsed() >= PROGRESS_INTERVAL {
+            progress.progress(importer.progress(import_id, bytes_read, total_bytes, false));
+            last_progress = Instant::now();
+        }
+    }
+
+    if !importer.stopped {
+        match decoder.finish() {
+            Ok(()) => {
+                for item in splitter.finish() {
+                    importer.handle(item).await;
+                }
+            }
+            Err(e) => importer.fail(splitter.line(), e),
+        }
+    }
+    importer.copy = None;
+
+    let failed = !importer.summary.errors.is_empty();
+    if options.single_transaction {
+        let end = if failed { "ROLLBACK" } else { "COMMIT" };
+        println!("🦀 [Import] {}", end);
+        if let Err(e) = client.batch_execute(end).await {
+            client.batch_execute("ROLLBACK").await.ok();
+            if failed {
+                return Err(format!("Failed to roll back import: {}", simple_db::db_error_message(&e)));
+            }
+            importer
+                .summary
+                .errors
+                .push(format!("Failed to commit import: {}", simple_db::db_error_message(&e)));
+        }
+        importer.summary.rolled_back = !importer.summary.errors.is_empty();
+    } else if importer.open_transaction.is_open() {
+        // The connection goes back to the pool, so it must not stay inside the file's transaction
+        client.batch_execute("ROLLBACK").await.ok();
+        importer
+            .summary
+            .warnings
+            .push("The file left a transaction open; it was rolled back".to_string());
+    }
+
+    progress.progress(importer.progress(import_id, bytes_read, total_bytes, true));
+    Ok(importer.summary)
+}
+
+/// Run `import_sql` on a connection from the session's pool, so the shared connection stays
+/// free for other tabs while a large file loads
+pub async fn import_sql_on_pool<R: AsyncRead + Unpin>(
+    session: &DatabaseSession,
+    reader: R,
+    total_bytes: Option<u64>,
+    options: &SqlImportOptions,
+    import_id: &str,
+    progress: &dyn ImportProgressSink,
+) -> Result<SqlImportSummary, String> {
+    let client = session.pool.lock().await.get_connection().await?;
+
+    // Loading data can take far longer than any interactive query
+    client
+        .batch_execute("SET statement_timeout = 0")
+        .await
+        .map_err(|e| format!("Failed to set statement timeout: {}", e))?;
+
+    let result = import_sql(&client, reader, total_bytes, options, import_id, progress).await;
+    // Dumps change settings like search_path, which must not follow the connection back
+    // into the pool; a connection that cannot be reset is closed instead
+    if let Err(e) = client.batch_execute("RESET ALL").await {
+        println!("🦀 [Import] Failed to reset settings after import, closing the connection: {}", e);
+        drop(deadpool_postgres::Object::take(client));
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_utf8_decoder_carries_split_characters() {
+        let text = "naïve — ok";
+        let bytes = text.as_bytes();
+        let mut decoder = Utf8Decoder::default();
+        let mut decoded = String::new();
+        for chunk in bytes.chunks(3) {
+            decoded.push_str(&decoder.decode(chunk).unwrap());
+        }
+        assert!(decoder.finish().is_ok());
+        assert_eq!(decoded, text);
+
+        let mut decoder = Utf8Decoder::default();
+        assert_eq!(decoder.decode(&bytes[..3]).unwrap(), "na");
+        assert!(decoder.finish().is_err());
+        assert!(Utf8Decoder::default().decode(&[b'a', 0xff, b'b']).is_err());
+    }
+}
@@ -0,0 +1,261 @@
+//! Runs a script of several statements, reporting each one separately.
+//!
+//! Statements run one after another on a single connection, so session settings and
+//! temporary tables carry over between them just as they would in psql.
+
+use crate::simple_db::{self, SimpleQueryResult};
+use crate::sql_splitter::{leading_keyword, SqlStatement};
+use crate::transaction_session::transaction_control_keyword;
+use crate::value_codec::{self, SessionTimeZone};
+use postgres_native_tls::MakeTlsConnector;
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+use tokio_postgres::Client;
+
+/// What to do when a statement fails
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScriptErrorMode {
+    /// Skip everything after the failed statement
+    #[default]
+    StopOnError,
+    /// Run the remaining statements anyway
+    Continue,
+    /// Run the script in one transaction and roll all of it back on the first failure
+    Transaction,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ScriptOptions {
+    #[serde(default)]
+    pub on_error: ScriptErrorMode,
+    /// Limit for each statement; the connection's query timeout when not set
+    #[serde(default)]
+    pub timeout_ms: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StatementStatus {
+    Succeeded,
+    Failed,
+    /// Not run because an earlier statement failed
+    Skipped,
+    /// Ran successfully, but the script's transaction was rolled back
+    RolledBack,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StatementResult {
+    /// 0-based position in the script
+    pub index: usize,
+    /// 1-based line the statement starts on
+    pub line: usize,
+    pub sql: String,
+    /// Leading keyword, e.g. `SELECT` or `CREATE`
+    pub command: String,
+    pub status: StatementStatus,
+    pub result: Option<SimpleQueryResult>,
+    pub error: Option<String>,
+    pub execution_time_ms: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScriptResult {
+    pub statements: Vec<StatementResult>,
+    pub succeeded: usize,
+    pub failed: usize,
+    pub skipped: usize,
+    /// Something the caller should know beyond the per-statement results
+    pub warning: Option<String>,
+    pub execution_time_ms: u64,
+}
+
+impl ScriptResult {
+    fn new(statements: Vec<StatementResult>, warning: Option<String>, started: Instant) -> Self {
+        let count = |status| statements.iter().filter(|s| s.status == status).count();
+        Self {
+            succeeded: count(StatementStatus::Succeeded),
+            failed: count(StatementStatus::Failed),
+            skipped: count(StatementStatus::Skipped),
+            statements,
+            warning,
+            execution_time_ms: started.elapsed().as_millis() as u64,
+        }
+    }
+}
+
+/// Tracks whether a script has left a transaction open on the connection
+#[derive(Debug, Default)]
+pub(crate) struct OpenTransaction(bool);
+
+impl OpenTransaction {
+    /// Account for a statement that ran successfully
+    pub(crate) fn update(&mut self, sql: &str) {
+        match leading_keyword(sql).as_str() {
+            "BEGIN" | "START" => self.0 = true,
+            "COMMIT" | "END" => self.0 = false,
+            // `ROLLBACK TO SAVEPOINT` keeps the transaction open
+            "ROLLBACK" | "ABORT" if !sql.split_whitespace().any(|word| word.eq_ignore_ascii_case("TO")) => {
+                self.0 = false
+            }
+            _ => {}
+        }
+    }
+
+    pub(crate) fn is_open(&self) -> bool {
+        self.0
+    }
+}
+
+/// Reject scripts that would interfere with the transaction wrapped around them
+fn check_transaction_script(statements: &[SqlStatement]) -> Result<(), String> {
+    match statements
+        .iter()
+        .find_map(|statement| transaction_control_keyword(&statement.sql).map(|keyword| (statement.line, keyword)))
+    {
+        Some((line, keyword)) => Err(format!(
+            "{} on line {} cannot run inside the script's transaction; run the script without it",
+            keyword, line
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Run `statements` in order on `client`. Only failures to set up or finish the script
+/// are errors; statement failures are reported in the result.
+pub async fn run_script(
+    client: &Client,
+    statements: Vec<SqlStatement>,
+    mode: ScriptErrorMode,
+    timeout: Duration,
+    tls: Option<&MakeTlsConnector>,
+    zone: &SessionTimeZone,
+) -> Result<ScriptResult, String> {
+    let started = Instant::now();
+    let mut zone = *zone;
+    let in_transaction = mode == ScriptErrorMode::Transaction;
+    if in_transaction {
+        check_transaction_script(&statements)?;
+        client
+            .batch_execute("BEGIN")
+            .await
+            .map_err(|e| format!("Failed to start transaction: {}", simple_db::db_error_message(&e)))?;
+    }
+
+    let mut results = Vec::with_capacity(statements.len());
+    let mut open_transaction = OpenTransaction::default();
+    let mut stopped = false;
+    for (index, statement) in statements.into_iter().enumerate() {
+        let command = leading_keyword(&statement.sql);
+        if stopped {
+            results.push(StatementResult {
+                index,
+                line: statement.line,
+                sql: statement.sql,
+                command,
+                status: StatementStatus::Skipped,
+                result: None,
+                error: None,
+                execution_time_ms: 0,
+            });
+            continue;
+        }
+
+        println!("🦀 [Script] Running statement {} (line {}): {}", index + 1, statement.line, command);
+        let statement_started = Instant::now();
+        let outcome = simple_db::run_query(client, &statement.sql, &[], timeout, tls, &zone).await;
+        let execution_time_ms = statement_started.elapsed().as_millis() as u64;
+        let (status, result, error) = match outcome {
+            Ok(result) => {
+                open_transaction.update(&statement.sql);
+                if simple_db::changes_settings(&statement.sql) {
+                    zone = value_codec::read_time_zone(client).await;
+                }
+                (StatementStatus::Succeeded, Some(result), None)
+            }
+            Err(e) => {
+                println!("🦀 [Script] Statement {} failed: {}", index + 1, e);
+                stopped = mode != ScriptErrorMode::Continue;
+                (StatementStatus::Failed, None, Some(e))
+            }
+        };
+        results.push(StatementResult {
+            index,
+            line: statement.line,
+            sql: statement.sql,
+            command,
+            status,
+            result,
+            error,
+            execution_time_ms,
+        });
+    }
+
+    let mut warning = None;
+    if in_transaction {
+        let failed = results.iter().any(|r| r.status == StatementStatus::Failed);
+        let end = if failed { "ROLLBACK" } else { "COMMIT" };
+        println!("🦀 [Script] {}", end);
+        if let Err(e) = client.batch_execute(end).await {
+            let message = simple_db::db_error_message(&e);
+            // A failed COMMIT has still ended the transaction, without applying it
+            client.batch_execute("ROLLBACK").await.ok();
+            if failed {
+                return Err(format!("Failed to roll back script: {}", message));
+            }
+            for result in &mut results {
+                result.status = StatementStatus::RolledBack;
+            }
+            warning = Some(format!("The script could not be committed and was rolled back: {}", message));
+        } else if failed {
+            for result in results.iter_mut().filter(|r| r.status == StatementStatus::Succeeded) {
+                result.status = StatementStatus::RolledBack;
+            }
+        }
+    } else if open_transaction.is_open() {
+        // The connection is shared with other tabs, so it must not stay inside the script's transaction
+        client
+            .batch_execute("ROLLBACK")
+            .await
+            .map_err(|e| format!("Failed to roll back the script's open transaction: {}", e))?;
+        warning = Some("The script left a transaction open; it was rolled back".to_string());
+    }
+
+    Ok(ScriptResult::new(results, warning, started))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sql_splitter::split_statements;
+
+    #[test]
+    fn test_open_transaction_tracking() {
+        let mut open = OpenTransaction::default();
+        open.update("begin");
+        assert!(open.is_open());
+        open.update("ROLLBACK TO SAVEPOINT a");
+        assert!(open.is_open());
+        open.update("rollback");
+        assert!(!open.is_open());
+        open.update("START TRANSACTION READ ONLY");
+        open.update("END");
+        assert!(!open.is_open());
+    }
+
+    #[test]
+    fn test_transaction_script_rejects_transaction_control() {
+        assert!(check_transaction_script(&split_statements("SELECT 1; UPDATE t SET a = 'commit';").unwrap()).is_ok());
+        let error = check_transaction_script(&split_statements("SELECT 1;\nCOMMIT;").unwrap()).unwrap_err();
+        assert!(error.contains("COMMIT on line 2"), "{}", error);
+    }
+
+    #[test]
+    fn test_options_default_to_stop_on_error() {
+        let options: ScriptOptions = serde_json::from_str("{}").unwrap();
+        assert_eq!(options.on_error, ScriptErrorMode::StopOnError);
+        let options: ScriptOptions = serde_json::from_str(r#"{"on_error":"transaction"}"#).unwrap();
+        assert_eq!(options.on_error, ScriptErrorMode::Transaction);
+    }
+}
@@ -0,0 +1,536 @@
+//! Splits SQL text into statements the way the server's lexer sees it.
+//!
+//! Semicolons inside string literals (including `E''` strings with backslash escapes),
+//! quoted identifiers, dollar-quoted bodies, comments, parentheses and `BEGIN ATOMIC ... END`
+//! routine bodies do not end a statement. Text can be fed in chunks, so large files never
+//! have to be held in memory at once.
+//!
+//! Like psql, it also understands the parts of a dump that are not SQL: the data lines
+//! after `COPY ... FROM stdin`, up to `\.`, and backslash meta-commands such as `\connect`.
+
+/// One statement, without its terminating semicolon or leading comments
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SqlStatement {
+    pub sql: String,
+    /// 1-based line the statement starts on
+    pub line: usize,
+}
+
+/// A piece of a script, in order
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SqlItem {
+    Statement(SqlStatement),
+    /// A `COPY ... FROM stdin` statement; its data follows as `CopyData` items
+    Copy(SqlStatement),
+    /// Whole lines of COPY data, each ending in a newline
+    CopyData(String),
+    /// The `\.` line (or the end of input) that ends the COPY data
+    CopyEnd,
+    /// A psql backslash command, which the server would not understand
+    MetaCommand(SqlStatement),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum LexState {
+    Normal,
+    SingleQuote { backslash_escapes: bool },
+    DoubleQuote,
+    DollarQuote(String),
+    LineComment,
+    BlockComment(usize),
+    /// After a `COPY ... FROM stdin`; `line_start` is false until the statement's line ends
+    CopyData { line_start: bool },
+}
+
+/// Incremental statement splitter
+#[derive(Debug)]
+pub struct StatementSplitter {
+    /// Unconsumed input, starting at the current statement (or at `pos` between statements)
+    pending: String,
+    /// Next byte of `pending` to scan
+    pos: usize,
+    state: LexState,
+    line: usize,
+    /// Byte offset in `pending` and line of the current statement's first token
+    start: Option<(usize, usize)>,
+    /// Identifier being read
+    word: String,
+    /// First words of the current statement, uppercased
+    head: Vec<String>,
+    /// Previous word of the current statement, uppercased
+    last_word: String,
+    /// The current statement is `COPY ... FROM STDIN`
+    copy_from_stdin: bool,
+    paren_depth: usize,
+    /// `BEGIN ... END` / `CASE ... END` nesting inside a routine body
+    begin_depth: usize,
+}
+
+impl Default for StatementSplitter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn is_word_char(c: char, in_word: bool) -> bool {
+    c.is_alphanumeric() || c == '_' || (in_word && c == '$')
+}
+
+impl StatementSplitter {
+    pub fn new() -> Self {
+        Self {
+            pending: String::new(),
+            pos: 0,
+            state: LexState::Normal,
+            line: 1,
+            start: None,
+            word: String::new(),
+            head: Vec::new(),
+            last_word: String::new(),
+            copy_from_stdin: false,
+            paren_depth: 0,
+            begin_depth: 0,
+        }
+    }
+
+    /// Feed more text and return the items it completes
+    pub fn push(&mut self, text: &str) -> Vec<SqlItem> {
+        self.pending.push_str(text);
+        let statements = self.scan(false);
+        self.compact();
+        statements
+    }
+
+    /// Signal the end of input and return what is left: the items still pending, the
+    /// last statement possibly without a terminating semicolon
+    pub fn finish(&mut self) -> Vec<SqlItem> {
+        let mut statements = self.scan(true);
+        if let LexState::CopyData { .. } = self.state {
+            statements.push(SqlItem::CopyEnd);
+            self.state = LexState::Normal;
+        }
+        self.end_word();
+        if let Some((start, line)) = self.start.take() {
+            let sql = self.pending[start..].trim_end().to_string();
+            statements.push(SqlItem::Statement(SqlStatement { sql, line }));
+        }
+        self.pending.clear();
+        self.pos = 0;
+        statements
+    }
+
+    /// 1-based line reached so far
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    /// Drop the consumed input before the current statement
+    fn compact(&mut self) {
+        let keep_from = self.start.map_or(self.pos, |(start, _)| start);
+        if keep_from > 0 {
+            self.pending.drain(..keep_from);
+            self.pos -= keep_from;
+            if let Some((start, _)) = &mut self.start {
+                *start -= keep_from;
+            }
+        }
+    }
+
+    fn peek(&self, offset: usize) -> Option<char> {
+        self.pending[self.pos..].chars().nth(offset)
+    }
+
+    fn advance(&mut self, bytes: usize) {
+        self.line += self.pending[self.pos..self.pos + bytes].matches('\n').count();
+        self.pos += bytes;
+    }
+
+    fn mark_start(&mut self) {
+        if self.start.is_none() {
+            self.start = Some((self.pos, self.line));
+        }
+    }
+
+    /// Finish the identifier being read, tracking routine bodies
+    fn end_word(&mut self) -> String {
+        let word = std::mem::take(&mut self.word);
+        if word.is_empty() || word.starts_with(|c: char| c.is_ascii_digit()) {
+            return word;
+        }
+
+        let upper = word.to_uppercase();
+        if self.is_routine_definition() {
+            match upper.as_str() {
+                "BEGIN" | "CASE" => self.begin_depth += 1,
+                "END" => self.begin_depth = self.begin_depth.saturating_sub(1),
+                _ => {}
+            }
+        }
+        if self.paren_depth == 0 && self.last_word == "FROM" && upper == "STDIN" {
+            self.copy_from_stdin = self.head.first().is_some_and(|first| first == "COPY");
+        }
+        if self.head.len() < 4 {
+            self.head.push(upper.clone());
+        }
+        self.last_word = upper;
+        word
+    }
+
+    /// `CREATE [OR REPLACE] FUNCTION|PROCEDURE`, whose SQL-standard body may contain semicolons
+    fn is_routine_definition(&self) -> bool {
+        let head: Vec<&str> = self.head.iter().map(String::as_str).collect();
+        matches!(
+            head.as_slice(),
+            ["CREATE", "FUNCTION" | "PROCEDURE", ..] | ["CREATE", "OR", "REPLACE", "FUNCTION" | "PROCEDURE", ..]
+        )
+    }
+
+    fn scan(&mut self, at_end: bool) -> Vec<SqlItem> {
+        let mut statements = Vec::new();
+        let mut copy_data = String::new();
+
+        while let Some(c) = self.peek(0) {
+            // `None` means the next token needs input that has not arrived yet
+            let next = self.peek(1);
+            let need_more = next.is_none() && !at_end;
+
+            match self.state.clone() {
+                LexState::Normal => {
+                    if c == '\\' && self.start.is_none() {
+                        // Meta-commands run to the end of the line
+                        let rest = &self.pending[self.pos..];
+                        let end = match rest.find('\n') {
+                            Some(end) => end,
+                            None if at_end => rest.len(),
+                            None => break,
+                        };
+                        let sql = rest[..end].trim_end().to_string();
+                        statements.push(SqlItem::MetaCommand(SqlStatement { sql, line: self.line }));
+                        self.advance((end + 1).min(rest.len()));
+                        continue;
+                    }
+                    if is_word_char(c, !self.word.is_empty()) {
+                        self.mark_start();
+                        self.word.push(c);
+                        self.advance(c.len_utf8());
+                        continue;
+                    }
+                    if c == '$' && self.word.is_empty() {
+                        match self.dollar_tag(at_end) {
+                            None => break,
+                            Some(Some(tag)) => {
+                                self.mark_start();
+                                self.advance(tag.len() + 2);
+                                self.state = LexState::DollarQuote(tag);
+                                continue;
+                            }
+                            Some(None) => {}
+                        }
+                    }
+                    if (c == '-' || c == '/') && need_more {
+                        break;
+                    }
+
+                    let previous_word = self.end_word();
+                    match (c, next) {
+                        ('-', Some('-')) => {
+                            self.advance(2);
+                            self.state = LexState::LineComment;
+                        }
+                        ('/', Some('*')) => {
+                            self.advance(2);
+                            self.state = LexState::BlockComment(1);
+                        }
+                        ('\'', _) => {
+                            self.mark_start();
+                            self.advance(1);
+                            self.state = LexState::SingleQuote {
+                                backslash_escapes: previous_word.eq_ignore_ascii_case("e"),
+                            };
+                        }
+                        ('"', _) => {
+                            self.mark_start();
+                            self.advance(1);
+                            self.state = LexState::DoubleQuote;
+                        }
+                        (';', _) if self.paren_depth == 0 && self.begin_depth == 0 => {
+                            statements.extend(self.end_statement());
+                            self.advance(1);
+                        }
+                        _ => {
+                            if !c.is_whitespace() {
+                                self.mark_start();
+                            }
+                            match c {
+                                '(' => self.paren_depth += 1,
+                                ')' => self.paren_depth = self.paren_depth.saturating_sub(1),
+                                _ => {}
+                            }
+                            self.advance(c.len_utf8());
+                        }
+                    }
+                }
+                LexState::SingleQuote { backslash_escapes } => {
+                    if (c == '\'' || (c == '\\' && backslash_escapes)) && need_more {
+                        break;
+                    }
+                    match (c, next) {
+                        ('\\', Some(escaped)) if backslash_escapes => self.advance(1 + escaped.len_utf8()),
+                        ('\'', Some('\'')) => self.advance(2),
+                        ('\'', _) => {
+                            self.advance(1);
+                            self.state = LexState::Normal;
+                        }
+                        _ => self.advance(c.len_utf8()),
+                    }
+                }
+                LexState::DoubleQuote => {
+                    if c == '"' && need_more {
+                        break;
+                    }
+                    match (c, next) {
+                        ('"', Some('"')) => self.advance(2),
+                        ('"', _) => {
+                            self.advance(1);
+                            self.state = LexState::Normal;
+                        }
+                        _ => self.advance(c.len_utf8()),
+                    }
+                }
+                LexState::DollarQuote(tag) => {
+                    if c == '$' {
+                        let closing = format!("${}$", tag);
+                        let rest = &self.pending[self.pos..];
+                        if rest.starts_with(&closing) {
+                            self.advance(closing.len());
+                            self.state = LexState::Normal;
+                            continue;
+                        }
+                        if closing.starts_with(rest) && !at_end {
+                            break;
+                        }
+                    }
+                    self.advance(c.len_utf8());
+                }
+                LexState::LineComment => {
+                    if c == '\n' {
+                        self.state = LexState::Normal;
+                    }
+                    self.advance(c.len_utf8());
+                }
+                LexState::BlockComment(depth) => {
+                    if (c == '/' || c == '*') && need_more {
+                        break;
+                    }
+                    match (c, next) {
+                        ('/', Some('*')) => {
+                            self.advance(2);
+                            self.state = LexState::BlockComment(depth + 1);
+                        }
+                        ('*', Some('/')) => {
+                            self.advance(2);
+                            self.state = if depth == 1 { LexState::Normal } else { LexState::BlockComment(depth - 1) };
+                        }
+                        _ => self.advance(c.len_utf8()),
+                    }
+                }
+                LexState::CopyData { line_start } => {
+                    let rest = &self.pending[self.pos..];
+                    let end = match rest.find('\n') {
+                        Some(end) => end,
+                        None if at_end => rest.len(),
+                        None => break,
+                    };
+                    let line = &rest[..end];
+                    if !line_start {
+                        // Anything after the COPY statement on its line is not data
+                        self.state = LexState::CopyData { line_start: true };
+                    } else if line.trim_end_matches('\r') == "\\." {
+                        if !copy_data.is_empty() {
+                            statements.push(SqlItem::CopyData(std::mem::take(&mut copy_data)));
+                        }
+                        statements.push(SqlItem::CopyEnd);
+                        self.state = LexState::Normal;
+                    } else {
+                        copy_data.push_str(line);
+                        copy_data.push('\n');
+                    }
+                    self.advance((end + 1).min(rest.len()));
+                }
+            }
+        }
+
+        if !copy_data.is_empty() {
+            statements.push(SqlItem::CopyData(copy_data));
+        }
+        statements
+    }
+
+    /// At a `$`: `Some(Some(tag))` for a dollar-quote opener, `Some(None)` when it is
+    /// something else (like a `$1` parameter), `None` when more input is needed to tell
+    fn dollar_tag(&self, at_end: bool) -> Option<Option<String>> {
+        let rest = &self.pending[self.pos + 1..];
+        for (i, c) in rest.char_indices() {
+            if c == '$' {
+                return Some(Some(rest[..i].to_string()));
+            }
+            let valid = if i == 0 { c.is_alphabetic() || c == '_' } else { is_word_char(c, false) };
+            if !valid {
+                return Some(None);
+            }
+        }
+        if at_end {
+            Some(None)
+        } else {
+            None
+        }
+    }
+
+    /// The statement ending at the current `;`, unless it was empty.
+    /// After a `COPY ... FROM stdin` the following lines are read as its data.
+    fn end_statement(&mut self) -> Option<SqlItem> {
+        self.head.clear();
+        self.last_word.clear();
+        self.paren_depth = 0;
+        self.begin_depth = 0;
+        let copy_from_stdin = std::mem::take(&mut self.copy_from_stdin);
+        let (start, line) = self.start.take()?;
+        let statement = SqlStatement {
+            sql: self.pending[start..self.pos].trim_end().to_string(),
+            line,
+        };
+        if copy_from_stdin {
+            self.state = LexState::CopyData { line_start: false };
+            Some(SqlItem::Copy(statement))
+        } else {
+            Some(SqlItem::Statement(statement))
+        }
+    }
+}
+
+/// Split a complete script into plain SQL statements. COPY data and meta-commands are
+/// rejected; only the SQL importer can run those.
+pub fn split_statements(sql: &str) -> Result<Vec<SqlStatement>, String> {
+    let mut splitter = StatementSplitter::new();
+    let mut items = splitter.push(sql);
+    items.extend(splitter.finish());
+    items
+        .into_iter()
+        .filter_map(|item| match item {
+            SqlItem::Statement(statement) => Some(Ok(statement)),
+            SqlItem::Copy(statement) => Some(Err(format!(
+                "COPY FROM stdin on line {} needs its data loaded by importing the file",
+                statement.line
+            ))),
+            SqlItem::MetaCommand(statement) => Some(Err(format!(
+                "psql meta-command on line {} is not SQL: {}",
+                statement.line, statement.sql
+            ))),
+            SqlItem::CopyData(_) | SqlItem::CopyEnd => None,
+        })
+        .collect()
+}
+
+/// First keyword of a statement, uppercased (e.g. `SELECT`, `INSERT`, `CREATE`)
+pub fn leading_keyword(sql: &str) -> String {
+    sql.trim_start()
+        .chars()
+        .take_while(|c| c.is_alphabetic())
+        .collect::<String>()
+        .to_uppercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sqls(statements: &[SqlStatement]) -> Vec<&str> {
+        statements.iter().map(|s| s.sql.as_str()).collect()
+    }
+
+    #[test]
+    fn test_split_respects_quotes_and_comments() {
+        let script = "-- leading; comment\nSELECT 'a;b', \"c;d\" FROM t; /* x; /* nested; */ y */ SELECT E'it\\'s;';\n\nSELECT 1";
+        let statements = split_statements(script).unwrap();
+        assert_eq!(
+            sqls(&statements),
+            vec!["SELECT 'a;b', \"c;d\" FROM t", "SELECT E'it\\'s;'", "SELECT 1"]
+        );
+        assert_eq!(statements.iter().map(|s| s.line).collect::<Vec<_>>(), vec![2, 2, 4]);
+    }
+
+    #[test]
+    fn test_split_dollar_quotes_and_routine_bodies() {
+        let script = "CREATE FUNCTION f() RETURNS int AS $body$ BEGIN RETURN 1; END; $body$ LANGUAGE plpgsql;\n\
+                      DO $$ BEGIN PERFORM 1; END $$;\n\
+                      CREATE OR REPLACE PROCEDURE p() LANGUAGE sql BEGIN ATOMIC INSERT INTO t VALUES (1); SELECT CASE WHEN true THEN 1 END; END;\n\
+                      SELECT $1, a$b FROM t;";
+        let statements = split_statements(script).unwrap();
+        assert_eq!(statements.len(), 4);
+        assert!(statements[0].sql.ends_with("LANGUAGE plpgsql"));
+        assert!(statements[2].sql.ends_with("END; END"));
+        assert_eq!(statements[3].sql, "SELECT $1, a$b FROM t");
+    }
+
+    #[test]
+    fn test_split_in_chunks_matches_whole_input() {
+        let script = "SELECT 'x''y'; -- c\nSELECT $tag$ ; $tag$; /* ; */ SELECT \"q\"\"q\";";
+        let expected = split_statements(script).unwrap();
+
+        let mut splitter = StatementSplitter::new();
+        let mut items = Vec::new();
+        for c in script.chars() {
+            items.extend(splitter.push(&c.to_string()));
+        }
+        items.extend(splitter.finish());
+        let statements: Vec<SqlStatement> = items
+            .into_iter()
+            .map(|item| match item {
+                SqlItem::Statement(statement) => statement,
+                other => panic!("unexpected {:?}", other),
+            })
+            .collect();
+        assert_eq!(statements, expected);
+        assert_eq!(sqls(&statements), vec!["SELECT 'x''y'", "SELECT $tag$ ; $tag$", "SELECT \"q\"\"q\""]);
+    }
+
+    #[test]
+    fn test_empty_statements_and_trailing_comments_are_dropped() {
+        assert!(split_statements(";; -- nothing\n /* here */").unwrap().is_empty());
+        assert_eq!(leading_keyword("  insert into t values (1)"), "INSERT");
+    }
+
+    #[test]
+    fn test_copy_data_and_meta_commands() {
+        let dump = "\\connect app\nCOPY public.t (a, b) FROM stdin;\n1\tx;y\n2\t\\N\n\\.\nSELECT 1;\nCOPY t FROM STDIN;\n3\tz\n";
+        let mut splitter = StatementSplitter::new();
+        let mut items = Vec::new();
+        for chunk in dump.as_bytes().chunks(5) {
+            items.extend(splitter.push(std::str::from_utf8(chunk).unwrap()));
+        }
+        items.extend(splitter.finish());
+
+        let data: String = items
+            .iter()
+            .filter_map(|item| match item {
+                SqlItem::CopyData(data) => Some(data.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(data, "1\tx;y\n2\t\\N\n3\tz\n");
+        let others: Vec<&SqlItem> = items.iter().filter(|item| !matches!(item, SqlItem::CopyData(_))).collect();
+        assert_eq!(
+            others,
+            vec![
+                &SqlItem::MetaCommand(SqlStatement { sql: "\\connect app".to_string(), line: 1 }),
+                &SqlItem::Copy(SqlStatement { sql: "COPY public.t (a, b) FROM stdin".to_string(), line: 2 }),
+                &SqlItem::CopyEnd,
+                &SqlItem::Statement(SqlStatement { sql: "SELECT 1".to_string(), line: 6 }),
+                &SqlItem::Copy(SqlStatement { sql: "COPY t FROM STDIN".to_string(), line: 7 }),
+                &SqlItem::CopyEnd,
+            ]
+        );
+        assert!(split_statements("COPY t FROM stdin;\n1\n\\.\n").is_err());
+        assert_eq!(split_statements("COPY (SELECT 1) TO STDOUT").unwrap().len(), 1);
+    }
+}
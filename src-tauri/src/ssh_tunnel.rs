@@ -0,0 +1,383 @@
+use crate::connection_profile::{AdvancedConnectionConfig, SshAuthMethod, SshTunnelConfig};
+use crate::credential_vault::{CredentialVault, VaultError};
+use async_trait::async_trait;
+use russh::client;
+use russh_keys::key;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::task::JoinHandle;
+
+const SSH_SECRET_SUFFIX: &str = "_ssh";
+
+/// Vault entry holding the SSH password or key passphrase of a profile
+pub fn ssh_secret_id(profile_id: &str) -> String {
+    format!("{}{}", profile_id, SSH_SECRET_SUFFIX)
+}
+
+/// Whether a vault entry is an SSH secret rather than a profile's database credentials
+pub fn is_ssh_secret_id(id: &str) -> bool {
+    id.ends_with(SSH_SECRET_SUFFIX)
+}
+
+/// Read the SSH secret of a profile from the vault, if the profile uses a tunnel and one is stored
+pub async fn ssh_secret_from_vault(
+    vault: &CredentialVault,
+    profile_id: &str,
+    config: &AdvancedConnectionConfig,
+) -> Result<Option<String>, String> {
+    if config.ssh_tunnel.is_none() {
+        return Ok(None);
+    }
+
+    match vault.retrieve_credentials(&ssh_secret_id(profile_id)).await {
+        Ok(credentials) => Ok(Some(credentials.password.clone())),
+        Err(VaultError::ProfileNotFound(_)) => Ok(None),
+        Err(e) => Err(format!("Failed to retrieve SSH credentials: {}", e)),
+    }
+}
+
+/// Open a tunnel to the database host when the configuration asks for one
+pub async fn open_for_config(
+    config: &AdvancedConnectionConfig,
+    ssh_secret: Option<&str>,
+) -> Result<Option<SshTunnel>, String> {
+    match &config.ssh_tunnel {
+        Some(tunnel_config) => {
+            let tunnel = SshTunnel::open(
+                tunnel_config,
+                ssh_secret,
+                &config.host,
+                config.port,
+                config.connection_timeout,
+            )
+            .await?;
+            Ok(Some(tunnel))
+        }
+        None => Ok(None),
+    }
+}
+
+/// Local port forward through an SSH bastion host. The forward is closed when dropped.
+pub struct SshTunnel {
+    local_addr: SocketAddr,
+    accept_task: JoinHandle<()>,
+}
+
+impl SshTunnel {
+    /// Connect to the bastion, authenticate and start forwarding a local port
+    /// to `target_host:target_port` as seen from the bastion
+    pub async fn open(
+        config: &SshTunnelConfig,
+        secret: Option<&str>,
+        target_host: &str,
+        target_port: u16,
+        timeout: Duration,
+    ) -> Result<Self, String> {
+        let handle = tokio::time::timeout(timeout, Self::connect(config, secret))
+            .await
+            .map_err(|_| format!("SSH connection to {}:{} timed out", config.host, config.port))??;
+        let handle = Arc::new(handle);
+
+        let listener = TcpListener::bind(("127.0.0.1", config.local_port.unwrap_or(0)))
+            .await
+            .map_err(|e| format!("Failed to bind local SSH tunnel port: {}", e))?;
+        let local_addr = listener
+            .local_addr()
+            .map_err(|e| format!("Failed to bind local SSH tunnel port: {}", e))?;
+
+        let forward_host = target_host.to_string();
+        let accept_task = tokio::spawn(async move {
+            loop {
+                let (mut socket, peer) = match listener.accept().await {
+                    Ok(accepted) => accepted,
+                    Err(e) => {
+                        log::error!("SSH tunnel stopped accepting connections: {}", e);
+                        break;
+                    }
+                };
+
+                let handle = handle.clone();
+                let target_host = forward_host.clone();
+                tokio::spawn(async move {
+                    let channel = handle
+                        .channel_open_direct_tcpip(
+                            target_host.clone(),
+                            target_port as u32,
+                            peer.ip().to_string(),
+                            peer.port() as u32,
+                        )
+                        .await;
+
+                    match channel {
+                        Ok(channel) => {
+                            let mut stream = channel.into_stream();
+                            if let Err(e) = tokio::io::copy_bidirectional(&mut socket, &mut stream).await {
+                                log::debug!("SSH tunnel connection closed: {}", e);
+                            }
+                        }
+                        Err(e) => log::error!(
+                            "SSH tunnel could not open a channel to {}:{}: {}",
+                            target_host,
+                            target_port,
+                            e
+                        ),
+                    }
+                });
+            }
+        });
+
+        log::info!(
+            "SSH tunnel via {}:{} forwarding {} to {}:{}",
+            config.host,
+            config.port,
+            local_addr,
+            target_host,
+            target_port
+        );
+        Ok(Self {
+            local_addr,
+            accept_task,
+        })
+    }
+
+    /// Local address the database client should connect to
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    async fn connect(
+        config: &SshTunnelConfig,
+        secret: Option<&str>,
+    ) -> Result<client::Handle<HostKeyVerifier>, String> {
+        let ssh_config = Arc::new(client::Config {
+            keepalive_interval: Some(Duration::from_secs(30)),
+            ..Default::default()
+        });
+        let verifier = HostKeyVerifier {
+            host: config.host.clone(),
+            port: config.port,
+            strict: config.strict_host_key_checking,
+            known_hosts_path: config.known_hosts_path.as_ref().map(PathBuf::from),
+        };
+
+        let mut handle = client::connect(ssh_config, (config.host.as_str(), config.port), verifier)
+            .await
+            .map_err(|e| match e {
+                russh::Error::UnknownKey => {
+                    format!("SSH host key verification failed for {}:{}", config.host, config.port)
+                }
+                other => format!("SSH connection to {}:{} failed: {}", config.host, config.port, other),
+            })?;
+
+        let authenticated = match &config.auth {
+            SshAuthMethod::Password => {
+                let password = secret.ok_or("SSH password is not stored for this profile")?;
+                handle
+                    .authenticate_password(config.username.as_str(), password)
+                    .await
+            }
+            SshAuthMethod::PrivateKey { key_path } => {
+                let key_pair = russh_keys::load_secret_key(key_path, secret)
+                    .map_err(|e| format!("Failed to load SSH private key {}: {}", key_path, e))?;
+                handle
+                    .authenticate_publickey(config.username.as_str(), Arc::new(key_pair))
+                    .await
+            }
+        }
+        .map_err(|e| format!("SSH authentication error: {}", e))?;
+
+        if !authenticated {
+            return Err(format!(
+                "SSH authentication failed for {}@{}",
+                config.username, config.host
+            ));
+        }
+        Ok(handle)
+    }
+}
+
+impl Drop for SshTunnel {
+    fn drop(&mut self) {
+        // Dropping the accept loop also drops the SSH session handle
+        self.accept_task.abort();
+    }
+}
+
+/// Checks the bastion's host key against known_hosts
+struct HostKeyVerifier {
+    host: String,
+    port: u16,
+    strict: bool,
+    known_hosts_path: Option<PathBuf>,
+}
+
+#[async_trait]
+impl client::Handler for HostKeyVerifier {
+    type Error = russh::Error;
+
+    async fn check_server_key(&mut self, server_public_key: &key::PublicKey) -> Result<bool, Self::Error> {
+        let known = match &self.known_hosts_path {
+            Some(path) => russh_keys::check_known_hosts_path(&self.host, self.port, server_public_key, path),
+            None => russh_keys::check_known_hosts(&self.host, self.port, server_public_key),
+        };
+
+        match known {
+            Ok(true) => Ok(true),
+            Ok(false) if self.strict => {
+                log::error!("SSH host {}:{} is not in known_hosts", self.host, self.port);
+                Ok(false)
+            }
+            Ok(false) => {
+                // Trust on first use, like OpenSSH's StrictHostKeyChecking=accept-new
+                let learned = match &self.known_hosts_path {
+                    Some(path) => russh_keys::learn_known_hosts_path(&self.host, self.port, server_public_key, path),
+                    None => russh_keys::learn_known_hosts(&self.host, self.port, server_public_key),
+                };
+                if let Err(e) = learned {
+                    log::warn!("Could not record SSH host key for {}: {}", self.host, e);
+                }
+                Ok(true)
+            }
+            Err(e) => {
+                log::error!("SSH host key check failed for {}:{}: {}", self.host, self.port, e);
+                Ok(false)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use russh::server::{self, Auth, Msg, Session};
+    use russh::{Channel, MethodSet};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpStream;
+
+    /// Minimal sshd stand-in that accepts one password and forwards direct-tcpip channels
+    struct StandInServer;
+
+    #[async_trait]
+    impl server::Handler for StandInServer {
+        type Error = russh::Error;
+
+        async fn auth_password(&mut self, user: &str, password: &str) -> Result<Auth, Self::Error> {
+            if user == "tunnel" && password == "secret" {
+                Ok(Auth::Accept)
+            } else {
+                Ok(Auth::Reject { proceed_with_methods: None })
+            }
+        }
+
+        async fn channel_open_direct_tcpip(
+            &mut self,
+            channel: Channel<Msg>,
+            host_to_connect: &str,
+            port_to_connect: u32,
+            _originator_address: &str,
+            _originator_port: u32,
+            _session: &mut Session,
+        ) -> Result<bool, Self::Error> {
+            let target = format!("{}:{}", host_to_connect, port_to_connect);
+            tokio::spawn(async move {
+                if let Ok(mut upstream) = TcpStream::connect(target).await {
+                    let mut stream = channel.into_stream();
+                    let _ = tokio::io::copy_bidirectional(&mut upstream, &mut stream).await;
+                }
+            });
+            Ok(true)
+        }
+    }
+
+    async fn start_stand_in_sshd() -> u16 {
+        let config = Arc::new(server::Config {
+            methods: MethodSet::PASSWORD,
+            keys: vec![key::KeyPair::generate_ed25519().unwrap()],
+            auth_rejection_time: Duration::from_millis(10),
+            ..Default::default()
+        });
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        tokio::spawn(async move {
+            while let Ok((socket, _)) = listener.accept().await {
+                if let Ok(session) = server::run_stream(config.clone(), socket, StandInServer).await {
+                    tokio::spawn(session);
+                }
+            }
+        });
+        port
+    }
+
+    async fn start_echo_server() -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let (mut reader, mut writer) = socket.split();
+                    let _ = tokio::io::copy(&mut reader, &mut writer).await;
+                });
+            }
+        });
+        port
+    }
+
+    fn tunnel_config(port: u16, known_hosts: &std::path::Path, strict: bool) -> SshTunnelConfig {
+        SshTunnelConfig {
+            host: "127.0.0.1".to_string(),
+            port,
+            username: "tunnel".to_string(),
+            auth: SshAuthMethod::Password,
+            local_port: None,
+            strict_host_key_checking: strict,
+            known_hosts_path: Some(known_hosts.to_string_lossy().to_string()),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_tunnel_forwards_traffic() {
+        let dir = tempfile::tempdir().unwrap();
+        let known_hosts = dir.path().join("known_hosts");
+        let ssh_port = start_stand_in_sshd().await;
+        let echo_port = start_echo_server().await;
+
+        let config = tunnel_config(ssh_port, &known_hosts, false);
+        let tunnel = SshTunnel::open(&config, Some("secret"), "127.0.0.1", echo_port, Duration::from_secs(10))
+            .await
+            .unwrap();
+
+        let mut client = TcpStream::connect(tunnel.local_addr()).await.unwrap();
+        client.write_all(b"ping").await.unwrap();
+        let mut buf = [0u8; 4];
+        client.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"ping");
+
+        // The host key was trusted on first use
+        assert!(std::fs::read_to_string(&known_hosts).unwrap().contains("ssh-ed25519"));
+    }
+
+    #[tokio::test]
+    async fn test_tunnel_rejects_bad_password() {
+        let dir = tempfile::tempdir().unwrap();
+        let ssh_port = start_stand_in_sshd().await;
+
+        let config = tunnel_config(ssh_port, &dir.path().join("known_hosts"), false);
+        let result = SshTunnel::open(&config, Some("wrong"), "127.0.0.1", 5432, Duration::from_secs(10)).await;
+        assert!(result.err().unwrap().contains("authentication failed"));
+    }
+
+    #[tokio::test]
+    async fn test_strict_host_key_checking_rejects_unknown_host() {
+        let dir = tempfile::tempdir().unwrap();
+        let ssh_port = start_stand_in_sshd().await;
+
+        let config = tunnel_config(ssh_port, &dir.path().join("known_hosts"), true);
+        let result = SshTunnel::open(&config, Some("secret"), "127.0.0.1", 5432, Duration::from_secs(10)).await;
+        assert!(result.err().unwrap().contains("host key verification failed"));
+    }
+}
@@ -0,0 +1,556 @@
+//! Streams a table or query result to a file.
+//!
+//! Formats the server can write itself (CSV) are streamed with `COPY ... TO STDOUT`.
+//! The others are fetched in batches from a portal and written by the chosen
+//! `export_format` writer. Either way rows go into the (optionally compressed) file as
+//! they arrive, so exports are not limited by memory (XLSX excepted, as workbooks are
+//! built whole). The file is written next to the target as `<path>.part` and renamed
+//! once complete, so a cancelled or failed export never leaves a truncated file behind
+//! under the chosen name.
+
+use crate::export_format::{self, ExportFormatOptions, RowWriter, SharedBuffer};
+use crate::production_guard::Access;
+use crate::session_registry::DatabaseSession;
+use crate::simple_db::{self, QueryCanceller};
+use crate::sql_builder::qualified_name;
+use crate::sql_splitter;
+use crate::value_codec::{self, SessionTimeZone};
+use async_compression::tokio::write::{GzipEncoder, ZstdEncoder};
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncWrite, AsyncWriteExt, BufWriter};
+use tokio::sync::Mutex;
+
+/// Event reporting how far an export has got; the last one has `done` set
+pub const TABLE_EXPORT_PROGRESS_EVENT: &str = "table-export-progress";
+
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+const FETCH_BATCH_SIZE: i32 = 1000;
+
+/// Tauri state for running table exports
+pub type TableExportRegistryState = Arc<Mutex<TableExportRegistry>>;
+
+/// What an export reads its rows from
+#[derive(Debug, Clone)]
+pub enum ExportSource {
+    Table { schema: String, table: String },
+    Query(String),
+}
+
+impl ExportSource {
+    pub(crate) fn select_sql(&self) -> String {
+        match self {
+            ExportSource::Table { schema, table } => format!("SELECT * FROM {}", qualified_name(schema, table)),
+            // The splitter drops the semicolon and any comment after it
+            ExportSource::Query(query) => match sql_splitter::split_statements(query) {
+                Ok(statements) if statements.len() == 1 => statements[0].sql.clone(),
+                _ => query.trim().trim_end_matches(';').to_string(),
+            },
+        }
+    }
+
+    /// `COPY (SELECT ...) TO STDOUT` of the source with `copy_options`. A bare `COPY table`
+    /// would fail for views and partitioned tables; the closing parenthesis goes on its own
+    /// line so a trailing `-- comment` cannot swallow it.
+    fn copy_sql(&self, copy_options: &str) -> String {
+        format!("COPY (\n{}\n) TO STDOUT WITH ({})", self.select_sql(), copy_options)
+    }
+
+    fn describe(&self) -> String {
+        match self {
+            ExportSource::Table { schema, table } => format!("{}.{}", schema, table),
+            ExportSource::Query(_) => "query".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportCompression {
+    #[default]
+    None,
+    Gzip,
+    Zstd,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TableExportOptions {
+    /// Name of one of `export_format::FORMATS`, e.g. `csv` or `parquet`
+    pub format: String,
+    /// CSV and XLSX: write a header row; true by default
+    #[serde(default)]
+    pub include_headers: Option<bool>,
+    /// CSV: single character; `,` by default
+    #[serde(default)]
+    pub delimiter: Option<String>,
+    /// JSON: indent each row
+    #[serde(default)]
+    pub pretty_json: bool,
+    #[serde(default)]
+    pub compression: ExportCompression,
+}
+
+impl TableExportOptions {
+    fn format_options(&self) -> Result<ExportFormatOptions, String> {
+        let delimiter = self.delimiter.as_deref().unwrap_or(",");
+        if delimiter.len() != 1 {
+            return Err(format!("CSV delimiter must be a single ASCII character, got '{}'", delimiter));
+        }
+        Ok(ExportFormatOptions {
+            include_headers: self.include_headers.unwrap_or(true),
+            delimiter: delimiter.as_bytes()[0],
+            pretty_json: self.pretty_json,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TableExportStatus {
+    Running,
+    Completed,
+    Cancelled,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TableExportProgress {
+    pub export_id: String,
+    pub status: TableExportStatus,
+    pub rows_written: u64,
+    /// Bytes of data before compression
+    pub bytes_written: u64,
+    /// Row count from the planner's statistics, when it has any
+    pub estimated_rows: Option<u64>,
+    pub file_path: String,
+    pub execution_time_ms: u64,
+    pub error: Option<String>,
+    pub done: bool,
+}
+
+/// Where progress events go; the app emits them as Tauri events
+pub trait TableExportSink: Send + Sync + 'static {
+    fn progress(&self, progress: TableExportProgress);
+}
+
+impl<R: tauri::Runtime> TableExportSink for tauri::AppHandle<R> {
+    fn progress(&self, progress: TableExportProgress) {
+        use tauri::Emitter;
+        if let Err(e) = self.emit(TABLE_EXPORT_PROGRESS_EVENT, progress) {
+            log::error!("Failed to emit table export progress: {}", e);
+        }
+    }
+}
+
+/// Cancellation shared between a running export and the registry
+#[derive(Clone, Default)]
+pub struct ExportControl {
+    cancelled: Arc<AtomicBool>,
+    /// Stops the running COPY or fetch on the server, so a cancel does not wait for it
+    canceller: Arc<std::sync::Mutex<Option<QueryCanceller>>>,
+}
+
+impl ExportControl {
+    fn set_canceller(&self, canceller: QueryCanceller) {
+        *self.canceller.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) = Some(canceller);
+    }
+
+    pub async fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+        let canceller = self.canceller.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).take();
+        if let Some(canceller) = canceller {
+            if let Err(e) = canceller.cancel().await {
+                log::warn!("Failed to cancel table export: {}", e);
+            }
+        }
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+}
+
+/// Running exports keyed by export id
+#[derive(Default)]
+pub struct TableExportRegistry {
+    exports: HashMap<String, ExportControl>,
+}
+
+impl TableExportRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a new export and return its id
+    pub fn register(&mut self) -> (String, ExportControl) {
+        let export_id = uuid::Uuid::new_v4().to_string();
+        let control = ExportControl::default();
+        self.exports.insert(export_id.clone(), control.clone());
+        (export_id, control)
+    }
+
+    /// Take a running export out of the registry so it can be cancelled
+    pub fn take(&mut self, export_id: &str) -> Option<ExportControl> {
+        self.exports.remove(export_id)
+    }
+}
+
+fn compressed_writer(file: tokio::fs::File, compression: ExportCompression) -> Box<dyn AsyncWrite + Unpin + Send> {
+    let file = BufWriter::new(file);
+    match compression {
+        ExportCompression::None => Box::new(file),
+        ExportCompression::Gzip => Box::new(GzipEncoder::new(file)),
+        ExportCompression::Zstd => Box::new(ZstdEncoder::new(file)),
+    }
+}
+
+#[derive(Default)]
+struct ExportProgress {
+    rows_written: u64,
+    bytes_written: u64,
+    estimated_rows: Option<u64>,
+}
+
+/// Export `source` to `file_path`, reporting progress to `sink` until the final event,
+/// which is also returned
+#[allow(clippy::too_many_arguments)]
+pub async fn run_table_export<S: TableExportSink>(
+    session: Arc<DatabaseSession>,
+    registry: TableExportRegistryState,
+    export_id: String,
+    source: ExportSource,
+    file_path: String,
+    options: TableExportOptions,
+    access: Access,
+    control: ExportControl,
+    sink: S,
+) -> TableExportProgress {
+    let start_time = Instant::now();
+    let mut progress = ExportProgress::default();
+    let part_path = format!("{}.part", file_path);
+
+    let result = export_rows(
+        &session, &export_id, &source, &file_path, &part_path, &options, access, &control, &sink, &mut progress,
+    )
+    .await;
+    registry.lock().await.take(&export_id);
+
+    let (status, error) = match result {
+        Ok(()) if control.is_cancelled() => (TableExportStatus::Cancelled, None),
+        Ok(()) => (TableExportStatus::Completed, None),
+        // Cancelling the COPY or fetch on the server surfaces as an error
+        Err(_) if control.is_cancelled() => (TableExportStatus::Cancelled, None),
+        Err(e) => (TableExportStatus::Failed, Some(e)),
+    };
+    if status != TableExportStatus::Completed {
+        tokio::fs::remove_file(&part_path).await.ok();
+    }
+    println!(
+        "🦀 [TableExport] Export {} of {} finished: {:?}, {} rows",
+        export_id,
+        source.describe(),
+        status,
+        progress.rows_written
+    );
+
+    let end = TableExportProgress {
+        export_id,
+        status,
+        rows_written: progress.rows_written,
+        bytes_written: progress.bytes_written,
+        estimated_rows: progress.estimated_rows,
+        file_path,
+        execution_time_ms: start_time.elapsed().as_millis() as u64,
+        error,
+        done: true,
+    };
+    sink.progress(end.clone());
+    end
+}
+
+/// Move what the format writer has produced so far into the file
+async fn drain(
+    buffer: &SharedBuffer,
+    writer: &mut (dyn AsyncWrite + Unpin + Send),
+    part_path: &str,
+    progress: &mut ExportProgress,
+) -> Result<(), String> {
+    let bytes = buffer.take();
+    writer
+        .write_all(&bytes)
+        .await
+        .map_err(|e| format!("Failed to write {}: {}", part_path, e))?;
+    progress.bytes_written += bytes.len() as u64;
+    Ok(())
+}
+
+fn report_progress<S: TableExportSink>(sink: &S, export_id: &str, file_path: &str, progress: &ExportProgress) {
+    sink.progress(TableExportProgress {
+        export_id: export_id.to_string(),
+        status: TableExportStatus::Running,
+        rows_written: progress.rows_written,
+        bytes_written: progress.bytes_written,
+        estimated_rows: progress.estimated_rows,
+        file_path: file_path.to_string(),
+        execution_time_ms: 0,
+        error: None,
+        done: false,
+    });
+}
+
+fn export_error(e: tokio_postgres::Error) -> String {
+    format!("Export failed: {}", simple_db::db_error_message(&e))
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn export_rows<S: TableExportSink>(
+    session: &DatabaseSession,
+    export_id: &str,
+    source: &ExportSource,
+    file_path: &str,
+    part_path: &str,
+    options: &TableExportOptions,
+    access: Access,
+    control: &ExportControl,
+    sink: &S,
+    progress: &mut ExportProgress,
+) -> Result<(), String> {
+    let format = export_format::find_format(&options.format)?;
+    let format_options = options.format_options()?;
+    let (mut client, tls) = {
+        let pool = session.pool.lock().await;
+        (pool.get_connection().await?, pool.tls_connector())
+    };
+    if let Some(tls) = tls {
+        control.set_canceller(QueryCanceller::new(client.cancel_token(), tls));
+    }
+
+    if let ExportSource::Table { schema, table } = source {
+        let estimate = client
+            .query_one(
+                "SELECT reltuples::bigint FROM pg_class WHERE oid = to_regclass($1)",
+                &[&qualified_name(schema, table)],
+            )
+            .await;
+        progress.estimated_rows = estimate.ok().map(|row| row.get::<_, i64>(0)).filter(|&n| n > 0).map(|n| n as u64);
+    }
+
+    let zone = value_codec::read_time_zone(&client).await;
+    // Dropping the transaction on an early return rolls it back and closes the cursor
+    let transaction = client
+        .build_transaction()
+        .read_only(access.is_read_only())
+        .start()
+        .await
+        .map_err(export_error)?;
+    // Pooled connections start with the interactive query timeout, which would cancel
+    // exactly the large exports this is for
+    transaction
+        .batch_execute("SET LOCAL statement_timeout = 0")
+        .await
+        .map_err(export_error)?;
+
+    let file = tokio::fs::File::create(part_path)
+        .await
+        .map_err(|e| format!("Failed to create {}: {}", part_path, e))?;
+    let mut writer = compressed_writer(file, options.compression);
+    let target = ExportTarget { export_id, file_path, part_path, control, sink };
+    match format.copy_options(&format_options) {
+        Some(copy_options) => {
+            let copy_sql = source.copy_sql(&copy_options);
+            copy_rows(&transaction, &copy_sql, format_options.include_headers, writer.as_mut(), &target, progress).await?
+        }
+        None => {
+            let statement = transaction.prepare(&source.select_sql()).await.map_err(export_error)?;
+            let buffer = SharedBuffer::default();
+            let rows_writer = format.writer(
+                &value_codec::column_types(statement.columns()),
+                &format_options,
+                Box::new(buffer.clone()),
+            )?;
+            fetch_rows(&transaction, &statement, &zone, rows_writer, &buffer, writer.as_mut(), &target, progress).await?
+        }
+    }
+    if control.is_cancelled() {
+        return Ok(());
+    }
+
+    // Flushes the buffer and writes the compression trailer
+    writer
+        .shutdown()
+        .await
+        .map_err(|e| format!("Failed to write {}: {}", part_path, e))?;
+    transaction
+        .commit()
+        .await
+        .map_err(|e| format!("Failed to commit transaction: {}", e))?;
+    tokio::fs::rename(part_path, file_path)
+        .await
+        .map_err(|e| format!("Failed to move export to {}: {}", file_path, e))
+}
+
+/// Where a running export reports to
+struct ExportTarget<'a, S> {
+    export_id: &'a str,
+    file_path: &'a str,
+    part_path: &'a str,
+    control: &'a ExportControl,
+    sink: &'a S,
+}
+
+/// Stream the file straight from `COPY ... TO STDOUT`
+async fn copy_rows<S: TableExportSink>(
+    transaction: &tokio_postgres::Transaction<'_>,
+    copy_sql: &str,
+    header: bool,
+    writer: &mut (dyn AsyncWrite + Unpin + Send),
+    target: &ExportTarget<'_, S>,
+    progress: &mut ExportProgress,
+) -> Result<(), String> {
+    let stream = transaction.copy_out(copy_sql).await.map_err(export_error)?;
+    let mut stream = std::pin::pin!(stream);
+    // The server sends the CSV header as its own message
+    let mut header_pending = header;
+
+    let mut last_progress = Instant::now();
+    // Each message from the server holds exactly one row
+    while let Some(message) = stream.next().await {
+        let message = message.map_err(export_error)?;
+        if target.control.is_cancelled() {
+            return Ok(());
+        }
+        writer
+            .write_all(&message)
+            .await
+            .map_err(|e| format!("Failed to write {}: {}", target.part_path, e))?;
+        progress.bytes_written += message.len() as u64;
+        if std::mem::take(&mut header_pending) {
+            continue;
+        }
+        progress.rows_written += 1;
+
+        if last_progress.elapsed() >= PROGRESS_INTERVAL {
+            report_progress(target.sink, target.export_id, target.file_path, progress);
+            last_progress = Instant::now();
+        }
+    }
+    Ok(())
+}
+
+/// Fetch `statement`'s rows in batches from a portal and hand them to the format writer
+#[allow(clippy::too_many_arguments)]
+async fn fetch_rows<S: TableExportSink>(
+    transaction: &tokio_postgres::Transaction<'_>,
+    statement: &tokio_postgres::Statement,
+    zone: &SessionTimeZone,
+    mut rows_writer: Box<dyn RowWriter>,
+    buffer: &SharedBuffer,
+    writer: &mut (dyn AsyncWrite + Unpin + Send),
+    target: &ExportTarget<'_, S>,
+    progress: &mut ExportProgress,
+) -> Result<(), String> {
+    let portal = transaction.bind(statement, &[]).await.map_err(export_error)?;
+
+    let mut last_progress = Instant::now();
+    loop {
+        if target.control.is_cancelled() {
+            return Ok(());
+        }
+        let rows = transaction
+            .query_portal(&portal, FETCH_BATCH_SIZE)
+            .await
+            .map_err(export_error)?;
+        for row in &rows {
+            rows_writer.write_row(&value_codec::decode_row(row, zone))?;
+        }
+        progress.rows_written += rows.len() as u64;
+        drain(buffer, writer, target.part_path, progress).await?;
+
+        if rows.len() < FETCH_BATCH_SIZE as usize {
+            break;
+        }
+        if last_progress.elapsed() >= PROGRESS_INTERVAL {
+            report_progress(target.sink, target.export_id, target.file_path, progress);
+            last_progress = Instant::now();
+        }
+    }
+
+    rows_writer.finish()?;
+    drain(buffer, writer, target.part_path, progress).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(format: &str) -> TableExportOptions {
+        TableExportOptions {
+            format: format.to_string(),
+            include_headers: None,
+            delimiter: None,
+            pretty_json: false,
+            compression: ExportCompression::None,
+        }
+    }
+
+    #[test]
+    fn test_format_options() {
+        let mut csv = options("csv");
+        csv.delimiter = Some(";".to_string());
+        let format_options = csv.format_options().unwrap();
+        assert_eq!(format_options.delimiter, b';');
+        assert!(format_options.include_headers);
+        csv.delimiter = Some("§".to_string());
+        assert!(csv.format_options().is_err());
+    }
+
+    #[test]
+    fn test_source_select_sql() {
+        let table = ExportSource::Table {
+            schema: "s".to_string(),
+            table: "t".to_string(),
+        };
+        assert_eq!(table.select_sql(), "SELECT * FROM \"s\".\"t\"");
+        assert_eq!(ExportSource::Query(" SELECT 1;\n".to_string()).select_sql(), "SELECT 1");
+        assert_eq!(ExportSource::Query("SELECT 1; -- done\n".to_string()).select_sql(), "SELECT 1");
+    }
+
+    #[test]
+    fn test_csv_is_copied_by_the_server() {
+        let mut csv = options("csv");
+        csv.delimiter = Some("'".to_string());
+        let format_options = csv.format_options().unwrap();
+        let copy_options = export_format::find_format("csv").unwrap().copy_options(&format_options).unwrap();
+        let table = ExportSource::Table {
+            schema: "public".to_string(),
+            table: "t".to_string(),
+        };
+        assert_eq!(
+            table.copy_sql(&copy_options),
+            "COPY (\nSELECT * FROM \"public\".\"t\"\n) TO STDOUT WITH (FORMAT csv, HEADER true, DELIMITER '''')"
+        );
+        assert_eq!(
+            ExportSource::Query("SELECT 1;".to_string()).copy_sql("FORMAT csv"),
+            "COPY (\nSELECT 1\n) TO STDOUT WITH (FORMAT csv)"
+        );
+        assert_eq!(
+            ExportSource::Query("SELECT 1 -- all of it".to_string()).copy_sql("FORMAT csv"),
+            "COPY (\nSELECT 1 -- all of it\n) TO STDOUT WITH (FORMAT csv)"
+        );
+        assert!(export_format::find_format("parquet").unwrap().copy_options(&format_options).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_cancel_marks_export_cancelled() {
+        let mut registry = TableExportRegistry::new();
+        let (export_id, control) = registry.register();
+        let taken = registry.take(&export_id).unwrap();
+        taken.cancel().await;
+        assert!(control.is_cancelled());
+        assert!(registry.take(&export_id).is_none());
+    }
+}
@@ -0,0 +1,621 @@
+//! Imports CSV, JSON and NDJSON files into a table.
+//!
+//! Rows are bulk loaded with `COPY ... FROM STDIN` in batches, each under a savepoint.
+//! When a batch fails its rows are retried one by one, so a bad row is reported and
+//! skipped instead of failing the whole import.
+
+use crate::simple_db;
+use crate::sql_builder::{qualified_name, quote_ident};
+use bytes::Bytes;
+use futures_util::SinkExt;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use tokio_postgres::Client;
+
+/// Rows per `COPY`
+const BATCH_SIZE: usize = 5000;
+/// Bytes of COPY data buffered before sending
+const COPY_CHUNK_SIZE: usize = 64 * 1024;
+/// Row errors kept in the result; later ones are only counted
+const MAX_ROW_ERRORS: usize = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TableImportFormat {
+    Csv,
+    /// An array of objects
+    Json,
+    /// One object per line
+    Ndjson,
+}
+
+impl TableImportFormat {
+    pub fn parse(format: &str) -> Result<Self, String> {
+        match format.to_lowercase().as_str() {
+            "csv" => Ok(TableImportFormat::Csv),
+            "json" => Ok(TableImportFormat::Json),
+            "ndjson" | "jsonl" => Ok(TableImportFormat::Ndjson),
+            other => Err(format!("Unsupported import format: {}", other)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CsvOptions {
+    /// Single character; `,` by default
+    pub delimiter: Option<String>,
+    /// Single character; `"` by default
+    pub quote: Option<String>,
+    /// Escape character inside quotes; quotes are escaped by doubling them when not set
+    pub escape: Option<String>,
+    /// The first row names the columns; true by default
+    pub has_header: Option<bool>,
+    /// Field value read as NULL; empty fields by default
+    pub null_marker: Option<String>,
+    /// WHATWG encoding label such as `windows-1252`; UTF-8 by default
+    pub encoding: Option<String>,
+}
+
+/// Everything needed to import one file into one table
+#[derive(Debug, Clone)]
+pub struct TableImportRequest {
+    pub format: TableImportFormat,
+    pub schema: String,
+    pub table: String,
+    pub csv: CsvOptions,
+    /// Source column to target column; unmapped source columns are skipped
+    pub column_mapping: Option<HashMap<String, String>>,
+    pub create_table: bool,
+    pub truncate: bool,
+    /// Roll back everything at the first bad row instead of skipping it
+    pub stop_on_error: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ImportRowError {
+    /// 1-based data row (CSV record or JSON object) in the file
+    pub row: usize,
+    pub error: String,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct TableImportSummary {
+    pub rows_imported: u64,
+    pub rows_failed: usize,
+    /// The first `MAX_ROW_ERRORS` row errors
+    pub row_errors: Vec<ImportRowError>,
+    pub warnings: Vec<String>,
+    pub created_table: bool,
+    /// Nothing was applied because of `stop_on_error`
+    pub rolled_back: bool,
+}
+
+impl TableImportSummary {
+    fn row_error(&mut self, row: usize, error: String) {
+        self.rows_failed += 1;
+        if self.row_errors.len() < MAX_ROW_ERRORS {
+            self.row_errors.push(ImportRowError { row, error });
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct SourceRow {
+    number: usize,
+    values: Vec<Option<String>>,
+}
+
+/// Parsed file: column names and rows of values in the same order
+#[derive(Debug, Default)]
+struct SourceData {
+    columns: Vec<String>,
+    rows: Vec<SourceRow>,
+    errors: Vec<ImportRowError>,
+}
+
+fn decode(bytes: &[u8], encoding: Option<&str>) -> Result<String, String> {
+    let label = encoding.unwrap_or("utf-8");
+    let encoding = encoding_rs::Encoding::for_label(label.as_bytes())
+        .ok_or_else(|| format!("Unknown encoding: {}", label))?;
+    // Strips a byte order mark, which also overrides the requested encoding
+    let (text, used, had_errors) = encoding.decode(bytes);
+    if had_errors {
+        return Err(format!("The file is not valid {}", used.name()));
+    }
+    Ok(text.into_owned())
+}
+
+fn single_byte(value: &Option<String>, default: u8, name: &str) -> Result<u8, String> {
+    match value.as_deref() {
+        None => Ok(default),
+        Some(value) if value.len() == 1 => Ok(value.as_bytes()[0]),
+        Some(value) => Err(format!("CSV {} must be a single ASCII character, got '{}'", name, value)),
+    }
+}
+
+fn parse_csv(text: &str, options: &CsvOptions) -> Result<SourceData, String> {
+    let has_header = options.has_header.unwrap_or(true);
+    let null_marker = options.null_marker.as_deref().unwrap_or("");
+    let mut builder = csv::ReaderBuilder::new();
+    builder
+        .delimiter(single_byte(&options.delimiter, b',', "delimiter")?)
+        .quote(single_byte(&options.quote, b'"', "quote")?)
+        .has_headers(has_header)
+        .flexible(true);
+    if options.escape.is_some() {
+        builder.escape(Some(single_byte(&options.escape, b'\\', "escape")?)).double_quote(false);
+    }
+    let mut reader = builder.from_reader(text.as_bytes());
+
+    let mut data = SourceData::default();
+    if has_header {
+        let headers = reader.headers().map_err(|e| format!("Failed to read CSV header: {}", e))?;
+        data.columns = headers
+            .iter()
+            .enumerate()
+            .map(|(i, name)| if name.trim().is_empty() { format!("column{}", i + 1) } else { name.trim().to_string() })
+            .collect();
+    }
+
+    for (i, record) in reader.records().enumerate() {
+        let number = i + 1;
+        let record = match record {
+            Ok(record) => record,
+            Err(e) => {
+                data.errors.push(ImportRowError { row: number, error: e.to_string() });
+                continue;
+            }
+        };
+        if data.columns.is_empty() {
+            data.columns = (1..=record.len()).map(|n| format!("column{}", n)).collect();
+        }
+        if record.len() != data.columns.len() {
+            data.errors.push(ImportRowError {
+                row: number,
+                error: format!("Expected {} fields, found {}", data.columns.len(), record.len()),
+            });
+            continue;
+        }
+        let values = record
+            .iter()
+            .map(|field| if field == null_marker { None } else { Some(field.to_string()) })
+            .collect();
+        data.rows.push(SourceRow { number, values });
+    }
+    Ok(data)
+}
+
+fn json_text(value: serde_json::Value) -> Option<String> {
+    match value {
+        serde_json::Value::Null => None,
+        serde_json::Value::String(s) => Some(s),
+        other => Some(other.to_string()),
+    }
+}
+
+/// Columns are the keys of all objects, in the order they are first seen
+fn parse_json(text: &str, format: TableImportFormat) -> Result<SourceData, String> {
+    let mut objects = Vec::new();
+    let mut data = SourceData::default();
+    if format == TableImportFormat::Ndjson {
+        let lines = text.lines().enumerate().filter(|(_, line)| !line.trim().is_empty());
+        for (number, line) in lines.map(|(i, line)| (i + 1, line)) {
+            match serde_json::from_str(line) {
+                Ok(value) => objects.push((number, value)),
+                Err(e) => data.errors.push(ImportRowError { row: number, error: format!("Invalid JSON: {}", e) }),
+            }
+        }
+    } else {
+        match serde_json::from_str(text).map_err(|e| format!("Invalid JSON: {}", e))? {
+            serde_json::Value::Array(values) => objects.extend(values.into_iter().enumerate().map(|(i, v)| (i + 1, v))),
+            object @ serde_json::Value::Object(_) => objects.push((1, object)),
+            _ => return Err("JSON imports need an array of objects".to_string()),
+        }
+    }
+
+    let mut index: HashMap<String, usize> = HashMap::new();
+    let mut rows = Vec::with_capacity(objects.len());
+    for (number, value) in objects {
+        let serde_json::Value::Object(object) = value else {
+            data.errors.push(ImportRowError { row: number, error: "Row is not a JSON object".to_string() });
+            continue;
+        };
+        let mut values = Vec::new();
+        for (key, value) in object {
+            let position = *index.entry(key.clone()).or_insert_with(|| {
+                data.columns.push(key);
+                data.columns.len() - 1
+            });
+            values.push((position, json_text(value)));
+        }
+        rows.push((number, values));
+    }
+
+    // Objects seen before a key first appeared get NULL for it
+    data.rows = rows
+        .into_iter()
+        .map(|(number, values)| {
+            let mut row = vec![None; data.columns.len()];
+            for (position, value) in values {
+                row[position] = value;
+            }
+            SourceRow { number, values: row }
+        })
+        .collect();
+    Ok(data)
+}
+
+/// Narrowest type that accepts every value, for columns of a new table
+pub fn infer_column_type<'a>(values: impl Iterator<Item = &'a str>) -> &'static str {
+    const TYPES: [&str; 7] = ["boolean", "bigint", "numeric", "date", "timestamp", "timestamptz", "jsonb"];
+    let mut candidates = [true; 7];
+    let mut seen = false;
+    for value in values {
+        seen = true;
+        let checks = [
+            matches!(value.to_lowercase().as_str(), "true" | "false" | "t" | "f"),
+            value.parse::<i64>().is_ok(),
+            value.parse::<f64>().is_ok() && value.chars().all(|c| c.is_ascii_digit() || "+-.eE".contains(c)),
+            chrono::NaiveDate::parse_from_str(value, "%Y-%m-%d").is_ok(),
+            chrono::NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S%.f").is_ok()
+                || chrono::NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S%.f").is_ok(),
+            chrono::DateTime::parse_from_rfc3339(value).is_ok(),
+            (value.starts_with('{') || value.starts_with('['))
+                && serde_json::from_str::<serde_json::Value>(value).is_ok(),
+        ];
+        for (candidate, ok) in candidates.iter_mut().zip(checks) {
+            *candidate &= ok;
+        }
+        if !candidates.contains(&true) {
+            break;
+        }
+    }
+
+    match candidates.iter().position(|&candidate| candidate) {
+        Some(i) if seen => TYPES[i],
+        _ => "text",
+    }
+}
+
+/// Pair source columns with target columns. `table_columns` is `None` for a table that
+/// is about to be created from the source columns.
+fn plan_columns(
+    source: &[String],
+    mapping: Option<&HashMap<String, String>>,
+    table_columns: Option<&[String]>,
+    warnings: &mut Vec<String>,
+) -> Result<Vec<(usize, String)>, String> {
+    if let Some(mapping) = mapping {
+        if let Some(missing) = mapping.keys().find(|key| !source.contains(key)) {
+            return Err(format!("Mapped column '{}' is not in the file", missing));
+        }
+    }
+
+    let mut planned = Vec::new();
+    let mut targets = HashSet::new();
+    for (index, name) in source.iter().enumerate() {
+        let target = match mapping {
+            Some(mapping) => match mapping.get(name) {
+                Some(target) => target.clone(),
+                None => continue,
+            },
+            None => name.clone(),
+        };
+        let target = match table_columns {
+            None => target,
+            Some(columns) => match columns.iter().find(|c| **c == target) {
+                Some(column) => column.clone(),
+                None => match columns.iter().find(|c| c.eq_ignore_ascii_case(&target)) {
+                    Some(column) => column.clone(),
+                    None if mapping.is_some() => return Err(format!("Column '{}' does not exist in the table", target)),
+                    None => {
+                        warnings.push(format!("Column '{}' is not in the table and was skipped", name));
+                        continue;
+                    }
+                },
+            },
+        };
+        if !targets.insert(target.clone()) {
+            return Err(format!("More than one source column maps to '{}'", target));
+        }
+        planned.push((index, target));
+    }
+
+    if planned.is_empty() {
+        return Err("None of the file's columns match the table".to_string());
+    }
+    Ok(planned)
+}
+
+/// Append a value in COPY text format
+fn push_copy_value(value: Option<&str>, out: &mut String) {
+    let Some(value) = value else {
+        out.push_str("\\N");
+        return;
+    };
+    for c in value.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '\t' => out.push_str("\\t"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            c => out.push(c),
+        }
+    }
+}
+
+fn push_copy_row(row: &SourceRow, columns: &[(usize, String)], out: &mut String) {
+    for (i, (index, _)) in columns.iter().enumerate() {
+        if i > 0 {
+            out.push('\t');
+        }
+        push_copy_value(row.values[*index].as_deref(), out);
+    }
+    out.push('\n');
+}
+
+async fn copy_rows(
+    client: &Client,
+    copy_sql: &str,
+    rows: &[SourceRow],
+    columns: &[(usize, String)],
+) -> Result<u64, tokio_postgres::Error> {
+    let mut sink = Box::pin(client.copy_in::<_, Bytes>(copy_sql).await?);
+    let mut buffer = String::new();
+    for row in rows {
+        push_copy_row(row, columns, &mut buffer);
+        if buffer.len() >= COPY_CHUNK_SIZE {
+            sink.send(Bytes::from(std::mem::take(&mut buffer))).await?;
+        }
+    }
+    if !buffer.is_empty() {
+        sink.send(Bytes::from(buffer)).await?;
+    }
+    sink.as_mut().finish().await
+}
+
+async fn table_columns(client: &Client, qualified: &str) -> Result<Option<Vec<String>>, String> {
+    let exists = client
+        .query_one("SELECT to_regclass($1) IS NOT NULL", &[&qualified])
+        .await
+        .map_err(|e| format!("Failed to look up table: {}", simple_db::db_error_message(&e)))?;
+    if !exists.get::<_, bool>(0) {
+        return Ok(None);
+    }
+    let rows = client
+        .query(
+            "SELECT attname::text FROM pg_attribute
+             WHERE attrelid = to_regclass($1) AND attnum > 0 AND NOT attisdropped
+             ORDER BY attnum",
+            &[&qualified],
+        )
+        .await
+        .map_err(|e| format!("Failed to read table columns: {}", simple_db::db_error_message(&e)))?;
+    Ok(Some(rows.iter().map(|row| row.get(0)).collect()))
+}
+
+/// Load the rows, retrying a failed batch row by row. Returns false when it stopped
+/// because of `stop_on_error`.
+async fn load_rows(
+    client: &Client,
+    copy_sql: &str,
+    data: &SourceData,
+    columns: &[(usize, String)],
+    stop_on_error: bool,
+    summary: &mut TableImportSummary,
+) -> Result<bool, String> {
+    let execute = |sql: &'static str| async move {
+        client
+            .batch_execute(sql)
+            .await
+            .map_err(|e| format!("Import failed: {}", simple_db::db_error_message(&e)))
+    };
+
+    for batch in data.rows.chunks(BATCH_SIZE) {
+        execute("SAVEPOINT import_batch").await?;
+        if let Ok(rows) = copy_rows(client, copy_sql, batch, columns).await {
+            execute("RELEASE SAVEPOINT import_batch").await?;
+            summary.rows_imported += rows;
+            continue;
+        }
+        execute("ROLLBACK TO SAVEPOINT import_batch").await?;
+
+        for row in batch {
+            execute("SAVEPOINT import_row").await?;
+            match copy_rows(client, copy_sql, std::slice::from_ref(row), columns).await {
+                Ok(rows) => {
+                    execute("RELEASE SAVEPOINT import_row").await?;
+                    summary.rows_imported += rows;
+                }
+                Err(e) => {
+                    execute("ROLLBACK TO SAVEPOINT import_row").await?;
+                    execute("RELEASE SAVEPOINT import_row").await?;
+                    summary.row_error(row.number, simple_db::db_error_message(&e));
+                    if stop_on_error {
+                        return Ok(false);
+                    }
+                }
+            }
+        }
+        execute("RELEASE SAVEPOINT import_batch").await?;
+    }
+    Ok(true)
+}
+
+/// Import a CSV or JSON file into a table, in one transaction
+pub async fn import_table(client: &Client, bytes: &[u8], request: &TableImportRequest) -> Result<TableImportSummary, String> {
+    let text = decode(bytes, request.csv.encoding.as_deref())?;
+    let data = match request.format {
+        TableImportFormat::Csv => parse_csv(&text, &request.csv)?,
+        format => parse_json(&text, format)?,
+    };
+    drop(text);
+    println!(
+        "🦀 [Import] Parsed {} rows with {} columns, {} bad rows",
+        data.rows.len(),
+        data.columns.len(),
+        data.errors.len()
+    );
+
+    let mut summary = TableImportSummary::default();
+    for error in &data.errors {
+        summary.row_error(error.row, error.error.clone());
+    }
+    if request.stop_on_error && summary.rows_failed > 0 {
+        summary.rolled_back = true;
+        return Ok(summary);
+    }
+
+    let qualified = qualified_name(&request.schema, &request.table);
+    let existing = table_columns(client, &qualified).await?;
+    if existing.is_none() && !request.create_table {
+        return Err(format!("Table {} does not exist", qualified));
+    }
+    let columns = plan_columns(
+        &data.columns,
+        request.column_mapping.as_ref(),
+        existing.as_deref(),
+        &mut summary.warnings,
+    )?;
+    let column_list = columns.iter().map(|(_, name)| quote_ident(name)).collect::<Vec<_>>().join(", ");
+    let copy_sql = format!("COPY {} ({}) FROM STDIN", qualified, column_list);
+
+    client
+        .batch_execute("BEGIN")
+        .await
+        .map_err(|e| format!("Failed to start transaction: {}", simple_db::db_error_message(&e)))?;
+    let result = async {
+        if existing.is_none() {
+            let definitions = columns
+                .iter()
+                .map(|(index, name)| {
+                    let values = data.rows.iter().filter_map(|row| row.values[*index].as_deref());
+                    format!("{} {}", quote_ident(name), infer_column_type(values))
+                })
+                .collect::<Vec<_>>()
+                .join(", ");
+            let create = format!("CREATE TABLE {} ({})", qualified, definitions);
+            println!("🦀 [Import] {}", create);
+            client
+                .batch_execute(&create)
+                .await
+                .map_err(|e| format!("Failed to create table: {}", simple_db::db_error_message(&e)))?;
+            summary.created_table = true;
+        } else if request.truncate {
+            client
+                .batch_execute(&format!("TRUNCATE TABLE {}", qualified))
+                .await
+                .map_err(|e| format!("Failed to truncate table: {}", simple_db::db_error_message(&e)))?;
+        }
+        load_rows(client, &copy_sql, &data, &columns, request.stop_on_error, &mut summary).await
+    }
+    .await;
+
+    match result {
+        Ok(true) => client
+            .batch_execute("COMMIT")
+            .await
+            .map_err(|e| format!("Failed to commit import: {}", simple_db::db_error_message(&e)))?,
+        Ok(false) => {
+            client.batch_execute("ROLLBACK").await.ok();
+            summary.rows_imported = 0;
+            summary.created_table = false;
+            summary.rolled_back = true;
+        }
+        Err(e) => {
+            client.batch_execute("ROLLBACK").await.ok();
+            return Err(e);
+        }
+    }
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_csv_with_options() {
+        let options = CsvOptions {
+            delimiter: Some(";".to_string()),
+            null_marker: Some("NULL".to_string()),
+            ..Default::default()
+        };
+        let data = parse_csv("id;name\n1;\"a;b\"\n2;NULL\n3\n", &options).unwrap();
+        assert_eq!(data.columns, vec!["id", "name"]);
+        assert_eq!(data.rows[0].values, vec![Some("1".to_string()), Some("a;b".to_string())]);
+        assert_eq!(data.rows[1].values, vec![Some("2".to_string()), None]);
+        assert_eq!(data.errors, vec![ImportRowError { row: 3, error: "Expected 2 fields, found 1".to_string() }]);
+
+        let options = CsvOptions { has_header: Some(false), ..Default::default() };
+        assert_eq!(parse_csv("1,2\n", &options).unwrap().columns, vec!["column1", "column2"]);
+        let options = CsvOptions { delimiter: Some("||".to_string()), ..Default::default() };
+        assert!(parse_csv("a", &options).is_err());
+    }
+
+    #[test]
+    fn test_parse_json_and_ndjson() {
+        let data = parse_json(r#"[{"a": 1, "b": {"x": true}}, {"a": null, "c": "s"}, 5]"#, TableImportFormat::Json).unwrap();
+        assert_eq!(data.columns, vec!["a", "b", "c"]);
+        assert_eq!(data.rows[0].values, vec![Some("1".to_string()), Some(r#"{"x":true}"#.to_string()), None]);
+        assert_eq!(data.rows[1].values, vec![None, None, Some("s".to_string())]);
+        assert_eq!(data.errors[0].row, 3);
+
+        let data = parse_json("{\"a\": 1}\n\nnot json\n{\"a\": 2}\n", TableImportFormat::Ndjson).unwrap();
+        assert_eq!(data.rows.iter().map(|r| r.number).collect::<Vec<_>>(), vec![1, 4]);
+        assert_eq!(data.errors[0].row, 3);
+    }
+
+    #[test]
+    fn test_parse_json_keeps_key_order() {
+        let data = parse_json(r#"[{"z": 1, "a": 2}, {"m": 3, "z": 4}]"#, TableImportFormat::Json).unwrap();
+        assert_eq!(data.columns, vec!["z", "a", "m"]);
+        assert_eq!(data.rows[1].values, vec![Some("4".to_string()), None, Some("3".to_string())]);
+    }
+
+    #[test]
+    fn test_infer_column_type() {
+        assert_eq!(infer_column_type(["1", "-20"].into_iter()), "bigint");
+        assert_eq!(infer_column_type(["1", "2.5", "1e3"].into_iter()), "numeric");
+        assert_eq!(infer_column_type(["true", "F"].into_iter()), "boolean");
+        assert_eq!(infer_column_type(["2024-01-31"].into_iter()), "date");
+        assert_eq!(infer_column_type(["2024-01-31 10:00:00", "2024-01-31T10:00:00.5"].into_iter()), "timestamp");
+        assert_eq!(infer_column_type(["2024-01-31T10:00:00Z"].into_iter()), "timestamptz");
+        assert_eq!(infer_column_type([r#"{"a": 1}"#].into_iter()), "jsonb");
+        assert_eq!(infer_column_type(["1", "x"].into_iter()), "text");
+        assert_eq!(infer_column_type(std::iter::empty()), "text");
+    }
+
+    #[test]
+    fn test_plan_columns() {
+        let source = vec!["id".to_string(), "Name".to_string(), "extra".to_string()];
+        let table = vec!["id".to_string(), "name".to_string()];
+        let mut warnings = Vec::new();
+        let planned = plan_columns(&source, None, Some(&table), &mut warnings).unwrap();
+        assert_eq!(planned, vec![(0, "id".to_string()), (1, "name".to_string())]);
+        assert_eq!(warnings.len(), 1);
+
+        let mapping = HashMap::from([("extra".to_string(), "name".to_string())]);
+        let planned = plan_columns(&source, Some(&mapping), Some(&table), &mut warnings).unwrap();
+        assert_eq!(planned, vec![(2, "name".to_string())]);
+        let mapping = HashMap::from([("missing".to_string(), "name".to_string())]);
+        assert!(plan_columns(&source, Some(&mapping), Some(&table), &mut warnings).is_err());
+        let mapping = HashMap::from([("extra".to_string(), "nope".to_string())]);
+        assert!(plan_columns(&source, Some(&mapping), Some(&table), &mut warnings).is_err());
+    }
+
+    #[test]
+    fn test_copy_text_escaping() {
+        let row = SourceRow { number: 1, values: vec![Some("a\tb\\c\nd".to_string()), None] };
+        let mut out = String::new();
+        push_copy_row(&row, &[(0, "x".to_string()), (1, "y".to_string())], &mut out);
+        assert_eq!(out, "a\\tb\\\\c\\nd\t\\N\n");
+    }
+
+    #[test]
+    fn test_decode_encodings() {
+        assert_eq!(decode(b"caf\xe9", Some("windows-1252")).unwrap(), "café");
+        assert_eq!(decode("\u{feff}a".as_bytes(), None).unwrap(), "a");
+        assert!(decode(b"caf\xe9", None).is_err());
+        assert!(decode(b"a", Some("klingon")).is_err());
+    }
+}
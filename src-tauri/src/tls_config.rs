@@ -0,0 +1,213 @@
+use crate::connection_profile::{SSLConfig, SSLMode};
+use native_tls::{Certificate, Identity, TlsConnector};
+use postgres_native_tls::MakeTlsConnector;
+use tokio_postgres::config::SslMode;
+use tokio_postgres::Client;
+
+/// How strictly the server certificate is checked for a given mode
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Verification {
+    verify_chain: bool,
+    verify_hostname: bool,
+}
+
+/// Map a profile SSL mode to the mode tokio-postgres negotiates with.
+///
+/// tokio-postgres has no `allow` mode (plaintext first, then TLS), so it is
+/// treated like `prefer`. The verify modes only differ in how the connector
+/// checks the certificate, see [`make_tls_connector`].
+pub fn pg_ssl_mode(mode: &SSLMode) -> SslMode {
+    match mode {
+        SSLMode::Disable => SslMode::Disable,
+        SSLMode::Allow | SSLMode::Prefer => SslMode::Prefer,
+        SSLMode::Require | SSLMode::VerifyCa | SSLMode::VerifyFull => SslMode::Require,
+    }
+}
+
+/// Certificate checks libpq performs for each mode. As in libpq, `require`
+/// behaves like `verify-ca` when a root certificate is configured.
+fn verification_for(mode: &SSLMode, has_ca: bool) -> Verification {
+    match mode {
+        SSLMode::Disable | SSLMode::Allow | SSLMode::Prefer => Verification {
+            verify_chain: false,
+            verify_hostname: false,
+        },
+        SSLMode::Require => Verification {
+            verify_chain: has_ca,
+            verify_hostname: false,
+        },
+        SSLMode::VerifyCa => Verification {
+            verify_chain: true,
+            verify_hostname: false,
+        },
+        SSLMode::VerifyFull => Verification {
+            verify_chain: true,
+            verify_hostname: true,
+        },
+    }
+}
+
+/// Build the TLS connector for a profile's SSL settings, loading the CA bundle
+/// and client certificate when configured
+pub fn make_tls_connector(ssl: &SSLConfig) -> Result<MakeTlsConnector, String> {
+    let ca_path = ssl.ca.as_deref().filter(|path| !path.trim().is_empty());
+    let verification = verification_for(&ssl.mode, ca_path.is_some());
+
+    let mut builder = TlsConnector::builder();
+    builder
+        .danger_accept_invalid_certs(!verification.verify_chain)
+        .danger_accept_invalid_hostnames(!verification.verify_hostname);
+
+    if let Some(ca_path) = ca_path {
+        for certificate in load_ca_bundle(ca_path)? {
+            builder.add_root_certificate(certificate);
+        }
+    }
+
+    let cert_path = ssl.cert.as_deref().filter(|path| !path.trim().is_empty());
+    let key_path = ssl.key.as_deref().filter(|path| !path.trim().is_empty());
+    match (cert_path, key_path) {
+        (Some(cert_path), Some(key_path)) => {
+            builder.identity(load_client_identity(cert_path, key_path)?);
+        }
+        (None, None) => {}
+        _ => return Err("SSL client certificate and key must be provided together".to_string()),
+    }
+
+    let connector = builder
+        .build()
+        .map_err(|e| format!("SSL setup failed: {}", e))?;
+    Ok(MakeTlsConnector::new(connector))
+}
+
+/// Connector with full certificate verification, used for raw connection strings
+pub fn default_tls_connector() -> Result<MakeTlsConnector, String> {
+    let connector = TlsConnector::builder()
+        .danger_accept_invalid_certs(false)
+        .build()
+        .map_err(|e| format!("SSL setup failed: {}", e))?;
+    Ok(MakeTlsConnector::new(connector))
+}
+
+/// Ask the server whether the current connection is encrypted
+pub async fn ssl_in_use(client: &Client) -> bool {
+    match client
+        .query_opt("SELECT ssl FROM pg_stat_ssl WHERE pid = pg_backend_pid()", &[])
+        .await
+    {
+        Ok(Some(row)) => row.try_get::<_, bool>(0).unwrap_or(false),
+        _ => false,
+    }
+}
+
+/// Load every certificate from a PEM bundle
+fn load_ca_bundle(path: &str) -> Result<Vec<Certificate>, String> {
+    let pem = std::fs::read_to_string(path)
+        .map_err(|e| format!("SSL setup failed: cannot read CA file {}: {}", path, e))?;
+
+    let certificates = split_pem_certificates(&pem)
+        .iter()
+        .map(|block| Certificate::from_pem(block.as_bytes()))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("SSL setup failed: invalid CA certificate in {}: {}", path, e))?;
+
+    if certificates.is_empty() {
+        return Err(format!("SSL setup failed: no certificates found in {}", path));
+    }
+    Ok(certificates)
+}
+
+/// Load a PEM client certificate and its PKCS#8 private key
+fn load_client_identity(cert_path: &str, key_path: &str) -> Result<Identity, String> {
+    let cert = std::fs::read(cert_path)
+        .map_err(|e| format!("SSL setup failed: cannot read client certificate {}: {}", cert_path, e))?;
+    let key = std::fs::read(key_path)
+        .map_err(|e| format!("SSL setup failed: cannot read client key {}: {}", key_path, e))?;
+
+    Identity::from_pkcs8(&cert, &key).map_err(|e| {
+        format!(
+            "SSL setup failed: invalid client certificate or key (the key must be PEM-encoded PKCS#8): {}",
+            e
+        )
+    })
+}
+
+/// Split a PEM bundle into individual certificate blocks
+fn split_pem_certificates(pem: &str) -> Vec<String> {
+    const BEGIN: &str = "-----BEGIN CERTIFICATE-----";
+    const END: &str = "-----END CERTIFICATE-----";
+
+    let mut blocks = Vec::new();
+    let mut rest = pem;
+    while let Some(start) = rest.find(BEGIN) {
+        match rest[start..].find(END) {
+            Some(end) => {
+                let end = start + end + END.len();
+                blocks.push(rest[start..end].to_string());
+                rest = &rest[end..];
+            }
+            None => break,
+        }
+    }
+    blocks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ssl_mode_mapping() {
+        assert_eq!(pg_ssl_mode(&SSLMode::Disable), SslMode::Disable);
+        assert_eq!(pg_ssl_mode(&SSLMode::Allow), SslMode::Prefer);
+        assert_eq!(pg_ssl_mode(&SSLMode::Prefer), SslMode::Prefer);
+        assert_eq!(pg_ssl_mode(&SSLMode::Require), SslMode::Require);
+        assert_eq!(pg_ssl_mode(&SSLMode::VerifyCa), SslMode::Require);
+        assert_eq!(pg_ssl_mode(&SSLMode::VerifyFull), SslMode::Require);
+    }
+
+    #[test]
+    fn test_verification_levels() {
+        let none = Verification { verify_chain: false, verify_hostname: false };
+        let chain = Verification { verify_chain: true, verify_hostname: false };
+        let full = Verification { verify_chain: true, verify_hostname: true };
+
+        assert_eq!(verification_for(&SSLMode::Prefer, true), none);
+        assert_eq!(verification_for(&SSLMode::Require, false), none);
+        assert_eq!(verification_for(&SSLMode::Require, true), chain);
+        assert_eq!(verification_for(&SSLMode::VerifyCa, false), chain);
+        assert_eq!(verification_for(&SSLMode::VerifyFull, false), full);
+    }
+
+    #[test]
+    fn test_split_pem_certificates() {
+        let bundle = "junk\n-----BEGIN CERTIFICATE-----\nAAA\n-----END CERTIFICATE-----\n\
+                      -----BEGIN CERTIFICATE-----\nBBB\n-----END CERTIFICATE-----\n";
+        let blocks = split_pem_certificates(bundle);
+        assert_eq!(blocks.len(), 2);
+        assert!(blocks[1].contains("BBB"));
+    }
+
+    #[test]
+    fn test_invalid_certificate_configuration() {
+        let missing_ca = SSLConfig {
+            mode: SSLMode::VerifyFull,
+            cert: None,
+            key: None,
+            ca: Some("/nonexistent/root.crt".to_string()),
+        };
+        let err = make_tls_connector(&missing_ca).err().unwrap();
+        assert!(err.contains("cannot read CA file"));
+
+        let cert_without_key = SSLConfig {
+            mode: SSLMode::Require,
+            cert: Some("/nonexistent/client.crt".to_string()),
+            key: None,
+            ca: None,
+        };
+        let err = make_tls_connector(&cert_without_key).err().unwrap();
+        assert!(err.contains("provided together"));
+
+        assert!(make_tls_connector(&SSLConfig::default()).is_ok());
+    }
+}
@@ -0,0 +1,440 @@
+//! Interactive transactions pinned to one pooled connection.
+//!
+//! A transaction takes a connection out of the session's pool and keeps it until it is
+//! committed or rolled back, so statements from other tabs (which use the session's
+//! shared client) never land inside it. State is tracked here because the server only
+//! reports it implicitly: any failed statement aborts the transaction until it is rolled
+//! back, entirely or to a savepoint.
+
+use crate::production_guard::Access;
+use crate::simple_db::{self, QueryCanceller, SimpleQueryResult};
+use crate::sql_builder::quote_ident;
+use crate::sql_splitter::leading_keyword;
+use crate::value_codec::{self, SessionTimeZone};
+use chrono::{DateTime, Utc};
+use postgres_native_tls::MakeTlsConnector;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tokio::sync::{MappedMutexGuard, Mutex, MutexGuard};
+
+/// Whether a session has a transaction open, and whether it can still do work
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TransactionState {
+    Idle,
+    InTransaction,
+    /// A statement failed; only a rollback (to a savepoint) makes it usable again
+    Failed,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IsolationLevel {
+    ReadCommitted,
+    RepeatableRead,
+    Serializable,
+}
+
+impl IsolationLevel {
+    fn as_sql(&self) -> &'static str {
+        match self {
+            IsolationLevel::ReadCommitted => "READ COMMITTED",
+            IsolationLevel::RepeatableRead => "REPEATABLE READ",
+            IsolationLevel::Serializable => "SERIALIZABLE",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TransactionOptions {
+    /// Server default (normally read committed) when not set
+    #[serde(default)]
+    pub isolation_level: Option<IsolationLevel>,
+    #[serde(default)]
+    pub read_only: bool,
+    /// Only meaningful for serializable read-only transactions
+    #[serde(default)]
+    pub deferrable: bool,
+}
+
+impl TransactionOptions {
+    /// The `BEGIN` statement for these options
+    pub fn begin_sql(&self) -> String {
+        let mut modes = Vec::new();
+        if let Some(level) = &self.isolation_level {
+            modes.push(format!("ISOLATION LEVEL {}", level.as_sql()));
+        }
+        if self.read_only {
+            modes.push("READ ONLY".to_string());
+        }
+        if self.deferrable {
+            modes.push("DEFERRABLE".to_string());
+        }
+
+        if modes.is_empty() {
+            "BEGIN".to_string()
+        } else {
+            format!("BEGIN {}", modes.join(", "))
+        }
+    }
+}
+
+/// Snapshot of a session's transaction for the frontend
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TransactionInfo {
+    pub transaction_id: Option<String>,
+    pub state: TransactionState,
+    pub isolation_level: Option<IsolationLevel>,
+    pub read_only: bool,
+    /// Open savepoints, oldest first
+    pub savepoints: Vec<String>,
+    pub started_at: Option<DateTime<Utc>>,
+    /// Statements run in the transaction; non-zero means there may be uncommitted changes
+    pub statement_count: u64,
+}
+
+impl TransactionInfo {
+    pub fn idle() -> Self {
+        Self {
+            transaction_id: None,
+            state: TransactionState::Idle,
+            isolation_level: None,
+            read_only: false,
+            savepoints: Vec::new(),
+            started_at: None,
+            statement_count: 0,
+        }
+    }
+}
+
+/// Mutable bookkeeping of an open transaction
+#[derive(Debug, Clone)]
+struct TransactionStatus {
+    failed: bool,
+    savepoints: Vec<String>,
+    statement_count: u64,
+}
+
+impl TransactionStatus {
+    fn new() -> Self {
+        Self {
+            failed: false,
+            savepoints: Vec::new(),
+            statement_count: 0,
+        }
+    }
+
+    fn position(&self, name: &str) -> Result<usize, String> {
+        self.savepoints
+            .iter()
+            .rposition(|savepoint| savepoint == name)
+            .ok_or_else(|| format!("Savepoint '{}' does not exist", name))
+    }
+
+    /// `ROLLBACK TO` keeps the savepoint but discards every later one
+    fn rolled_back_to(&mut self, name: &str) -> Result<(), String> {
+        let position = self.position(name)?;
+        self.savepoints.truncate(position + 1);
+        self.failed = false;
+        Ok(())
+    }
+
+    /// `RELEASE` removes the savepoint and every later one
+    fn released(&mut self, name: &str) -> Result<(), String> {
+        let position = self.position(name)?;
+        self.savepoints.truncate(position);
+        Ok(())
+    }
+}
+
+/// First keyword of statements that would end or restructure the transaction behind
+/// this module's back; they have dedicated commands instead
+pub(crate) fn transaction_control_keyword(sql: &str) -> Option<String> {
+    let keyword = leading_keyword(sql);
+    match keyword.as_str() {
+        "BEGIN" | "START" | "COMMIT" | "END" | "ROLLBACK" | "ABORT" | "SAVEPOINT" | "RELEASE" => Some(keyword),
+        _ => None,
+    }
+}
+
+/// Savepoint that read-only statements run under and are rolled back to
+const READ_ONLY_SAVEPOINT: &str = "production_guard_read";
+
+/// Statements that only change session state, which rolling back would undo. Anything
+/// they might run later runs under a statement that is not exempt (FETCH from a cursor).
+fn changes_session_only(sql: &str) -> bool {
+    matches!(
+        leading_keyword(sql).as_str(),
+        "SET" | "RESET" | "SHOW" | "DECLARE" | "CLOSE" | "LISTEN" | "UNLISTEN" | "DEALLOCATE" | "DISCARD"
+    )
+}
+
+/// An open transaction on its own connection
+pub struct TransactionSession {
+    pub id: String,
+    /// Held while a statement runs, so statements in the transaction run one at a time.
+    /// `None` once the connection was discarded because the transaction could not be ended.
+    client: Mutex<Option<deadpool_postgres::Client>>,
+    tls: Option<MakeTlsConnector>,
+    options: TransactionOptions,
+    query_timeout: Duration,
+    started_at: DateTime<Utc>,
+    status: std::sync::Mutex<TransactionStatus>,
+    /// The connection's `TimeZone`, which `timestamptz` values are rendered in
+    time_zone: std::sync::Mutex<SessionTimeZone>,
+}
+
+impl TransactionSession {
+    /// Start a transaction on `client`, a connection taken from the session's pool
+    pub async fn begin(
+        client: deadpool_postgres::Client,
+        tls: Option<MakeTlsConnector>,
+        options: TransactionOptions,
+        query_timeout: Duration,
+    ) -> Result<Self, String> {
+        let begin = options.begin_sql();
+        println!("🦀 [Transaction] {}", begin);
+        client
+            .batch_execute(&begin)
+            .await
+            .map_err(|e| format!("Failed to start transaction: {}", simple_db::db_error_message(&e)))?;
+        let time_zone = value_codec::read_time_zone(&client).await;
+
+        Ok(Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            client: Mutex::new(Some(client)),
+            tls,
+            options,
+            query_timeout,
+            started_at: Utc::now(),
+            status: std::sync::Mutex::new(TransactionStatus::new()),
+            time_zone: std::sync::Mutex::new(time_zone),
+        })
+    }
+
+    fn status(&self) -> std::sync::MutexGuard<'_, TransactionStatus> {
+        self.status.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn time_zone(&self) -> SessionTimeZone {
+        *self.time_zone.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    pub fn info(&self) -> TransactionInfo {
+        let status = self.status();
+        TransactionInfo {
+            transaction_id: Some(self.id.clone()),
+            state: if status.failed { TransactionState::Failed } else { TransactionState::InTransaction },
+            isolation_level: self.options.isolation_level,
+            read_only: self.options.read_only,
+            savepoints: status.savepoints.clone(),
+            started_at: Some(self.started_at),
+            statement_count: status.statement_count,
+        }
+    }
+
+    /// The pinned connection
+    async fn client(&self) -> Result<MappedMutexGuard<'_, deadpool_postgres::Client>, String> {
+        MutexGuard::try_map(self.client.lock().await, Option::as_mut)
+            .map_err(|_| "The transaction's connection was closed".to_string())
+    }
+
+    /// Close the pinned connection instead of returning it to the pool, where its next
+    /// user would inherit whatever is left of this transaction
+    async fn discard_client(&self) {
+        if let Some(client) = self.client.lock().await.take() {
+            println!("🦀 [Transaction] Discarding connection {}", self.id);
+            drop(deadpool_postgres::Client::take(client));
+        }
+    }
+
+    /// Handle for cancelling the statement running in this transaction
+    pub async fn canceller(&self) -> Result<QueryCanceller, String> {
+        match &self.tls {
+            Some(tls) => Ok(QueryCanceller::new(self.client().await?.cancel_token(), tls.clone())),
+            None => Err("Connection pool is not connected".to_string()),
+        }
+    }
+
+    /// Run one statement in the transaction. With read-only `access` it runs under a
+    /// read-only savepoint that is rolled back afterwards, so the server refuses any
+    /// write and a failing read does not fail the transaction.
+    pub async fn execute(
+        &self,
+        query: &str,
+        params: &[Option<String>],
+        timeout: Option<Duration>,
+        access: Access,
+    ) -> Result<SimpleQueryResult, String> {
+        if let Some(keyword) = transaction_control_keyword(query) {
+            return Err(format!("{} is not allowed here; use the transaction commands instead", keyword));
+        }
+        if self.status().failed {
+            return Err("The transaction has failed; roll back or roll back to a savepoint first".to_string());
+        }
+
+        let timeout = timeout.unwrap_or(self.query_timeout);
+        let client = self.client().await?;
+        // Scoped to the transaction, so it never leaks to the pooled connection's next user
+        let result = match client
+            .batch_execute(&format!("SET LOCAL statement_timeout = {}", timeout.as_millis()))
+            .await
+        {
+            Ok(()) if access.is_read_only() && !changes_session_only(query) => {
+                return self.execute_read_only(&client, query, params, timeout).await;
+            }
+            Ok(()) => simple_db::run_query(&client, query, params, timeout, self.tls.as_ref(), &self.time_zone()).await,
+            Err(e) => Err(format!("Failed to set statement timeout: {}", e)),
+        };
+        if result.is_ok() && simple_db::changes_settings(query) {
+            let time_zone = value_codec::read_time_zone(&client).await;
+            *self.time_zone.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) = time_zone;
+        }
+
+        let mut status = self.status();
+        status.statement_count += 1;
+        if result.is_err() {
+            status.failed = true;
+        }
+        result
+    }
+
+    async fn execute_read_only(
+        &self,
+        client: &deadpool_postgres::Client,
+        query: &str,
+        params: &[Option<String>],
+        timeout: Duration,
+    ) -> Result<SimpleQueryResult, String> {
+        let savepoint = quote_ident(READ_ONLY_SAVEPOINT);
+        let result = match client
+            .batch_execute(&format!("SAVEPOINT {}; SET LOCAL transaction_read_only = on", savepoint))
+            .await
+        {
+            Ok(()) => simple_db::run_query(client, query, params, timeout, self.tls.as_ref(), &self.time_zone()).await,
+            Err(e) => Err(format!("Failed to start a read-only savepoint: {}", e)),
+        };
+        // Undoes the read-only mode along with anything the statement did
+        let restored = client
+            .batch_execute(&format!("ROLLBACK TO SAVEPOINT {0}; RELEASE SAVEPOINT {0}", savepoint))
+            .await;
+
+        let mut status = self.status();
+        status.statement_count += 1;
+        if let Err(e) = restored {
+            status.failed = true;
+            return Err(format!("Failed to leave the read-only savepoint: {}", simple_db::db_error_message(&e)));
+        }
+        result
+    }
+
+    /// Run a transaction-control statement, keeping the transaction failed if it fails
+    async fn control(&self, sql: &str) -> Result<(), String> {
+        println!("🦀 [Transaction] {}", sql);
+        let result = self.client().await?.batch_execute(sql).await;
+        result.map_err(|e| {
+            self.status().failed = true;
+            simple_db::db_error_message(&e)
+        })
+    }
+
+    pub async fn create_savepoint(&self, name: &str) -> Result<TransactionInfo, String> {
+        if self.status().failed {
+            return Err("The transaction has failed; roll back or roll back to a savepoint first".to_string());
+        }
+        self.control(&format!("SAVEPOINT {}", quote_ident(name)))
+            .await
+            .map_err(|e| format!("Failed to create savepoint: {}", e))?;
+        self.status().savepoints.push(name.to_string());
+        Ok(self.info())
+    }
+
+    pub async fn rollback_to_savepoint(&self, name: &str) -> Result<TransactionInfo, String> {
+        self.status().position(name)?;
+        self.control(&format!("ROLLBACK TO SAVEPOINT {}", quote_ident(name)))
+            .await
+            .map_err(|e| format!("Failed to roll back to savepoint: {}", e))?;
+        self.status().rolled_back_to(name)?;
+        Ok(self.info())
+    }
+
+    pub async fn release_savepoint(&self, name: &str) -> Result<TransactionInfo, String> {
+        self.status().position(name)?;
+        self.control(&format!("RELEASE SAVEPOINT {}", quote_ident(name)))
+            .await
+            .map_err(|e| format!("Failed to release savepoint: {}", e))?;
+        self.status().released(name)?;
+        Ok(self.info())
+    }
+
+    /// Commit, or roll back instead when the transaction has failed (which is what the
+    /// server would do anyway) and report that as an error
+    pub async fn commit(&self) -> Result<(), String> {
+        if self.status().failed {
+            self.rollback().await?;
+            return Err("The transaction had failed and was rolled back".to_string());
+        }
+        if let Err(e) = self.control("COMMIT").await {
+            self.discard_client().await;
+            return Err(format!("Failed to commit transaction: {}", e));
+        }
+        Ok(())
+    }
+
+    /// Roll back; if that fails the connection is discarded, which ends the transaction
+    /// on the server too
+    pub async fn rollback(&self) -> Result<(), String> {
+        if let Err(e) = self.control("ROLLBACK").await {
+            self.discard_client().await;
+            return Err(format!("Failed to roll back transaction: {}", e));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_begin_sql_includes_options() {
+        assert_eq!(TransactionOptions::default().begin_sql(), "BEGIN");
+        let options = TransactionOptions {
+            isolation_level: Some(IsolationLevel::Serializable),
+            read_only: true,
+            deferrable: true,
+        };
+        assert_eq!(options.begin_sql(), "BEGIN ISOLATION LEVEL SERIALIZABLE, READ ONLY, DEFERRABLE");
+    }
+
+    #[test]
+    fn test_savepoint_bookkeeping() {
+        let mut status = TransactionStatus::new();
+        status.savepoints = vec!["a".to_string(), "b".to_string(), "c".to_string()];
+        status.failed = true;
+
+        status.rolled_back_to("b").unwrap();
+        assert_eq!(status.savepoints, vec!["a".to_string(), "b".to_string()]);
+        assert!(!status.failed);
+
+        status.released("a").unwrap();
+        assert!(status.savepoints.is_empty());
+        assert!(status.released("a").is_err());
+    }
+
+    #[test]
+    fn test_session_statements_skip_the_read_only_savepoint() {
+        assert!(changes_session_only("set search_path = app"));
+        assert!(changes_session_only("DECLARE c CURSOR FOR SELECT 1"));
+        assert!(!changes_session_only("FETCH 10 FROM c"));
+        assert!(!changes_session_only("SELECT 1"));
+    }
+
+    #[test]
+    fn test_transaction_control_statements_are_rejected() {
+        assert_eq!(transaction_control_keyword("  commit;"), Some("COMMIT".to_string()));
+        assert_eq!(transaction_control_keyword("Rollback to savepoint a"), Some("ROLLBACK".to_string()));
+        assert_eq!(transaction_control_keyword("SELECT 'COMMIT'"), None);
+        assert_eq!(transaction_control_keyword("ending"), None);
+    }
+}
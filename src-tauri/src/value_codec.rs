@@ -0,0 +1,1010 @@
+//! Decoding of PostgreSQL binary result values into JSON without losing precision.
+//!
+//! Values are read from the raw wire bytes by type OID, so types tokio-postgres has no
+//! `FromSql` impl for (inet, ranges, hstore, geometric types, composites...) are decoded
+//! too. Values JSON cannot represent exactly (numeric, large int8, dates) are rendered
+//! as strings in PostgreSQL's own text format.
+
+use base64::{engine::general_purpose, Engine as _};
+use chrono::{Datelike, FixedOffset, NaiveDate, NaiveDateTime, Offset, TimeZone, Timelike};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::error::Error;
+use std::net::{Ipv4Addr, Ipv6Addr};
+use tokio_postgres::types::{FromSql, Kind, Type};
+use tokio_postgres::{Client, Column, Row};
+
+/// Type of a result column, sent alongside the rows
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ColumnType {
+    pub name: String,
+    pub type_oid: u32,
+    pub type_name: String,
+}
+
+pub fn column_types(columns: &[Column]) -> Vec<ColumnType> {
+    columns
+        .iter()
+        .map(|column| ColumnType {
+            name: column.name().to_string(),
+            type_oid: column.type_().oid(),
+            type_name: column.type_().name().to_string(),
+        })
+        .collect()
+}
+
+/// The session's `TimeZone` setting, which `timestamptz` values are rendered in
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SessionTimeZone {
+    Named(Tz),
+    /// A POSIX-style zone such as `<+05>-05`, which `SET TIME ZONE` with a number sets
+    Fixed(FixedOffset),
+}
+
+impl Default for SessionTimeZone {
+    fn default() -> Self {
+        SessionTimeZone::Named(Tz::UTC)
+    }
+}
+
+impl SessionTimeZone {
+    /// Interpret a `SHOW TimeZone` result; unknown zones fall back to UTC
+    pub fn parse(setting: &str) -> Self {
+        if let Ok(zone) = setting.parse::<Tz>() {
+            return SessionTimeZone::Named(zone);
+        }
+        match posix_offset(setting) {
+            Some(offset) => SessionTimeZone::Fixed(offset),
+            None => {
+                println!("🦀 [ValueCodec] Unknown time zone {}, showing timestamps in UTC", setting);
+                SessionTimeZone::default()
+            }
+        }
+    }
+
+    /// Seconds east of UTC at the instant `utc`
+    fn offset_at(&self, utc: NaiveDateTime) -> i32 {
+        match self {
+            SessionTimeZone::Named(zone) => zone.offset_from_utc_datetime(&utc).fix().local_minus_utc(),
+            SessionTimeZone::Fixed(offset) => offset.local_minus_utc(),
+        }
+    }
+}
+
+/// Offset of `<abbrev>[+-]hh[:mm[:ss]]`; POSIX counts hours west of UTC
+fn posix_offset(setting: &str) -> Option<FixedOffset> {
+    let rest = match setting.strip_prefix('<') {
+        Some(quoted) => &quoted[quoted.find('>')? + 1..],
+        None => setting.trim_start_matches(|c: char| c.is_ascii_alphabetic()),
+    };
+    let (sign, digits) = match rest.strip_prefix('-') {
+        Some(digits) => (1, digits),
+        None => (-1, rest.strip_prefix('+').unwrap_or(rest)),
+    };
+    let parts: Vec<&str> = digits.split(':').collect();
+    if parts.len() > 3 {
+        return None;
+    }
+    let mut seconds = 0;
+    for (part, unit) in parts.iter().zip([3600, 60, 1]) {
+        seconds += part.parse::<i32>().ok()? * unit;
+    }
+    FixedOffset::east_opt(sign * seconds)
+}
+
+/// Read a connection's `TimeZone`, UTC if it cannot be read
+pub async fn read_time_zone(client: &Client) -> SessionTimeZone {
+    match client.query_one("SHOW TimeZone", &[]).await {
+        Ok(row) => SessionTimeZone::parse(row.get::<_, &str>(0)),
+        Err(e) => {
+            println!("🦀 [ValueCodec] Failed to read the session time zone: {}", e);
+            SessionTimeZone::default()
+        }
+    }
+}
+
+/// Decode every column of a row
+pub fn decode_row(row: &Row, zone: &SessionTimeZone) -> Vec<Value> {
+    row.columns()
+        .iter()
+        .enumerate()
+        .map(|(i, column)| match row.try_get::<_, Option<RawValue>>(i) {
+            Ok(Some(raw)) => decode_value(column.type_(), raw.0, zone).unwrap_or_else(|e| {
+                Value::String(format!("Error decoding {}: {}", column.type_().name(), e))
+            }),
+            Ok(None) => Value::Null,
+            Err(e) => Value::String(format!("Error decoding {}: {}", column.type_().name(), e)),
+        })
+        .collect()
+}
+
+/// Borrows a value's raw bytes regardless of its type
+struct RawValue<'a>(&'a [u8]);
+
+impl<'a> FromSql<'a> for RawValue<'a> {
+    fn from_sql(_ty: &Type, raw: &'a [u8]) -> Result<Self, Box<dyn Error + Sync + Send>> {
+        Ok(RawValue(raw))
+    }
+
+    fn accepts(_ty: &Type) -> bool {
+        true
+    }
+}
+
+/// OIDs of the built-in types decoded here
+mod oid {
+    pub const BOOL: u32 = 16;
+    pub const BYTEA: u32 = 17;
+    pub const CHAR: u32 = 18;
+    pub const NAME: u32 = 19;
+    pub const INT8: u32 = 20;
+    pub const INT2: u32 = 21;
+    pub const INT4: u32 = 23;
+    pub const REGPROC: u32 = 24;
+    pub const TEXT: u32 = 25;
+    pub const OID: u32 = 26;
+    pub const TID: u32 = 27;
+    pub const XID: u32 = 28;
+    pub const CID: u32 = 29;
+    pub const JSON: u32 = 114;
+    pub const XML: u32 = 142;
+    pub const POINT: u32 = 600;
+    pub const LSEG: u32 = 601;
+    pub const PATH: u32 = 602;
+    pub const BOX: u32 = 603;
+    pub const POLYGON: u32 = 604;
+    pub const LINE: u32 = 628;
+    pub const CIDR: u32 = 650;
+    pub const FLOAT4: u32 = 700;
+    pub const FLOAT8: u32 = 701;
+    pub const UNKNOWN: u32 = 705;
+    pub const CIRCLE: u32 = 718;
+    pub const MACADDR8: u32 = 774;
+    pub const MONEY: u32 = 790;
+    pub const MACADDR: u32 = 829;
+    pub const INET: u32 = 869;
+    pub const BPCHAR: u32 = 1042;
+    pub const VARCHAR: u32 = 1043;
+    pub const DATE: u32 = 1082;
+    pub const TIME: u32 = 1083;
+    pub const TIMESTAMP: u32 = 1114;
+    pub const TIMESTAMPTZ: u32 = 1184;
+    pub const INTERVAL: u32 = 1186;
+    pub const TIMETZ: u32 = 1266;
+    pub const BIT: u32 = 1560;
+    pub const VARBIT: u32 = 1562;
+    pub const NUMERIC: u32 = 1700;
+    pub const REGPROCEDURE: u32 = 2202;
+    pub const REGOPER: u32 = 2203;
+    pub const REGOPERATOR: u32 = 2204;
+    pub const REGCLASS: u32 = 2205;
+    pub const REGTYPE: u32 = 2206;
+    pub const UUID: u32 = 2950;
+    pub const PG_LSN: u32 = 3220;
+    pub const REGCONFIG: u32 = 3734;
+    pub const REGDICTIONARY: u32 = 3769;
+    pub const JSONB: u32 = 3802;
+    pub const REGNAMESPACE: u32 = 4089;
+    pub const REGROLE: u32 = 4096;
+    pub const XID8: u32 = 5069;
+}
+
+/// Largest integer a JavaScript number holds exactly
+const MAX_SAFE_INTEGER: i64 = (1 << 53) - 1;
+
+/// Microseconds and days are counted from 2000-01-01 on the wire
+fn pg_epoch() -> NaiveDateTime {
+    NaiveDate::from_ymd_opt(2000, 1, 1).unwrap().and_hms_opt(0, 0, 0).unwrap()
+}
+
+/// Decode one non-NULL value from its binary representation
+pub fn decode_value(ty: &Type, raw: &[u8], zone: &SessionTimeZone) -> Result<Value, String> {
+    match ty.kind() {
+        Kind::Array(element) => return decode_array(element, raw, zone),
+        Kind::Range(element) => return decode_range(element, raw, zone).map(Value::String),
+        Kind::Multirange(element) => return decode_multirange(element, raw, zone).map(Value::String),
+        Kind::Domain(base) => return decode_value(base, raw, zone),
+        Kind::Composite(fields) => {
+            let types: Vec<_> = fields.iter().map(|field| (field.name(), field.type_())).collect();
+            return decode_composite(&types, raw, zone);
+        }
+        Kind::Enum(_) => return utf8(raw).map(Value::String),
+        _ => {}
+    }
+    if ty.name() == "hstore" {
+        return decode_hstore(raw);
+    }
+
+    let mut reader = Reader::new(raw);
+    let value = match ty.oid() {
+        oid::BOOL => Value::Bool(reader.u8()? != 0),
+        oid::INT2 => Value::from(reader.i16()?),
+        oid::INT4 => Value::from(reader.i32()?),
+        oid::INT8 => int8_value(reader.i64()?),
+        oid::OID | oid::XID | oid::CID | oid::REGPROC | oid::REGPROCEDURE | oid::REGOPER
+        | oid::REGOPERATOR | oid::REGCLASS | oid::REGTYPE | oid::REGCONFIG | oid::REGDICTIONARY
+        | oid::REGNAMESPACE | oid::REGROLE => Value::from(reader.u32()?),
+        oid::XID8 => Value::String(reader.u64()?.to_string()),
+        oid::FLOAT4 => float_value(reader.f32()? as f64),
+        oid::FLOAT8 => float_value(reader.f64()?),
+        oid::NUMERIC => Value::String(decode_numeric(&mut reader)?),
+        oid::MONEY => Value::String(format_money(reader.i64()?)),
+        oid::CHAR => Value::String((reader.u8()? as char).to_string()),
+        oid::TEXT | oid::VARCHAR | oid::BPCHAR | oid::NAME | oid::XML | oid::UNKNOWN => {
+            Value::String(utf8(raw)?)
+        }
+        oid::JSON => parse_json(raw)?,
+        oid::JSONB => {
+            if reader.u8()? != 1 {
+                return Err("unsupported jsonb version".to_string());
+            }
+            parse_json(reader.rest())?
+        }
+        oid::BYTEA => Value::String(general_purpose::STANDARD.encode(raw)),
+        oid::UUID => Value::String(
+            uuid::Uuid::from_slice(raw)
+                .map_err(|e| e.to_string())?
+                .hyphenated()
+                .to_string(),
+        ),
+        oid::DATE => Value::String(format_date(reader.i32()?)?),
+        oid::TIME => Value::String(format_time(reader.i64()?)),
+        oid::TIMETZ => {
+            let micros = reader.i64()?;
+            let seconds_west = reader.i32()?;
+            Value::String(format!("{}{}", format_time(micros), format_offset(-seconds_west)))
+        }
+        oid::TIMESTAMP => Value::String(format_timestamp(reader.i64()?)?),
+        oid::TIMESTAMPTZ => Value::String(format_timestamptz(reader.i64()?, zone)?),
+        oid::INTERVAL => {
+            let micros = reader.i64()?;
+            let days = reader.i32()?;
+            let months = reader.i32()?;
+            Value::String(format_interval(months, days, micros))
+        }
+        oid::INET | oid::CIDR => Value::String(decode_inet(&mut reader)?),
+        oid::MACADDR | oid::MACADDR8 => Value::String(
+            raw.iter()
+                .map(|byte| format!("{:02x}", byte))
+                .collect::<Vec<_>>()
+                .join(":"),
+        ),
+        oid::BIT | oid::VARBIT => Value::String(decode_bits(&mut reader)?),
+        oid::PG_LSN => {
+            let lsn = reader.u64()?;
+            Value::String(format!("{:X}/{:X}", lsn >> 32, lsn & 0xFFFF_FFFF))
+        }
+        oid::TID => {
+            let block = reader.u32()?;
+            let offset = reader.u16()?;
+            Value::String(format!("({},{})", block, offset))
+        }
+        oid::POINT => Value::String(format_point(reader.f64()?, reader.f64()?)),
+        oid::LSEG => Value::String(format!("[{}]", read_points(&mut reader, 2)?)),
+        oid::BOX => Value::String(read_points(&mut reader, 2)?),
+        oid::PATH => {
+            let closed = reader.u8()? != 0;
+            let count = reader.i32()?;
+            let points = read_points(&mut reader, count)?;
+            Value::String(if closed { format!("({})", points) } else { format!("[{}]", points) })
+        }
+        oid::POLYGON => {
+            let count = reader.i32()?;
+            Value::String(format!("({})", read_points(&mut reader, count)?))
+        }
+        oid::LINE => Value::String(format!("{{{},{},{}}}", reader.f64()?, reader.f64()?, reader.f64()?)),
+        oid::CIRCLE => {
+            let center = format_point(reader.f64()?, reader.f64()?);
+            Value::String(format!("<{},{}>", center, reader.f64()?))
+        }
+        // Extension types such as citext send their text form
+        _ => match std::str::from_utf8(raw) {
+            Ok(text) => Value::String(text.to_string()),
+            Err(_) => Value::String(format!("\\x{}", hex(raw))),
+        },
+    };
+    Ok(value)
+}
+
+fn int8_value(n: i64) -> Value {
+    if (-MAX_SAFE_INTEGER..=MAX_SAFE_INTEGER).contains(&n) {
+        Value::from(n)
+    } else {
+        Value::String(n.to_string())
+    }
+}
+
+fn float_value(n: f64) -> Value {
+    match serde_json::Number::from_f64(n) {
+        Some(number) => Value::Number(number),
+        None if n.is_nan() => Value::String("NaN".to_string()),
+        None if n > 0.0 => Value::String("Infinity".to_string()),
+        None => Value::String("-Infinity".to_string()),
+    }
+}
+
+fn utf8(raw: &[u8]) -> Result<String, String> {
+    String::from_utf8(raw.to_vec()).map_err(|e| e.to_string())
+}
+
+fn parse_json(raw: &[u8]) -> Result<Value, String> {
+    serde_json::from_slice(raw).map_err(|e| e.to_string())
+}
+
+fn hex(raw: &[u8]) -> String {
+    raw.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Numeric is sent as base-10000 digit groups with a weight and display scale
+fn decode_numeric(reader: &mut Reader) -> Result<String, String> {
+    const POSITIVE: u16 = 0x0000;
+    const NEGATIVE: u16 = 0x4000;
+    const NAN: u16 = 0xC000;
+    const POSITIVE_INFINITY: u16 = 0xD000;
+    const NEGATIVE_INFINITY: u16 = 0xF000;
+
+    let ndigits = reader.i16()?;
+    let weight = reader.i16()? as i32;
+    let sign = reader.u16()?;
+    let dscale = reader.u16()? as i32;
+    let digits = (0..ndigits).map(|_| reader.i16()).collect::<Result<Vec<_>, _>>()?;
+    let digit = |index: i32| -> i16 {
+        if index >= 0 && (index as usize) < digits.len() {
+            digits[index as usize]
+        } else {
+            0
+        }
+    };
+
+    match sign {
+        NAN => return Ok("NaN".to_string()),
+        POSITIVE_INFINITY => return Ok("Infinity".to_string()),
+        NEGATIVE_INFINITY => return Ok("-Infinity".to_string()),
+        POSITIVE | NEGATIVE => {}
+        other => return Err(format!("invalid numeric sign {:#x}", other)),
+    }
+
+    let mut text = String::new();
+    if sign == NEGATIVE {
+        text.push('-');
+    }
+    if weight < 0 {
+        text.push('0');
+    } else {
+        text.push_str(&digit(0).to_string());
+        for index in 1..=weight {
+            text.push_str(&format!("{:04}", digit(index)));
+        }
+    }
+    if dscale > 0 {
+        let mut fraction = String::new();
+        for group in 0..(dscale + 3) / 4 {
+            fraction.push_str(&format!("{:04}", digit(weight + 1 + group)));
+        }
+        fraction.truncate(dscale as usize);
+        text.push('.');
+        text.push_str(&fraction);
+    }
+    Ok(text)
+}
+
+fn format_money(cents: i64) -> String {
+    let sign = if cents < 0 { "-" } else { "" };
+    let cents = (cents as i128).abs();
+    format!("{}{}.{:02}", sign, cents / 100, cents % 100)
+}
+
+/// Date part in PostgreSQL's ISO style, with a BC suffix for years before 1 AD
+fn format_ymd(date: NaiveDate) -> String {
+    if date.year() <= 0 {
+        format!("{:04}-{:02}-{:02} BC", 1 - date.year(), date.month(), date.day())
+    } else {
+        format!("{:04}-{:02}-{:02}", date.year(), date.month(), date.day())
+    }
+}
+
+fn format_date(days: i32) -> Result<String, String> {
+    match days {
+        i32::MAX => Ok("infinity".to_string()),
+        i32::MIN => Ok("-infinity".to_string()),
+        _ => pg_epoch()
+            .date()
+            .checked_add_signed(chrono::Duration::days(days as i64))
+            .map(format_ymd)
+            .ok_or_else(|| "date out of range".to_string()),
+    }
+}
+
+/// `HH:MM:SS` followed by microseconds with trailing zeros trimmed
+fn format_clock(hours: i64, minutes: i64, seconds: i64, micros: i64) -> String {
+    let mut text = format!("{:02}:{:02}:{:02}", hours, minutes, seconds);
+    if micros > 0 {
+        text.push_str(format!(".{:06}", micros).trim_end_matches('0'));
+    }
+    text
+}
+
+fn format_time(micros: i64) -> String {
+    let seconds = micros / 1_000_000;
+    format_clock(seconds / 3600, seconds / 60 % 60, seconds % 60, micros % 1_000_000)
+}
+
+fn format_timestamp(micros: i64) -> Result<String, String> {
+    match micros {
+        i64::MAX => Ok("infinity".to_string()),
+        i64::MIN => Ok("-infinity".to_string()),
+        _ => {
+            let timestamp = pg_epoch()
+                .checked_add_signed(chrono::Duration::microseconds(micros))
+                .ok_or_else(|| "timestamp out of range".to_string())?;
+            let time = timestamp.time();
+            let clock = format_clock(
+                time.hour() as i64,
+                time.minute() as i64,
+                time.second() as i64,
+                (time.nanosecond() / 1000) as i64,
+            );
+            // Keep the era suffix at the end, as PostgreSQL does
+            let date = format_ymd(timestamp.date());
+            Ok(match date.strip_suffix(" BC") {
+                Some(date) => format!("{} {} BC", date, clock),
+                None => format!("{} {}", date, clock),
+            })
+        }
+    }
+}
+
+/// `timestamptz` in the session's time zone followed by its offset there, as PostgreSQL
+/// prints it; the value is stored as UTC
+fn format_timestamptz(micros: i64, zone: &SessionTimeZone) -> Result<String, String> {
+    if micros == i64::MAX || micros == i64::MIN {
+        return format_timestamp(micros);
+    }
+    let utc = pg_epoch()
+        .checked_add_signed(chrono::Duration::microseconds(micros))
+        .ok_or_else(|| "timestamp out of range".to_string())?;
+    let offset = zone.offset_at(utc);
+    let text = format_timestamp(micros + offset as i64 * 1_000_000)?;
+    Ok(match text.strip_suffix(" BC") {
+        Some(text) => format!("{}{} BC", text, format_offset(offset)),
+        None => format!("{}{}", text, format_offset(offset)),
+    })
+}
+
+/// UTC offset in seconds east, as `+HH`, `+HH:MM` or `+HH:MM:SS`
+fn format_offset(seconds_east: i32) -> String {
+    let sign = if seconds_east < 0 { '-' } else { '+' };
+    let total = seconds_east.abs();
+    let (hours, minutes, seconds) = (total / 3600, total / 60 % 60, total % 60);
+    match (minutes, seconds) {
+        (0, 0) => format!("{}{:02}", sign, hours),
+        (_, 0) => format!("{}{:02}:{:02}", sign, hours, minutes),
+        _ => format!("{}{:02}:{:02}:{:02}", sign, hours, minutes, seconds),
+    }
+}
+
+/// Interval in PostgreSQL's default `postgres` output style, e.g. `1 year 2 mons 3 days 04:05:06`
+fn format_interval(months: i32, days: i32, micros: i64) -> String {
+    fn unit(parts: &mut Vec<String>, value: i32, singular: &str, plural: &str) {
+        if value != 0 {
+            let name = if value == 1 { singular } else { plural };
+            parts.push(format!("{} {}", value, name));
+        }
+    }
+
+    let mut parts = Vec::new();
+    unit(&mut parts, months / 12, "year", "years");
+    unit(&mut parts, months % 12, "mon", "mons");
+    unit(&mut parts, days, "day", "days");
+
+    if micros != 0 || parts.is_empty() {
+        let sign = if micros < 0 { "-" } else { "" };
+        let micros = (micros as i128).abs();
+        let seconds = (micros / 1_000_000) as i64;
+        parts.push(format!(
+            "{}{}",
+            sign,
+            format_clock(seconds / 3600, seconds / 60 % 60, seconds % 60, (micros % 1_000_000) as i64)
+        ));
+    }
+    parts.join(" ")
+}
+
+fn decode_inet(reader: &mut Reader) -> Result<String, String> {
+    const AF_INET: u8 = 2;
+    const AF_INET6: u8 = 3;
+
+    let family = reader.u8()?;
+    let bits = reader.u8()?;
+    let is_cidr = reader.u8()? != 0;
+    let length = reader.u8()? as usize;
+    let address = reader.bytes(length)?;
+
+    let (address, full_mask) = match (family, length) {
+        (AF_INET, 4) => (Ipv4Addr::new(address[0], address[1], address[2], address[3]).to_string(), 32),
+        (AF_INET6, 16) => {
+            let mut octets = [0u8; 16];
+            octets.copy_from_slice(address);
+            (Ipv6Addr::from(octets).to_string(), 128)
+        }
+        _ => return Err(format!("invalid inet family {} with {} bytes", family, length)),
+    };
+
+    // inet omits a full-length mask, cidr always shows it
+    if is_cidr || bits != full_mask {
+        Ok(format!("{}/{}", address, bits))
+    } else {
+        Ok(address)
+    }
+}
+
+fn decode_bits(reader: &mut Reader) -> Result<String, String> {
+    let length = reader.i32()?.max(0) as usize;
+    let bytes = reader.bytes(length.div_ceil(8))?;
+    Ok((0..length)
+        .map(|bit| if bytes[bit / 8] & (0x80 >> (bit % 8)) != 0 { '1' } else { '0' })
+        .collect())
+}
+
+fn format_point(x: f64, y: f64) -> String {
+    format!("({},{})", x, y)
+}
+
+fn read_points(reader: &mut Reader, count: i32) -> Result<String, String> {
+    let mut points = Vec::new();
+    for _ in 0..count.max(0) {
+        points.push(format_point(reader.f64()?, reader.f64()?));
+    }
+    Ok(points.join(","))
+}
+
+/// Arrays become nested JSON arrays, one level per dimension
+fn decode_array(element: &Type, raw: &[u8], zone: &SessionTimeZone) -> Result<Value, String> {
+    let mut reader = Reader::new(raw);
+    let dimensions = reader.i32()?;
+    let _has_nulls = reader.i32()?;
+    let _element_oid = reader.u32()?;
+    if dimensions <= 0 {
+        return Ok(Value::Array(Vec::new()));
+    }
+
+    let mut lengths = Vec::new();
+    for _ in 0..dimensions {
+        lengths.push(reader.i32()?.max(0) as usize);
+        let _lower_bound = reader.i32()?;
+    }
+    decode_array_level(element, &lengths, &mut reader, zone)
+}
+
+fn decode_array_level(element: &Type, lengths: &[usize], reader: &mut Reader, zone: &SessionTimeZone) -> Result<Value, String> {
+    let mut values = Vec::with_capacity(lengths[0]);
+    for _ in 0..lengths[0] {
+        if lengths.len() > 1 {
+            values.push(decode_array_level(element, &lengths[1..], reader, zone)?);
+        } else {
+            values.push(match reader.value()? {
+                Some(raw) => decode_value(element, raw, zone)?,
+                None => Value::Null,
+            });
+        }
+    }
+    Ok(Value::Array(values))
+}
+
+/// Ranges are rendered as their text literal, e.g. `[1,10)` or `empty`
+fn decode_range(element: &Type, raw: &[u8], zone: &SessionTimeZone) -> Result<String, String> {
+    const EMPTY: u8 = 0x01;
+    const LOWER_INCLUSIVE: u8 = 0x02;
+    const UPPER_INCLUSIVE: u8 = 0x04;
+    const LOWER_INFINITE: u8 = 0x08;
+    const UPPER_INFINITE: u8 = 0x10;
+
+    let mut reader = Reader::new(raw);
+    let flags = reader.u8()?;
+    if flags & EMPTY != 0 {
+        return Ok("empty".to_string());
+    }
+
+    let mut bound = |infinite: bool| -> Result<String, String> {
+        if infinite {
+            return Ok(String::new());
+        }
+        match reader.value()? {
+            Some(raw) => Ok(quote_range_bound(&value_text(&decode_value(element, raw, zone)?))),
+            None => Ok(String::new()),
+        }
+    };
+    let lower = bound(flags & LOWER_INFINITE != 0)?;
+    let upper = bound(flags & UPPER_INFINITE != 0)?;
+
+    Ok(format!(
+        "{}{},{}{}",
+        if flags & LOWER_INCLUSIVE != 0 { '[' } else { '(' },
+        lower,
+        upper,
+        if flags & UPPER_INCLUSIVE != 0 { ']' } else { ')' }
+    ))
+}
+
+fn decode_multirange(element: &Type, raw: &[u8], zone: &SessionTimeZone) -> Result<String, String> {
+    let mut reader = Reader::new(raw);
+    let count = reader.i32()?;
+    let mut ranges = Vec::new();
+    for _ in 0..count.max(0) {
+        let length = reader.i32()?.max(0) as usize;
+        ranges.push(decode_range(element, reader.bytes(length)?, zone)?);
+    }
+    Ok(format!("{{{}}}", ranges.join(",")))
+}
+
+fn quote_range_bound(text: &str) -> String {
+    let needs_quotes = text.is_empty()
+        || text
+            .chars()
+            .any(|c| c.is_whitespace() || matches!(c, '"' | '\\' | ',' | '(' | ')' | '[' | ']'));
+    if needs_quotes {
+        format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+    } else {
+        text.to_string()
+    }
+}
+
+/// Text form of an already decoded value, for embedding in range literals
+fn value_text(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// Composites become JSON objects keyed by attribute name
+fn decode_composite(fields: &[(&str, &Type)], raw: &[u8], zone: &SessionTimeZone) -> Result<Value, String> {
+    let mut reader = Reader::new(raw);
+    let count = reader.i32()?.max(0) as usize;
+    let mut object = Map::new();
+    for index in 0..count {
+        let _oid = reader.u32()?;
+        let value = reader.value()?;
+        let (name, ty) = fields
+            .get(index)
+            .ok_or_else(|| "composite has more attributes than its type".to_string())?;
+        let value = match value {
+            Some(raw) => decode_value(ty, raw, zone)?,
+            None => Value::Null,
+        };
+        object.insert(name.to_string(), value);
+    }
+    Ok(Value::Object(object))
+}
+
+fn decode_hstore(raw: &[u8]) -> Result<Value, String> {
+    let mut reader = Reader::new(raw);
+    let count = reader.i32()?.max(0) as usize;
+    let mut object = Map::new();
+    for _ in 0..count {
+        let key = reader.value()?.ok_or_else(|| "hstore key is NULL".to_string())?;
+        let value = match reader.value()? {
+            Some(value) => Value::String(utf8(value)?),
+            None => Value::Null,
+        };
+        object.insert(utf8(key)?, value);
+    }
+    Ok(Value::Object(object))
+}
+
+/// Big-endian cursor over a binary value
+struct Reader<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn new(buf: &'a [u8]) -> Self {
+        Self { buf }
+    }
+
+    fn bytes(&mut self, length: usize) -> Result<&'a [u8], String> {
+        if self.buf.len() < length {
+            return Err("unexpected end of value".to_string());
+        }
+        let (head, tail) = self.buf.split_at(length);
+        self.buf = tail;
+        Ok(head)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], String> {
+        let mut bytes = [0u8; N];
+        bytes.copy_from_slice(self.bytes(N)?);
+        Ok(bytes)
+    }
+
+    fn rest(&mut self) -> &'a [u8] {
+        std::mem::take(&mut self.buf)
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        Ok(u16::from_be_bytes(self.array()?))
+    }
+
+    fn i16(&mut self) -> Result<i16, String> {
+        Ok(i16::from_be_bytes(self.array()?))
+    }
+
+    fn i32(&mut self) -> Result<i32, String> {
+        Ok(i32::from_be_bytes(self.array()?))
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_be_bytes(self.array()?))
+    }
+
+    fn i64(&mut self) -> Result<i64, String> {
+        Ok(i64::from_be_bytes(self.array()?))
+    }
+
+    fn u64(&mut self) -> Result<u64, String> {
+        Ok(u64::from_be_bytes(self.array()?))
+    }
+
+    fn f32(&mut self) -> Result<f32, String> {
+        Ok(f32::from_be_bytes(self.array()?))
+    }
+
+    fn f64(&mut self) -> Result<f64, String> {
+        Ok(f64::from_be_bytes(self.array()?))
+    }
+
+    /// Length-prefixed value as used in arrays, ranges and composites; `None` is NULL
+    fn value(&mut self) -> Result<Option<&'a [u8]>, String> {
+        let length = self.i32()?;
+        if length < 0 {
+            return Ok(None);
+        }
+        self.bytes(length as usize).map(Some)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio_postgres::types::Field;
+
+    fn numeric(weight: i16, sign: u16, dscale: u16, digits: &[i16]) -> Vec<u8> {
+        let mut raw = Vec::new();
+        raw.extend_from_slice(&(digits.len() as i16).to_be_bytes());
+        raw.extend_from_slice(&weight.to_be_bytes());
+        raw.extend_from_slice(&sign.to_be_bytes());
+        raw.extend_from_slice(&dscale.to_be_bytes());
+        for digit in digits {
+            raw.extend_from_slice(&digit.to_be_bytes());
+        }
+        raw
+    }
+
+    fn element(raw: Option<&[u8]>) -> Vec<u8> {
+        match raw {
+            Some(raw) => [&(raw.len() as i32).to_be_bytes()[..], raw].concat(),
+            None => (-1i32).to_be_bytes().to_vec(),
+        }
+    }
+
+    fn decode(ty: &Type, raw: &[u8]) -> Result<Value, String> {
+        decode_value(ty, raw, &SessionTimeZone::default())
+    }
+
+    fn text(value: Value) -> String {
+        value.as_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn test_numeric_is_exact() {
+        // 12345678901234567890.0123
+        let raw = numeric(4, 0x0000, 4, &[1234, 5678, 9012, 3456, 7890, 123]);
+        assert_eq!(text(decode(&Type::NUMERIC, &raw).unwrap()), "12345678901234567890.0123");
+
+        let raw = numeric(-2, 0x4000, 8, &[1]);
+        assert_eq!(text(decode(&Type::NUMERIC, &raw).unwrap()), "-0.00000001");
+
+        let raw = numeric(0, 0x0000, 2, &[19, 9900]);
+        assert_eq!(text(decode(&Type::NUMERIC, &raw).unwrap()), "19.99");
+
+        let raw = numeric(0, 0xC000, 0, &[]);
+        assert_eq!(text(decode(&Type::NUMERIC, &raw).unwrap()), "NaN");
+    }
+
+    #[test]
+    fn test_integers_and_floats() {
+        assert_eq!(decode(&Type::INT4, &42i32.to_be_bytes()).unwrap(), Value::from(42));
+        assert_eq!(decode(&Type::INT8, &7i64.to_be_bytes()).unwrap(), Value::from(7));
+        assert_eq!(
+            decode(&Type::INT8, &i64::MAX.to_be_bytes()).unwrap(),
+            Value::String("9223372036854775807".to_string())
+        );
+        assert_eq!(
+            decode(&Type::FLOAT8, &f64::NAN.to_be_bytes()).unwrap(),
+            Value::String("NaN".to_string())
+        );
+    }
+
+    #[test]
+    fn test_uuid_and_network_types() {
+        let id = uuid::Uuid::new_v4();
+        assert_eq!(text(decode(&Type::UUID, id.as_bytes()).unwrap()), id.to_string());
+
+        let inet = [2u8, 32, 0, 4, 192, 168, 0, 1];
+        assert_eq!(text(decode(&Type::INET, &inet).unwrap()), "192.168.0.1");
+        let cidr = [2u8, 24, 1, 4, 10, 0, 0, 0];
+        assert_eq!(text(decode(&Type::CIDR, &cidr).unwrap()), "10.0.0.0/24");
+        let mut inet6 = vec![3u8, 64, 0, 16];
+        inet6.extend_from_slice(&"2001:db8::1".parse::<Ipv6Addr>().unwrap().octets());
+        assert_eq!(text(decode(&Type::INET, &inet6).unwrap()), "2001:db8::1/64");
+
+        let mac = [8u8, 0, 0x2b, 1, 2, 3];
+        assert_eq!(text(decode(&Type::MACADDR, &mac).unwrap()), "08:00:2b:01:02:03");
+    }
+
+    #[test]
+    fn test_date_time_values() {
+        // 2024-03-01 12:30:45.5 is 762611445.5 seconds after 2000-01-01
+        let micros: i64 = 762_611_445_500_000;
+        assert_eq!(
+            text(decode(&Type::TIMESTAMP, &micros.to_be_bytes()).unwrap()),
+            "2024-03-01 12:30:45.5"
+        );
+        assert_eq!(
+            text(decode(&Type::TIMESTAMPTZ, &micros.to_be_bytes()).unwrap()),
+            "2024-03-01 12:30:45.5+00"
+        );
+        let berlin = SessionTimeZone::parse("Europe/Berlin");
+        assert_eq!(
+            text(decode_value(&Type::TIMESTAMPTZ, &micros.to_be_bytes(), &berlin).unwrap()),
+            "2024-03-01 13:30:45.5+01"
+        );
+        // Summer time: 2024-07-01 12:00:00 UTC
+        let july: i64 = 773_150_400_000_000;
+        assert_eq!(
+            text(decode_value(&Type::TIMESTAMPTZ, &july.to_be_bytes(), &berlin).unwrap()),
+            "2024-07-01 14:00:00+02"
+        );
+        let india = SessionTimeZone::parse("<+05:30>-05:30");
+        assert_eq!(
+            text(decode_value(&Type::TIMESTAMPTZ, &micros.to_be_bytes(), &india).unwrap()),
+            "2024-03-01 18:00:45.5+05:30"
+        );
+        assert_eq!(
+            text(decode(&Type::TIMESTAMPTZ, &i64::MAX.to_be_bytes()).unwrap()),
+            "infinity"
+        );
+        assert_eq!(text(decode(&Type::DATE, &(-1i32).to_be_bytes()).unwrap()), "1999-12-31");
+
+        let mut timetz = (3_600_000_000i64 * 9).to_be_bytes().to_vec();
+        timetz.extend_from_slice(&(-19800i32).to_be_bytes());
+        assert_eq!(text(decode(&Type::TIMETZ, &timetz).unwrap()), "09:00:00+05:30");
+    }
+
+    #[test]
+    fn test_interval_formatting() {
+        assert_eq!(format_interval(14, 3, 14_706_789_000), "1 year 2 mons 3 days 04:05:06.789");
+        assert_eq!(format_interval(0, -1, 0), "-1 days");
+        assert_eq!(format_interval(0, 0, -90_000_000), "-00:01:30");
+        assert_eq!(format_interval(0, 0, 0), "00:00:00");
+    }
+
+    #[test]
+    fn test_text_array_with_nulls_and_quotes() {
+        let mut raw = Vec::new();
+        raw.extend_from_slice(&1i32.to_be_bytes());
+        raw.extend_from_slice(&1i32.to_be_bytes());
+        raw.extend_from_slice(&Type::TEXT.oid().to_be_bytes());
+        raw.extend_from_slice(&3i32.to_be_bytes());
+        raw.extend_from_slice(&1i32.to_be_bytes());
+        raw.extend(element(Some(b"a,\"b\"")));
+        raw.extend(element(None));
+        raw.extend(element(Some(b"{c}")));
+
+        let value = decode(&Type::TEXT_ARRAY, &raw).unwrap();
+        assert_eq!(value, serde_json::json!(["a,\"b\"", null, "{c}"]));
+    }
+
+    #[test]
+    fn test_two_dimensional_int_array() {
+        let mut raw = Vec::new();
+        raw.extend_from_slice(&2i32.to_be_bytes());
+        raw.extend_from_slice(&0i32.to_be_bytes());
+        raw.extend_from_slice(&Type::INT4.oid().to_be_bytes());
+        for _ in 0..2 {
+            raw.extend_from_slice(&2i32.to_be_bytes());
+            raw.extend_from_slice(&1i32.to_be_bytes());
+        }
+        for n in 1..=4i32 {
+            raw.extend(element(Some(&n.to_be_bytes())));
+        }
+
+        let value = decode(&Type::INT4_ARRAY, &raw).unwrap();
+        assert_eq!(value, serde_json::json!([[1, 2], [3, 4]]));
+    }
+
+    #[test]
+    fn test_ranges() {
+        let mut raw = vec![0x02];
+        raw.extend(element(Some(&1i32.to_be_bytes())));
+        raw.extend(element(Some(&10i32.to_be_bytes())));
+        assert_eq!(text(decode(&Type::INT4_RANGE, &raw).unwrap()), "[1,10)");
+
+        assert_eq!(text(decode(&Type::INT4_RANGE, &[0x01]).unwrap()), "empty");
+
+        let mut raw = vec![0x02 | 0x10];
+        raw.extend(element(Some(&0i64.to_be_bytes())));
+        assert_eq!(
+            text(decode(&Type::TS_RANGE, &raw).unwrap()),
+            "[\"2000-01-01 00:00:00\",)"
+        );
+    }
+
+    #[test]
+    fn test_composite_enum_and_hstore() {
+        let composite = Type::new(
+            "money_amount".to_string(),
+            90001,
+            Kind::Composite(vec![
+                Field::new("currency".to_string(), Type::TEXT),
+                Field::new("amount".to_string(), Type::NUMERIC),
+            ]),
+            "public".to_string(),
+        );
+        let mut raw = 2i32.to_be_bytes().to_vec();
+        raw.extend_from_slice(&Type::TEXT.oid().to_be_bytes());
+        raw.extend(element(Some(b"EUR")));
+        raw.extend_from_slice(&Type::NUMERIC.oid().to_be_bytes());
+        raw.extend(element(Some(&numeric(0, 0x0000, 2, &[10, 5000]))));
+        assert_eq!(
+            decode(&composite, &raw).unwrap(),
+            serde_json::json!({"currency": "EUR", "amount": "10.50"})
+        );
+
+        let mood = Type::new(
+            "mood".to_string(),
+            90002,
+            Kind::Enum(vec!["happy".to_string(), "sad".to_string()]),
+            "public".to_string(),
+        );
+        assert_eq!(text(decode(&mood, b"happy").unwrap()), "happy");
+
+        let hstore = Type::new("hstore".to_string(), 90003, Kind::Simple, "public".to_string());
+        let mut raw = 2i32.to_be_bytes().to_vec();
+        raw.extend(element(Some(b"a")));
+        raw.extend(element(Some(b"1")));
+        raw.extend(element(Some(b"b")));
+        raw.extend(element(None));
+        assert_eq!(
+            decode(&hstore, &raw).unwrap(),
+            serde_json::json!({"a": "1", "b": null})
+        );
+    }
+
+    #[test]
+    fn test_geometric_types() {
+        let mut point = 1.5f64.to_be_bytes().to_vec();
+        point.extend_from_slice(&(-2f64).to_be_bytes());
+        assert_eq!(text(decode(&Type::POINT, &point).unwrap()), "(1.5,-2)");
+
+        let mut circle = point.clone();
+        circle.extend_from_slice(&3f64.to_be_bytes());
+        assert_eq!(text(decode(&Type::CIRCLE, &circle).unwrap()), "<(1.5,-2),3>");
+
+        let mut path = vec![0u8];
+        path.extend_from_slice(&2i32.to_be_bytes());
+        path.extend_from_slice(&point);
+        path.extend_from_slice(&point);
+        assert_eq!(text(decode(&Type::PATH, &path).unwrap()), "[(1.5,-2),(1.5,-2)]");
+    }
+
+    #[test]
+    fn test_truncated_value_is_an_error() {
+        assert!(decode(&Type::INT8, &[0, 1]).is_err());
+    }
+}
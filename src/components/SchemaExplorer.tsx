@@ -1036,7 +1036,7 @@ export default function SchemaExplorer({ isConnected, onTableSelect, tableRowCou
                                         onClick={() => {
                                           console.log('🔗 [SchemaExplorer] Foreign key clicked:', fk);
                                           // Execute a query to show related data
-                                          onTableSelect?.(fk.referenced_table, 'public');
+                                          onTableSelect?.(fk.referenced_table, fk.referenced_schema || 'public');
                                         }}
                                         title={`Click to view ${fk.referenced_table} table`}
                                       >
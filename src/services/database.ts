@@ -1,5 +1,5 @@
 import { invoke } from '@tauri-apps/api/core';
-import { QueryResult, TableInfo, ColumnInfo, DetailedColumnInfo, QueryValidationResponse, TableSizeInfo, IndexInfo, ForeignKeyInfo, TableStatistics, DatabaseStatistics, ExportOptions, ExportResult, ExportPreview, SqlExportType, ImportOptions, ImportResult, ViewInfo, StoredProcedureInfo, MaterializedViewInfo, CreateIndexOptions } from '../types/database';
+import { QueryResult, TableInfo, ColumnInfo, DetailedColumnInfo, QueryValidationResponse, TableSizeInfo, IndexInfo, ForeignKeyInfo, TableStatistics, DatabaseStatistics, ExportOptions, ExportResult, ExportPreview, SqlExportType, ImportOptions, ImportResult, ViewInfo, StoredProcedureInfo, MaterializedViewInfo, CreateIndexOptions, RowChangeResult, RowVersionCheck, RowIdentifier, TransactionInfo, TransactionOptions, ScriptOptions, ScriptResult, TableExportOptions, GuardOperation, RowOperation, GuardDecision, GuardStatus, HistoryFilter, HistoryPage, QueryHistoryEntry, RetentionPolicy, SavedQuery, SavedQuerySearch, SavedQueryImportSummary, ExplainOptions, QueryPlan, SchemaDiff } from '../types/database';
 
 export interface ConnectionStatus {
   connected: boolean;
@@ -140,12 +140,12 @@ export class DatabaseService {
     return invoke('simple_test_connection', { connectionString });
   }
 
-  static async executeQuery(query: string): Promise<QueryResult> {
+  static async executeQuery(query: string, transactionId?: string, confirmationToken?: string): Promise<QueryResult> {
     console.log('🔧 [DatabaseService] executeQuery called with:', query);
     
     try {
       console.log('🔧 [DatabaseService] Starting invoke call...');
-      const result = await invoke('execute_query', { query });
+      const result = await invoke('execute_query', { query, transactionId, confirmationToken });
       console.log('🔧 [DatabaseService] executeQuery result:', result);
       return result as QueryResult;
     } catch (error) {
@@ -154,6 +154,11 @@ export class DatabaseService {
     }
   }
 
+  static async executeScript(script: string, options?: ScriptOptions, executionId?: string, confirmationToken?: string): Promise<ScriptResult> {
+    console.log('🔧 [DatabaseService] executeScript called');
+    return invoke('execute_script', { script, options, executionId, confirmationToken });
+  }
+
   static async executeQueryPaginated(query: string, page?: number, pageSize?: number): Promise<QueryResult> {
     return invoke('execute_query_paginated', { query, page, pageSize });
   }
@@ -201,6 +206,10 @@ export class DatabaseService {
     return invoke('query_table', { tableName, schemaName });
   }
 
+  static async getRowIdentifier(tableName: string, schemaName?: string): Promise<RowIdentifier | null> {
+    return invoke('get_row_identifier', { tableName, schemaName });
+  }
+
   // Alias for compatibility
   static async simpleQueryTable(tableName: string, schemaName?: string, _limit?: number): Promise<QueryResult> {
     return this.queryTable(tableName, schemaName);
@@ -242,16 +251,20 @@ export class DatabaseService {
     return invoke('get_stored_procedures');
   }
 
+  static async compareSchemas(sourceSessionId: string, targetSessionId: string, schemas?: string[]): Promise<SchemaDiff> {
+    return invoke('compare_schemas', { sourceSessionId, targetSessionId, schemas });
+  }
+
   static async getMaterializedViews(): Promise<MaterializedViewInfo[]> {
     return invoke('get_materialized_views');
   }
 
-  static async createIndex(options: CreateIndexOptions): Promise<string> {
-    return invoke('create_index', { options });
+  static async createIndex(options: CreateIndexOptions, confirmationToken?: string): Promise<string> {
+    return invoke('create_index', { options, confirmationToken });
   }
 
-  static async dropIndex(indexName: string, schemaName?: string): Promise<string> {
-    return invoke('drop_index', { indexName, schemaName });
+  static async dropIndex(indexName: string, schemaName?: string, confirmationToken?: string): Promise<string> {
+    return invoke('drop_index', { indexName, schemaName, confirmationToken });
   }
 
   static async getDatabaseStatistics(): Promise<DatabaseStatistics> {
@@ -287,26 +300,32 @@ export class DatabaseService {
     schemaName: string | undefined, 
     primaryKeyColumns: string[], 
     primaryKeyValues: any[], 
-    columnUpdates: Record<string, any>
-  ): Promise<number> {
+    columnUpdates: Record<string, any>,
+    check?: RowVersionCheck,
+    confirmationToken?: string
+  ): Promise<RowChangeResult> {
     return invoke('update_row', { 
       tableName, 
       schemaName, 
       primaryKeyColumns, 
       primaryKeyValues, 
-      columnUpdates 
+      columnUpdates,
+      check,
+      confirmationToken
     });
   }
 
   static async insertRow(
     tableName: string, 
     schemaName: string | undefined, 
-    columnValues: Record<string, any>
-  ): Promise<number> {
+    columnValues: Record<string, any>,
+    confirmationToken?: string
+  ): Promise<RowChangeResult> {
     return invoke('insert_row', { 
       tableName, 
       schemaName, 
-      columnValues 
+      columnValues,
+      confirmationToken
     });
   }
 
@@ -314,30 +333,156 @@ export class DatabaseService {
     tableName: string, 
     schemaName: string | undefined, 
     primaryKeyColumns: string[], 
-    primaryKeyValues: any[]
-  ): Promise<number> {
+    primaryKeyValues: any[],
+    check?: RowVersionCheck,
+    confirmationToken?: string
+  ): Promise<RowChangeResult> {
     return invoke('delete_row', { 
       tableName, 
       schemaName, 
       primaryKeyColumns, 
-      primaryKeyValues 
+      primaryKeyValues,
+      check,
+      confirmationToken
     });
   }
 
-  static async beginTransaction(): Promise<void> {
-    return invoke('begin_transaction');
+  static async beginTransaction(options?: TransactionOptions): Promise<TransactionInfo> {
+    return invoke('begin_transaction', { options });
   }
 
-  static async commitTransaction(): Promise<void> {
+  static async commitTransaction(): Promise<TransactionInfo> {
     return invoke('commit_transaction');
   }
 
-  static async rollbackTransaction(): Promise<void> {
+  static async rollbackTransaction(): Promise<TransactionInfo> {
     return invoke('rollback_transaction');
   }
 
-  static async executeTransaction(operations: any[]): Promise<number[]> {
-    return invoke('execute_transaction', { operations });
+  static async createSavepoint(name: string): Promise<TransactionInfo> {
+    return invoke('create_savepoint', { name });
+  }
+
+  static async rollbackToSavepoint(name: string): Promise<TransactionInfo> {
+    return invoke('rollback_to_savepoint', { name });
+  }
+
+  static async releaseSavepoint(name: string): Promise<TransactionInfo> {
+    return invoke('release_savepoint', { name });
+  }
+
+  static async getTransactionState(): Promise<TransactionInfo> {
+    return invoke('get_transaction_state');
+  }
+
+  // On a conflict nothing is applied and the results end at the operation whose result has `conflict`
+  static async executeTransaction(operations: any[], confirmationToken?: string): Promise<RowChangeResult[]> {
+    return invoke('execute_transaction', { operations, confirmationToken });
+  }
+
+  // Production safety guard
+  static async checkOperation(operation: GuardOperation): Promise<GuardDecision> {
+    return invoke('check_operation', { operation });
+  }
+
+  // The operation to check before updateRow/insertRow/deleteRow with the same arguments
+  static rowChange(
+    tableName: string,
+    schemaName: string | undefined,
+    primaryKeyValues: any[],
+    operation: RowOperation
+  ): GuardOperation {
+    return { kind: 'row_change', schema: schemaName ?? null, table: tableName, key: primaryKeyValues, operation };
+  }
+
+  // `confirmation` must repeat the profile name
+  static async unlockProductionWrites(confirmation: string, durationMinutes?: number): Promise<GuardStatus> {
+    return invoke('unlock_production_writes', { confirmation, durationMinutes });
+  }
+
+  static async lockProductionWrites(): Promise<GuardStatus> {
+    return invoke('lock_production_writes');
+  }
+
+  static async explainQuery(query: string, options?: ExplainOptions, confirmationToken?: string): Promise<QueryPlan> {
+    return invoke('explain_query', { query, options, confirmationToken });
+  }
+
+  // Query history
+  static async searchQueryHistory(filter?: HistoryFilter): Promise<HistoryPage> {
+    return invoke('search_query_history', { filter });
+  }
+
+  static async pinQueryHistoryEntry(id: string, pinned: boolean): Promise<QueryHistoryEntry> {
+    return invoke('pin_query_history_entry', { id, pinned });
+  }
+
+  // Returns how many entries were removed; pinned entries are kept unless includePinned
+  static async purgeQueryHistory(filter?: HistoryFilter, includePinned?: boolean): Promise<number> {
+    return invoke('purge_query_history', { filter, includePinned });
+  }
+
+  static async getHistoryRetention(): Promise<RetentionPolicy> {
+    return invoke('get_history_retention');
+  }
+
+  static async setHistoryRetention(retention: RetentionPolicy): Promise<number> {
+    return invoke('set_history_retention', { retention });
+  }
+
+  // Saved query library
+  static async searchSavedQueries(search?: SavedQuerySearch): Promise<SavedQuery[]> {
+    return invoke('search_saved_queries', { search });
+  }
+
+  static async getSavedQuery(id: string): Promise<SavedQuery> {
+    return invoke('get_saved_query', { id });
+  }
+
+  static async createSavedQuery(query: SavedQuery): Promise<SavedQuery> {
+    return invoke('create_saved_query', { query });
+  }
+
+  static async updateSavedQuery(id: string, query: SavedQuery): Promise<SavedQuery> {
+    return invoke('update_saved_query', { id, query });
+  }
+
+  static async deleteSavedQuery(id: string): Promise<SavedQuery> {
+    return invoke('delete_saved_query', { id });
+  }
+
+  static async getSavedQueryFolders(): Promise<string[]> {
+    return invoke('get_saved_query_folders');
+  }
+
+  static async getSavedQueryTags(): Promise<string[]> {
+    return invoke('get_saved_query_tags');
+  }
+
+  static async exportSavedQueries(directory: string): Promise<number> {
+    return invoke('export_saved_queries', { directory });
+  }
+
+  static async importSavedQueries(directory: string): Promise<SavedQueryImportSummary> {
+    return invoke('import_saved_queries', { directory });
+  }
+
+  // Values are bound server-side to the query's :name placeholders; null binds NULL
+  static async executeSavedQuery(
+    queryId: string,
+    parameters?: Record<string, string | number | boolean | object | null>,
+    transactionId?: string,
+    confirmationToken?: string
+  ): Promise<QueryResult> {
+    return invoke('execute_saved_query', { queryId, parameters, transactionId, confirmationToken });
+  }
+
+  // The guard decision for executeSavedQuery with the same parameters
+  static async checkSavedQuery(
+    queryId: string,
+    parameters?: Record<string, string | number | boolean | object | null>
+  ): Promise<GuardDecision> {
+    return invoke('check_saved_query', { queryId, parameters });
   }
 
   // Performance optimization methods
@@ -414,13 +559,29 @@ export class DatabaseService {
     }
   }
 
-  static async importSQLFile(sqlContent: string, importOptions: ImportOptions): Promise<ImportResult> {
+  static async startTableExport(tableName: string, schemaName: string | undefined, filePath: string, options: TableExportOptions): Promise<string> {
+    console.log('🔧 [DatabaseService] startTableExport called with:', { tableName, schemaName, filePath });
+    return invoke('start_table_export', { tableName, schemaName, filePath, options });
+  }
+
+  static async startQueryExport(query: string, filePath: string, options: TableExportOptions): Promise<string> {
+    console.log('🔧 [DatabaseService] startQueryExport called with:', { filePath });
+    return invoke('start_query_export', { query, filePath, options });
+  }
+
+  static async cancelTableExport(exportId: string): Promise<boolean> {
+    return invoke('cancel_table_export', { exportId });
+  }
+
+  static async importSQLFile(sqlContent: string, importOptions: ImportOptions, importId?: string, confirmationToken?: string): Promise<ImportResult> {
     console.log('🔧 [DatabaseService] importSQLFile called');
     
     try {
       const result = await invoke('import_sql_file', {
         sqlContent,
-        importOptions
+        importOptions,
+        importId,
+        confirmationToken
       });
       console.log('🔧 [DatabaseService] importSQLFile result:', result);
       return result as ImportResult;
@@ -430,13 +591,15 @@ export class DatabaseService {
     }
   }
 
-  static async importSQLFromFile(filePath: string, importOptions: ImportOptions): Promise<ImportResult> {
+  static async importSQLFromFile(filePath: string, importOptions: ImportOptions, importId?: string, confirmationToken?: string): Promise<ImportResult> {
     console.log('🔧 [DatabaseService] importSQLFromFile called with:', { filePath });
     
     try {
       const result = await invoke('import_sql_from_file', {
         filePath,
-        importOptions
+        importOptions,
+        importId,
+        confirmationToken
       });
       console.log('🔧 [DatabaseService] importSQLFromFile result:', result);
       return result as ImportResult;
@@ -445,4 +608,21 @@ export class DatabaseService {
       throw error;
     }
   }
+
+  static async importTableData(filePath: string, importOptions: ImportOptions, confirmationToken?: string): Promise<ImportResult> {
+    console.log('🔧 [DatabaseService] importTableData called with:', { filePath });
+    
+    try {
+      const result = await invoke('import_table_data', {
+        filePath,
+        importOptions,
+        confirmationToken
+      });
+      console.log('🔧 [DatabaseService] importTableData result:', result);
+      return result as ImportResult;
+    } catch (error) {
+      console.error('🔧 [DatabaseService] importTableData error:', error);
+      throw error;
+    }
+  }
 }
\ No newline at end of file
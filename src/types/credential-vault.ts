@@ -14,6 +14,20 @@ export interface CredentialResponse {
   message: string;
 }
 
+export type RotationStatus = 'rotated' | 'skipped' | 'unchanged' | 'rolled_back' | 'failed';
+
+export interface ProfileRotationResult {
+  profile_id: string;
+  status: RotationStatus;
+  message: string | null;
+}
+
+export interface RotationReport {
+  rotated: boolean; // false when the rotation was aborted and the old key is still active
+  profiles: ProfileRotationResult[];
+  rotated_at: string; // ISO 8601 datetime string
+}
+
 export interface StoreCredentialsRequest {
   profile_id: string;
   username: string;
@@ -117,12 +131,11 @@ export class CredentialVaultService {
   }
 
   /**
-   * Rotate the master encryption key
-   * WARNING: This may require re-encryption of existing credentials
+   * Rotate the master encryption key, re-encrypting every stored credential
    */
-  static async rotateMasterKey(): Promise<CredentialResponse> {
+  static async rotateMasterKey(): Promise<RotationReport> {
     const { invoke } = await import('@tauri-apps/api/core');
-    return invoke<CredentialResponse>('rotate_vault_master_key');
+    return invoke<RotationReport>('rotate_vault_master_key');
   }
 }
 
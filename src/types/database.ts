@@ -38,6 +38,90 @@ export interface QueryResult {
   rows_affected?: number;
   query_type?: string;
   pagination?: PaginationInfo;
+  column_types?: ColumnType[];
+  row_identifier?: RowIdentifier | null;
+}
+
+export interface RowIdentifier {
+  kind: 'primary_key' | 'unique_index' | 'ctid';
+  columns: string[];
+  index_name: string | null;
+  warning: string | null;
+}
+
+export interface ColumnType {
+  name: string;
+  type_oid: number;
+  type_name: string;
+}
+
+export interface RowChangeResult {
+  rows_affected: number;
+  columns: string[];
+  column_types: ColumnType[];
+  rows: any[][];
+  row_version?: string | null;
+  conflict?: RowConflict | null;
+}
+
+export type TransactionState = 'idle' | 'in_transaction' | 'failed';
+
+export type IsolationLevel = 'read_committed' | 'repeatable_read' | 'serializable';
+
+export interface TransactionOptions {
+  isolation_level?: IsolationLevel;
+  read_only?: boolean;
+  deferrable?: boolean;
+}
+
+export interface TransactionInfo {
+  transaction_id: string | null;
+  state: TransactionState;
+  isolation_level: IsolationLevel | null;
+  read_only: boolean;
+  savepoints: string[];
+  started_at: string | null;
+  statement_count: number;
+}
+
+export type ScriptErrorMode = 'stop_on_error' | 'continue' | 'transaction';
+
+export interface ScriptOptions {
+  on_error?: ScriptErrorMode;
+  timeout_ms?: number;
+}
+
+export type StatementStatus = 'succeeded' | 'failed' | 'skipped' | 'rolled_back';
+
+export interface StatementResult {
+  index: number;
+  line: number;
+  sql: string;
+  command: string;
+  status: StatementStatus;
+  result: QueryResult | null;
+  error: string | null;
+  execution_time_ms: number;
+}
+
+export interface ScriptResult {
+  statements: StatementResult[];
+  succeeded: number;
+  failed: number;
+  skipped: number;
+  warning: string | null;
+  execution_time_ms: number;
+}
+
+export interface RowVersionCheck {
+  original_values?: Record<string, any>;
+  row_version?: string;
+}
+
+export interface RowConflict {
+  current_values: any[] | null;
+  current_row_version: string | null;
+  changed_columns: string[];
 }
 
 export interface PaginationInfo {
@@ -95,6 +179,8 @@ export interface DetailedColumnInfo {
   name: string;
   data_type: string;
   udt_name: string; // User-defined type name (for enums, custom types)
+  udt_schema: string;
+  is_enum: boolean;
   is_nullable: boolean;
   default_value?: string;
   character_maximum_length?: number;
@@ -141,6 +227,7 @@ export interface IndexInfo {
   index_type: string;
   definition: string;
   size_bytes?: number;
+  constraint_definition?: string; // UNIQUE or EXCLUDE constraint the index belongs to
 }
 
 export interface ViewInfo {
@@ -184,6 +271,7 @@ export interface ForeignKeyInfo {
   name: string;
   table_name: string;
   column_name: string;
+  referenced_schema: string;
   referenced_table: string;
   referenced_column: string;
 }
@@ -240,11 +328,30 @@ export enum SqlExportType {
 }
 
 export interface ImportOptions {
-  format: ExportFormat;
+  format: ExportFormat | 'NDJSON';
   table_name?: string;
   schema_name?: string;
   truncate_before_import?: boolean;
   create_table_if_not_exists?: boolean;
+  single_transaction?: boolean;
+  stop_on_error?: boolean;
+  csv_options?: CsvImportOptions;
+  // Source column -> target column; unmapped source columns are skipped
+  column_mapping?: Record<string, string>;
+}
+
+export interface CsvImportOptions {
+  delimiter?: string;
+  quote?: string;
+  escape?: string;
+  has_header?: boolean;
+  null_marker?: string;
+  encoding?: string;
+}
+
+export interface ImportRowError {
+  row: number;
+  error: string;
 }
 
 export interface ImportResult {
@@ -253,12 +360,61 @@ export interface ImportResult {
   errors: string[];
   warnings: string[];
   execution_time_ms: number;
+  statements_executed?: number;
+  rolled_back?: boolean;
+  rows_failed?: number;
+  row_errors?: ImportRowError[];
+  created_table?: boolean;
+}
+
+// Payload of the `sql-import-progress` event
+export interface SqlImportProgress {
+  import_id: string;
+  bytes_read: number;
+  total_bytes: number | null;
+  statements_executed: number;
+  rows_imported: number;
+  errors: number;
+  done: boolean;
 }
 
 export enum ExportFormat {
   CSV = "CSV",
   JSON = "JSON",
   SQL = "SQL",
+  NDJSON = "NDJSON",
+  MARKDOWN = "MARKDOWN",
+  HTML = "HTML",
+  // Binary; only available when exporting straight to a file
+  PARQUET = "PARQUET",
+  XLSX = "XLSX",
+}
+
+export type ExportFileFormat = 'csv' | 'json' | 'ndjson' | 'parquet' | 'xlsx' | 'markdown' | 'html';
+
+export type ExportCompression = 'none' | 'gzip' | 'zstd';
+
+export interface TableExportOptions {
+  format: ExportFileFormat;
+  include_headers?: boolean;
+  delimiter?: string;
+  pretty_json?: boolean;
+  compression?: ExportCompression;
+}
+
+export type TableExportStatus = 'running' | 'completed' | 'cancelled' | 'failed';
+
+// Payload of the `table-export-progress` event
+export interface TableExportProgress {
+  export_id: string;
+  status: TableExportStatus;
+  rows_written: number;
+  bytes_written: number;
+  estimated_rows: number | null;
+  file_path: string;
+  execution_time_ms: number;
+  error: string | null;
+  done: boolean;
 }
 
 export interface ExportResult {
@@ -558,4 +714,211 @@ export const DEFAULT_MONITORING_CONFIG: MonitoringConfig = {
   checkIntervalMinutes: 5,
   enableNotifications: true,
   criticalConnectionIds: [],
-};
\ No newline at end of file
+};
+
+// Production safety guard
+export type StatementClass = 'read' | 'dml' | 'ddl' | 'destructive';
+
+export type RowOperation = 'insert' | 'update' | 'delete';
+
+// A row_change token only confirms the same table, key and operation; `key` is empty for inserts
+export type GuardOperation =
+  | { kind: 'sql'; sql: string }
+  | { kind: 'row_change'; schema?: string | null; table: string; key: any[]; operation: RowOperation }
+  | { kind: 'row_changes'; operations: any[] }
+  | { kind: 'import' }
+  | { kind: 'create_index' }
+  | { kind: 'drop_index' };
+
+export interface GuardDecision {
+  class: StatementClass;
+  production: boolean;
+  allowed: boolean;
+  requires_unlock: boolean;
+  // Pass back with the operation to confirm it; single use
+  confirmation_token: string | null;
+  token_expires_at: string | null;
+  message: string | null;
+}
+
+export interface GuardStatus {
+  production: boolean;
+  unlocked_until: string | null;
+}
+
+// Query History Types
+
+export interface QueryHistoryEntry {
+  id: string;
+  // Null for sessions opened from a raw connection string
+  profile_id: string | null;
+  session_id: string;
+  sql: string;
+  executed_at: string;
+  duration_ms: number;
+  row_count: number | null;
+  success: boolean;
+  error: string | null;
+  pinned: boolean;
+}
+
+export interface HistoryFilter {
+  query?: string;
+  profile_id?: string;
+  from?: string;
+  to?: string;
+  success?: boolean;
+  pinned_only?: boolean;
+  limit?: number;
+  offset?: number;
+}
+
+export interface HistoryPage {
+  entries: QueryHistoryEntry[];
+  total: number;
+}
+
+// Pinned entries are never removed by retention
+export interface RetentionPolicy {
+  max_entries_per_profile: number | null;
+  max_age_days: number | null;
+}
+
+// Saved Query Types
+
+export type ParameterType =
+  | 'text'
+  | 'integer'
+  | 'numeric'
+  | 'boolean'
+  | 'date'
+  | 'timestamp'
+  | 'timestamptz'
+  | 'uuid'
+  | 'json';
+
+export interface QueryParameter {
+  name: string;
+  type: ParameterType;
+  // Without a default the parameter is required
+  default_value?: string | null;
+}
+
+export interface SavedQuery {
+  id: string;
+  name: string;
+  description?: string | null;
+  sql: string;
+  // '/'-separated path, e.g. 'Diagnostics/Locks'
+  folder?: string | null;
+  tags: string[];
+  default_profile_id?: string | null;
+  // Kept in sync with the :name placeholders in sql by the backend
+  parameters: QueryParameter[];
+  created_at: string;
+  updated_at: string;
+}
+
+export interface SavedQuerySearch {
+  query?: string;
+  folder?: string;
+  tags?: string[];
+  default_profile_id?: string;
+}
+
+export interface SavedQueryImportSummary {
+  created: number;
+  updated: number;
+  errors: string[];
+}
+
+// Query Plan Types
+
+export interface ExplainOptions {
+  // Runs the statement; writes are rolled back afterwards
+  analyze?: boolean;
+  buffers?: boolean;
+  verbose?: boolean;
+}
+
+export type HotspotKind = 'seq_scan' | 'row_misestimate' | 'disk_spill';
+
+export interface Hotspot {
+  node_id: number;
+  kind: HotspotKind;
+  message: string;
+}
+
+export interface PlanNode {
+  // Depth-first position in the tree, 0 for the root
+  id: number;
+  node_type: string;
+  relation_name: string | null;
+  schema: string | null;
+  alias: string | null;
+  index_name: string | null;
+  join_type: string | null;
+  parent_relationship: string | null;
+  startup_cost: number;
+  total_cost: number;
+  plan_rows: number;
+  plan_width: number;
+  // Actual values are per loop and only present with ANALYZE
+  actual_startup_time_ms: number | null;
+  actual_total_time_ms: number | null;
+  actual_rows: number | null;
+  actual_loops: number | null;
+  self_time_ms: number | null;
+  rows_removed_by_filter: number | null;
+  filter: string | null;
+  index_cond: string | null;
+  sort_method: string | null;
+  sort_space_type: string | null;
+  sort_space_used_kb: number | null;
+  hash_batches: number | null;
+  shared_hit_blocks: number | null;
+  shared_read_blocks: number | null;
+  temp_written_blocks: number | null;
+  // Remaining fields of the node as reported by the server
+  details: Record<string, unknown>;
+  children: PlanNode[];
+}
+
+export interface QueryPlan {
+  plan: PlanNode;
+  planning_time_ms: number | null;
+  execution_time_ms: number | null;
+  analyzed: boolean;
+  rolled_back: boolean;
+  hotspots: Hotspot[];
+  raw: unknown;
+}
+
+// Schema comparison
+export type SchemaObjectKind = 'table' | 'column' | 'primary_key' | 'index' | 'foreign_key' | 'view' | 'routine';
+
+// 'added' exists only in the source, 'removed' only in the target
+export type SchemaChangeKind = 'added' | 'removed' | 'changed';
+
+export interface SchemaChange {
+  object: SchemaObjectKind;
+  change: SchemaChangeKind;
+  schema: string;
+  table: string | null;
+  name: string;
+  differences: string[];
+}
+
+export interface MigrationStep {
+  description: string;
+  sql: string;
+  destructive: boolean;
+}
+
+export interface SchemaDiff {
+  changes: SchemaChange[];
+  // In the order they must run
+  migration: MigrationStep[];
+  warnings: string[];
+  script: string;
+}